@@ -0,0 +1,154 @@
+//! Benchmarks of compile time and run time for representative programs.
+//!
+//! Each program is measured five ways: lexing and parsing, name resolution
+//! of the parsed program, the front end and IR generation alone, a full
+//! native compile including linking, and running the resulting executable.
+//! The `names` group compares interned names with the `String`s that tokens
+//! and the AST held before. Run with `cargo bench`.
+
+use std::collections::HashMap;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use tempfile::TempDir;
+use virtuc::codegen::generate_ir;
+use virtuc::interner::Symbol;
+use virtuc::lexer::{Token, lex};
+use virtuc::parser::parse;
+use virtuc::resolve::resolve;
+use virtuc::run::{RunConfig, run_program};
+use virtuc::semantic::analyze;
+use virtuc::{CompileOptions, compile_with_options};
+
+const PROGRAMS: [(&str, &str); 3] = [
+    (
+        "fib",
+        r#"
+        int fib(int n) {
+            if (n < 2) {
+                return n;
+            }
+            return fib(n - 1) + fib(n - 2);
+        }
+
+        int main() {
+            return fib(27) - 196418;
+        }
+        "#,
+    ),
+    (
+        "nested_loops",
+        r#"
+        int main() {
+            int total = 0;
+            for (int i = 0; i < 1000; i = i + 1) {
+                for (int j = 0; j < 1000; j = j + 1) {
+                    total = total + i * j / (j + 1);
+                }
+            }
+            return total - total;
+        }
+        "#,
+    ),
+    (
+        "float_math",
+        r#"
+        float step(float x) {
+            return x * 0.5 + 1.0 / (x + 1.0);
+        }
+
+        int main() {
+            float x = 1.0;
+            for (int i = 0; i < 1000000; i = i + 1) {
+                x = step(x);
+            }
+            return 0;
+        }
+        "#,
+    ),
+];
+
+fn bench_programs(c: &mut Criterion) {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    for (name, source) in PROGRAMS {
+        let mut group = c.benchmark_group(name);
+
+        group.bench_function("parse", |b| {
+            b.iter(|| parse(&lex(source).unwrap()).unwrap())
+        });
+
+        let program = parse(&lex(source).unwrap()).unwrap();
+        group.bench_function("resolve", |b| b.iter(|| resolve(&program)));
+
+        group.bench_function("generate_ir", |b| {
+            b.iter(|| {
+                let program = parse(&lex(source).unwrap()).unwrap();
+                assert!(analyze(&program).is_empty());
+                generate_ir(&program).unwrap()
+            })
+        });
+
+        let output = temp_dir.path().join(name);
+        group.bench_function("compile_native", |b| {
+            b.iter(|| compile_with_options(source, &output, &CompileOptions::default()).unwrap())
+        });
+
+        group.bench_function("run_native", |b| {
+            b.iter(|| {
+                let result = run_program(&output, &RunConfig::default()).unwrap();
+                assert_eq!(result.exit_code, Some(0));
+            })
+        });
+
+        group.finish();
+    }
+}
+
+/// Reads each identifier of the programs into a name, then looks every name
+/// up in a scope declaring them, as the lexer and name resolution do, with
+/// names interned and with names as `String`s.
+fn bench_names(c: &mut Criterion) {
+    let source: String = PROGRAMS.iter().map(|(_, source)| *source).collect();
+    let identifiers: Vec<&str> = lex(&source)
+        .unwrap()
+        .into_iter()
+        .filter_map(|token| match token {
+            Token::Identifier(name) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    let mut group = c.benchmark_group("names");
+
+    group.bench_function("symbol", |b| {
+        b.iter(|| {
+            let names: Vec<Symbol> = identifiers
+                .iter()
+                .map(|name| Symbol::intern(name))
+                .collect();
+            let scope: HashMap<Symbol, usize> = names.iter().copied().zip(0..).collect();
+            names
+                .iter()
+                .filter_map(|name| scope.get(name))
+                .sum::<usize>()
+        })
+    });
+
+    group.bench_function("string", |b| {
+        b.iter(|| {
+            let names: Vec<String> = identifiers.iter().map(|name| name.to_string()).collect();
+            let scope: HashMap<String, usize> = names.iter().cloned().zip(0..).collect();
+            names
+                .iter()
+                .filter_map(|name| scope.get(name))
+                .sum::<usize>()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_programs, bench_names
+}
+criterion_main!(benches);
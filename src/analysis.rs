@@ -0,0 +1,636 @@
+//! # Program Analysis
+//!
+//! This module provides whole-program analyses over a validated AST, for
+//! tools and for warnings about likely mistakes.
+//!
+//! ## Call Graph
+//!
+//! [`call_graph`] records which functions each function calls directly.
+//! Cycles in the graph are recursion: a function calling itself, or a group
+//! of functions calling each other. The graph can be printed in Graphviz
+//! `dot` format.
+//!
+//! ## Warnings
+//!
+//! [`lint`] reports programs that compile but are likely wrong, such as a
+//! function that calls itself before it can return, which recurses until
+//! the stack overflows, or functions that are never used. Within each
+//! function, the [`dataflow`](crate::dataflow) analyses of its
+//! [MIR](crate::mir) find variables that may be read before they are
+//! assigned, and values that are assigned but never read.
+//!
+//! ## Unused Functions
+//!
+//! A function is used if `main` calls it, directly or indirectly. Functions
+//! only called by unused ones are unused too. Extern functions declared by
+//! included headers are not reported, as the program did not declare them
+//! itself. [`strip_unused`] removes unused functions and extern declarations
+//! before code generation, to shrink the emitted module.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fmt::Write;
+
+use crate::ast::{Arena, Expr, ExprId, Program, Stmt, StmtId};
+use crate::dataflow::{DefinitionSite, Liveness, ReachingDefinitions, solve};
+use crate::header_registry;
+use crate::mir::{self, Body, Statement};
+
+/// The function programs start at.
+pub const ENTRY: &str = "main";
+
+/// The direct calls between the functions of a program.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CallGraph {
+    /// Defined functions followed by extern functions, in program order
+    functions: Vec<String>,
+    /// Functions called by each defined function, in order of first call
+    calls: HashMap<String, Vec<String>>,
+}
+
+/// Builds the call graph of a program.
+pub fn call_graph(program: &Program) -> CallGraph {
+    let mut functions: Vec<String> = program.functions.iter().map(|f| f.name.clone()).collect();
+    for extern_func in &program.extern_functions {
+        if !functions.contains(&extern_func.name) {
+            functions.push(extern_func.name.clone());
+        }
+    }
+    let calls = program
+        .functions
+        .iter()
+        .map(|function| {
+            let mut callees = Vec::new();
+            calls_in_stmt(&function.arena, function.body, &mut |name| {
+                if !callees.iter().any(|callee| callee == name) {
+                    callees.push(name.to_string());
+                }
+            });
+            (function.name.clone(), callees)
+        })
+        .collect();
+    CallGraph { functions, calls }
+}
+
+impl CallGraph {
+    /// Returns every function of the program, defined ones first.
+    pub fn functions(&self) -> &[String] {
+        &self.functions
+    }
+
+    /// Returns the functions that `name` calls directly.
+    pub fn callees(&self, name: &str) -> &[String] {
+        self.calls.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the functions reachable from `name` through calls, including
+    /// `name` itself, in program order.
+    pub fn reachable_from(&self, name: &str) -> Vec<&str> {
+        let mut reached = HashSet::new();
+        let mut pending = vec![name];
+        while let Some(name) = pending.pop() {
+            if self.functions.iter().any(|f| f == name) && reached.insert(name) {
+                pending.extend(self.callees(name).iter().map(String::as_str));
+            }
+        }
+        self.functions
+            .iter()
+            .map(String::as_str)
+            .filter(|f| reached.contains(f))
+            .collect()
+    }
+
+    /// Returns the recursive groups of functions: each function that calls
+    /// itself, and each set of functions that call each other in a cycle.
+    ///
+    /// Groups are listed, and their functions ordered, as in the program.
+    pub fn cycles(&self) -> Vec<Vec<&str>> {
+        let mut cycles: Vec<Vec<&str>> = Vec::new();
+        for name in &self.functions {
+            if cycles.iter().flatten().any(|f| f == name) {
+                continue;
+            }
+            // The functions that both reach and are reached from `name`
+            let group: Vec<&str> = self
+                .reachable_from(name)
+                .into_iter()
+                .filter(|&f| self.reachable_from(f).contains(&name.as_str()))
+                .collect();
+            if group.len() > 1 || self.callees(name).contains(name) {
+                cycles.push(group);
+            }
+        }
+        cycles
+    }
+
+    /// Renders the graph in Graphviz `dot` format. Extern functions are
+    /// drawn as boxes.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph calls {\n");
+        for name in &self.functions {
+            let shape = if self.calls.contains_key(name) {
+                "ellipse"
+            } else {
+                "box"
+            };
+            writeln!(out, "    \"{}\" [shape={}];", name, shape).unwrap();
+        }
+        for name in &self.functions {
+            for callee in self.callees(name) {
+                writeln!(out, "    \"{}\" -> \"{}\";", name, callee).unwrap();
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+impl fmt::Display for CallGraph {
+    /// Lists each defined function with the functions it calls.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for name in self
+            .functions
+            .iter()
+            .filter(|f| self.calls.contains_key(*f))
+        {
+            writeln!(f, "{} -> {}", name, self.callees(name).join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A likely mistake in a program that compiles.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Warning {
+    /// The function calls itself on every path before it can return
+    UnboundedRecursion(String),
+    /// The function is not reachable from `main`
+    UnusedFunction(String),
+    /// The extern function is not reachable from `main`
+    UnusedExtern(String),
+    /// A branch of an `if` in the function is the null statement, usually
+    /// because of a stray `;` after the condition or `else`
+    EmptyBody(String),
+    /// The variable (second) in the function (first) may be read before it
+    /// is assigned
+    UninitializedVariable(String, String),
+    /// A value assigned to the variable (second) in the function (first) is
+    /// never read
+    DeadStore(String, String),
+}
+
+impl Warning {
+    /// Returns the stable code of the warning.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Warning::UnboundedRecursion(_) => "W0001",
+            Warning::UnusedFunction(_) => "W0002",
+            Warning::UnusedExtern(_) => "W0003",
+            Warning::EmptyBody(_) => "W0004",
+            Warning::UninitializedVariable(..) => "W0005",
+            Warning::DeadStore(..) => "W0006",
+        }
+    }
+
+    /// Returns the name of the function the warning is about.
+    pub fn function(&self) -> &str {
+        match self {
+            Warning::UnboundedRecursion(name)
+            | Warning::UnusedFunction(name)
+            | Warning::UnusedExtern(name)
+            | Warning::EmptyBody(name)
+            | Warning::UninitializedVariable(name, _)
+            | Warning::DeadStore(name, _) => name,
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::UnboundedRecursion(name) => write!(
+                f,
+                "function '{}' calls itself before it can return, so it never terminates",
+                name
+            ),
+            Warning::UnusedFunction(name) => write!(f, "function '{}' is never used", name),
+            Warning::UnusedExtern(name) => {
+                write!(f, "extern function '{}' is never used", name)
+            }
+            Warning::EmptyBody(name) => write!(
+                f,
+                "an 'if' in function '{}' has an empty body; is there a stray ';'?",
+                name
+            ),
+            Warning::UninitializedVariable(function, variable) => write!(
+                f,
+                "variable '{}' in function '{}' may be used before it is initialized",
+                variable, function
+            ),
+            Warning::DeadStore(function, variable) => write!(
+                f,
+                "value assigned to '{}' in function '{}' is never read",
+                variable, function
+            ),
+        }
+    }
+}
+
+/// Checks a validated program for likely mistakes.
+pub fn lint(program: &Program) -> Vec<Warning> {
+    let mut warnings: Vec<Warning> = program
+        .functions
+        .iter()
+        .filter(|function| recurses_unconditionally(&function.arena, &function.name, function.body))
+        .map(|function| Warning::UnboundedRecursion(function.name.clone()))
+        .collect();
+    warnings.extend(
+        program
+            .functions
+            .iter()
+            .filter(|function| has_empty_if_body(&function.arena, function.body))
+            .map(|function| Warning::EmptyBody(function.name.clone())),
+    );
+    for body in &mir::lower(program).bodies {
+        warnings.extend(
+            uninitialized_variables(body)
+                .into_iter()
+                .map(|name| Warning::UninitializedVariable(body.name.clone(), name)),
+        );
+        warnings.extend(
+            dead_stores(body)
+                .into_iter()
+                .map(|name| Warning::DeadStore(body.name.clone(), name)),
+        );
+    }
+    if let Some(used) = used_functions(program, ENTRY) {
+        let from_headers: Vec<_> = program
+            .includes
+            .iter()
+            .flat_map(|header| header_registry::externs_for_header(header))
+            .map(|e| e.name)
+            .collect();
+        warnings.extend(
+            program
+                .functions
+                .iter()
+                .filter(|f| !used.contains(&f.name))
+                .map(|f| Warning::UnusedFunction(f.name.clone())),
+        );
+        warnings.extend(
+            program
+                .extern_functions
+                .iter()
+                .filter(|e| !used.contains(&e.name) && !from_headers.contains(&e.name))
+                .map(|e| Warning::UnusedExtern(e.name.clone())),
+        );
+    }
+    warnings
+}
+
+/// Returns the variables of a body that some path reads before assigning,
+/// each once, in the order of the first such read.
+fn uninitialized_variables(body: &Body) -> Vec<String> {
+    let analysis = ReachingDefinitions::new(body);
+    let results = solve(&analysis, body);
+    let mut names = Vec::new();
+    for id in body.block_ids() {
+        let block = body.block(id);
+        results.visit(&analysis, body, id, |location, state| {
+            let operands = match block.statements.get(location.statement) {
+                Some(statement) => statement.operands(),
+                None => block.terminator.operands(),
+            };
+            for local in operands.iter().filter_map(|op| op.local()) {
+                let uninitialized = analysis
+                    .reaching(state, local)
+                    .any(|d| d.site == DefinitionSite::Uninitialized);
+                if let Some(name) = &body.locals[local.0].name
+                    && uninitialized
+                    && !names.contains(name)
+                {
+                    names.push(*name);
+                }
+            }
+        });
+    }
+    names.into_iter().map(String::from).collect()
+}
+
+/// Returns the variables of a body assigned a value that is never read,
+/// each once, in the order of the first such assignment.
+fn dead_stores(body: &Body) -> Vec<String> {
+    let results = solve(&Liveness, body);
+    let mut names = Vec::new();
+    for id in body.block_ids() {
+        let block = body.block(id);
+        let mut dead = Vec::new();
+        results.visit(&Liveness, body, id, |location, live| {
+            if let Some(Statement::Assign(local, _)) = block.statements.get(location.statement)
+                && !live.contains(local.0)
+                && let Some(name) = &body.locals[local.0].name
+            {
+                dead.push(*name);
+            }
+        });
+        // Liveness visits the block backwards
+        for name in dead.into_iter().rev() {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names.into_iter().map(String::from).collect()
+}
+
+/// Removes the functions and extern declarations that are not reachable
+/// from `main`. Programs without `main` are returned unchanged.
+pub fn strip_unused(program: &Program) -> Program {
+    strip_unused_from(program, ENTRY)
+}
+
+/// Removes the functions and extern declarations that are not reachable
+/// from the function `entry`, as [`strip_unused`] does from `main`.
+pub fn strip_unused_from(program: &Program, entry: &str) -> Program {
+    let Some(used) = used_functions(program, entry) else {
+        return program.clone();
+    };
+    Program {
+        includes: program.includes.clone(),
+        imports: program.imports.clone(),
+        extern_functions: program
+            .extern_functions
+            .iter()
+            .filter(|e| used.contains(&e.name))
+            .cloned()
+            .collect(),
+        functions: program
+            .functions
+            .iter()
+            .filter(|f| used.contains(&f.name))
+            .cloned()
+            .collect(),
+    }
+}
+
+/// Returns the names of the functions reachable from `entry`, or `None` if
+/// the program does not define it.
+fn used_functions(program: &Program, entry: &str) -> Option<Vec<String>> {
+    program.functions.iter().find(|f| f.name == entry)?;
+    let graph = call_graph(program);
+    Some(
+        graph
+            .reachable_from(entry)
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+    )
+}
+
+/// Returns true if the statement `stmt` of an arena calls `name` before any
+/// statement that may leave the function, i.e. without a base case guarding
+/// the call.
+fn recurses_unconditionally(arena: &Arena, name: &str, stmt: StmtId) -> bool {
+    let calls = |expr: &ExprId| {
+        let mut found = false;
+        calls_in_expr(arena, *expr, &mut |callee| found |= callee == name);
+        found
+    };
+    match &arena[stmt] {
+        Stmt::Declaration { init, .. } => init.as_ref().is_some_and(calls),
+        Stmt::Return(value) => value.as_ref().is_some_and(calls),
+        Stmt::Expr(expr) => calls(expr),
+        Stmt::Empty => false,
+        Stmt::Block(stmts) => {
+            for stmt in stmts {
+                if recurses_unconditionally(arena, name, *stmt) {
+                    return true;
+                }
+                if may_return(arena, *stmt) {
+                    return false;
+                }
+            }
+            false
+        }
+        // Only the parts that always run are considered
+        Stmt::If { cond, .. } => calls(cond),
+        Stmt::For { init, cond, .. } => {
+            init.is_some_and(|init| recurses_unconditionally(arena, name, init))
+                || cond.as_ref().is_some_and(calls)
+        }
+    }
+}
+
+/// Returns true if the statement `stmt` of an arena contains an `if` with a
+/// null statement as a branch. A `for` loop with an empty body is a common
+/// idiom, so it is not reported.
+fn has_empty_if_body(arena: &Arena, stmt: StmtId) -> bool {
+    let empty = |stmt: StmtId| arena[stmt] == Stmt::Empty;
+    match &arena[stmt] {
+        Stmt::If { then, else_, .. } => {
+            empty(*then)
+                || else_.is_some_and(empty)
+                || has_empty_if_body(arena, *then)
+                || else_.is_some_and(|else_| has_empty_if_body(arena, else_))
+        }
+        Stmt::Block(stmts) => stmts.iter().any(|stmt| has_empty_if_body(arena, *stmt)),
+        Stmt::For { body, .. } => has_empty_if_body(arena, *body),
+        Stmt::Declaration { .. } | Stmt::Return(_) | Stmt::Expr(_) | Stmt::Empty => false,
+    }
+}
+
+/// Returns true if the statement `stmt` of an arena contains a `return`.
+fn may_return(arena: &Arena, stmt: StmtId) -> bool {
+    match &arena[stmt] {
+        Stmt::Return(_) => true,
+        Stmt::Block(stmts) => stmts.iter().any(|stmt| may_return(arena, *stmt)),
+        Stmt::If { then, else_, .. } => {
+            may_return(arena, *then) || else_.is_some_and(|else_| may_return(arena, else_))
+        }
+        Stmt::For { body, .. } => may_return(arena, *body),
+        Stmt::Declaration { .. } | Stmt::Expr(_) | Stmt::Empty => false,
+    }
+}
+
+/// Calls `f` with the name of every function called in the statement `stmt`
+/// of an arena.
+fn calls_in_stmt(arena: &Arena, stmt: StmtId, f: &mut impl FnMut(&str)) {
+    match &arena[stmt] {
+        Stmt::Declaration { init, .. } => {
+            if let Some(init) = init {
+                calls_in_expr(arena, *init, f);
+            }
+        }
+        Stmt::Return(value) => {
+            if let Some(value) = value {
+                calls_in_expr(arena, *value, f);
+            }
+        }
+        Stmt::Block(stmts) => {
+            for stmt in stmts {
+                calls_in_stmt(arena, *stmt, f);
+            }
+        }
+        Stmt::If { cond, then, else_ } => {
+            calls_in_expr(arena, *cond, f);
+            calls_in_stmt(arena, *then, f);
+            if let Some(else_) = else_ {
+                calls_in_stmt(arena, *else_, f);
+            }
+        }
+        Stmt::For {
+            init,
+            cond,
+            update,
+            body,
+        } => {
+            if let Some(init) = init {
+                calls_in_stmt(arena, *init, f);
+            }
+            for expr in cond.iter().chain(update) {
+                calls_in_expr(arena, *expr, f);
+            }
+            calls_in_stmt(arena, *body, f);
+        }
+        Stmt::Expr(expr) => calls_in_expr(arena, *expr, f),
+        Stmt::Empty => {}
+    }
+}
+
+/// Calls `f` with the name of every function called in the expression
+/// `expr` of an arena.
+fn calls_in_expr(arena: &Arena, expr: ExprId, f: &mut impl FnMut(&str)) {
+    match &arena[expr] {
+        Expr::Literal(_) | Expr::Identifier(_) => {}
+        Expr::Binary { left, right, .. } => {
+            calls_in_expr(arena, *left, f);
+            calls_in_expr(arena, *right, f);
+        }
+        Expr::Call { name, args } => {
+            f(name.as_str());
+            for arg in args {
+                calls_in_expr(arena, *arg, f);
+            }
+        }
+        Expr::Assignment { target, value } => {
+            calls_in_expr(arena, *target, f);
+            calls_in_expr(arena, *value, f);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::parse;
+
+    fn program(source: &str) -> Program {
+        parse(&lex(source).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_call_graph() {
+        let graph = call_graph(&program(
+            "extern int abs(int);
+             int even(int n) { if (n == 0) { return 1; } return odd(n - 1); }
+             int odd(int n) { if (n == 0) { return 0; } return even(n - 1); }
+             int fact(int n) { if (n < 2) { return 1; } return n * fact(n - 1); }
+             int main() { return even(abs(4)) + fact(3) + fact(2); }",
+        ));
+        assert_eq!(graph.callees("main"), ["even", "abs", "fact"]);
+        assert_eq!(graph.callees("abs"), [] as [String; 0]);
+        assert_eq!(graph.reachable_from("even"), ["even", "odd"]);
+        assert_eq!(graph.cycles(), [vec!["even", "odd"], vec!["fact"]]);
+        assert_eq!(
+            graph.to_string(),
+            "even -> odd\nodd -> even\nfact -> fact\nmain -> even, abs, fact\n"
+        );
+        let dot = graph.to_dot();
+        assert!(dot.contains("\"abs\" [shape=box];"));
+        assert!(dot.contains("\"main\" -> \"abs\";"));
+    }
+
+    #[test]
+    fn test_lint_unbounded_recursion() {
+        let warnings = lint(&program(
+            "int forever(int n) { int m = n + 1; return forever(m); }
+             int guarded(int n) { if (n == 0) { return 0; } return guarded(n - 1); }
+             int looped(int n) { for (int i = looped(n); i < n; i = i + 1) { } return 0; }
+             int main() { return guarded(3) + forever(1) + looped(2); }",
+        ));
+        assert_eq!(
+            warnings,
+            [
+                Warning::UnboundedRecursion("forever".to_string()),
+                Warning::UnboundedRecursion("looped".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lint_unused_functions() {
+        let source = "#include <stdio.h>
+             extern int abs(int);
+             extern int labs(int);
+             int helper(int n) { return abs(n); }
+             int dead() { return helper(1) + labs(2); }
+             int main() { return helper(0); }";
+        assert_eq!(
+            lint(&program(source)),
+            [
+                Warning::UnusedFunction("dead".to_string()),
+                Warning::UnusedExtern("labs".to_string())
+            ]
+        );
+
+        let stripped = strip_unused(&program(source));
+        let names: Vec<_> = stripped.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["helper", "main"]);
+        let externs: Vec<_> = stripped
+            .extern_functions
+            .iter()
+            .map(|e| e.name.as_str())
+            .collect();
+        assert_eq!(externs, ["abs"]);
+
+        // Without an entry point, nothing is known to be unused
+        assert_eq!(lint(&program("int f() { return 1; }")), []);
+    }
+
+    #[test]
+    fn test_lint_empty_body() {
+        let warnings = lint(&program(
+            "int f(int x) { if (x > 0); return x; }
+             int g(int x) { if (x > 0) { return 1; } else; ; return 0; }
+             int h(int x) { for (int i = 0; i < x; i = i + 1); return 0; }
+             int main() { return f(1) + g(2) + h(3); };",
+        ));
+        assert_eq!(
+            warnings,
+            [
+                Warning::EmptyBody("f".to_string()),
+                Warning::EmptyBody("g".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lint_dataflow() {
+        let warnings = lint(&program(
+            "int f(int c) { int x; if (c) { x = 1; } return x; }
+             int g(int c) { int x; if (c) { x = 1; } else { x = 2; } return x; }
+             int h(int n) { int total = 0; total = n; int unused = n * 2; return total; }
+             int main() { return f(1) + g(0) + h(2); }",
+        ));
+        assert_eq!(
+            warnings,
+            [
+                Warning::UninitializedVariable("f".to_string(), "x".to_string()),
+                Warning::DeadStore("h".to_string(), "total".to_string()),
+                Warning::DeadStore("h".to_string(), "unused".to_string()),
+            ]
+        );
+        assert_eq!(warnings[2].code(), "W0006");
+    }
+}
@@ -0,0 +1,33 @@
+//! # Compile Artifacts
+//!
+//! This module describes what a successful compilation produced. The compile
+//! APIs return a [`CompileArtifacts`], so that build tools (and
+//! `virtuc build`) can report the files written and how they were built
+//! without repeating any of the work.
+
+use std::path::{Path, PathBuf};
+
+use crate::analysis::Warning;
+use crate::timings::Timings;
+
+/// The files a compilation wrote, and how they were built.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CompileArtifacts {
+    /// Files written, the executable or library first
+    pub outputs: Vec<PathBuf>,
+    /// Target triple the code was generated for
+    pub target: String,
+    /// Optimization level (0 to 3)
+    pub opt_level: u8,
+    /// Time spent in each phase
+    pub timings: Timings,
+    /// Likely mistakes found by [`analysis::lint`](crate::analysis::lint)
+    pub warnings: Vec<Warning>,
+}
+
+impl CompileArtifacts {
+    /// Returns the main file written: the executable or library.
+    pub fn output(&self) -> Option<&Path> {
+        self.outputs.first().map(PathBuf::as_path)
+    }
+}
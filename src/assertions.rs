@@ -0,0 +1,166 @@
+//! # Assertions
+//!
+//! Including `<assert.h>` makes the builtin `assert(cond)` available. In
+//! checked builds, a failing assertion aborts the program with its location
+//! and condition; with optimizations or `NDEBUG`, assertions are compiled
+//! out and their condition is not evaluated.
+//!
+//! ## Locations
+//!
+//! The AST does not record where a statement came from, so the front end
+//! passes the failure message of each assertion statement to code
+//! generation as a second, string argument: [`locate`] rewrites
+//! `assert(x > 0)` on line 3 of `hello.c` into
+//!
+//! ```text
+//! assert(x > 0, "hello.c:3: Assertion `x > 0' failed.")
+//! ```
+//!
+//! Assertions that were not located, such as those of programs built with
+//! [`builder`](crate::builder), report their condition only.
+
+use crate::ast::{Arena, Expr, ExprId, Literal, Program, Stmt, StmtId};
+use crate::printer::print_expr;
+
+/// The header that declares `assert`.
+pub const HEADER: &str = "assert.h";
+
+/// The name of the builtin.
+pub const NAME: &str = "assert";
+
+/// Returns true if the program includes [`HEADER`], making `assert` a
+/// builtin rather than an ordinary function.
+pub fn enabled(program: &Program) -> bool {
+    program.includes.iter().any(|header| header == HEADER)
+}
+
+/// Returns the condition of an assertion statement of an arena, and its
+/// failure message if [`locate`] recorded one.
+pub fn assertion(arena: &Arena, stmt: StmtId) -> Option<(ExprId, Option<&str>)> {
+    let Stmt::Expr(call) = &arena[stmt] else {
+        return None;
+    };
+    match &arena[*call] {
+        Expr::Call { name, args } if name == NAME => match args.as_slice() {
+            [cond] => Some((*cond, None)),
+            [cond, message] => match &arena[*message] {
+                Expr::Literal(Literal::String(message)) => Some((*cond, Some(message))),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the message printed when the condition `cond` of an arena fails,
+/// given the location of the assertion as `file:line` or `line N`.
+pub fn message(arena: &Arena, cond: ExprId, location: Option<&str>) -> String {
+    let failed = format!("Assertion `{}' failed.", print_expr(arena, cond));
+    match location {
+        Some(location) => format!("{}: {}", location, failed),
+        None => failed,
+    }
+}
+
+/// Records the failure message of every assertion statement in a program.
+///
+/// `starts` holds the byte offset in `source` of each statement, in the
+/// order of [`coverage::statements`](crate::coverage::statements).
+pub fn locate(mut program: Program, source: &str, starts: &[usize], file: Option<&str>) -> Program {
+    if !enabled(&program) {
+        return program;
+    }
+    let mut starts = starts.iter();
+    for function in &mut program.functions {
+        let mut stmts = Vec::new();
+        if let Stmt::Block(body) = function.body() {
+            for stmt in body {
+                visit(&function.arena, *stmt, &mut stmts);
+            }
+        }
+        let arena = &mut function.arena;
+        for stmt in stmts {
+            let Some(&start) = starts.next() else {
+                break;
+            };
+            let Some((cond, None)) = assertion(arena, stmt) else {
+                continue;
+            };
+            let line = source[..start].matches('\n').count() + 1;
+            let location = match file {
+                Some(file) => format!("{}:{}", file, line),
+                None => format!("line {}", line),
+            };
+            let message = message(arena, cond, Some(&location));
+            let message = arena.alloc_expr(Expr::Literal(Literal::String(message)));
+            if let Stmt::Expr(call) = arena[stmt]
+                && let Expr::Call { args, .. } = &mut arena[call]
+            {
+                args.push(message);
+            }
+        }
+    }
+    program
+}
+
+/// Appends a statement to `stmts` after its nested statements, in the order
+/// of [`coverage::statements`](crate::coverage::statements).
+fn visit(arena: &Arena, stmt: StmtId, stmts: &mut Vec<StmtId>) {
+    match &arena[stmt] {
+        Stmt::Block(body) => {
+            for stmt in body {
+                visit(arena, *stmt, stmts);
+            }
+        }
+        Stmt::If { then, else_, .. } => {
+            visit(arena, *then, stmts);
+            if let Some(else_) = else_ {
+                visit(arena, *else_, stmts);
+            }
+        }
+        Stmt::For { body, .. } => visit(arena, *body, stmts),
+        Stmt::Declaration { .. } | Stmt::Return(_) | Stmt::Expr(_) | Stmt::Empty => {}
+    }
+    stmts.push(stmt);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex_with_spans;
+    use crate::options::CompileOptions;
+    use crate::parser::parse_with_statement_positions;
+
+    #[test]
+    fn test_locate() {
+        let source = "#include <assert.h>
+int main() {
+    int x = 1;
+    for (int i = 0; i < 3; i = i + 1) {
+        assert(i < 3);
+    }
+    assert(x * 2 == 2);
+    return 0;
+}
+";
+        let (tokens, spans): (Vec<_>, Vec<_>) = lex_with_spans(source).unwrap().into_iter().unzip();
+        let (program, positions) =
+            parse_with_statement_positions(&tokens, &CompileOptions::default()).unwrap();
+        let starts: Vec<usize> = positions.iter().map(|&i| spans[i].start).collect();
+        let program = locate(program, source, &starts, Some("t.c"));
+
+        let messages: Vec<_> = crate::coverage::statements(&program)
+            .into_iter()
+            .filter_map(|(function, stmt)| assertion(&program.functions[function].arena, stmt))
+            .map(|(_, message)| message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                Some("t.c:5: Assertion `i < 3' failed."),
+                Some("t.c:7: Assertion `x * 2 == 2' failed.")
+            ]
+        );
+    }
+}
@@ -14,14 +14,27 @@
 //!
 //! ## Design
 //!
-//! AST nodes are defined as enums and structs with owned data to simplify
-//! lifetime management. Each node includes source location information for
-//! error reporting and debugging.
+//! The expressions and statements of a function live in its [`Arena`] and
+//! refer to each other by [`ExprId`] and [`StmtId`] handles rather than
+//! through nested boxes. Cloning a function copies two flat vectors, and
+//! passes may keep handles to nodes, or rewrite a node in place, without
+//! borrowing the tree. Expressions and statements outside of a function,
+//! such as parsed fragments, come as a [`Tree`] with an arena of their own.
+//!
+//! A [`Node`] views a handle as the tree rooted at it: nodes compare and
+//! print as trees, so a [`Function`] equals another with the same code
+//! whatever the layout of their arenas.
+
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+use crate::interner::Symbol;
 
 /// Represents the primitive types in the C subset.
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Type {
-    /// 64-bit integer type
+    /// Integer type, 64-bit unless chosen otherwise with
+    /// [`CompileOptions::int_width`](crate::options::CompileOptions::int_width)
     Int,
     /// 64-bit floating-point type
     Float,
@@ -66,56 +79,520 @@ pub enum Literal {
 }
 
 /// Represents expressions in the AST.
+///
+/// Operands are [`ExprId`]s into the [`Arena`] of the enclosing function, so
+/// comparing two expressions compares the handles of their operands; compare
+/// [`Node`]s to compare the trees they stand for.
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expr {
     /// Literal value
     Literal(Literal),
     /// Variable identifier
-    Identifier(String),
+    Identifier(Symbol),
     /// Binary operation
     Binary {
-        left: Box<Expr>,
+        left: ExprId,
         op: BinOp,
-        right: Box<Expr>,
+        right: ExprId,
     },
     /// Function call
-    Call { name: String, args: Vec<Expr> },
-    /// Assignment expression
-    Assignment { name: String, value: Box<Expr> },
+    Call { name: Symbol, args: Vec<ExprId> },
+    /// Assignment expression; the target must be an lvalue
+    Assignment { target: ExprId, value: ExprId },
 }
 
 /// Represents statements in the AST.
+///
+/// Like [`Expr`], statements refer to their parts by handle.
 #[derive(Debug, PartialEq, Clone)]
 pub enum Stmt {
     /// Variable declaration
     Declaration {
         ty: Type,
-        name: String,
-        init: Option<Expr>,
+        name: Symbol,
+        init: Option<ExprId>,
     },
     /// Return statement
-    Return(Option<Expr>),
+    Return(Option<ExprId>),
     /// Block of statements
-    Block(Vec<Stmt>),
+    Block(Vec<StmtId>),
     /// If-else statement
     If {
-        cond: Expr,
-        then: Box<Stmt>,
-        else_: Option<Box<Stmt>>,
+        cond: ExprId,
+        then: StmtId,
+        else_: Option<StmtId>,
     },
     /// For loop
     For {
-        init: Option<Box<Stmt>>,
-        cond: Option<Expr>,
-        update: Option<Expr>,
-        body: Box<Stmt>,
+        init: Option<StmtId>,
+        cond: Option<ExprId>,
+        update: Option<ExprId>,
+        body: StmtId,
     },
     /// Expression statement (for function calls, etc.)
-    Expr(Expr),
+    Expr(ExprId),
+    /// The null statement `;`, which does nothing
+    Empty,
+}
+
+/// Handle of an [`Expr`] in an [`Arena`].
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
+pub struct ExprId(u32);
+
+/// Handle of a [`Stmt`] in an [`Arena`].
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
+pub struct StmtId(u32);
+
+/// The expressions and statements of a function.
+///
+/// Nodes are never freed, so a handle stays valid for as long as the arena
+/// lives, however the arena is moved, cloned, or grown. Rewrites allocate
+/// the nodes they create and leave the nodes they replace unreachable.
+#[derive(Default, Clone)]
+pub struct Arena {
+    exprs: Vec<Expr>,
+    stmts: Vec<Stmt>,
+}
+
+impl Arena {
+    /// Creates an empty arena.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allocates an expression, returning its handle.
+    pub fn alloc_expr(&mut self, expr: Expr) -> ExprId {
+        self.exprs.push(expr);
+        ExprId(u32::try_from(self.exprs.len() - 1).expect("too many expressions"))
+    }
+
+    /// Allocates a statement, returning its handle.
+    pub fn alloc_stmt(&mut self, stmt: Stmt) -> StmtId {
+        self.stmts.push(stmt);
+        StmtId(u32::try_from(self.stmts.len() - 1).expect("too many statements"))
+    }
+
+    /// Returns the number of nodes allocated, reachable or not.
+    pub fn len(&self) -> usize {
+        self.exprs.len() + self.stmts.len()
+    }
+
+    /// Returns true if no node was allocated.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the numbers of expressions and statements allocated so far,
+    /// to later [`truncate`](Self::truncate) the arena to.
+    pub(crate) fn mark(&self) -> (usize, usize) {
+        (self.exprs.len(), self.stmts.len())
+    }
+
+    /// Drops the nodes allocated since `mark` was taken. Their handles must
+    /// not be used again.
+    pub(crate) fn truncate(&mut self, (exprs, stmts): (usize, usize)) {
+        self.exprs.truncate(exprs);
+        self.stmts.truncate(stmts);
+    }
+
+    /// Views a node as the tree rooted at it, for comparison and debugging.
+    pub fn node<Id>(&self, id: Id) -> Node<'_, Id> {
+        Node { arena: self, id }
+    }
+
+    /// Copies the expression `id`, with its operands, returning the handle
+    /// of the copy.
+    pub fn clone_expr(&mut self, id: ExprId) -> ExprId {
+        let expr = match self[id].clone() {
+            Expr::Binary { left, op, right } => Expr::Binary {
+                left: self.clone_expr(left),
+                op,
+                right: self.clone_expr(right),
+            },
+            Expr::Call { name, args } => Expr::Call {
+                name,
+                args: args.into_iter().map(|arg| self.clone_expr(arg)).collect(),
+            },
+            Expr::Assignment { target, value } => Expr::Assignment {
+                target: self.clone_expr(target),
+                value: self.clone_expr(value),
+            },
+            expr @ (Expr::Literal(_) | Expr::Identifier(_)) => expr,
+        };
+        self.alloc_expr(expr)
+    }
+
+    /// Copies the statement `id`, with its parts, returning the handle of
+    /// the copy.
+    pub fn clone_stmt(&mut self, id: StmtId) -> StmtId {
+        let stmt = match self[id].clone() {
+            Stmt::Declaration { ty, name, init } => Stmt::Declaration {
+                ty,
+                name,
+                init: init.map(|e| self.clone_expr(e)),
+            },
+            Stmt::Return(value) => Stmt::Return(value.map(|e| self.clone_expr(e))),
+            Stmt::Block(stmts) => {
+                Stmt::Block(stmts.into_iter().map(|s| self.clone_stmt(s)).collect())
+            }
+            Stmt::If { cond, then, else_ } => Stmt::If {
+                cond: self.clone_expr(cond),
+                then: self.clone_stmt(then),
+                else_: else_.map(|s| self.clone_stmt(s)),
+            },
+            Stmt::For {
+                init,
+                cond,
+                update,
+                body,
+            } => Stmt::For {
+                init: init.map(|s| self.clone_stmt(s)),
+                cond: cond.map(|e| self.clone_expr(e)),
+                update: update.map(|e| self.clone_expr(e)),
+                body: self.clone_stmt(body),
+            },
+            Stmt::Expr(expr) => Stmt::Expr(self.clone_expr(expr)),
+            Stmt::Empty => Stmt::Empty,
+        };
+        self.alloc_stmt(stmt)
+    }
+
+    /// Copies the expression `id` of another arena into this one, with its
+    /// operands, returning the handle of the copy.
+    pub fn copy_expr(&mut self, from: &Arena, id: ExprId) -> ExprId {
+        let expr = match &from[id] {
+            Expr::Binary { left, op, right } => Expr::Binary {
+                left: self.copy_expr(from, *left),
+                op: *op,
+                right: self.copy_expr(from, *right),
+            },
+            Expr::Call { name, args } => Expr::Call {
+                name: *name,
+                args: args.iter().map(|arg| self.copy_expr(from, *arg)).collect(),
+            },
+            Expr::Assignment { target, value } => Expr::Assignment {
+                target: self.copy_expr(from, *target),
+                value: self.copy_expr(from, *value),
+            },
+            expr @ (Expr::Literal(_) | Expr::Identifier(_)) => expr.clone(),
+        };
+        self.alloc_expr(expr)
+    }
+
+    /// Copies the statement `id` of another arena into this one, with its
+    /// parts, returning the handle of the copy.
+    pub fn copy_stmt(&mut self, from: &Arena, id: StmtId) -> StmtId {
+        let stmt = match &from[id] {
+            Stmt::Declaration { ty, name, init } => Stmt::Declaration {
+                ty: *ty,
+                name: *name,
+                init: init.map(|e| self.copy_expr(from, e)),
+            },
+            Stmt::Return(value) => Stmt::Return(value.map(|e| self.copy_expr(from, e))),
+            Stmt::Block(stmts) => {
+                Stmt::Block(stmts.iter().map(|s| self.copy_stmt(from, *s)).collect())
+            }
+            Stmt::If { cond, then, else_ } => Stmt::If {
+                cond: self.copy_expr(from, *cond),
+                then: self.copy_stmt(from, *then),
+                else_: else_.map(|s| self.copy_stmt(from, s)),
+            },
+            Stmt::For {
+                init,
+                cond,
+                update,
+                body,
+            } => Stmt::For {
+                init: init.map(|s| self.copy_stmt(from, s)),
+                cond: cond.map(|e| self.copy_expr(from, e)),
+                update: update.map(|e| self.copy_expr(from, e)),
+                body: self.copy_stmt(from, *body),
+            },
+            Stmt::Expr(expr) => Stmt::Expr(self.copy_expr(from, *expr)),
+            Stmt::Empty => Stmt::Empty,
+        };
+        self.alloc_stmt(stmt)
+    }
+}
+
+impl Index<ExprId> for Arena {
+    type Output = Expr;
+
+    fn index(&self, id: ExprId) -> &Expr {
+        &self.exprs[id.0 as usize]
+    }
+}
+
+impl IndexMut<ExprId> for Arena {
+    fn index_mut(&mut self, id: ExprId) -> &mut Expr {
+        &mut self.exprs[id.0 as usize]
+    }
+}
+
+impl Index<StmtId> for Arena {
+    type Output = Stmt;
+
+    fn index(&self, id: StmtId) -> &Stmt {
+        &self.stmts[id.0 as usize]
+    }
+}
+
+impl IndexMut<StmtId> for Arena {
+    fn index_mut(&mut self, id: StmtId) -> &mut Stmt {
+        &mut self.stmts[id.0 as usize]
+    }
+}
+
+impl fmt::Debug for Arena {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Arena")
+            .field("exprs", &self.exprs.len())
+            .field("stmts", &self.stmts.len())
+            .finish()
+    }
+}
+
+/// A node of an [`Arena`] seen as the tree rooted at it.
+///
+/// Nodes compare and print as trees, the same whichever arena and handles
+/// hold them.
+#[derive(Clone, Copy)]
+pub struct Node<'a, Id> {
+    /// Arena holding the tree
+    pub arena: &'a Arena,
+    /// Handle of the root
+    pub id: Id,
+}
+
+impl<'a, Id: Copy> Node<'a, Id>
+where
+    Arena: Index<Id>,
+{
+    /// Returns the root node.
+    pub fn get(&self) -> &'a <Arena as Index<Id>>::Output {
+        &self.arena[self.id]
+    }
+}
+
+impl PartialEq for Node<'_, ExprId> {
+    fn eq(&self, other: &Self) -> bool {
+        let (a, b) = (self.arena, other.arena);
+        match (&a[self.id], &b[other.id]) {
+            (
+                Expr::Binary { left, op, right },
+                Expr::Binary {
+                    left: left2,
+                    op: op2,
+                    right: right2,
+                },
+            ) => op == op2 && a.node(*left) == b.node(*left2) && a.node(*right) == b.node(*right2),
+            (
+                Expr::Call { name, args },
+                Expr::Call {
+                    name: name2,
+                    args: args2,
+                },
+            ) => {
+                name == name2
+                    && args.len() == args2.len()
+                    && args
+                        .iter()
+                        .zip(args2)
+                        .all(|(x, y)| a.node(*x) == b.node(*y))
+            }
+            (
+                Expr::Assignment { target, value },
+                Expr::Assignment {
+                    target: target2,
+                    value: value2,
+                },
+            ) => a.node(*target) == b.node(*target2) && a.node(*value) == b.node(*value2),
+            (x, y) => x == y,
+        }
+    }
+}
+
+impl PartialEq for Node<'_, StmtId> {
+    fn eq(&self, other: &Self) -> bool {
+        let (a, b) = (self.arena, other.arena);
+        let exprs =
+            |x: Option<ExprId>, y: Option<ExprId>| x.map(|x| a.node(x)) == y.map(|y| b.node(y));
+        let stmts =
+            |x: Option<StmtId>, y: Option<StmtId>| x.map(|x| a.node(x)) == y.map(|y| b.node(y));
+        match (&a[self.id], &b[other.id]) {
+            (
+                Stmt::Declaration { ty, name, init },
+                Stmt::Declaration {
+                    ty: ty2,
+                    name: name2,
+                    init: init2,
+                },
+            ) => ty == ty2 && name == name2 && exprs(*init, *init2),
+            (Stmt::Return(value), Stmt::Return(value2)) => exprs(*value, *value2),
+            (Stmt::Block(body), Stmt::Block(body2)) => {
+                body.len() == body2.len()
+                    && body
+                        .iter()
+                        .zip(body2)
+                        .all(|(x, y)| a.node(*x) == b.node(*y))
+            }
+            (
+                Stmt::If { cond, then, else_ },
+                Stmt::If {
+                    cond: cond2,
+                    then: then2,
+                    else_: else2,
+                },
+            ) => {
+                a.node(*cond) == b.node(*cond2)
+                    && a.node(*then) == b.node(*then2)
+                    && stmts(*else_, *else2)
+            }
+            (
+                Stmt::For {
+                    init,
+                    cond,
+                    update,
+                    body,
+                },
+                Stmt::For {
+                    init: init2,
+                    cond: cond2,
+                    update: update2,
+                    body: body2,
+                },
+            ) => {
+                stmts(*init, *init2)
+                    && exprs(*cond, *cond2)
+                    && exprs(*update, *update2)
+                    && a.node(*body) == b.node(*body2)
+            }
+            (Stmt::Expr(expr), Stmt::Expr(expr2)) => a.node(*expr) == b.node(*expr2),
+            (x, y) => x == y,
+        }
+    }
+}
+
+impl fmt::Debug for Node<'_, ExprId> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let node = |id| self.arena.node(id);
+        match &self.arena[self.id] {
+            Expr::Literal(lit) => f.debug_tuple("Literal").field(lit).finish(),
+            Expr::Identifier(name) => f.debug_tuple("Identifier").field(name).finish(),
+            Expr::Binary { left, op, right } => f
+                .debug_struct("Binary")
+                .field("left", &node(*left))
+                .field("op", op)
+                .field("right", &node(*right))
+                .finish(),
+            Expr::Call { name, args } => f
+                .debug_struct("Call")
+                .field("name", name)
+                .field(
+                    "args",
+                    &args.iter().map(|arg| node(*arg)).collect::<Vec<_>>(),
+                )
+                .finish(),
+            Expr::Assignment { target, value } => f
+                .debug_struct("Assignment")
+                .field("target", &node(*target))
+                .field("value", &node(*value))
+                .finish(),
+        }
+    }
+}
+
+impl fmt::Debug for Node<'_, StmtId> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let expr = |id| self.arena.node(id);
+        let stmt = |id| self.arena.node(id);
+        match &self.arena[self.id] {
+            Stmt::Declaration { ty, name, init } => f
+                .debug_struct("Declaration")
+                .field("ty", ty)
+                .field("name", name)
+                .field("init", &init.map(expr))
+                .finish(),
+            Stmt::Return(value) => f.debug_tuple("Return").field(&value.map(expr)).finish(),
+            Stmt::Block(stmts) => f
+                .debug_tuple("Block")
+                .field(&stmts.iter().map(|s| stmt(*s)).collect::<Vec<_>>())
+                .finish(),
+            Stmt::If { cond, then, else_ } => f
+                .debug_struct("If")
+                .field("cond", &expr(*cond))
+                .field("then", &stmt(*then))
+                .field("else_", &else_.map(stmt))
+                .finish(),
+            Stmt::For {
+                init,
+                cond,
+                update,
+                body,
+            } => f
+                .debug_struct("For")
+                .field("init", &init.map(stmt))
+                .field("cond", &cond.map(expr))
+                .field("update", &update.map(expr))
+                .field("body", &stmt(*body))
+                .finish(),
+            Stmt::Expr(e) => f.debug_tuple("Expr").field(&expr(*e)).finish(),
+            Stmt::Empty => write!(f, "Empty"),
+        }
+    }
+}
+
+/// An expression or statement outside of any function, such as a parsed
+/// fragment: the arena holding it and the handle of its root.
+#[derive(Clone)]
+pub struct Tree<Id> {
+    /// Arena holding the tree
+    pub arena: Arena,
+    /// Handle of the root
+    pub root: Id,
+}
+
+/// An expression outside of any function.
+pub type ExprTree = Tree<ExprId>;
+
+/// A statement outside of any function.
+pub type StmtTree = Tree<StmtId>;
+
+impl<Id: Copy> Tree<Id> {
+    /// Returns the root node.
+    pub fn get(&self) -> &<Arena as Index<Id>>::Output
+    where
+        Arena: Index<Id>,
+    {
+        &self.arena[self.root]
+    }
+
+    /// Views the tree as a [`Node`].
+    pub fn node(&self) -> Node<'_, Id> {
+        self.arena.node(self.root)
+    }
+}
+
+impl<Id: Copy> PartialEq for Tree<Id>
+where
+    for<'a> Node<'a, Id>: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.node() == other.node()
+    }
+}
+
+impl<Id: Copy> fmt::Debug for Tree<Id>
+where
+    for<'a> Node<'a, Id>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.node().fmt(f)
+    }
 }
 
 /// Represents a function definition.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Clone)]
 pub struct Function {
     /// Return type of the function
     pub return_ty: Type,
@@ -124,7 +601,36 @@ pub struct Function {
     /// Parameters: (type, name) pairs
     pub params: Vec<(Type, String)>,
     /// Function body
-    pub body: Stmt,
+    pub body: StmtId,
+    /// The expressions and statements of the body
+    pub arena: Arena,
+}
+
+impl Function {
+    /// Returns the body statement.
+    pub fn body(&self) -> &Stmt {
+        &self.arena[self.body]
+    }
+}
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        self.return_ty == other.return_ty
+            && self.name == other.name
+            && self.params == other.params
+            && self.arena.node(self.body) == other.arena.node(other.body)
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("return_ty", &self.return_ty)
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .field("body", &self.arena.node(self.body))
+            .finish()
+    }
 }
 
 /// Represents an extern function declaration.
@@ -145,6 +651,8 @@ pub struct ExternFunction {
 pub struct Program {
     /// List of include directives (header names)
     pub includes: Vec<String>,
+    /// List of imported source files (`#include "file.c"`), as written
+    pub imports: Vec<String>,
     /// List of extern function declarations
     pub extern_functions: Vec<ExternFunction>,
     /// List of function definitions
@@ -154,39 +662,36 @@ pub struct Program {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::builder::{FnBuilder, expr, stmt};
 
     #[test]
     fn test_simple_function() {
-        let func = Function {
-            return_ty: Type::Int,
-            name: "add".to_string(),
-            params: vec![(Type::Int, "a".to_string()), (Type::Int, "b".to_string())],
-            body: Stmt::Block(vec![Stmt::Return(Some(Expr::Binary {
-                left: Box::new(Expr::Identifier("a".to_string())),
-                op: BinOp::Plus,
-                right: Box::new(Expr::Identifier("b".to_string())),
-            }))]),
-        };
+        let func = FnBuilder::new("add", Type::Int)
+            .param(Type::Int, "a")
+            .param(Type::Int, "b")
+            .stmt(stmt::ret(expr::add(expr::ident("a"), expr::ident("b"))))
+            .build();
         // Basic construction test
         assert_eq!(func.name, "add");
         assert_eq!(func.return_ty, Type::Int);
+        assert_eq!(func.params.len(), 2);
     }
 
     #[test]
     fn test_if_statement() {
-        let if_stmt = Stmt::If {
-            cond: Expr::Binary {
-                left: Box::new(Expr::Identifier("x".to_string())),
-                op: BinOp::GreaterThan,
-                right: Box::new(Expr::Literal(Literal::Int(0))),
-            },
-            then: Box::new(Stmt::Return(Some(Expr::Identifier("x".to_string())))),
-            else_: Some(Box::new(Stmt::Return(Some(Expr::Literal(Literal::Int(0)))))),
-        };
+        let if_stmt = stmt::if_(
+            expr::gt(expr::ident("x"), expr::int(0)),
+            stmt::ret(expr::ident("x")),
+            stmt::ret(expr::int(0)),
+        );
         // Test structure
-        if let Stmt::If { cond, then, else_ } = if_stmt {
-            assert!(matches!(cond, Expr::Binary { .. }));
-            assert!(matches!(*then, Stmt::Return(Some(Expr::Identifier(_)))));
+        let arena = &if_stmt.arena;
+        if let Stmt::If { cond, then, else_ } = if_stmt.get() {
+            assert!(matches!(arena[*cond], Expr::Binary { .. }));
+            let Stmt::Return(Some(value)) = arena[*then] else {
+                panic!("Expected return statement");
+            };
+            assert!(matches!(arena[value], Expr::Identifier(_)));
             assert!(else_.is_some());
         } else {
             panic!("Expected If statement");
@@ -0,0 +1,375 @@
+//! # AST Builder
+//!
+//! This module provides constructors for building [`Program`]s in code, for
+//! tools and tests that generate programs without going through the parser.
+//!
+//! ## Usage
+//!
+//! ```
+//! use virtuc::ast::Type;
+//! use virtuc::builder::{FnBuilder, ProgramBuilder, expr, stmt};
+//!
+//! let main = FnBuilder::new("main", Type::Int)
+//!     .stmt(stmt::expr(expr::call("printf", [expr::string("hi\n")])))
+//!     .stmt(stmt::ret(expr::int(0)))
+//!     .build();
+//! let program = ProgramBuilder::new().include("stdio.h").function(main).build();
+//! assert_eq!(program.functions[0].name, "main");
+//! ```
+
+use crate::ast::*;
+
+/// Constructors for expressions.
+///
+/// Each expression comes in an arena of its own, which the expressions and
+/// statements built from it take over.
+pub mod expr {
+    use crate::ast::*;
+    use crate::interner::Symbol;
+
+    /// An expression without operands.
+    fn leaf(expr: Expr) -> ExprTree {
+        let mut arena = Arena::new();
+        let root = arena.alloc_expr(expr);
+        Tree { arena, root }
+    }
+
+    /// An integer literal.
+    pub fn int(value: i64) -> ExprTree {
+        leaf(Expr::Literal(Literal::Int(value)))
+    }
+
+    /// A float literal.
+    pub fn float(value: f64) -> ExprTree {
+        leaf(Expr::Literal(Literal::Float(value)))
+    }
+
+    /// A string literal.
+    pub fn string(value: impl Into<String>) -> ExprTree {
+        leaf(Expr::Literal(Literal::String(value.into())))
+    }
+
+    /// A variable reference.
+    pub fn ident(name: impl Into<Symbol>) -> ExprTree {
+        leaf(Expr::Identifier(name.into()))
+    }
+
+    /// A binary operation.
+    pub fn binary(left: ExprTree, op: BinOp, right: ExprTree) -> ExprTree {
+        let mut arena = left.arena;
+        let right = arena.copy_expr(&right.arena, right.root);
+        let root = arena.alloc_expr(Expr::Binary {
+            left: left.root,
+            op,
+            right,
+        });
+        Tree { arena, root }
+    }
+
+    /// `left + right`
+    pub fn add(left: ExprTree, right: ExprTree) -> ExprTree {
+        binary(left, BinOp::Plus, right)
+    }
+
+    /// `left - right`
+    pub fn sub(left: ExprTree, right: ExprTree) -> ExprTree {
+        binary(left, BinOp::Minus, right)
+    }
+
+    /// `left * right`
+    pub fn mul(left: ExprTree, right: ExprTree) -> ExprTree {
+        binary(left, BinOp::Multiply, right)
+    }
+
+    /// `left / right`
+    pub fn div(left: ExprTree, right: ExprTree) -> ExprTree {
+        binary(left, BinOp::Divide, right)
+    }
+
+    /// `left == right`
+    pub fn eq(left: ExprTree, right: ExprTree) -> ExprTree {
+        binary(left, BinOp::Equal, right)
+    }
+
+    /// `left != right`
+    pub fn ne(left: ExprTree, right: ExprTree) -> ExprTree {
+        binary(left, BinOp::NotEqual, right)
+    }
+
+    /// `left < right`
+    pub fn lt(left: ExprTree, right: ExprTree) -> ExprTree {
+        binary(left, BinOp::LessThan, right)
+    }
+
+    /// `left > right`
+    pub fn gt(left: ExprTree, right: ExprTree) -> ExprTree {
+        binary(left, BinOp::GreaterThan, right)
+    }
+
+    /// `left <= right`
+    pub fn le(left: ExprTree, right: ExprTree) -> ExprTree {
+        binary(left, BinOp::LessEqual, right)
+    }
+
+    /// `left >= right`
+    pub fn ge(left: ExprTree, right: ExprTree) -> ExprTree {
+        binary(left, BinOp::GreaterEqual, right)
+    }
+
+    /// A function call.
+    pub fn call(name: impl Into<Symbol>, args: impl IntoIterator<Item = ExprTree>) -> ExprTree {
+        let mut arena = Arena::new();
+        let args = args
+            .into_iter()
+            .map(|arg| arena.copy_expr(&arg.arena, arg.root))
+            .collect();
+        let root = arena.alloc_expr(Expr::Call {
+            name: name.into(),
+            args,
+        });
+        Tree { arena, root }
+    }
+
+    /// `target = value`
+    pub fn assign(target: ExprTree, value: ExprTree) -> ExprTree {
+        let mut arena = target.arena;
+        let value = arena.copy_expr(&value.arena, value.root);
+        let root = arena.alloc_expr(Expr::Assignment {
+            target: target.root,
+            value,
+        });
+        Tree { arena, root }
+    }
+}
+
+/// Constructors for statements.
+pub mod stmt {
+    use crate::ast::*;
+    use crate::interner::Symbol;
+
+    /// Builds a statement in a new arena, into which `parts` copies the
+    /// expressions and statements it is made of.
+    fn build(parts: impl FnOnce(&mut Parts) -> Stmt) -> StmtTree {
+        let mut arena = Arena::new();
+        let stmt = parts(&mut Parts(&mut arena));
+        let root = arena.alloc_stmt(stmt);
+        Tree { arena, root }
+    }
+
+    /// Copies the parts of a statement into its arena.
+    struct Parts<'a>(&'a mut Arena);
+
+    impl Parts<'_> {
+        fn expr(&mut self, expr: ExprTree) -> ExprId {
+            self.0.copy_expr(&expr.arena, expr.root)
+        }
+
+        fn stmt(&mut self, stmt: StmtTree) -> StmtId {
+            self.0.copy_stmt(&stmt.arena, stmt.root)
+        }
+    }
+
+    /// A variable declaration, with or without an initializer.
+    pub fn decl(ty: Type, name: impl Into<Symbol>, init: impl Into<Option<ExprTree>>) -> StmtTree {
+        build(|parts| Stmt::Declaration {
+            ty,
+            name: name.into(),
+            init: init.into().map(|e| parts.expr(e)),
+        })
+    }
+
+    /// A return statement, with or without a value.
+    pub fn ret(value: impl Into<Option<ExprTree>>) -> StmtTree {
+        build(|parts| Stmt::Return(value.into().map(|e| parts.expr(e))))
+    }
+
+    /// A block of statements.
+    pub fn block(stmts: impl IntoIterator<Item = StmtTree>) -> StmtTree {
+        build(|parts| Stmt::Block(stmts.into_iter().map(|s| parts.stmt(s)).collect()))
+    }
+
+    /// An if statement with an optional else branch.
+    pub fn if_(cond: ExprTree, then: StmtTree, else_: impl Into<Option<StmtTree>>) -> StmtTree {
+        build(|parts| Stmt::If {
+            cond: parts.expr(cond),
+            then: parts.stmt(then),
+            else_: else_.into().map(|s| parts.stmt(s)),
+        })
+    }
+
+    /// A for loop; any of the header parts may be omitted.
+    pub fn for_(
+        init: impl Into<Option<StmtTree>>,
+        cond: impl Into<Option<ExprTree>>,
+        update: impl Into<Option<ExprTree>>,
+        body: StmtTree,
+    ) -> StmtTree {
+        build(|parts| Stmt::For {
+            init: init.into().map(|s| parts.stmt(s)),
+            cond: cond.into().map(|e| parts.expr(e)),
+            update: update.into().map(|e| parts.expr(e)),
+            body: parts.stmt(body),
+        })
+    }
+
+    /// An expression statement.
+    pub fn expr(expr: ExprTree) -> StmtTree {
+        build(|parts| Stmt::Expr(parts.expr(expr)))
+    }
+
+    /// The null statement `;`.
+    pub fn empty() -> StmtTree {
+        build(|_| Stmt::Empty)
+    }
+}
+
+/// Builds a function definition statement by statement.
+#[derive(Debug, Clone)]
+pub struct FnBuilder {
+    function: Function,
+    stmts: Vec<StmtId>,
+}
+
+impl FnBuilder {
+    /// Starts a function with the given name and return type.
+    pub fn new(name: impl Into<String>, return_ty: Type) -> Self {
+        let mut arena = Arena::new();
+        let body = arena.alloc_stmt(Stmt::Empty);
+        Self {
+            function: Function {
+                return_ty,
+                name: name.into(),
+                params: Vec::new(),
+                body,
+                arena,
+            },
+            stmts: Vec::new(),
+        }
+    }
+
+    /// Adds a parameter.
+    pub fn param(mut self, ty: Type, name: impl Into<String>) -> Self {
+        self.function.params.push((ty, name.into()));
+        self
+    }
+
+    /// Appends a statement to the body.
+    pub fn stmt(mut self, stmt: StmtTree) -> Self {
+        let stmt = self.function.arena.copy_stmt(&stmt.arena, stmt.root);
+        self.stmts.push(stmt);
+        self
+    }
+
+    /// Finishes the function.
+    pub fn build(mut self) -> Function {
+        self.function.arena[self.function.body] = Stmt::Block(self.stmts);
+        self.function
+    }
+}
+
+/// Builds a program from includes, extern declarations, and functions.
+#[derive(Debug, Clone)]
+pub struct ProgramBuilder {
+    program: Program,
+}
+
+impl Default for ProgramBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProgramBuilder {
+    /// Starts an empty program.
+    pub fn new() -> Self {
+        Self {
+            program: Program {
+                includes: Vec::new(),
+                imports: Vec::new(),
+                extern_functions: Vec::new(),
+                functions: Vec::new(),
+            },
+        }
+    }
+
+    /// Adds an include directive. The header's known functions that the
+    /// program calls are declared by [`build`](Self::build), as the parser
+    /// does.
+    pub fn include(mut self, header: impl Into<String>) -> Self {
+        self.program.includes.push(header.into());
+        self
+    }
+
+    /// Declares an extern function.
+    pub fn extern_function(
+        mut self,
+        name: impl Into<String>,
+        return_ty: Type,
+        param_types: impl IntoIterator<Item = Type>,
+        is_variadic: bool,
+    ) -> Self {
+        self.program.extern_functions.push(ExternFunction {
+            return_ty,
+            name: name.into(),
+            param_types: param_types.into_iter().collect(),
+            is_variadic,
+        });
+        self
+    }
+
+    /// Adds a function definition.
+    pub fn function(mut self, function: Function) -> Self {
+        self.program.functions.push(function);
+        self
+    }
+
+    /// Finishes the program.
+    pub fn build(mut self) -> Program {
+        crate::header_registry::declare_used_externs(&mut self.program);
+        self.program
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_builder_matches_parser() {
+        let source = r#"
+            #include <stdio.h>
+            int sum(int n) {
+                int total = 0;
+                for (int i = 0; i < n; i = i + 1) total = total + i;
+                if (total > 10) { printf("big\n"); } else return 0;
+                return total;
+            }
+        "#;
+        let sum = FnBuilder::new("sum", Type::Int)
+            .param(Type::Int, "n")
+            .stmt(stmt::decl(Type::Int, "total", expr::int(0)))
+            .stmt(stmt::for_(
+                stmt::decl(Type::Int, "i", expr::int(0)),
+                expr::lt(expr::ident("i"), expr::ident("n")),
+                expr::assign(expr::ident("i"), expr::add(expr::ident("i"), expr::int(1))),
+                stmt::expr(expr::assign(
+                    expr::ident("total"),
+                    expr::add(expr::ident("total"), expr::ident("i")),
+                )),
+            ))
+            .stmt(stmt::if_(
+                expr::gt(expr::ident("total"), expr::int(10)),
+                stmt::block([stmt::expr(expr::call("printf", [expr::string("big\n")]))]),
+                stmt::ret(expr::int(0)),
+            ))
+            .stmt(stmt::ret(expr::ident("total")))
+            .build();
+        let program = ProgramBuilder::new()
+            .include("stdio.h")
+            .function(sum)
+            .build();
+        assert_eq!(program, parse(&lex(source).unwrap()).unwrap());
+    }
+}
@@ -0,0 +1,126 @@
+//! # C Header Generation
+//!
+//! This module writes a C header declaring the functions of a program, so
+//! that C and C++ code can call them once the program is built as a static
+//! library.
+//!
+//! ## Type Mapping
+//!
+//! - `int` is `int64_t`, or `int` when compiled with a 32-bit `int`
+//! - `float` is `double`
+//! - `string` is `const char *`
+//!
+//! `main` is left out: it is the entry point of an executable, not a
+//! function for other code to call.
+
+use crate::analysis::ENTRY;
+use crate::ast::{Function, Program, Type};
+use crate::options::IntWidth;
+
+/// Generates a header declaring the functions of `program`, which was
+/// compiled with the given `int` width. `name` is the file name of the
+/// header, from which its include guard is derived.
+pub fn generate(program: &Program, name: &str, int_width: IntWidth) -> String {
+    let guard = include_guard(name);
+    let mut out = format!(
+        "/* Generated by virtuc {}; do not edit. */\n#ifndef {guard}\n#define {guard}\n\n",
+        crate::VERSION
+    );
+    if int_width == IntWidth::W64 {
+        out.push_str("#include <stdint.h>\n\n");
+    }
+    out.push_str("#ifdef __cplusplus\nextern \"C\" {\n#endif\n\n");
+    for function in program.functions.iter().filter(|f| f.name != ENTRY) {
+        out.push_str(&prototype(function, int_width));
+        out.push('\n');
+    }
+    out.push_str(&format!(
+        "\n#ifdef __cplusplus\n}}\n#endif\n\n#endif /* {guard} */\n"
+    ));
+    out
+}
+
+/// Returns the C prototype of a function.
+fn prototype(function: &Function, int_width: IntWidth) -> String {
+    let params = if function.params.is_empty() {
+        "void".to_string()
+    } else {
+        function
+            .params
+            .iter()
+            .map(|(ty, name)| declaration(*ty, name, int_width))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let name = format!("{}({})", function.name, params);
+    format!("{};", declaration(function.return_ty, &name, int_width))
+}
+
+/// Returns the C declaration of `name` with a value type, e.g. `double x`
+/// or `const char *s`.
+fn declaration(ty: Type, name: &str, int_width: IntWidth) -> String {
+    let ty = c_type(ty, int_width);
+    if ty.ends_with('*') {
+        format!("{}{}", ty, name)
+    } else {
+        format!("{} {}", ty, name)
+    }
+}
+
+/// Returns the C type of a value type.
+fn c_type(ty: Type, int_width: IntWidth) -> &'static str {
+    match (ty, int_width) {
+        (Type::Int, IntWidth::W64) => "int64_t",
+        (Type::Int, IntWidth::W32) => "int",
+        (Type::Float, _) => "double",
+        (Type::String, _) => "const char *",
+    }
+}
+
+/// Derives an include guard such as `MATH_UTIL_H` from a file name.
+fn include_guard(name: &str) -> String {
+    let mut guard: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if guard.starts_with(|c: char| c.is_ascii_digit()) {
+        guard.insert(0, '_');
+    }
+    guard
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_generate_header() {
+        let program = parse(
+            &lex("int scale(int x, float by) { return x; }
+                  float zero() { return 0.0; }
+                  int greet(string name) { return 0; }
+                  int main() { return 0; }")
+            .unwrap(),
+        )
+        .unwrap();
+        let header = generate(&program, "math-util.h", IntWidth::W64);
+        assert!(header.contains("#ifndef MATH_UTIL_H\n#define MATH_UTIL_H\n"));
+        assert!(header.contains("#include <stdint.h>"));
+        assert!(header.contains(
+            "int64_t scale(int64_t x, double by);\ndouble zero(void);\nint64_t greet(const char *name);\n"
+        ));
+        assert!(!header.contains("main"));
+
+        let header = generate(&program, "math-util.h", IntWidth::W32);
+        assert!(header.contains("int scale(int x, double by);"));
+        assert!(!header.contains("stdint.h"));
+    }
+}
@@ -16,66 +16,245 @@
 //! Uses `inkwell` to build LLVM IR incrementally. Handles type mapping from
 //! the C subset types to LLVM types, and generates efficient code with
 //! optimizations enabled.
+//!
+//! ## Floating Point
+//!
+//! Float operations follow IEEE 754 exactly at every optimization level:
+//! they carry no fast-math flags, and a multiply and add are never fused
+//! into one rounding. A float program therefore computes the same bits on
+//! every target, matching the [`consteval`] folding of constant expressions.
+//!
+//! With [`CompileOptions::fast_math`], float arithmetic and comparisons get
+//! every fast-math flag instead: LLVM may reassociate, fuse, and assume that
+//! no value is NaN or infinite, which speeds up numeric loops but changes
+//! their results.
 
 use inkwell::AddressSpace;
+use inkwell::OptimizationLevel;
+use inkwell::attributes::{Attribute, AttributeLoc};
 use inkwell::builder::Builder;
 use inkwell::context::Context;
-use inkwell::module::Module;
-use inkwell::targets::{InitializationConfig, Target, TargetMachine};
-use inkwell::types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum};
-use inkwell::values::{BasicMetadataValueEnum, BasicValueEnum, PointerValue};
+use inkwell::module::{Linkage, Module};
+use inkwell::passes::PassBuilderOptions;
+use inkwell::targets::{
+    CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine,
+};
+use inkwell::types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, IntType};
+use inkwell::values::{
+    AnyValue, BasicMetadataValueEnum, BasicValueEnum, FunctionValue, GlobalValue, InstructionValue,
+    PointerValue,
+};
 use inkwell::{FloatPredicate, IntPredicate};
 use std::collections::HashMap;
+use std::path::Path;
 
+use crate::analysis::ENTRY;
+use crate::assertions;
 use crate::ast::*;
+use crate::consteval::{self, ConstValue};
+use crate::coverage;
+use crate::embed::{self, Embedded};
 use crate::error::CodegenError;
+use crate::ice;
+use crate::optimize;
+use crate::options::{CompileOptions, IntWidth, Sanitizer};
+use crate::random;
+use crate::resolve::{self, Resolution, SymbolId};
+use crate::runtime;
+
+/// All of LLVM's fast-math flags, as `LLVMFastMathAll` in the C API.
+const FAST_MATH_ALL: u32 = 0x7f;
 
 /// Code generator for LLVM IR.
 pub struct CodeGenerator<'ctx> {
     context: &'ctx Context,
     module: Module<'ctx>,
     builder: Builder<'ctx>,
-    /// Variable environment: name -> (pointer to value, type)
-    variables: HashMap<String, (PointerValue<'ctx>, Type)>,
+    /// The symbols of the program being generated
+    resolution: Resolution,
+    /// Variable environment: symbol -> (pointer to value, type)
+    variables: HashMap<SymbolId, (PointerValue<'ctx>, Type)>,
+    /// Index in the program of the function being generated, for looking
+    /// up its symbols
+    function: usize,
+    /// Width of the `int` type
+    int_width: IntWidth,
+    /// Call depth limit, if recursion is checked
+    recursion_limit: Option<u32>,
+    /// Sanitizers the executable is built with
+    sanitizers: Vec<Sanitizer>,
+    /// Whether float operations get fast-math flags
+    fast_math: bool,
+    /// Whether functions get stack protectors and probes
+    hardening: bool,
+    /// The options the generator was created with, for the rules they
+    /// define, such as which functions are exported
+    options: CompileOptions,
+    /// The function the program starts at
+    entry: String,
+    /// Counts file of a coverage build
+    coverage_file: Option<String>,
+    /// Statement counters, in coverage builds
+    coverage: Option<Coverage<'ctx>>,
+    /// Whether `assert` is the builtin of `<assert.h>`
+    assert_builtin: bool,
+    /// Whether assertions are checked rather than compiled out
+    check_assertions: bool,
+    /// Whether `rand_seeded` and `rand_next` are the builtins of
+    /// `<virtuc/random.h>`
+    random_builtins: bool,
+}
+
+/// The statement counters of a coverage build.
+struct Coverage<'ctx> {
+    /// The global `i64` array of counters
+    counters: GlobalValue<'ctx>,
+    /// Number of counters
+    len: u32,
+    /// Counter index of each statement, keyed by the index of its function
+    /// and its handle
+    indices: HashMap<(usize, StmtId), u32>,
+    /// Writes the counters on exit
+    dump: FunctionValue<'ctx>,
 }
 
 impl<'ctx> CodeGenerator<'ctx> {
     /// Creates a new code generator.
     pub fn new(context: &'ctx Context) -> Self {
+        Self::with_options(context, &CompileOptions::default())
+    }
+
+    /// Creates a new code generator configured by the compile options.
+    pub fn with_options(context: &'ctx Context, options: &CompileOptions) -> Self {
         // Initialize native target to ensure we can get the default triple
         Target::initialize_native(&InitializationConfig::default()).ok();
 
         let module = context.create_module("virtuc");
+        if let Some(name) = options.recorded_source_name() {
+            module.set_source_file_name(name);
+        }
 
         // Set the target triple to the host machine's triple
         let triple = TargetMachine::get_default_triple();
         module.set_triple(&triple);
 
+        // Use the data layout of the host target machine, so downstream tools
+        // agree with us on type sizes and alignment
+        if let Some(machine) = host_target_machine(OptimizationLevel::Default) {
+            module.set_data_layout(&machine.get_target_data().get_data_layout());
+        }
+
+        // Record the producer, as clang does
+        let producer = context.metadata_string(&format!("virtuc version {}", crate::VERSION));
+        module
+            .add_global_metadata("llvm.ident", &context.metadata_node(&[producer.into()]))
+            .ok();
+
         let builder = context.create_builder();
         Self {
             context,
             module,
             builder,
+            resolution: Resolution::default(),
             variables: HashMap::new(),
+            function: 0,
+            int_width: options.int_width,
+            recursion_limit: options.recursion_limit,
+            sanitizers: options.sanitizers.clone(),
+            fast_math: options.uses_fast_math(),
+            hardening: options.hardening,
+            options: options.clone(),
+            entry: options.entry().to_string(),
+            coverage_file: options.coverage_file.clone(),
+            coverage: None,
+            assert_builtin: false,
+            check_assertions: options.checks_assertions(),
+            random_builtins: false,
         }
     }
 
     /// Generates LLVM IR for the program.
     pub fn generate(&mut self, program: &Program) -> Result<(), CodegenError> {
+        self.generate_filtered(program, |_| true)
+    }
+
+    /// Generates LLVM IR for the functions of the program that `include`
+    /// accepts. The others are only declared, as if defined in another
+    /// module, so that the generated functions may still call them.
+    pub fn generate_filtered(
+        &mut self,
+        program: &Program,
+        include: impl Fn(&Function) -> bool,
+    ) -> Result<(), CodegenError> {
+        self.resolution = resolve::resolve(program);
+        if let Some(file) = &self.coverage_file {
+            self.coverage = Some(self.declare_coverage(program, file));
+        }
+        self.assert_builtin = assertions::enabled(program);
+        self.random_builtins = random::enabled(program);
         for extern_func in &program.extern_functions {
+            if self.assert_builtin && extern_func.name == assertions::NAME {
+                continue;
+            }
+            if self.random_builtins && random::is_builtin(&extern_func.name) {
+                continue;
+            }
             self.declare_extern_function(extern_func)?;
         }
+        // Declare every function first, so that calls may precede definitions
         for function in &program.functions {
+            self.declare_function(function);
+        }
+        for (index, function) in program.functions.iter().enumerate() {
+            if !include(function) {
+                continue;
+            }
+            ice::enter_function(Some(&function.name));
+            self.function = index;
             self.generate_function(function)?;
         }
+        ice::enter_function(None);
+        if self.entry != ENTRY
+            && program
+                .functions
+                .iter()
+                .any(|function| function.name == self.entry && include(function))
+        {
+            self.generate_entry_wrapper();
+        }
         Ok(())
     }
 
+    /// Generates a `main` that returns the result of calling the entry
+    /// point chosen in its place.
+    fn generate_entry_wrapper(&mut self) {
+        let entry = self
+            .module
+            .get_function(&self.entry)
+            .expect("the entry point is declared");
+        let main = self.module.add_function(ENTRY, entry.get_type(), None);
+        self.add_int_abi_attributes(main, Type::Int, &[]);
+        let block = self.context.append_basic_block(main, "entry");
+        self.builder.position_at_end(block);
+        let result = self
+            .builder
+            .build_call(entry, &[], "result")
+            .unwrap()
+            .try_as_basic_value()
+            .unwrap_basic();
+        self.builder.build_return(Some(&result)).unwrap();
+    }
+
     /// Gets the LLVM IR as a string.
     pub fn get_ir(&self) -> String {
         self.module.print_to_string().to_string()
     }
 
+    /// Returns the generated module.
+    pub fn into_module(self) -> Module<'ctx> {
+        self.module
+    }
+
     /// Declares an extern function.
     fn declare_extern_function(
         &mut self,
@@ -89,13 +268,89 @@ impl<'ctx> CodeGenerator<'ctx> {
         let fn_type = self
             .llvm_type(extern_func.return_ty)
             .fn_type(&param_types, extern_func.is_variadic);
-        self.module.add_function(&extern_func.name, fn_type, None);
+        let llvm_function = self.module.add_function(&extern_func.name, fn_type, None);
+        self.add_int_abi_attributes(
+            llvm_function,
+            extern_func.return_ty,
+            &extern_func.param_types,
+        );
         Ok(())
     }
 
-    /// Generates a function.
-    fn generate_function(&mut self, function: &Function) -> Result<(), CodegenError> {
-        // Create function type
+    /// Marks 32-bit `int` parameters and return values as `signext`, as the C ABI
+    /// requires on targets that pass them in 64-bit registers.
+    fn add_int_abi_attributes(
+        &self,
+        function: FunctionValue<'ctx>,
+        return_ty: Type,
+        param_types: &[Type],
+    ) {
+        if self.int_width != IntWidth::W32 {
+            return;
+        }
+        if return_ty == Type::Int {
+            function.add_attribute(AttributeLoc::Return, self.signext_attribute());
+        }
+        for (i, ty) in param_types.iter().enumerate() {
+            if *ty == Type::Int {
+                function.add_attribute(AttributeLoc::Param(i as u32), self.signext_attribute());
+            }
+        }
+    }
+
+    /// Marks a function for instrumentation by the enabled sanitizers, and
+    /// keeps its frame pointer so that their reports have full stack traces.
+    fn add_sanitizer_attributes(&self, function: FunctionValue<'ctx>) {
+        if self.sanitizers.is_empty() {
+            return;
+        }
+        function.add_attribute(
+            AttributeLoc::Function,
+            self.context.create_string_attribute("frame-pointer", "all"),
+        );
+        if self.sanitizers.contains(&Sanitizer::Address) {
+            let kind = Attribute::get_named_enum_kind_id("sanitize_address");
+            function.add_attribute(
+                AttributeLoc::Function,
+                self.context.create_enum_attribute(kind, 0),
+            );
+        }
+    }
+
+    /// Gives a float instruction every fast-math flag, if fast math is on.
+    fn relax_float_math(&self, instruction: Option<InstructionValue<'ctx>>) {
+        // Operations on constants are folded rather than built
+        if let Some(instruction) = instruction.filter(|_| self.fast_math) {
+            instruction.set_fast_math_flags(FAST_MATH_ALL);
+        }
+    }
+
+    /// Gives a function a stack protector and stack probes, as clang's
+    /// `-fstack-protector-strong` and `-fstack-clash-protection` do.
+    fn add_hardening_attributes(&self, function: FunctionValue<'ctx>) {
+        if !self.hardening {
+            return;
+        }
+        let kind = Attribute::get_named_enum_kind_id("sspstrong");
+        function.add_attribute(
+            AttributeLoc::Function,
+            self.context.create_enum_attribute(kind, 0),
+        );
+        function.add_attribute(
+            AttributeLoc::Function,
+            self.context
+                .create_string_attribute("probe-stack", "inline-asm"),
+        );
+    }
+
+    /// Creates the `signext` parameter attribute.
+    fn signext_attribute(&self) -> Attribute {
+        let kind = Attribute::get_named_enum_kind_id("signext");
+        self.context.create_enum_attribute(kind, 0)
+    }
+
+    /// Declares a function defined by the program.
+    fn declare_function(&mut self, function: &Function) {
         let param_types: Vec<BasicMetadataTypeEnum> = function
             .params
             .iter()
@@ -105,8 +360,22 @@ impl<'ctx> CodeGenerator<'ctx> {
             .llvm_type(function.return_ty)
             .fn_type(&param_types, false);
 
-        // Create function
         let llvm_function = self.module.add_function(&function.name, fn_type, None);
+        let param_tys: Vec<Type> = function.params.iter().map(|(ty, _)| *ty).collect();
+        self.add_int_abi_attributes(llvm_function, function.return_ty, &param_tys);
+        self.add_sanitizer_attributes(llvm_function);
+        self.add_hardening_attributes(llvm_function);
+        if !self.options.exports_function(&function.name) {
+            llvm_function.set_linkage(Linkage::Internal);
+        }
+    }
+
+    /// Generates a function.
+    fn generate_function(&mut self, function: &Function) -> Result<(), CodegenError> {
+        let llvm_function = self
+            .module
+            .get_function(&function.name)
+            .expect("functions are declared before their bodies are generated");
 
         // Create entry block
         let entry_block = self.context.append_basic_block(llvm_function, "entry");
@@ -116,15 +385,33 @@ impl<'ctx> CodeGenerator<'ctx> {
         self.variables.clear();
 
         // Allocate parameters
-        for (i, (ty, name)) in function.params.iter().enumerate() {
+        let symbols = self.resolution.params(self.function).to_vec();
+        for (i, ((ty, name), symbol)) in function.params.iter().zip(symbols).enumerate() {
             let param = llvm_function.get_nth_param(i as u32).unwrap();
             let alloca = self.builder.build_alloca(param.get_type(), name).unwrap();
             self.builder.build_store(alloca, param).unwrap();
-            self.variables.insert(name.clone(), (alloca, *ty));
+            self.variables.insert(symbol, (alloca, *ty));
+        }
+
+        // Write the coverage counts however the program exits
+        if let Some(coverage) = &self.coverage
+            && function.name == self.entry
+        {
+            let atexit = self.module.get_function("atexit").unwrap_or_else(|| {
+                let ptr_type = self.context.ptr_type(AddressSpace::default());
+                let fn_type = self.context.i32_type().fn_type(&[ptr_type.into()], false);
+                self.module.add_function("atexit", fn_type, None)
+            });
+            let dump = coverage.dump.as_global_value().as_pointer_value();
+            self.builder.build_call(atexit, &[dump.into()], "").unwrap();
+        }
+
+        if let Some(limit) = self.recursion_limit {
+            self.generate_recursion_guard(llvm_function, &function.name, limit)?;
         }
 
         // Generate function body
-        self.generate_stmt(&function.body)?;
+        self.generate_stmt(&function.arena, function.body)?;
 
         // Check if the current block has a terminator
         let current_block = self.builder.get_insert_block().unwrap();
@@ -132,71 +419,240 @@ impl<'ctx> CodeGenerator<'ctx> {
             // Add implicit return if missing
             match function.return_ty {
                 Type::Int => {
-                    self.builder
-                        .build_return(Some(&self.context.i64_type().const_zero()))
-                        .unwrap();
+                    self.build_return(Some(self.int_type().const_zero().into()));
                 }
                 Type::Float => {
-                    self.builder
-                        .build_return(Some(&self.context.f64_type().const_zero()))
-                        .unwrap();
+                    self.build_return(Some(self.context.f64_type().const_zero().into()));
                 }
                 Type::String => {
-                    self.builder
-                        .build_return(Some(
-                            &self.context.ptr_type(AddressSpace::default()).const_null(),
-                        ))
-                        .unwrap();
+                    self.build_return(Some(
+                        self.context
+                            .ptr_type(AddressSpace::default())
+                            .const_null()
+                            .into(),
+                    ));
                 }
             }
         }
 
-        // Verify function
-        if llvm_function.verify(true) {
-            Ok(())
-        } else {
-            Err(CodegenError("Function verification failed".to_string()))
+        self.verify_function(llvm_function, &function.name)
+    }
+
+    /// Checks a generated function with LLVM's verifier, returning its
+    /// explanation and the function's IR if the function is invalid.
+    fn verify_function(
+        &self,
+        llvm_function: FunctionValue<'ctx>,
+        name: &str,
+    ) -> Result<(), CodegenError> {
+        // Without printing, the verifier only reports whether the function is
+        // valid; its messages come from verifying the module, where every
+        // other function has already passed
+        if llvm_function.verify(false) {
+            return Ok(());
         }
+        let explanation = match self.module.verify() {
+            Ok(()) => String::new(),
+            Err(message) => format!(":\n{}", message.to_string().trim_end()),
+        };
+        Err(CodegenError {
+            message: format!("function '{}' failed verification{}", name, explanation),
+            function: Some(name.to_string()),
+            function_ir: Some(llvm_function.print_to_string().to_string()),
+            module_ir: None,
+        })
+    }
+
+    /// Generates the prologue that counts the call depth and aborts with a
+    /// message when it exceeds `limit`.
+    fn generate_recursion_guard(
+        &mut self,
+        llvm_function: FunctionValue<'ctx>,
+        name: &str,
+        limit: u32,
+    ) -> Result<(), CodegenError> {
+        let i32_type = self.context.i32_type();
+        let depth_ptr = self.depth_counter().as_pointer_value();
+        let depth = self
+            .builder
+            .build_load(i32_type, depth_ptr, "depth")
+            .unwrap()
+            .into_int_value();
+        let depth = self
+            .builder
+            .build_int_add(depth, i32_type.const_int(1, false), "depth.next")
+            .unwrap();
+        self.builder.build_store(depth_ptr, depth).unwrap();
+        let too_deep = self
+            .builder
+            .build_int_compare(
+                IntPredicate::UGT,
+                depth,
+                i32_type.const_int(limit as u64, false),
+                "too_deep",
+            )
+            .unwrap();
+        let overflow_block = self
+            .context
+            .append_basic_block(llvm_function, "recursion.overflow");
+        let body_block = self.context.append_basic_block(llvm_function, "body");
+        self.builder
+            .build_conditional_branch(too_deep, overflow_block, body_block)
+            .unwrap();
+
+        // Report the overflow on stderr and abort
+        self.builder.position_at_end(overflow_block);
+        let message = format!(
+            "recursion limit of {} calls exceeded in function '{}'",
+            limit, name
+        );
+        let message = self
+            .builder
+            .build_global_string_ptr(&message, "recursion.message")
+            .map_err(|e| CodegenError::new(format!("Builder error: {:?}", e)))?;
+        let abort = runtime::abort(self.context, &self.module);
+        self.builder
+            .build_call(abort, &[message.as_pointer_value().into()], "")
+            .unwrap();
+        self.builder.build_unreachable().unwrap();
+
+        self.builder.position_at_end(body_block);
+        Ok(())
+    }
+
+    /// Returns the global call depth counter, declaring it on first use.
+    fn depth_counter(&self) -> GlobalValue<'ctx> {
+        const NAME: &str = "virtuc.recursion_depth";
+        self.module.get_global(NAME).unwrap_or_else(|| {
+            let i32_type = self.context.i32_type();
+            let global = self.module.add_global(i32_type, None, NAME);
+            global.set_linkage(Linkage::Internal);
+            global.set_initializer(&i32_type.const_zero());
+            global
+        })
+    }
+
+    /// Returns from the current function, first leaving the call depth
+    /// counted by the recursion guard.
+    fn build_return(&mut self, value: Option<BasicValueEnum<'ctx>>) {
+        if self.recursion_limit.is_some() {
+            let i32_type = self.context.i32_type();
+            let depth_ptr = self.depth_counter().as_pointer_value();
+            let depth = self
+                .builder
+                .build_load(i32_type, depth_ptr, "depth")
+                .unwrap()
+                .into_int_value();
+            let depth = self
+                .builder
+                .build_int_sub(depth, i32_type.const_int(1, false), "depth.prev")
+                .unwrap();
+            self.builder.build_store(depth_ptr, depth).unwrap();
+        }
+        match value {
+            Some(value) => self.builder.build_return(Some(&value)).unwrap(),
+            None => self.builder.build_return(None).unwrap(),
+        };
+    }
+
+    /// Declares the statement counters of a coverage build and the function
+    /// that writes them to `file`.
+    fn declare_coverage(&self, program: &Program, file: &str) -> Coverage<'ctx> {
+        let statements = coverage::statements(program);
+        let len = statements.len() as u32;
+        let array_type = self.context.i64_type().array_type(len);
+        let counters = self
+            .module
+            .add_global(array_type, None, "virtuc.coverage.counters");
+        counters.set_linkage(Linkage::Internal);
+        counters.set_initializer(&array_type.const_zero());
+        Coverage {
+            counters,
+            len,
+            indices: statements
+                .into_iter()
+                .enumerate()
+                .map(|(i, stmt)| (stmt, i as u32))
+                .collect(),
+            dump: runtime::coverage_dump(self.context, &self.module, counters, len, file),
+        }
+    }
+
+    /// Increments the coverage counter of a statement, if it has one.
+    fn count_statement(&self, stmt: StmtId) {
+        let Some(coverage) = &self.coverage else {
+            return;
+        };
+        let Some(&index) = coverage.indices.get(&(self.function, stmt)) else {
+            return;
+        };
+        let i64_type = self.context.i64_type();
+        let counter = unsafe {
+            self.builder.build_in_bounds_gep(
+                i64_type.array_type(coverage.len),
+                coverage.counters.as_pointer_value(),
+                &[
+                    i64_type.const_zero(),
+                    i64_type.const_int(index as u64, false),
+                ],
+                "counter",
+            )
+        }
+        .unwrap();
+        let count = self
+            .builder
+            .build_load(i64_type, counter, "count")
+            .unwrap()
+            .into_int_value();
+        let count = self
+            .builder
+            .build_int_add(count, i64_type.const_int(1, false), "count.next")
+            .unwrap();
+        self.builder.build_store(counter, count).unwrap();
     }
 
     /// Generates a statement.
-    fn generate_stmt(&mut self, stmt: &Stmt) -> Result<(), CodegenError> {
+    fn generate_stmt(&mut self, arena: &Arena, id: StmtId) -> Result<(), CodegenError> {
+        self.count_statement(id);
+        let stmt = &arena[id];
         match stmt {
             Stmt::Declaration { ty, name, init } => {
                 let llvm_ty = self.llvm_type(*ty);
-                let alloca = self.builder.build_alloca(llvm_ty, name).unwrap();
-                self.variables.insert(name.clone(), (alloca, *ty));
+                let alloca = self.builder.build_alloca(llvm_ty, name.as_str()).unwrap();
+                let symbol = self
+                    .resolution
+                    .declaration(self.function, id)
+                    .ok_or_else(|| {
+                        CodegenError::new(format!("Unresolved declaration: {}", name))
+                    })?;
+                self.variables.insert(symbol, (alloca, *ty));
                 if let Some(expr) = init {
-                    let value = self.generate_expr(expr)?;
+                    let value = self.generate_expr(arena, *expr)?;
                     self.builder.build_store(alloca, value).unwrap();
                 }
             }
             Stmt::Return(expr) => {
-                if let Some(e) = expr {
-                    let value = self.generate_expr(e)?;
-                    self.builder.build_return(Some(&value)).unwrap();
-                } else {
-                    self.builder.build_return(None).unwrap();
-                }
+                let value = expr.map(|e| self.generate_expr(arena, e)).transpose()?;
+                self.build_return(value);
             }
             Stmt::Block(stmts) => {
                 for stmt in stmts {
-                    self.generate_stmt(stmt)?;
+                    self.generate_stmt(arena, *stmt)?;
                 }
             }
             Stmt::If { cond, then, else_ } => {
-                let cond_value = self.generate_expr(cond)?;
+                let cond_value = self.generate_expr(arena, *cond)?;
                 let cond_bool = if cond_value.get_type().is_int_type() {
                     self.builder
                         .build_int_compare(
                             IntPredicate::NE,
                             cond_value.into_int_value(),
-                            self.context.i64_type().const_zero(),
+                            self.int_type().const_zero(),
                             "cond",
                         )
                         .unwrap()
                 } else {
-                    return Err(CodegenError("Non-integer condition".to_string()));
+                    return Err(CodegenError::new("Non-integer condition".to_string()));
                 };
 
                 let current_fn = self
@@ -215,7 +671,7 @@ impl<'ctx> CodeGenerator<'ctx> {
 
                 // Then block
                 self.builder.position_at_end(then_block);
-                self.generate_stmt(then)?;
+                self.generate_stmt(arena, *then)?;
                 if self
                     .builder
                     .get_insert_block()
@@ -231,7 +687,7 @@ impl<'ctx> CodeGenerator<'ctx> {
                 // Else block
                 self.builder.position_at_end(else_block);
                 if let Some(else_stmt) = else_ {
-                    self.generate_stmt(else_stmt)?;
+                    self.generate_stmt(arena, *else_stmt)?;
                 }
                 if self
                     .builder
@@ -290,7 +746,7 @@ impl<'ctx> CodeGenerator<'ctx> {
 
                 // Step 1: Generate initialization statement (executes once before loop)
                 if let Some(init_stmt) = init {
-                    self.generate_stmt(init_stmt)?;
+                    self.generate_stmt(arena, *init_stmt)?;
                 }
 
                 let current_fn = self
@@ -300,10 +756,13 @@ impl<'ctx> CodeGenerator<'ctx> {
                     .get_parent()
                     .unwrap();
 
-                // Step 2: Create four basic blocks for the loop structure
+                // Step 2: Create the basic blocks for the loop structure; the
+                // update block only exists when there is an update clause
                 let cond_block = self.context.append_basic_block(current_fn, "loop.cond");
                 let body_block = self.context.append_basic_block(current_fn, "loop.body");
-                let update_block = self.context.append_basic_block(current_fn, "loop.update");
+                let update_block = update
+                    .as_ref()
+                    .map(|_| self.context.append_basic_block(current_fn, "loop.update"));
                 let after_loop = self.context.append_basic_block(current_fn, "loop.end");
 
                 // Step 3: Branch from initialization to condition check
@@ -313,19 +772,21 @@ impl<'ctx> CodeGenerator<'ctx> {
                 // This block is entered at the start of each iteration to check if loop should continue
                 self.builder.position_at_end(cond_block);
                 if let Some(cond_expr) = cond {
-                    let cond_value = self.generate_expr(cond_expr)?;
+                    let cond_value = self.generate_expr(arena, *cond_expr)?;
                     // Convert condition to boolean (non-zero = true)
                     let cond_bool = if cond_value.get_type().is_int_type() {
                         self.builder
                             .build_int_compare(
                                 IntPredicate::NE,
                                 cond_value.into_int_value(),
-                                self.context.i64_type().const_zero(),
+                                self.int_type().const_zero(),
                                 "loop.cond.bool",
                             )
                             .unwrap()
                     } else {
-                        return Err(CodegenError("Loop condition must be integer".to_string()));
+                        return Err(CodegenError::new(
+                            "Loop condition must be integer".to_string(),
+                        ));
                     };
                     // Conditional branch: if true go to body, if false exit loop
                     self.builder
@@ -339,7 +800,7 @@ impl<'ctx> CodeGenerator<'ctx> {
                 // Step 5: Generate body block
                 // Executes loop statements
                 self.builder.position_at_end(body_block);
-                self.generate_stmt(body)?;
+                self.generate_stmt(arena, *body)?;
                 // After body, if no early exit (return/break), continue to update or condition
                 if self
                     .builder
@@ -348,22 +809,18 @@ impl<'ctx> CodeGenerator<'ctx> {
                     .get_terminator()
                     .is_none()
                 {
-                    if update.is_some() {
-                        // If update exists, go to update block
-                        self.builder
-                            .build_unconditional_branch(update_block)
-                            .unwrap();
-                    } else {
-                        // Otherwise, loop back to condition
-                        self.builder.build_unconditional_branch(cond_block).unwrap();
-                    }
+                    // Go to the update block if it exists, otherwise loop back
+                    // to the condition
+                    self.builder
+                        .build_unconditional_branch(update_block.unwrap_or(cond_block))
+                        .unwrap();
                 }
 
                 // Step 6: Generate update block (if exists)
                 // Executes update expression at end of each iteration
-                if let Some(update_expr) = update {
+                if let (Some(update_expr), Some(update_block)) = (update, update_block) {
                     self.builder.position_at_end(update_block);
-                    self.generate_expr(update_expr)?;
+                    self.generate_expr(arena, *update_expr)?;
                     // After update, jump back to condition to check if loop continues
                     self.builder.build_unconditional_branch(cond_block).unwrap();
                 }
@@ -371,363 +828,597 @@ impl<'ctx> CodeGenerator<'ctx> {
                 // Step 7: Continue code generation after the loop
                 self.builder.position_at_end(after_loop);
             }
+            Stmt::Expr(_) if self.assert_builtin && assertions::assertion(arena, id).is_some() => {
+                if self.check_assertions {
+                    let (cond, message) = assertions::assertion(arena, id).unwrap();
+                    self.generate_assertion(arena, cond, message)?;
+                }
+            }
             Stmt::Expr(expr) => {
-                self.generate_expr(expr)?;
+                self.generate_expr(arena, *expr)?;
             }
+            Stmt::Empty => {}
+        }
+        Ok(())
+    }
+
+    /// Generates a check that aborts with a message when `cond` is zero.
+    fn generate_assertion(
+        &mut self,
+        arena: &Arena,
+        cond: ExprId,
+        message: Option<&str>,
+    ) -> Result<(), CodegenError> {
+        let cond_value = self.generate_expr(arena, cond)?;
+        if !cond_value.is_int_value() {
+            return Err(CodegenError::new("Non-integer condition".to_string()));
         }
+        let failed = self
+            .builder
+            .build_int_compare(
+                IntPredicate::EQ,
+                cond_value.into_int_value(),
+                self.int_type().const_zero(),
+                "assert.failed",
+            )
+            .unwrap();
+
+        let current_fn = self
+            .builder
+            .get_insert_block()
+            .unwrap()
+            .get_parent()
+            .unwrap();
+        let fail_block = self.context.append_basic_block(current_fn, "assert.fail");
+        let ok_block = self.context.append_basic_block(current_fn, "assert.ok");
+        self.builder
+            .build_conditional_branch(failed, fail_block, ok_block)
+            .unwrap();
+
+        // Report the failed assertion on stderr and abort
+        self.builder.position_at_end(fail_block);
+        let message =
+            message.map_or_else(|| assertions::message(arena, cond, None), str::to_string);
+        let message = self
+            .builder
+            .build_global_string_ptr(&message, "assert.message")
+            .map_err(|e| CodegenError::new(format!("Builder error: {:?}", e)))?;
+        let abort = runtime::abort(self.context, &self.module);
+        self.builder
+            .build_call(abort, &[message.as_pointer_value().into()], "")
+            .unwrap();
+        self.builder.build_unreachable().unwrap();
+
+        self.builder.position_at_end(ok_block);
         Ok(())
     }
 
+    /// Generates a binary operation on two evaluated operands.
+    fn generate_binary(
+        &mut self,
+        op: BinOp,
+        left_val: BasicValueEnum<'ctx>,
+        right_val: BasicValueEnum<'ctx>,
+    ) -> Result<BasicValueEnum<'ctx>, CodegenError> {
+        // Fold constants as semantic analysis evaluated them
+        if let (Some(l), Some(r)) = (self.const_value(left_val), self.const_value(right_val))
+            && let Ok(value) = consteval::eval_binary(op, l, r, self.int_width)
+        {
+            return Ok(match value {
+                ConstValue::Int(n) => self.int_type().const_int(n as u64, true).into(),
+                ConstValue::Float(f) => self.context.f64_type().const_float(f).into(),
+            });
+        }
+        match op {
+            BinOp::Plus => {
+                if left_val.get_type().is_int_type() {
+                    Ok(self
+                        .builder
+                        .build_int_add(left_val.into_int_value(), right_val.into_int_value(), "add")
+                        .unwrap()
+                        .into())
+                } else {
+                    let value = self
+                        .builder
+                        .build_float_add(
+                            left_val.into_float_value(),
+                            right_val.into_float_value(),
+                            "fadd",
+                        )
+                        .unwrap();
+                    self.relax_float_math(value.as_instruction());
+                    Ok(value.into())
+                }
+            }
+            BinOp::Minus => {
+                if left_val.get_type().is_int_type() {
+                    Ok(self
+                        .builder
+                        .build_int_sub(left_val.into_int_value(), right_val.into_int_value(), "sub")
+                        .unwrap()
+                        .into())
+                } else {
+                    let value = self
+                        .builder
+                        .build_float_sub(
+                            left_val.into_float_value(),
+                            right_val.into_float_value(),
+                            "fsub",
+                        )
+                        .unwrap();
+                    self.relax_float_math(value.as_instruction());
+                    Ok(value.into())
+                }
+            }
+            BinOp::Multiply => {
+                if left_val.get_type().is_int_type() {
+                    Ok(self
+                        .builder
+                        .build_int_mul(left_val.into_int_value(), right_val.into_int_value(), "mul")
+                        .unwrap()
+                        .into())
+                } else {
+                    let value = self
+                        .builder
+                        .build_float_mul(
+                            left_val.into_float_value(),
+                            right_val.into_float_value(),
+                            "fmul",
+                        )
+                        .unwrap();
+                    self.relax_float_math(value.as_instruction());
+                    Ok(value.into())
+                }
+            }
+            BinOp::Divide => {
+                if left_val.get_type().is_int_type() {
+                    Ok(self
+                        .builder
+                        .build_int_signed_div(
+                            left_val.into_int_value(),
+                            right_val.into_int_value(),
+                            "div",
+                        )
+                        .unwrap()
+                        .into())
+                } else {
+                    let value = self
+                        .builder
+                        .build_float_div(
+                            left_val.into_float_value(),
+                            right_val.into_float_value(),
+                            "fdiv",
+                        )
+                        .unwrap();
+                    self.relax_float_math(value.as_instruction());
+                    Ok(value.into())
+                }
+            }
+            BinOp::Equal => {
+                if left_val.get_type().is_int_type() {
+                    let cmp = self
+                        .builder
+                        .build_int_compare(
+                            IntPredicate::EQ,
+                            left_val.into_int_value(),
+                            right_val.into_int_value(),
+                            "eq",
+                        )
+                        .unwrap();
+                    Ok(self
+                        .builder
+                        .build_int_z_extend(cmp, self.int_type(), "bool_ext")
+                        .unwrap()
+                        .into())
+                } else {
+                    let cmp = self
+                        .builder
+                        .build_float_compare(
+                            FloatPredicate::OEQ,
+                            left_val.into_float_value(),
+                            right_val.into_float_value(),
+                            "feq",
+                        )
+                        .unwrap();
+                    self.relax_float_math(cmp.as_instruction());
+                    Ok(self
+                        .builder
+                        .build_int_z_extend(cmp, self.int_type(), "bool_ext")
+                        .unwrap()
+                        .into())
+                }
+            }
+            BinOp::NotEqual => {
+                if left_val.get_type().is_int_type() {
+                    let cmp = self
+                        .builder
+                        .build_int_compare(
+                            IntPredicate::NE,
+                            left_val.into_int_value(),
+                            right_val.into_int_value(),
+                            "ne",
+                        )
+                        .unwrap();
+                    Ok(self
+                        .builder
+                        .build_int_z_extend(cmp, self.int_type(), "bool_ext")
+                        .unwrap()
+                        .into())
+                } else {
+                    let cmp = self
+                        .builder
+                        .build_float_compare(
+                            FloatPredicate::ONE,
+                            left_val.into_float_value(),
+                            right_val.into_float_value(),
+                            "fne",
+                        )
+                        .unwrap();
+                    self.relax_float_math(cmp.as_instruction());
+                    Ok(self
+                        .builder
+                        .build_int_z_extend(cmp, self.int_type(), "bool_ext")
+                        .unwrap()
+                        .into())
+                }
+            }
+            BinOp::LessThan => {
+                if left_val.get_type().is_int_type() {
+                    let cmp = self
+                        .builder
+                        .build_int_compare(
+                            IntPredicate::SLT,
+                            left_val.into_int_value(),
+                            right_val.into_int_value(),
+                            "lt",
+                        )
+                        .unwrap();
+                    Ok(self
+                        .builder
+                        .build_int_z_extend(cmp, self.int_type(), "bool_ext")
+                        .unwrap()
+                        .into())
+                } else {
+                    let cmp = self
+                        .builder
+                        .build_float_compare(
+                            FloatPredicate::OLT,
+                            left_val.into_float_value(),
+                            right_val.into_float_value(),
+                            "flt",
+                        )
+                        .unwrap();
+                    self.relax_float_math(cmp.as_instruction());
+                    Ok(self
+                        .builder
+                        .build_int_z_extend(cmp, self.int_type(), "bool_ext")
+                        .unwrap()
+                        .into())
+                }
+            }
+            BinOp::GreaterThan => {
+                if left_val.get_type().is_int_type() {
+                    let cmp = self
+                        .builder
+                        .build_int_compare(
+                            IntPredicate::SGT,
+                            left_val.into_int_value(),
+                            right_val.into_int_value(),
+                            "gt",
+                        )
+                        .unwrap();
+                    Ok(self
+                        .builder
+                        .build_int_z_extend(cmp, self.int_type(), "bool_ext")
+                        .unwrap()
+                        .into())
+                } else {
+                    let cmp = self
+                        .builder
+                        .build_float_compare(
+                            FloatPredicate::OGT,
+                            left_val.into_float_value(),
+                            right_val.into_float_value(),
+                            "fgt",
+                        )
+                        .unwrap();
+                    self.relax_float_math(cmp.as_instruction());
+                    Ok(self
+                        .builder
+                        .build_int_z_extend(cmp, self.int_type(), "bool_ext")
+                        .unwrap()
+                        .into())
+                }
+            }
+            BinOp::LessEqual => {
+                if left_val.get_type().is_int_type() {
+                    let cmp = self
+                        .builder
+                        .build_int_compare(
+                            IntPredicate::SLE,
+                            left_val.into_int_value(),
+                            right_val.into_int_value(),
+                            "le",
+                        )
+                        .unwrap();
+                    Ok(self
+                        .builder
+                        .build_int_z_extend(cmp, self.int_type(), "bool_ext")
+                        .unwrap()
+                        .into())
+                } else {
+                    let cmp = self
+                        .builder
+                        .build_float_compare(
+                            FloatPredicate::OLE,
+                            left_val.into_float_value(),
+                            right_val.into_float_value(),
+                            "fle",
+                        )
+                        .unwrap();
+                    self.relax_float_math(cmp.as_instruction());
+                    Ok(self
+                        .builder
+                        .build_int_z_extend(cmp, self.int_type(), "bool_ext")
+                        .unwrap()
+                        .into())
+                }
+            }
+            BinOp::GreaterEqual => {
+                if left_val.get_type().is_int_type() {
+                    let cmp = self
+                        .builder
+                        .build_int_compare(
+                            IntPredicate::SGE,
+                            left_val.into_int_value(),
+                            right_val.into_int_value(),
+                            "ge",
+                        )
+                        .unwrap();
+                    Ok(self
+                        .builder
+                        .build_int_z_extend(cmp, self.int_type(), "bool_ext")
+                        .unwrap()
+                        .into())
+                } else {
+                    let cmp = self
+                        .builder
+                        .build_float_compare(
+                            FloatPredicate::OGE,
+                            left_val.into_float_value(),
+                            right_val.into_float_value(),
+                            "fge",
+                        )
+                        .unwrap();
+                    self.relax_float_math(cmp.as_instruction());
+                    Ok(self
+                        .builder
+                        .build_int_z_extend(cmp, self.int_type(), "bool_ext")
+                        .unwrap()
+                        .into())
+                }
+            }
+        }
+    }
+
     /// Generates an expression.
-    fn generate_expr(&mut self, expr: &Expr) -> Result<BasicValueEnum<'ctx>, CodegenError> {
+    fn generate_expr(
+        &mut self,
+        arena: &Arena,
+        id: ExprId,
+    ) -> Result<BasicValueEnum<'ctx>, CodegenError> {
+        let expr = &arena[id];
         match expr {
             Expr::Literal(lit) => match lit {
-                Literal::Int(n) => Ok(self.context.i64_type().const_int(*n as u64, false).into()),
+                Literal::Int(n) => Ok(self.int_type().const_int(*n as u64, false).into()),
                 Literal::Float(f) => Ok(self.context.f64_type().const_float(*f).into()),
                 Literal::String(s) => {
                     let global = self
                         .builder
                         .build_global_string_ptr(s, "str")
-                        .map_err(|e| CodegenError(format!("Builder error: {:?}", e)))?;
+                        .map_err(|e| CodegenError::new(format!("Builder error: {:?}", e)))?;
                     Ok(global.as_pointer_value().into())
                 }
             },
             Expr::Identifier(name) => {
-                if let Some((ptr, ty)) = self.variables.get(name) {
-                    Ok(self
-                        .builder
-                        .build_load(self.llvm_type(*ty), *ptr, name)
-                        .unwrap())
-                } else {
-                    Err(CodegenError(format!("Undefined variable: {}", name)))
+                let (ptr, ty) = self.variable(id, name.as_str())?;
+                Ok(self
+                    .builder
+                    .build_load(self.llvm_type(ty), ptr, name.as_str())
+                    .unwrap())
+            }
+            Expr::Binary { .. } => {
+                // Walk a chain such as `a + b + c` down its left operands
+                // instead of recursing, so that long chains fit on the stack
+                let mut operands = Vec::new();
+                let mut leftmost = id;
+                while let Expr::Binary { left, op, right } = &arena[leftmost] {
+                    operands.push((*op, *right));
+                    leftmost = *left;
+                }
+                let mut value = self.generate_expr(arena, leftmost)?;
+                for (op, right) in operands.into_iter().rev() {
+                    let right_val = self.generate_expr(arena, right)?;
+                    value = self.generate_binary(op, value, right_val)?;
                 }
+                Ok(value)
             }
-            Expr::Binary { left, op, right } => {
-                let left_val = self.generate_expr(left)?;
-                let right_val = self.generate_expr(right)?;
-                match op {
-                    BinOp::Plus => {
-                        if left_val.get_type().is_int_type() {
-                            Ok(self
-                                .builder
-                                .build_int_add(
-                                    left_val.into_int_value(),
-                                    right_val.into_int_value(),
-                                    "add",
-                                )
-                                .unwrap()
-                                .into())
-                        } else {
-                            Ok(self
-                                .builder
-                                .build_float_add(
-                                    left_val.into_float_value(),
-                                    right_val.into_float_value(),
-                                    "fadd",
-                                )
-                                .unwrap()
-                                .into())
-                        }
-                    }
-                    BinOp::Minus => {
-                        if left_val.get_type().is_int_type() {
-                            Ok(self
-                                .builder
-                                .build_int_sub(
-                                    left_val.into_int_value(),
-                                    right_val.into_int_value(),
-                                    "sub",
-                                )
-                                .unwrap()
-                                .into())
-                        } else {
-                            Ok(self
-                                .builder
-                                .build_float_sub(
-                                    left_val.into_float_value(),
-                                    right_val.into_float_value(),
-                                    "fsub",
-                                )
-                                .unwrap()
-                                .into())
-                        }
-                    }
-                    BinOp::Multiply => {
-                        if left_val.get_type().is_int_type() {
-                            Ok(self
-                                .builder
-                                .build_int_mul(
-                                    left_val.into_int_value(),
-                                    right_val.into_int_value(),
-                                    "mul",
-                                )
-                                .unwrap()
-                                .into())
-                        } else {
-                            Ok(self
-                                .builder
-                                .build_float_mul(
-                                    left_val.into_float_value(),
-                                    right_val.into_float_value(),
-                                    "fmul",
-                                )
-                                .unwrap()
-                                .into())
-                        }
-                    }
-                    BinOp::Divide => {
-                        if left_val.get_type().is_int_type() {
-                            Ok(self
-                                .builder
-                                .build_int_signed_div(
-                                    left_val.into_int_value(),
-                                    right_val.into_int_value(),
-                                    "div",
-                                )
-                                .unwrap()
-                                .into())
-                        } else {
-                            Ok(self
-                                .builder
-                                .build_float_div(
-                                    left_val.into_float_value(),
-                                    right_val.into_float_value(),
-                                    "fdiv",
-                                )
-                                .unwrap()
-                                .into())
-                        }
-                    }
-                    BinOp::Equal => {
-                        if left_val.get_type().is_int_type() {
-                            let cmp = self
-                                .builder
-                                .build_int_compare(
-                                    IntPredicate::EQ,
-                                    left_val.into_int_value(),
-                                    right_val.into_int_value(),
-                                    "eq",
-                                )
-                                .unwrap();
-                            Ok(self
-                                .builder
-                                .build_int_z_extend(cmp, self.context.i64_type(), "bool_ext")
-                                .unwrap()
-                                .into())
-                        } else {
-                            let cmp = self
-                                .builder
-                                .build_float_compare(
-                                    FloatPredicate::OEQ,
-                                    left_val.into_float_value(),
-                                    right_val.into_float_value(),
-                                    "feq",
-                                )
-                                .unwrap();
-                            Ok(self
-                                .builder
-                                .build_int_z_extend(cmp, self.context.i64_type(), "bool_ext")
-                                .unwrap()
-                                .into())
-                        }
-                    }
-                    BinOp::NotEqual => {
-                        if left_val.get_type().is_int_type() {
-                            let cmp = self
-                                .builder
-                                .build_int_compare(
-                                    IntPredicate::NE,
-                                    left_val.into_int_value(),
-                                    right_val.into_int_value(),
-                                    "ne",
-                                )
-                                .unwrap();
-                            Ok(self
-                                .builder
-                                .build_int_z_extend(cmp, self.context.i64_type(), "bool_ext")
-                                .unwrap()
-                                .into())
-                        } else {
-                            let cmp = self
-                                .builder
-                                .build_float_compare(
-                                    FloatPredicate::ONE,
-                                    left_val.into_float_value(),
-                                    right_val.into_float_value(),
-                                    "fne",
-                                )
-                                .unwrap();
-                            Ok(self
-                                .builder
-                                .build_int_z_extend(cmp, self.context.i64_type(), "bool_ext")
-                                .unwrap()
-                                .into())
-                        }
-                    }
-                    BinOp::LessThan => {
-                        if left_val.get_type().is_int_type() {
-                            let cmp = self
-                                .builder
-                                .build_int_compare(
-                                    IntPredicate::SLT,
-                                    left_val.into_int_value(),
-                                    right_val.into_int_value(),
-                                    "lt",
-                                )
-                                .unwrap();
-                            Ok(self
-                                .builder
-                                .build_int_z_extend(cmp, self.context.i64_type(), "bool_ext")
-                                .unwrap()
-                                .into())
-                        } else {
-                            let cmp = self
-                                .builder
-                                .build_float_compare(
-                                    FloatPredicate::OLT,
-                                    left_val.into_float_value(),
-                                    right_val.into_float_value(),
-                                    "flt",
-                                )
-                                .unwrap();
-                            Ok(self
-                                .builder
-                                .build_int_z_extend(cmp, self.context.i64_type(), "bool_ext")
-                                .unwrap()
-                                .into())
-                        }
-                    }
-                    BinOp::GreaterThan => {
-                        if left_val.get_type().is_int_type() {
-                            let cmp = self
-                                .builder
-                                .build_int_compare(
-                                    IntPredicate::SGT,
-                                    left_val.into_int_value(),
-                                    right_val.into_int_value(),
-                                    "gt",
-                                )
-                                .unwrap();
-                            Ok(self
-                                .builder
-                                .build_int_z_extend(cmp, self.context.i64_type(), "bool_ext")
-                                .unwrap()
-                                .into())
-                        } else {
-                            let cmp = self
-                                .builder
-                                .build_float_compare(
-                                    FloatPredicate::OGT,
-                                    left_val.into_float_value(),
-                                    right_val.into_float_value(),
-                                    "fgt",
-                                )
-                                .unwrap();
-                            Ok(self
-                                .builder
-                                .build_int_z_extend(cmp, self.context.i64_type(), "bool_ext")
-                                .unwrap()
-                                .into())
-                        }
-                    }
-                    BinOp::LessEqual => {
-                        if left_val.get_type().is_int_type() {
-                            let cmp = self
-                                .builder
-                                .build_int_compare(
-                                    IntPredicate::SLE,
-                                    left_val.into_int_value(),
-                                    right_val.into_int_value(),
-                                    "le",
-                                )
-                                .unwrap();
-                            Ok(self
-                                .builder
-                                .build_int_z_extend(cmp, self.context.i64_type(), "bool_ext")
-                                .unwrap()
-                                .into())
+            // Unless the program defines a function of the same name
+            Expr::Call { name, args }
+                if self.random_builtins
+                    && random::is_builtin(name.as_str())
+                    && self.module.get_function(name.as_str()).is_none() =>
+            {
+                self.generate_random_call(arena, name.as_str(), args)
+            }
+            Expr::Call { name, args } => {
+                let function = self
+                    .module
+                    .get_function(name.as_str())
+                    .ok_or_else(|| CodegenError::new(format!("Undefined function: {}", name)))?;
+                let fixed_params = function.count_params() as usize;
+                let is_variadic = function.get_type().is_var_arg();
+                let arg_values: Vec<BasicMetadataValueEnum> = args
+                    .iter()
+                    .enumerate()
+                    .map(|(i, arg)| {
+                        let value = self.generate_expr(arena, *arg)?;
+                        Ok(if is_variadic && i >= fixed_params {
+                            self.promote_variadic_arg(value)
                         } else {
-                            let cmp = self
-                                .builder
-                                .build_float_compare(
-                                    FloatPredicate::OLE,
-                                    left_val.into_float_value(),
-                                    right_val.into_float_value(),
-                                    "fle",
-                                )
-                                .unwrap();
-                            Ok(self
-                                .builder
-                                .build_int_z_extend(cmp, self.context.i64_type(), "bool_ext")
-                                .unwrap()
-                                .into())
+                            value
                         }
+                        .into())
+                    })
+                    .collect::<Result<_, CodegenError>>()?;
+                let call = self
+                    .builder
+                    .build_call(function, &arg_values, "call")
+                    .unwrap();
+                // Mirror the callee's int ABI attributes on the call site
+                if self.int_width == IntWidth::W32 {
+                    if function.get_type().get_return_type() == Some(self.int_type().into()) {
+                        call.add_attribute(AttributeLoc::Return, self.signext_attribute());
                     }
-                    BinOp::GreaterEqual => {
-                        if left_val.get_type().is_int_type() {
-                            let cmp = self
-                                .builder
-                                .build_int_compare(
-                                    IntPredicate::SGE,
-                                    left_val.into_int_value(),
-                                    right_val.into_int_value(),
-                                    "ge",
-                                )
-                                .unwrap();
-                            Ok(self
-                                .builder
-                                .build_int_z_extend(cmp, self.context.i64_type(), "bool_ext")
-                                .unwrap()
-                                .into())
-                        } else {
-                            let cmp = self
-                                .builder
-                                .build_float_compare(
-                                    FloatPredicate::OGE,
-                                    left_val.into_float_value(),
-                                    right_val.into_float_value(),
-                                    "fge",
-                                )
-                                .unwrap();
-                            Ok(self
-                                .builder
-                                .build_int_z_extend(cmp, self.context.i64_type(), "bool_ext")
-                                .unwrap()
-                                .into())
+                    for (i, value) in arg_values.iter().enumerate() {
+                        if let BasicMetadataValueEnum::IntValue(v) = value
+                            && v.get_type() == self.int_type()
+                        {
+                            call.add_attribute(
+                                AttributeLoc::Param(i as u32),
+                                self.signext_attribute(),
+                            );
                         }
                     }
                 }
+                call.try_as_basic_value().basic().ok_or_else(|| {
+                    CodegenError::new(format!("Function {} does not return a value", name))
+                })
             }
-            Expr::Call { name, args } => {
-                // For simplicity, assume function exists
-                let function = self.module.get_function(name).unwrap();
-                let arg_values: Vec<BasicMetadataValueEnum> = args
-                    .iter()
-                    .map(|arg| self.generate_expr(arg).map(|v| v.into()))
-                    .collect::<Result<_, _>>()?;
-                Ok(self
-                    .builder
-                    .build_call(function, &arg_values, "call")
-                    .unwrap()
-                    .try_as_basic_value()
-                    .unwrap_basic())
+            Expr::Assignment { target, value } => {
+                let val = self.generate_expr(arena, *value)?;
+                let ptr = self.generate_lvalue(arena, *target)?;
+                self.builder.build_store(ptr, val).unwrap();
+                Ok(val)
             }
-            Expr::Assignment { name, value } => {
-                let val = self.generate_expr(value)?;
-                if let Some((ptr, _)) = self.variables.get(name) {
-                    self.builder.build_store(*ptr, val).unwrap();
-                    Ok(val)
-                } else {
-                    Err(CodegenError(format!("Undefined variable: {}", name)))
-                }
+        }
+    }
+
+    /// Applies C's default argument promotions to an argument passed through
+    /// `...`: integers narrower than a C `int` are widened to one, and
+    /// single-precision floats to `double`. Values of the language's own
+    /// types are already promoted and pass through unchanged.
+    fn promote_variadic_arg(&self, value: BasicValueEnum<'ctx>) -> BasicValueEnum<'ctx> {
+        let c_int = self.context.i32_type();
+        match value {
+            // Booleans are 0 or 1, not 0 or -1
+            BasicValueEnum::IntValue(v) if v.get_type().get_bit_width() == 1 => self
+                .builder
+                .build_int_z_extend(v, c_int, "promoted")
+                .unwrap()
+                .into(),
+            BasicValueEnum::IntValue(v) if v.get_type().get_bit_width() < 32 => self
+                .builder
+                .build_int_s_extend(v, c_int, "promoted")
+                .unwrap()
+                .into(),
+            BasicValueEnum::FloatValue(v) if v.get_type() == self.context.f32_type() => self
+                .builder
+                .build_float_ext(v, self.context.f64_type(), "promoted")
+                .unwrap()
+                .into(),
+            other => other,
+        }
+    }
+
+    /// Generates a call to `rand_seeded` or `rand_next`, whose generator
+    /// works on 64 bits whatever the width of `int`.
+    fn generate_random_call(
+        &mut self,
+        arena: &Arena,
+        name: &str,
+        args: &[ExprId],
+    ) -> Result<BasicValueEnum<'ctx>, CodegenError> {
+        let i64_type = self.context.i64_type();
+        if name == random::SEED {
+            let [seed] = args else {
+                return Err(CodegenError::new(format!("{} takes one argument", name)));
+            };
+            let seed = self.generate_expr(arena, *seed)?.into_int_value();
+            let seed = self
+                .builder
+                .build_int_cast_sign_flag(seed, i64_type, true, "seed")
+                .unwrap();
+            let helper = runtime::rand_seed(self.context, &self.module);
+            self.builder.build_call(helper, &[seed.into()], "").unwrap();
+            Ok(self.int_type().const_zero().into())
+        } else {
+            let helper = runtime::rand_next(self.context, &self.module);
+            let next = self
+                .builder
+                .build_call(helper, &[], "rand")
+                .unwrap()
+                .try_as_basic_value()
+                .unwrap_basic()
+                .into_int_value();
+            // The number has 31 bits, so it fits either width
+            Ok(self
+                .builder
+                .build_int_cast_sign_flag(next, self.int_type(), false, "rand")
+                .unwrap()
+                .into())
+        }
+    }
+
+    /// Generates the address that an assignment target refers to.
+    fn generate_lvalue(
+        &mut self,
+        arena: &Arena,
+        id: ExprId,
+    ) -> Result<PointerValue<'ctx>, CodegenError> {
+        let expr = &arena[id];
+        match expr {
+            Expr::Identifier(name) => self.variable(id, name.as_str()).map(|(ptr, _)| ptr),
+            _ => Err(CodegenError::new(
+                "Assignment target is not assignable".to_string(),
+            )),
+        }
+    }
+
+    /// Returns the storage and type of the variable an identifier refers to.
+    fn variable(
+        &self,
+        expr: ExprId,
+        name: &str,
+    ) -> Result<(PointerValue<'ctx>, Type), CodegenError> {
+        self.resolution
+            .expr(self.function, expr)
+            .and_then(|symbol| self.variables.get(&symbol).copied())
+            .ok_or_else(|| CodegenError::new(format!("Undefined variable: {}", name)))
+    }
+
+    /// Returns the value of a constant `int` or `float`.
+    fn const_value(&self, value: BasicValueEnum<'ctx>) -> Option<ConstValue> {
+        match value {
+            BasicValueEnum::IntValue(v) if v.is_const() => {
+                v.get_sign_extended_constant().map(ConstValue::Int)
+            }
+            BasicValueEnum::FloatValue(v) if v.is_const() => {
+                v.get_constant().map(|(f, _)| ConstValue::Float(f))
             }
+            _ => None,
         }
     }
 
+    /// Returns the LLVM integer type used for `int`.
+    fn int_type(&self) -> IntType<'ctx> {
+        self.context.custom_width_int_type(self.int_width.bits())
+    }
+
     /// Maps C type to LLVM type.
     fn llvm_type(&self, ty: Type) -> BasicTypeEnum<'ctx> {
         match ty {
-            Type::Int => self.context.i64_type().into(),
+            Type::Int => self.int_type().into(),
             Type::Float => self.context.f64_type().into(),
             Type::String => self.context.ptr_type(AddressSpace::default()).into(),
         }
@@ -736,27 +1427,571 @@ impl<'ctx> CodeGenerator<'ctx> {
 
 /// Generates LLVM IR for the program.
 pub fn generate_ir(program: &Program) -> Result<String, CodegenError> {
+    generate_ir_with_options(program, &CompileOptions::default())
+}
+
+/// Generates LLVM IR for the program using the given compile options.
+///
+/// The program is first rewritten by the passes of [`optimize::pipeline`]:
+/// when [`CompileOptions::strips_unused`], unused functions are removed, and
+/// when `options.opt_level` is above zero, the program is simplified.
+pub fn generate_ir_with_options(
+    program: &Program,
+    options: &CompileOptions,
+) -> Result<String, CodegenError> {
+    let context = Context::create();
+    let module = generate_module(&context, program, options)?;
+    Ok(module.print_to_string().to_string())
+}
+
+/// Generates LLVM IR for the function `name` alone, without the rest of the
+/// module.
+///
+/// # Errors
+///
+/// Returns an error if the program does not define `name`, or if generating
+/// it fails.
+pub fn generate_function_ir(program: &Program, name: &str) -> Result<String, CodegenError> {
     let context = Context::create();
-    let mut generator = CodeGenerator::new(&context);
-    generator.generate(program)?;
-    Ok(generator.get_ir())
+    let module = generate_module_of(&context, program, &CompileOptions::default(), Some(&[name]))?;
+    let function = module
+        .get_function(name)
+        .ok_or_else(|| CodegenError::new(format!("Undefined function: {}", name)))?;
+    Ok(function.print_to_string().to_string())
+}
+
+/// Generates LLVM IR for the program as [`generate_ir_with_options`] does,
+/// but only for the functions in `names`. The other functions are declared
+/// without a body, so the module can be linked with one that defines them.
+///
+/// # Errors
+///
+/// Returns an error if the program does not define one of `names`, or if
+/// generating a function fails.
+pub fn generate_functions_ir(
+    program: &Program,
+    names: &[&str],
+    options: &CompileOptions,
+) -> Result<String, CodegenError> {
+    let context = Context::create();
+    let module = generate_module_of(&context, program, options, Some(names))?;
+    Ok(module.print_to_string().to_string())
+}
+
+/// Generates the LLVM module of the program in `context`, as
+/// [`generate_ir_with_options`] does.
+pub fn generate_module<'ctx>(
+    context: &'ctx Context,
+    program: &Program,
+    options: &CompileOptions,
+) -> Result<Module<'ctx>, CodegenError> {
+    generate_module_of(context, program, options, None)
+}
+
+/// Generates the LLVM module of the program in `context`, restricted to the
+/// functions in `only` if given.
+pub(crate) fn generate_module_of<'ctx>(
+    context: &'ctx Context,
+    program: &Program,
+    options: &CompileOptions,
+    only: Option<&[&str]>,
+) -> Result<Module<'ctx>, CodegenError> {
+    if let Some(name) = only
+        .unwrap_or_default()
+        .iter()
+        .find(|name| !program.functions.iter().any(|f| f.name == **name))
+    {
+        return Err(CodegenError::new(format!("Undefined function: {}", name)));
+    }
+    let program = &optimize::optimize_program(program, options);
+    let mut generator = CodeGenerator::with_options(context, options);
+    let include = |function: &Function| only.is_none_or(|names| names.contains(&&*function.name));
+    if let Err(mut e) = generator.generate_filtered(program, include) {
+        if options.dump_ir_on_error {
+            e.module_ir = Some(generator.module.print_to_string().to_string());
+        }
+        return Err(e);
+    }
+    Ok(generator.into_module())
+}
+
+/// Returns the target triple of the host, for which code is generated.
+pub fn host_triple() -> String {
+    TargetMachine::get_default_triple()
+        .as_str()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Returns a machine for the host target, generating position-independent
+/// code so that objects can be linked into any executable.
+fn host_target_machine(level: OptimizationLevel) -> Option<TargetMachine> {
+    let triple = TargetMachine::get_default_triple();
+    Target::from_triple(&triple).ok()?.create_target_machine(
+        &triple,
+        "generic",
+        "",
+        level,
+        RelocMode::PIC,
+        CodeModel::Default,
+    )
+}
+
+/// Returns the LLVM optimization level of `opt_level` (0 to 3).
+pub(crate) fn optimization_level(opt_level: u8) -> OptimizationLevel {
+    match opt_level {
+        0 => OptimizationLevel::None,
+        1 => OptimizationLevel::Less,
+        2 => OptimizationLevel::Default,
+        _ => OptimizationLevel::Aggressive,
+    }
+}
+
+/// Returns a machine for the host target at `opt_level`, initializing it
+/// first.
+fn native_target_machine(opt_level: u8) -> Result<TargetMachine, CodegenError> {
+    Target::initialize_native(&InitializationConfig::default()).ok();
+    host_target_machine(optimization_level(opt_level))
+        .ok_or_else(|| CodegenError::new("The host target is not supported".to_string()))
+}
+
+/// Runs LLVM's optimization pipeline for `options.opt_level` on a module,
+/// as the C compiler does when it builds an executable from IR. Loops are
+/// unrolled and vectorized as [`CompileOptions::unrolls_loops`] and
+/// [`CompileOptions::vectorizes`] say. With [`CompileOptions::lto`], only
+/// the part of the pipeline before link-time optimization runs. Does nothing
+/// at `-O0`.
+pub fn optimize_module(module: &Module, options: &CompileOptions) -> Result<(), CodegenError> {
+    if options.opt_level == 0 {
+        return Ok(());
+    }
+    let machine = native_target_machine(options.opt_level)?;
+    let pass_options = PassBuilderOptions::create();
+    pass_options.set_loop_unrolling(options.unrolls_loops());
+    pass_options.set_loop_vectorization(options.vectorizes());
+    pass_options.set_loop_slp_vectorization(options.vectorizes());
+    pass_options.set_loop_interleaving(options.unrolls_loops());
+    let pipeline = format!(
+        "{}<O{}>",
+        if options.lto {
+            "lto-pre-link"
+        } else {
+            "default"
+        },
+        options.opt_level.min(3)
+    );
+    module
+        .run_passes(&pipeline, &machine, pass_options)
+        .map_err(|e| {
+            CodegenError::new(format!(
+                "Optimizing the module failed: {}",
+                e.to_string_lossy()
+            ))
+        })
+}
+
+/// Writes a module as an object file for the host, optimized at
+/// `opt_level` (0 to 3).
+pub fn write_object(module: &Module, path: &Path, opt_level: u8) -> Result<(), CodegenError> {
+    let machine = native_target_machine(opt_level)?;
+    machine
+        .write_to_file(module, FileType::Object, path)
+        .map_err(|e| {
+            CodegenError::new(format!(
+                "Writing object file failed: {}",
+                e.to_string_lossy()
+            ))
+        })
+}
+
+/// Writes a module as LLVM bitcode, for link-time optimization.
+pub fn write_bitcode(module: &Module, path: &Path) -> Result<(), CodegenError> {
+    if module.write_bitcode_to_path(path) {
+        Ok(())
+    } else {
+        Err(CodegenError::new(format!(
+            "Writing bitcode to {} failed",
+            path.display()
+        )))
+    }
+}
+
+/// Adds an embedded build record to a module, in the section
+/// [`embed::section`] names for its target. The record is marked used, so
+/// that it survives optimization.
+pub fn embed(module: &Module, record: &Embedded) {
+    let context = module.get_context();
+    let data = context.const_string(&record.encode(), false);
+    let global = module.add_global(data.get_type(), None, "virtuc.embedded");
+    global.set_initializer(&data);
+    global.set_constant(true);
+    global.set_linkage(Linkage::Private);
+    global.set_alignment(1);
+    let triple = module.get_triple();
+    global.set_section(Some(embed::section(&triple.as_str().to_string_lossy())));
+
+    let ptr_type = context.ptr_type(AddressSpace::default());
+    let used = module.add_global(ptr_type.array_type(1), None, "llvm.used");
+    used.set_linkage(Linkage::Appending);
+    used.set_section(Some("llvm.metadata"));
+    used.set_initializer(&ptr_type.const_array(&[global.as_pointer_value()]));
+}
+
+/// Links modules of the same context into one, as `llvm-link` does.
+///
+/// Each external function may be defined by one module only; the others
+/// declare it. Internal definitions, such as the runtime helpers, are
+/// renamed apart.
+pub fn link_modules<'ctx>(modules: Vec<Module<'ctx>>) -> Result<Module<'ctx>, CodegenError> {
+    let mut modules = modules.into_iter();
+    let linked = modules
+        .next()
+        .ok_or_else(|| CodegenError::new("No modules to link".to_string()))?;
+    for module in modules {
+        linked.link_in_module(module).map_err(|e| {
+            CodegenError::new(format!("Linking modules failed: {}", e.to_string_lossy()))
+        })?;
+    }
+    Ok(linked)
+}
+
+/// Normalizes textual IR for snapshot comparisons.
+///
+/// Module-level lines that depend on the host or the compiler version
+/// (module ID, source file name, target triple, data layout, and the
+/// `llvm.ident` metadata) are dropped. Local values and block labels are
+/// renamed `%v0`, `%v1`, ... in order of appearance within each function,
+/// and `; preds` comments are removed, so that renaming a temporary does not
+/// change the snapshot.
+pub fn normalize_ir(ir: &str) -> String {
+    let mut out = String::new();
+    let mut names: HashMap<String, String> = HashMap::new();
+    for line in ir.lines() {
+        if line.starts_with("; ModuleID")
+            || line.starts_with("source_filename")
+            || line.starts_with("target triple")
+            || line.starts_with("target datalayout")
+            || line.starts_with("!llvm.ident")
+            || line.contains("!\"virtuc version")
+            || (line.is_empty() && out.is_empty())
+        {
+            continue;
+        }
+        if line.starts_with("define ") {
+            names.clear();
+        }
+        // Globals may contain '%' in string constants
+        if line.starts_with('@') {
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        }
+        let mut rest = line;
+        // A block label definition, e.g. `entry:` or `for.cond:  ; preds = %0`
+        if let Some((label, tail)) = line.split_once(':')
+            && !label.is_empty()
+            && label.chars().all(is_ir_name_char)
+        {
+            let renamed = rename_local(&mut names, label);
+            out.push_str(&renamed);
+            out.push(':');
+            rest = if tail.trim_start().starts_with("; preds") {
+                ""
+            } else {
+                tail
+            };
+        }
+        while let Some(start) = rest.find('%') {
+            out.push_str(&rest[..=start]);
+            rest = &rest[start + 1..];
+            let end = rest.find(|c| !is_ir_name_char(c)).unwrap_or(rest.len());
+            if end > 0 {
+                out.push_str(&rename_local(&mut names, &rest[..end]));
+                rest = &rest[end..];
+            }
+        }
+        out.push_str(rest);
+        out.push('\n');
+    }
+    format!("{}\n", out.trim_end())
+}
+
+fn is_ir_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '$' | '-')
+}
+
+/// Returns the canonical name of a local, assigning the next one if unseen.
+fn rename_local(names: &mut HashMap<String, String>, name: &str) -> String {
+    let next = format!("v{}", names.len());
+    names.entry(name.to_string()).or_insert(next).clone()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::builder::{FnBuilder, ProgramBuilder, expr, stmt};
+    use crate::ir;
     use crate::lexer::lex;
     use crate::parser::parse;
 
     #[test]
     fn test_generate_simple_function() {
-        let input = "int add(int a, int b) { return a + b; }";
-        let tokens = lex(input).unwrap();
-        let ast = parse(&tokens).unwrap();
+        let add = FnBuilder::new("add", Type::Int)
+            .param(Type::Int, "a")
+            .param(Type::Int, "b")
+            .stmt(stmt::ret(expr::add(expr::ident("a"), expr::ident("b"))))
+            .build();
+        let ast = ProgramBuilder::new().function(add).build();
+        let module = ir::parse(&generate_ir(&ast).unwrap()).unwrap();
+        let add = module.function("add").unwrap();
+        assert_eq!(add.return_type, "i64");
+        assert_eq!(add.params, ["i64", "i64"]);
+        assert_eq!(add.count("add"), 1);
+        assert_eq!(add.count("ret"), 1);
+    }
+
+    #[test]
+    fn test_generate_32_bit_int() {
+        let f = FnBuilder::new("f", Type::Int)
+            .param(Type::Int, "a")
+            .stmt(stmt::ret(expr::add(
+                expr::call("abs", [expr::ident("a")]),
+                expr::int(1),
+            )))
+            .build();
+        let ast = ProgramBuilder::new()
+            .extern_function("abs", Type::Int, [Type::Int], false)
+            .function(f)
+            .build();
+        let options = CompileOptions {
+            int_width: IntWidth::W32,
+            ..CompileOptions::default()
+        };
+        let ir = generate_ir_with_options(&ast, &options).unwrap();
+        assert!(ir.contains("declare signext i32 @abs(i32 signext)"));
+        assert!(ir.contains("define signext i32 @f(i32 signext %0)"));
+        assert!(ir.contains("add i32"));
+    }
+
+    #[test]
+    fn test_fold_constant_expressions() {
+        let f = FnBuilder::new("f", Type::Int)
+            .stmt(stmt::ret(expr::sub(
+                expr::mul(expr::int(6), expr::int(7)),
+                expr::div(expr::int(100), expr::int(2)),
+            )))
+            .build();
+        let ast = ProgramBuilder::new().function(f).build();
         let ir = generate_ir(&ast).unwrap();
-        // Check that IR contains expected elements
-        assert!(ir.contains("define i64 @add(i64 %0, i64 %1)"));
-        assert!(ir.contains("add i64"));
-        assert!(ir.contains("ret i64"));
+        assert!(ir.contains("ret i64 -8"));
+    }
+
+    #[test]
+    fn test_sanitizer_attributes() {
+        let f = FnBuilder::new("main", Type::Int)
+            .stmt(stmt::ret(expr::int(0)))
+            .build();
+        let ast = ProgramBuilder::new().function(f).build();
+        assert!(!generate_ir(&ast).unwrap().contains("sanitize_address"));
+        let options = CompileOptions {
+            sanitizers: vec![Sanitizer::Address],
+            ..CompileOptions::default()
+        };
+        let ir = generate_ir_with_options(&ast, &options).unwrap();
+        assert!(ir.contains("sanitize_address"));
+        assert!(ir.contains("\"frame-pointer\"=\"all\""));
+    }
+
+    #[test]
+    fn test_exports() {
+        let source = "int helper(int x) { return x + 1; }\n\
+                      int api(int x) { return helper(x); }\n\
+                      int main() { return api(1); }";
+        let ast = parse(&lex(source).unwrap()).unwrap();
+        let options = CompileOptions {
+            exports: Some(vec!["api".to_string()]),
+            ..CompileOptions::default()
+        };
+        let module = ir::parse(&generate_ir_with_options(&ast, &options).unwrap()).unwrap();
+        let external = |name: &str| module.function(name).unwrap().is_external();
+        assert!(!external("helper"));
+        assert!(external("api"));
+        assert!(external("main"));
+    }
+
+    #[test]
+    fn test_entry_wrapper() {
+        let source = "int helper() { return 2; }\n\
+                      int solve() { return helper() + 1; }";
+        let ast = parse(&lex(source).unwrap()).unwrap();
+        assert!(!generate_ir(&ast).unwrap().contains("@main"));
+        let options = CompileOptions {
+            entry: Some("solve".to_string()),
+            exports: Some(Vec::new()),
+            strip_unused: true,
+            ..CompileOptions::default()
+        };
+        let module = ir::parse(&generate_ir_with_options(&ast, &options).unwrap()).unwrap();
+        assert!(!module.function("solve").unwrap().is_external());
+        assert!(!module.function("helper").unwrap().is_external());
+        let main = module.function("main").unwrap();
+        assert!(main.is_external());
+        assert_eq!(main.callees(), ["solve"]);
+        assert_eq!(main.opcodes(), ["call", "ret"]);
+    }
+
+    #[test]
+    fn test_hardening_attributes() {
+        let f = FnBuilder::new("main", Type::Int)
+            .stmt(stmt::ret(expr::int(0)))
+            .build();
+        let ast = ProgramBuilder::new().function(f).build();
+        assert!(!generate_ir(&ast).unwrap().contains("sspstrong"));
+        let options = CompileOptions {
+            hardening: true,
+            ..CompileOptions::default()
+        };
+        let ir = generate_ir_with_options(&ast, &options).unwrap();
+        assert!(ir.contains("sspstrong"));
+        assert!(ir.contains("\"probe-stack\"=\"inline-asm\""));
+    }
+
+    #[test]
+    fn test_normalize_ir() {
+        let program = |temp: &str| {
+            let source = format!(
+                "int main() {{ int {t} = 1; if ({t}) {{ return {t}; }} return 0; }}",
+                t = temp
+            );
+            let ir = generate_ir(&parse(&lex(&source).unwrap()).unwrap()).unwrap();
+            normalize_ir(&ir)
+        };
+        let normalized = program("a");
+        assert_eq!(normalized, program("a_much_longer_name"));
+        assert!(!normalized.contains("target triple"));
+        assert!(!normalized.contains("; preds"));
+        assert!(normalized.contains("define i64 @main() {\nv0:\n  %v1 = alloca i64"));
+    }
+
+    #[test]
+    fn test_module_metadata() {
+        let tokens = lex("int main() { return 0; }").unwrap();
+        let ast = parse(&tokens).unwrap();
+        let options = CompileOptions {
+            source_name: Some("hello.c".to_string()),
+            ..CompileOptions::default()
+        };
+        let ir = generate_ir_with_options(&ast, &options).unwrap();
+        assert!(ir.contains("source_filename = \"hello.c\""));
+        assert!(ir.contains("target datalayout = \""));
+        assert!(ir.contains("!llvm.ident"));
+        assert!(ir.contains(&format!("virtuc version {}", crate::VERSION)));
+    }
+
+    #[test]
+    fn test_verification_failure() {
+        let context = Context::create();
+        let generator = CodeGenerator::new(&context);
+        let fn_type = context.i64_type().fn_type(&[], false);
+        let broken = generator.module.add_function("broken", fn_type, None);
+        // A block without a terminator is invalid
+        context.append_basic_block(broken, "entry");
+
+        let e = generator.verify_function(broken, "broken").unwrap_err();
+        assert!(
+            e.message
+                .starts_with("function 'broken' failed verification:\n")
+        );
+        assert!(e.message.contains("terminator"));
+        assert_eq!(e.function.as_deref(), Some("broken"));
+        assert!(e.function_ir.unwrap().contains("define i64 @broken()"));
+        assert_eq!(e.module_ir, None);
+    }
+
+    #[test]
+    fn test_generate_subset() {
+        let source = "int square(int x) { return x * x; }\n\
+                      int main() { return square(3); }";
+        let ast = parse(&lex(source).unwrap()).unwrap();
+
+        let ir = generate_function_ir(&ast, "square").unwrap();
+        assert!(ir.trim_start().starts_with("define i64 @square(i64 %0)"));
+        assert!(!ir.contains("@main"));
+
+        // Functions left out are declared, so that calls to them still work
+        let ir = generate_functions_ir(&ast, &["main"], &CompileOptions::default()).unwrap();
+        assert!(ir.contains("define i64 @main()"));
+        assert!(ir.contains("declare i64 @square(i64)"));
+
+        assert_eq!(
+            generate_function_ir(&ast, "cube"),
+            Err(CodegenError::new("Undefined function: cube"))
+        );
+    }
+
+    #[test]
+    fn test_strict_floating_point() {
+        let source = "float scale(float a, float b, float c) { return a * b + c; }\n\
+                      int main() { return 0; }";
+        let ast = parse(&lex(source).unwrap()).unwrap();
+        let options = CompileOptions {
+            opt_level: 3,
+            ..CompileOptions::default()
+        };
+        let ir = generate_ir_with_options(&ast, &options).unwrap();
+        assert!(ir.contains("fmul double"));
+        assert!(ir.contains("fadd double"));
+        for relaxation in ["fast", "contract", "reassoc", "afn", "fmuladd"] {
+            assert!(
+                !ir.contains(relaxation),
+                "unexpected '{}' in:\n{}",
+                relaxation,
+                ir
+            );
+        }
+
+        let options = CompileOptions {
+            fast_math: true,
+            ..options
+        };
+        let ir = generate_ir_with_options(&ast, &options).unwrap();
+        assert!(ir.contains("fmul fast double"));
+        assert!(ir.contains("fadd fast double"));
+
+        // Deterministic builds stay strict
+        let options = CompileOptions {
+            deterministic: true,
+            ..options
+        };
+        let ir = generate_ir_with_options(&ast, &options).unwrap();
+        assert!(!ir.contains("fast"));
+    }
+
+    #[test]
+    fn test_loop_optimizations() {
+        let source = "int mix(int x) {\n\
+                          for (int i = 0; i < 8; i = i + 1) { x = x * 3 + i; }\n\
+                          return x;\n\
+                      }\n\
+                      int main() { return mix(5); }";
+        let ast = parse(&lex(source).unwrap()).unwrap();
+        let optimized = |options: CompileOptions| {
+            let context = Context::create();
+            let module = generate_module(&context, &ast, &options).unwrap();
+            optimize_module(&module, &options).unwrap();
+            let module = ir::parse(&module.print_to_string().to_string()).unwrap();
+            module.function("mix").unwrap().count("br")
+        };
+        let o2 = CompileOptions {
+            opt_level: 2,
+            ..CompileOptions::default()
+        };
+        // Unrolled, the loop disappears
+        assert_eq!(optimized(o2.clone()), 0);
+        let rolled = CompileOptions {
+            unroll_loops: Some(false),
+            ..o2
+        };
+        assert!(optimized(rolled) > 0);
     }
 }
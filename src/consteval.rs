@@ -0,0 +1,153 @@
+//! # Constant Evaluation
+//!
+//! This module evaluates constant integer and float expressions. Semantic
+//! analysis uses it to reject constant expressions whose value is undefined,
+//! such as integer overflow or division by zero, and code generation uses it
+//! to fold them, so that both phases agree on every constant's value.
+//!
+//! ## Semantics
+//!
+//! - Integer arithmetic is checked against the configured `int` width
+//! - Float arithmetic follows IEEE 754, so dividing by zero yields infinity
+//! - Comparisons yield the `int` values `0` and `1`
+//! - Operands of different types are not constant, as they are a type error
+
+use crate::ast::{Arena, BinOp, Expr, ExprId, Literal};
+use crate::error::ConstEvalError;
+use crate::options::IntWidth;
+
+/// The value of a constant expression.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ConstValue {
+    /// An `int` value
+    Int(i64),
+    /// A `float` value
+    Float(f64),
+}
+
+/// Evaluates the constant expression `expr` of an arena.
+///
+/// Errors in constant subexpressions are reported even when the expression
+/// as a whole is not constant, e.g. for `x + 1 / 0`.
+pub fn eval(
+    arena: &Arena,
+    expr: ExprId,
+    int_width: IntWidth,
+) -> Result<ConstValue, ConstEvalError> {
+    match &arena[expr] {
+        Expr::Literal(Literal::Int(n)) if int_width.fits(*n) => Ok(ConstValue::Int(*n)),
+        Expr::Literal(Literal::Int(_)) => Err(ConstEvalError::Overflow),
+        Expr::Literal(Literal::Float(f)) => Ok(ConstValue::Float(*f)),
+        Expr::Binary { left, op, right } => {
+            let left = eval(arena, *left, int_width);
+            let right = eval(arena, *right, int_width);
+            match (left, right) {
+                (Ok(left), Ok(right)) => eval_binary(*op, left, right, int_width),
+                (Err(ConstEvalError::NotConstant), Err(e)) | (Err(e), _) | (_, Err(e)) => Err(e),
+            }
+        }
+        _ => Err(ConstEvalError::NotConstant),
+    }
+}
+
+/// Applies a binary operator to constant operands.
+pub fn eval_binary(
+    op: BinOp,
+    left: ConstValue,
+    right: ConstValue,
+    int_width: IntWidth,
+) -> Result<ConstValue, ConstEvalError> {
+    match (left, right) {
+        (ConstValue::Int(l), ConstValue::Int(r)) => {
+            let value = match op {
+                BinOp::Plus => l.checked_add(r),
+                BinOp::Minus => l.checked_sub(r),
+                BinOp::Multiply => l.checked_mul(r),
+                BinOp::Divide if r == 0 => return Err(ConstEvalError::DivisionByZero),
+                BinOp::Divide => l.checked_div(r),
+                _ => Some(compare(op, Some(l.cmp(&r))) as i64),
+            };
+            match value {
+                Some(value) if int_width.fits(value) => Ok(ConstValue::Int(value)),
+                _ => Err(ConstEvalError::Overflow),
+            }
+        }
+        (ConstValue::Float(l), ConstValue::Float(r)) => Ok(match op {
+            BinOp::Plus => ConstValue::Float(l + r),
+            BinOp::Minus => ConstValue::Float(l - r),
+            BinOp::Multiply => ConstValue::Float(l * r),
+            BinOp::Divide => ConstValue::Float(l / r),
+            _ => ConstValue::Int(compare(op, l.partial_cmp(&r)) as i64),
+        }),
+        _ => Err(ConstEvalError::NotConstant),
+    }
+}
+
+/// Evaluates a comparison operator given the ordering of its operands, which
+/// is `None` for unordered floats (NaN).
+fn compare(op: BinOp, ordering: Option<std::cmp::Ordering>) -> bool {
+    use std::cmp::Ordering::*;
+    match op {
+        BinOp::Equal => ordering == Some(Equal),
+        BinOp::NotEqual => ordering != Some(Equal),
+        BinOp::LessThan => ordering == Some(Less),
+        BinOp::GreaterThan => ordering == Some(Greater),
+        BinOp::LessEqual => matches!(ordering, Some(Less | Equal)),
+        BinOp::GreaterEqual => matches!(ordering, Some(Greater | Equal)),
+        BinOp::Plus | BinOp::Minus | BinOp::Multiply | BinOp::Divide => {
+            unreachable!("not a comparison: {:?}", op)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::expr;
+
+    #[test]
+    fn test_eval_arithmetic() {
+        let e = expr::sub(expr::mul(expr::int(2), expr::int(3)), expr::int(10));
+        assert_eq!(
+            eval(&e.arena, e.root, IntWidth::W64),
+            Ok(ConstValue::Int(-4))
+        );
+        let e = expr::div(expr::float(1.0), expr::float(4.0));
+        assert_eq!(
+            eval(&e.arena, e.root, IntWidth::W64),
+            Ok(ConstValue::Float(0.25))
+        );
+        let e = expr::lt(expr::float(1.0), expr::float(2.0));
+        assert_eq!(
+            eval(&e.arena, e.root, IntWidth::W64),
+            Ok(ConstValue::Int(1))
+        );
+    }
+
+    #[test]
+    fn test_eval_errors() {
+        let e = expr::div(expr::int(1), expr::int(0));
+        assert_eq!(
+            eval(&e.arena, e.root, IntWidth::W64),
+            Err(ConstEvalError::DivisionByZero)
+        );
+
+        let e = expr::add(expr::int(i32::MAX as i64), expr::int(1));
+        assert_eq!(
+            eval(&e.arena, e.root, IntWidth::W32),
+            Err(ConstEvalError::Overflow)
+        );
+        assert!(eval(&e.arena, e.root, IntWidth::W64).is_ok());
+
+        let e = expr::add(expr::ident("x"), expr::int(1));
+        assert_eq!(
+            eval(&e.arena, e.root, IntWidth::W64),
+            Err(ConstEvalError::NotConstant)
+        );
+        let e = expr::add(expr::ident("x"), expr::div(expr::int(1), expr::int(0)));
+        assert_eq!(
+            eval(&e.arena, e.root, IntWidth::W64),
+            Err(ConstEvalError::DivisionByZero)
+        );
+    }
+}
@@ -0,0 +1,196 @@
+//! # Statement Coverage
+//!
+//! This module supports `--coverage` builds, which count how often each
+//! statement runs, and `virtuc cov report`, which shows those counts next to
+//! the source.
+//!
+//! ## Counters
+//!
+//! Each statement has a counter, numbered in the order of [`statements`].
+//! Code generation increments a statement's counter whenever the statement
+//! starts executing. When the program exits, the runtime writes the counters
+//! to a counts file: a [`COUNTS_HEADER`] line followed by one count per line.
+//!
+//! ## Reports
+//!
+//! A report locates every statement in the source by re-parsing it, and
+//! prints each source line with the count of the statements starting on it,
+//! in the style of `gcov`. Lines holding no statement show `-`, and lines
+//! whose statements never ran show `#####`.
+
+use std::fmt::Write;
+
+use crate::ast::{Arena, Program, Stmt, StmtId};
+use crate::error::CompileError;
+use crate::lexer;
+use crate::options::CompileOptions;
+use crate::parser;
+
+/// The first line of a counts file.
+pub const COUNTS_HEADER: &str = "virtuc coverage v1";
+
+/// Lists the statements of a program that have counters, in counter order.
+///
+/// Nested statements come before the statement containing them. Function
+/// bodies are covered by their statements, and the initializer of a `for`
+/// loop by the loop itself, so neither has a counter of its own.
+///
+/// Each statement is given as the index of its function in
+/// [`Program::functions`] and its handle in the function's arena.
+pub fn statements(program: &Program) -> Vec<(usize, StmtId)> {
+    let mut statements = Vec::new();
+    for (index, function) in program.functions.iter().enumerate() {
+        if let Stmt::Block(body) = function.body() {
+            for stmt in body {
+                collect(&function.arena, index, *stmt, &mut statements);
+            }
+        }
+    }
+    statements
+}
+
+/// Appends a statement of the function at index `function` to
+/// `statements`, after its nested statements.
+fn collect(arena: &Arena, function: usize, stmt: StmtId, statements: &mut Vec<(usize, StmtId)>) {
+    match &arena[stmt] {
+        Stmt::Block(stmts) => {
+            for stmt in stmts {
+                collect(arena, function, *stmt, statements);
+            }
+        }
+        Stmt::If { then, else_, .. } => {
+            collect(arena, function, *then, statements);
+            if let Some(else_) = else_ {
+                collect(arena, function, *else_, statements);
+            }
+        }
+        Stmt::For { body, .. } => collect(arena, function, *body, statements),
+        Stmt::Declaration { .. } | Stmt::Return(_) | Stmt::Expr(_) | Stmt::Empty => {}
+    }
+    statements.push((function, stmt));
+}
+
+/// Parses the contents of a counts file.
+pub fn parse_counts(text: &str) -> Result<Vec<u64>, String> {
+    let mut lines = text.lines();
+    if lines.next() != Some(COUNTS_HEADER) {
+        return Err(format!("not a counts file: expected '{}'", COUNTS_HEADER));
+    }
+    lines
+        .map(|line| {
+            line.parse()
+                .map_err(|_| format!("invalid count '{}' in counts file", line))
+        })
+        .collect()
+}
+
+/// Annotates `source` with the statement counts of a coverage run.
+///
+/// Fails if the source does not parse, and with
+/// [`CompileError::Io`] if the counts do not belong to this source.
+pub fn report(
+    source: &str,
+    counts: &[u64],
+    options: &CompileOptions,
+) -> Result<String, CompileError> {
+    let (tokens, spans): (Vec<_>, Vec<_>) = lexer::lex_with_options(source, options)?
+        .into_iter()
+        .unzip();
+    let (_, positions) = parser::parse_with_statement_positions(&tokens, options).map_err(|e| {
+        let span = e.position().and_then(|i| spans.get(i).copied());
+        CompileError::Parse(e, span)
+    })?;
+    if positions.len() != counts.len() {
+        return Err(CompileError::Io(format!(
+            "counts file has {} counters but the source has {} statements; rebuild with --coverage",
+            counts.len(),
+            positions.len()
+        )));
+    }
+
+    // Several statements may start on one line, such as a loop and its
+    // body; the line shows the largest of their counts
+    let mut line_counts: Vec<Option<u64>> = vec![None; source.lines().count()];
+    for (&position, &count) in positions.iter().zip(counts) {
+        let line = source[..spans[position].start].matches('\n').count();
+        let entry = &mut line_counts[line];
+        *entry = Some(entry.map_or(count, |c| c.max(count)));
+    }
+
+    let mut out = String::new();
+    for (number, (text, count)) in source.lines().zip(line_counts).enumerate() {
+        let count = match count {
+            None => "-".to_string(),
+            Some(0) => "#####".to_string(),
+            Some(n) => n.to_string(),
+        };
+        writeln!(out, "{:>9}:{:>5}:{}", count, number + 1, text).unwrap();
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+
+    const SOURCE: &str = "int main() {
+    int total = 0;
+    for (int i = 0; i < 3; i = i + 1) {
+        total = total + i;
+    }
+    if (total > 100) { return 1; }
+    return 0;
+}
+";
+
+    #[test]
+    fn test_statements_match_parser_positions() {
+        let tokens = lex(SOURCE).unwrap();
+        let (program, positions) =
+            parser::parse_with_statement_positions(&tokens, &CompileOptions::default()).unwrap();
+        let statements = statements(&program);
+        assert_eq!(statements.len(), positions.len());
+        let stmt = |i: usize| {
+            let (function, id) = statements[i];
+            &program.functions[function].arena[id]
+        };
+        // The loop body comes before the loop, and the return before the if
+        assert!(matches!(stmt(0), Stmt::Declaration { .. }));
+        assert!(matches!(stmt(1), Stmt::Expr(_)));
+        assert!(matches!(stmt(2), Stmt::Block(_)));
+        assert!(matches!(stmt(3), Stmt::For { .. }));
+        assert!(matches!(stmt(5), Stmt::Block(_)));
+        assert!(matches!(stmt(6), Stmt::If { .. }));
+        assert_eq!(positions[3], 10);
+    }
+
+    #[test]
+    fn test_report() {
+        let counts = [1, 3, 3, 1, 0, 0, 1, 1];
+        let annotated = report(SOURCE, &counts, &CompileOptions::default()).unwrap();
+        let lines: Vec<&str> = annotated.lines().collect();
+        assert_eq!(lines[0], "        -:    1:int main() {");
+        assert_eq!(lines[1], "        1:    2:    int total = 0;");
+        assert_eq!(
+            lines[2],
+            "        3:    3:    for (int i = 0; i < 3; i = i + 1) {"
+        );
+        assert_eq!(lines[3], "        3:    4:        total = total + i;");
+        assert_eq!(
+            lines[5],
+            "        1:    6:    if (total > 100) { return 1; }"
+        );
+        assert_eq!(lines[6], "        1:    7:    return 0;");
+
+        // Counts from a different program are rejected
+        assert!(report(SOURCE, &counts[1..], &CompileOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_parse_counts() {
+        assert_eq!(parse_counts("virtuc coverage v1\n4\n0\n"), Ok(vec![4, 0]));
+        assert!(parse_counts("4\n0\n").is_err());
+        assert!(parse_counts("virtuc coverage v1\nx\n").is_err());
+    }
+}
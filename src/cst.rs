@@ -0,0 +1,369 @@
+//! # Concrete Syntax Tree
+//!
+//! This module provides a lossless parse mode for tools such as formatters
+//! and refactoring tools. Unlike the AST, the concrete syntax tree (CST) keeps
+//! every token together with the whitespace and comments ("trivia") around
+//! it, so printing the tree reproduces the source exactly.
+//!
+//! ## Structure
+//!
+//! The tree groups tokens into nodes:
+//! - **Root**: the whole file
+//! - **Item**: a top-level include, extern declaration, or function
+//! - **Block**: a brace-delimited block
+//! - **Statement**: a statement inside a block
+//!
+//! Grouping is purely structural, so a tree is built even for source that
+//! does not parse. The lossy AST is derived from the tree with
+//! [`SyntaxTree::to_ast`], which runs the regular parser over its tokens.
+//!
+//! ## Trivia
+//!
+//! Trivia on the same line after a token, such as a trailing `// comment`, is
+//! attached to that token as trailing trivia. Everything from the next line
+//! break on is leading trivia of the following token, so comments above a
+//! function belong to the function's item node.
+
+use std::fmt;
+
+use crate::ast::Program;
+use crate::diagnostics::Span;
+use crate::error::{LexerError, ParseError};
+use crate::lexer::{Token, lex_with_spans};
+
+/// The kind of a piece of trivia.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TriviaKind {
+    /// Spaces, tabs, and line breaks
+    Whitespace,
+    /// A `//` comment, without the line break that ends it
+    Comment,
+}
+
+/// Whitespace or a comment between tokens.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Trivia {
+    /// What the trivia is
+    pub kind: TriviaKind,
+    /// The source text
+    pub text: String,
+}
+
+/// A token with its source text and surrounding trivia.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyntaxToken {
+    /// The token
+    pub token: Token,
+    /// The source text of the token
+    pub text: String,
+    /// The location of the token in the source
+    pub span: Span,
+    /// Trivia before the token, starting at a line break
+    pub leading: Vec<Trivia>,
+    /// Trivia after the token on the same line
+    pub trailing: Vec<Trivia>,
+}
+
+/// The kind of a syntax node.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NodeKind {
+    /// The whole file
+    Root,
+    /// A top-level include, extern declaration, or function
+    Item,
+    /// A brace-delimited block
+    Block,
+    /// A statement inside a block
+    Statement,
+}
+
+/// A child of a syntax node.
+#[derive(Debug, PartialEq, Clone)]
+pub enum SyntaxElement {
+    /// A nested node
+    Node(SyntaxNode),
+    /// A token
+    Token(SyntaxToken),
+}
+
+/// A node of the concrete syntax tree.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyntaxNode {
+    /// What the node represents
+    pub kind: NodeKind,
+    /// The nested nodes and tokens, in source order
+    pub children: Vec<SyntaxElement>,
+}
+
+impl SyntaxNode {
+    fn new(kind: NodeKind) -> Self {
+        Self {
+            kind,
+            children: Vec::new(),
+        }
+    }
+
+    /// Returns all tokens of the node in source order.
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a SyntaxToken>) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+    }
+
+    /// Returns the child nodes of the node.
+    pub fn nodes(&self) -> impl Iterator<Item = &SyntaxNode> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// Returns the comments directly preceding the node.
+    pub fn leading_comments(&self) -> Vec<&str> {
+        self.tokens()
+            .first()
+            .map(|token| {
+                token
+                    .leading
+                    .iter()
+                    .filter(|t| t.kind == TriviaKind::Comment)
+                    .map(|t| t.text.as_str())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    /// Writes the node's source text, including its trivia.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for token in self.tokens() {
+            for trivia in &token.leading {
+                write!(f, "{}", trivia.text)?;
+            }
+            write!(f, "{}", token.text)?;
+            for trivia in &token.trailing {
+                write!(f, "{}", trivia.text)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A lossless syntax tree of a source file.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyntaxTree {
+    /// The root node
+    pub root: SyntaxNode,
+    /// Trivia after the last token
+    pub end: Vec<Trivia>,
+}
+
+impl SyntaxTree {
+    /// Derives the AST by parsing the tree's tokens.
+    pub fn to_ast(&self) -> Result<Program, ParseError> {
+        let tokens: Vec<Token> = self
+            .root
+            .tokens()
+            .into_iter()
+            .map(|t| t.token.clone())
+            .collect();
+        crate::parser::parse(&tokens)
+    }
+}
+
+impl fmt::Display for SyntaxTree {
+    /// Writes the original source text.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.root)?;
+        for trivia in &self.end {
+            write!(f, "{}", trivia.text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses source code into a lossless syntax tree.
+pub fn parse(source: &str) -> Result<SyntaxTree, LexerError> {
+    let spanned = lex_with_spans(source)?;
+
+    // Split the text between tokens into trailing and leading trivia
+    let mut tokens = Vec::with_capacity(spanned.len());
+    let mut leading = split_trivia(&source[..spanned.first().map_or(source.len(), |t| t.1.start)]);
+    for (i, (token, span)) in spanned.iter().enumerate() {
+        let gap_end = spanned.get(i + 1).map_or(source.len(), |t| t.1.start);
+        let gap = &source[span.end..gap_end];
+        let line_end = gap.find('\n').unwrap_or(gap.len());
+        let (trailing, next_leading) = if i + 1 < spanned.len() {
+            (&gap[..line_end], &gap[line_end..])
+        } else {
+            // Trivia after the last token belongs to the tree
+            ("", gap)
+        };
+        tokens.push(SyntaxToken {
+            token: token.clone(),
+            text: source[span.start..span.end].to_string(),
+            span: *span,
+            leading: std::mem::replace(&mut leading, split_trivia(next_leading)),
+            trailing: split_trivia(trailing),
+        });
+    }
+
+    let mut builder = TreeBuilder {
+        tokens: tokens.into_iter().peekable(),
+    };
+    let mut root = SyntaxNode::new(NodeKind::Root);
+    while builder.tokens.peek().is_some() {
+        root.children
+            .push(SyntaxElement::Node(builder.group(NodeKind::Item)));
+    }
+    Ok(SyntaxTree { root, end: leading })
+}
+
+/// Splits text between tokens into whitespace and comments.
+fn split_trivia(mut text: &str) -> Vec<Trivia> {
+    let mut trivia = Vec::new();
+    while !text.is_empty() {
+        let (kind, len) = if text.starts_with("//") {
+            (TriviaKind::Comment, text.find('\n').unwrap_or(text.len()))
+        } else {
+            (
+                TriviaKind::Whitespace,
+                text.find("//").unwrap_or(text.len()),
+            )
+        };
+        trivia.push(Trivia {
+            kind,
+            text: text[..len].to_string(),
+        });
+        text = &text[len..];
+    }
+    trivia
+}
+
+/// Groups a flat token stream into nodes.
+struct TreeBuilder {
+    tokens: std::iter::Peekable<std::vec::IntoIter<SyntaxToken>>,
+}
+
+impl TreeBuilder {
+    /// Builds an item or statement: tokens up to a `;` outside parentheses,
+    /// or up to and including a block not followed by `else`.
+    fn group(&mut self, kind: NodeKind) -> SyntaxNode {
+        let mut node = SyntaxNode::new(kind);
+        let mut parens = 0usize;
+        while let Some(next) = self.tokens.peek() {
+            match next.token {
+                Token::LBrace => {
+                    node.children.push(SyntaxElement::Node(self.block()));
+                    if !self.at_else() {
+                        break;
+                    }
+                }
+                // A stray closing brace ends the enclosing block
+                Token::RBrace if kind == NodeKind::Statement => break,
+                Token::Include(_) | Token::LocalInclude(_) if node.children.is_empty() => {
+                    self.push_token(&mut node);
+                    break;
+                }
+                Token::Semicolon if parens == 0 => {
+                    self.push_token(&mut node);
+                    if !self.at_else() {
+                        break;
+                    }
+                }
+                _ => {
+                    match next.token {
+                        Token::LParen => parens += 1,
+                        Token::RParen => parens = parens.saturating_sub(1),
+                        _ => {}
+                    }
+                    self.push_token(&mut node);
+                }
+            }
+        }
+        node
+    }
+
+    /// Builds a block from `{` to the matching `}` or the end of input.
+    fn block(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(NodeKind::Block);
+        self.push_token(&mut node);
+        while let Some(next) = self.tokens.peek() {
+            if next.token == Token::RBrace {
+                self.push_token(&mut node);
+                break;
+            }
+            node.children
+                .push(SyntaxElement::Node(self.group(NodeKind::Statement)));
+        }
+        node
+    }
+
+    fn at_else(&mut self) -> bool {
+        self.tokens.peek().is_some_and(|t| t.token == Token::Else)
+    }
+
+    fn push_token(&mut self, node: &mut SyntaxNode) {
+        if let Some(token) = self.tokens.next() {
+            node.children.push(SyntaxElement::Token(token));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+
+    const SOURCE: &str = "#include <stdio.h>\n\n// Adds two numbers\nint add(int a, int b) {\n    return a + b; // sum\n}\n\nint main() {\n    if (add(1, 2) > 2) { return 1; } else return 0;\n}\n// end\n";
+
+    #[test]
+    fn test_round_trip() {
+        let tree = parse(SOURCE).unwrap();
+        assert_eq!(tree.to_string(), SOURCE);
+        assert_eq!(tree.end[1].text, "// end");
+    }
+
+    #[test]
+    fn test_structure_and_comments() {
+        let tree = parse(SOURCE).unwrap();
+        let items: Vec<&SyntaxNode> = tree.root.nodes().collect();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[1].leading_comments(), vec!["// Adds two numbers"]);
+
+        // The trailing comment stays with the return statement
+        let body = items[1].nodes().next().unwrap();
+        assert_eq!(body.kind, NodeKind::Block);
+        let ret = body.nodes().next().unwrap();
+        assert_eq!(ret.to_string().trim(), "return a + b; // sum");
+
+        // if/else is a single statement
+        let main_body = items[2].nodes().next().unwrap();
+        assert_eq!(main_body.nodes().count(), 1);
+    }
+
+    #[test]
+    fn test_to_ast_matches_parser() {
+        let tree = parse(SOURCE).unwrap();
+        let expected = crate::parser::parse(&lex(SOURCE).unwrap()).unwrap();
+        assert_eq!(tree.to_ast().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_unbalanced_source_is_lossless() {
+        let source = "int main() { return 0; } }\nint f() {";
+        let tree = parse(source).unwrap();
+        assert_eq!(tree.to_string(), source);
+        assert!(tree.to_ast().is_err());
+    }
+}
@@ -0,0 +1,480 @@
+//! # Dataflow Analysis
+//!
+//! This module solves dataflow problems over the basic blocks of a
+//! [`mir::Body`](crate::mir::Body). An analysis implements [`Analysis`]: its
+//! direction, the state at the boundary of the function, how states from
+//! several paths are joined, and the effect of each statement and
+//! terminator. [`solve`] iterates the effects to a fixed point, and
+//! [`Results::visit`] replays them within a block to give the state at each
+//! statement.
+//!
+//! Two analyses are provided:
+//! - [`Liveness`]: the locals whose current value may still be read, which
+//!   finds stores that are never read
+//! - [`ReachingDefinitions`]: the assignments whose value a local may hold,
+//!   including a pseudo-definition for each variable declared without an
+//!   initializer, which finds variables that may be read uninitialized
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::mir::{BlockId, Body, LocalId, Statement, Terminator};
+
+/// A set of small integers, such as local or definition indices.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BitSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitSet {
+    /// Creates an empty set for elements below `len`.
+    pub fn new_empty(len: usize) -> Self {
+        BitSet {
+            words: vec![0; len.div_ceil(64)],
+            len,
+        }
+    }
+
+    /// Adds an element, returning true if it was not present.
+    pub fn insert(&mut self, element: usize) -> bool {
+        assert!(element < self.len, "element {} out of range", element);
+        let (word, bit) = (element / 64, 1 << (element % 64));
+        let added = self.words[word] & bit == 0;
+        self.words[word] |= bit;
+        added
+    }
+
+    /// Removes an element, returning true if it was present.
+    pub fn remove(&mut self, element: usize) -> bool {
+        let (word, bit) = (element / 64, 1 << (element % 64));
+        let present = self.words[word] & bit != 0;
+        self.words[word] &= !bit;
+        present
+    }
+
+    /// Returns true if the set contains the element.
+    pub fn contains(&self, element: usize) -> bool {
+        element < self.len && self.words[element / 64] & (1 << (element % 64)) != 0
+    }
+
+    /// Adds every element of `other`, returning true if the set changed.
+    pub fn union(&mut self, other: &BitSet) -> bool {
+        let mut changed = false;
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            let new = *word | other;
+            changed |= new != *word;
+            *word = new;
+        }
+        changed
+    }
+
+    /// Returns the elements in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len).filter(|&element| self.contains(element))
+    }
+}
+
+/// The direction in which states flow.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Direction {
+    /// From the entry of the function towards its returns
+    Forward,
+    /// From the returns of the function towards its entry
+    Backward,
+}
+
+/// A point in a body: a statement of a block, or its terminator, whose
+/// index is the number of statements.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Location {
+    /// The block
+    pub block: BlockId,
+    /// Index of the statement within the block
+    pub statement: usize,
+}
+
+/// A dataflow problem.
+pub trait Analysis {
+    /// The facts known at a point of the body
+    type Domain: Clone + PartialEq;
+
+    /// The direction in which facts flow
+    const DIRECTION: Direction;
+
+    /// The state no path has reached yet, which joining leaves unchanged.
+    fn bottom(&self, body: &Body) -> Self::Domain;
+
+    /// The state at the entry of the function (forward analyses) or at each
+    /// return (backward analyses).
+    fn boundary(&self, body: &Body) -> Self::Domain;
+
+    /// Joins the state of another path into `state`.
+    fn join(&self, state: &mut Self::Domain, other: &Self::Domain);
+
+    /// Applies the effect of a statement.
+    fn statement_effect(&self, state: &mut Self::Domain, statement: &Statement, location: Location);
+
+    /// Applies the effect of a terminator.
+    fn terminator_effect(
+        &self,
+        state: &mut Self::Domain,
+        terminator: &Terminator,
+        location: Location,
+    );
+}
+
+/// The fixed point of an analysis: the state on entry to each block, in the
+/// direction of the analysis.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Results<D> {
+    /// For each block, the state before its first statement (forward
+    /// analyses) or after its terminator (backward analyses)
+    pub entry_states: Vec<D>,
+}
+
+impl<D: Clone> Results<D> {
+    /// Replays the effects of the block `block`, calling `visit` with each
+    /// location and the state that flows into it: the state before the
+    /// location executes (forward analyses), or after it (backward
+    /// analyses). Locations are visited in the direction of the analysis.
+    pub fn visit<A: Analysis<Domain = D>>(
+        &self,
+        analysis: &A,
+        body: &Body,
+        block: BlockId,
+        mut visit: impl FnMut(Location, &D),
+    ) {
+        let data = body.block(block);
+        let location = |statement| Location { block, statement };
+        let terminator = location(data.statements.len());
+        let mut state = self.entry_states[block.0].clone();
+        match A::DIRECTION {
+            Direction::Forward => {
+                for (i, statement) in data.statements.iter().enumerate() {
+                    visit(location(i), &state);
+                    analysis.statement_effect(&mut state, statement, location(i));
+                }
+                visit(terminator, &state);
+            }
+            Direction::Backward => {
+                visit(terminator, &state);
+                analysis.terminator_effect(&mut state, &data.terminator, terminator);
+                for (i, statement) in data.statements.iter().enumerate().rev() {
+                    visit(location(i), &state);
+                    analysis.statement_effect(&mut state, statement, location(i));
+                }
+            }
+        }
+    }
+}
+
+/// Solves an analysis over a body with a worklist of blocks.
+pub fn solve<A: Analysis>(analysis: &A, body: &Body) -> Results<A::Domain> {
+    let count = body.blocks.len();
+    let predecessors = body.predecessors();
+    let mut entry_states = vec![analysis.bottom(body); count];
+    for id in body.block_ids() {
+        let at_boundary = match A::DIRECTION {
+            Direction::Forward => id.0 == 0,
+            Direction::Backward => matches!(body.block(id).terminator, Terminator::Return(_)),
+        };
+        if at_boundary {
+            entry_states[id.0] = analysis.boundary(body);
+        }
+    }
+
+    let mut worklist: VecDeque<BlockId> = body.block_ids().collect();
+    let mut queued = vec![true; count];
+    while let Some(id) = worklist.pop_front() {
+        queued[id.0] = false;
+        let data = body.block(id);
+        let location = |statement| Location {
+            block: id,
+            statement,
+        };
+        let mut state = entry_states[id.0].clone();
+        let next = match A::DIRECTION {
+            Direction::Forward => {
+                for (i, statement) in data.statements.iter().enumerate() {
+                    analysis.statement_effect(&mut state, statement, location(i));
+                }
+                let terminator = location(data.statements.len());
+                analysis.terminator_effect(&mut state, &data.terminator, terminator);
+                data.terminator.successors()
+            }
+            Direction::Backward => {
+                let terminator = location(data.statements.len());
+                analysis.terminator_effect(&mut state, &data.terminator, terminator);
+                for (i, statement) in data.statements.iter().enumerate().rev() {
+                    analysis.statement_effect(&mut state, statement, location(i));
+                }
+                predecessors[id.0].clone()
+            }
+        };
+        for target in next {
+            let mut joined = entry_states[target.0].clone();
+            analysis.join(&mut joined, &state);
+            if joined != entry_states[target.0] {
+                entry_states[target.0] = joined;
+                if !std::mem::replace(&mut queued[target.0], true) {
+                    worklist.push_back(target);
+                }
+            }
+        }
+    }
+    Results { entry_states }
+}
+
+/// Live locals: those whose current value may be read later. A backward
+/// analysis over sets of locals.
+pub struct Liveness;
+
+impl Analysis for Liveness {
+    type Domain = BitSet;
+
+    const DIRECTION: Direction = Direction::Backward;
+
+    fn bottom(&self, body: &Body) -> BitSet {
+        BitSet::new_empty(body.locals.len())
+    }
+
+    fn boundary(&self, body: &Body) -> BitSet {
+        BitSet::new_empty(body.locals.len())
+    }
+
+    fn join(&self, state: &mut BitSet, other: &BitSet) {
+        state.union(other);
+    }
+
+    fn statement_effect(&self, state: &mut BitSet, statement: &Statement, _: Location) {
+        if let Statement::Assign(local, _) = statement {
+            state.remove(local.0);
+        }
+        for local in statement.operands().iter().filter_map(|op| op.local()) {
+            state.insert(local.0);
+        }
+    }
+
+    fn terminator_effect(&self, state: &mut BitSet, terminator: &Terminator, _: Location) {
+        for local in terminator.operands().iter().filter_map(|op| op.local()) {
+            state.insert(local.0);
+        }
+    }
+}
+
+/// Where a local gets a value.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DefinitionSite {
+    /// A parameter, defined by the caller
+    Parameter,
+    /// A variable declared without an initializer, whose value is
+    /// indeterminate until it is assigned
+    Uninitialized,
+    /// An assignment statement
+    Assignment(Location),
+}
+
+/// A definition of a local.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Definition {
+    /// The local defined
+    pub local: LocalId,
+    /// Where it is defined
+    pub site: DefinitionSite,
+}
+
+/// Reaching definitions: the definitions whose value a local may hold. A
+/// forward analysis over sets of indices into [`definitions`].
+///
+/// [`definitions`]: ReachingDefinitions::definitions
+pub struct ReachingDefinitions {
+    /// Every definition in the body
+    pub definitions: Vec<Definition>,
+    /// Definitions of each local
+    by_local: Vec<Vec<usize>>,
+    /// Definition of each assignment
+    by_location: HashMap<Location, usize>,
+}
+
+impl ReachingDefinitions {
+    /// Collects the definitions of a body. Variables (but not temporaries,
+    /// which are always assigned before they are read) start with an
+    /// uninitialized pseudo-definition.
+    pub fn new(body: &Body) -> Self {
+        let mut definitions: Vec<Definition> = body
+            .locals
+            .iter()
+            .enumerate()
+            .filter(|(_, local)| local.name.is_some())
+            .map(|(i, _)| Definition {
+                local: LocalId(i),
+                site: if i < body.param_count {
+                    DefinitionSite::Parameter
+                } else {
+                    DefinitionSite::Uninitialized
+                },
+            })
+            .collect();
+        let mut by_location = HashMap::new();
+        for id in body.block_ids() {
+            for (i, statement) in body.block(id).statements.iter().enumerate() {
+                if let Statement::Assign(local, _) = statement {
+                    let location = Location {
+                        block: id,
+                        statement: i,
+                    };
+                    by_location.insert(location, definitions.len());
+                    definitions.push(Definition {
+                        local: *local,
+                        site: DefinitionSite::Assignment(location),
+                    });
+                }
+            }
+        }
+        let mut by_local = vec![Vec::new(); body.locals.len()];
+        for (i, definition) in definitions.iter().enumerate() {
+            by_local[definition.local.0].push(i);
+        }
+        ReachingDefinitions {
+            definitions,
+            by_local,
+            by_location,
+        }
+    }
+
+    /// Returns the definitions of `local` in the state `state`.
+    pub fn reaching<'a>(
+        &'a self,
+        state: &'a BitSet,
+        local: LocalId,
+    ) -> impl Iterator<Item = &'a Definition> + 'a {
+        self.by_local[local.0]
+            .iter()
+            .filter(|&&i| state.contains(i))
+            .map(|&i| &self.definitions[i])
+    }
+}
+
+impl Analysis for ReachingDefinitions {
+    type Domain = BitSet;
+
+    const DIRECTION: Direction = Direction::Forward;
+
+    fn bottom(&self, _: &Body) -> BitSet {
+        BitSet::new_empty(self.definitions.len())
+    }
+
+    fn boundary(&self, _: &Body) -> BitSet {
+        let mut state = BitSet::new_empty(self.definitions.len());
+        for (i, definition) in self.definitions.iter().enumerate() {
+            if !matches!(definition.site, DefinitionSite::Assignment(_)) {
+                state.insert(i);
+            }
+        }
+        state
+    }
+
+    fn join(&self, state: &mut BitSet, other: &BitSet) {
+        state.union(other);
+    }
+
+    fn statement_effect(&self, state: &mut BitSet, statement: &Statement, location: Location) {
+        if let Statement::Assign(local, _) = statement {
+            for &definition in &self.by_local[local.0] {
+                state.remove(definition);
+            }
+            state.insert(self.by_location[&location]);
+        }
+    }
+
+    fn terminator_effect(&self, _: &mut BitSet, _: &Terminator, _: Location) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::mir;
+    use crate::parser::parse;
+
+    fn body(source: &str) -> Body {
+        let module = mir::lower(&parse(&lex(source).unwrap()).unwrap());
+        module.bodies.into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn test_bit_set() {
+        let mut set = BitSet::new_empty(130);
+        assert!(set.insert(3));
+        assert!(!set.insert(3));
+        assert!(set.insert(129));
+        let mut other = BitSet::new_empty(130);
+        other.insert(64);
+        assert!(set.union(&other));
+        assert!(!set.union(&other));
+        assert_eq!(set.iter().collect::<Vec<_>>(), [3, 64, 129]);
+        assert!(set.remove(64));
+        assert!(!set.contains(64));
+    }
+
+    #[test]
+    fn test_liveness() {
+        // x is read after the loop, so each store to it is live
+        let body = body(
+            "int f(int n) {\n\
+                 int x = 0;\n\
+                 int unused = 5;\n\
+                 for (int i = 0; i < n; i = i + 1) { x = x + i; }\n\
+                 return x;\n\
+             }",
+        );
+        let results = solve(&Liveness, &body);
+        let mut stores = Vec::new();
+        for id in body.block_ids() {
+            results.visit(&Liveness, &body, id, |location, live| {
+                let statements = &body.block(location.block).statements;
+                if let Some(Statement::Assign(local, _)) = statements.get(location.statement) {
+                    stores.push((*local, live.contains(local.0)));
+                }
+            });
+        }
+        let dead: Vec<_> = stores
+            .iter()
+            .filter(|(_, live)| !live)
+            .map(|(local, _)| body.locals[local.0].name.map(|name| name.as_str()))
+            .collect();
+        assert_eq!(dead, [Some("unused")]);
+    }
+
+    #[test]
+    fn test_reaching_definitions() {
+        let body = body(
+            "int f(int c) {\n\
+                 int x;\n\
+                 if (c) { x = 1; }\n\
+                 return x;\n\
+             }",
+        );
+        let analysis = ReachingDefinitions::new(&body);
+        let results = solve(&analysis, &body);
+        let x = LocalId(1);
+        let ret = body
+            .block_ids()
+            .find(|&id| matches!(body.block(id).terminator, Terminator::Return(Some(_))))
+            .unwrap();
+        let mut sites = Vec::new();
+        results.visit(&analysis, &body, ret, |location, state| {
+            if location.statement == body.block(ret).statements.len() {
+                sites = analysis.reaching(state, x).map(|d| d.site).collect();
+            }
+        });
+        // Either the assignment in the branch, or no assignment at all
+        assert_eq!(sites.len(), 2);
+        assert!(sites.contains(&DefinitionSite::Uninitialized));
+        assert!(
+            sites
+                .iter()
+                .any(|site| matches!(site, DefinitionSite::Assignment(_)))
+        );
+    }
+}
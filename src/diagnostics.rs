@@ -0,0 +1,621 @@
+//! # Diagnostics
+//!
+//! This module provides a unified representation of the messages the compiler
+//! reports to users. Errors from every phase are converted into [`Diagnostic`]
+//! values, which carry a severity, a stable error code, an optional source
+//! span, and any notes or suggestions.
+//!
+//! ## Output Formats
+//!
+//! - **Human**: rustc-style text with a severity tag, the source location, and
+//!   a snippet of the offending line, optionally colored with ANSI escapes
+//! - **JSON**: One JSON object per line, similar to rustc's
+//!   `--error-format=json`, for editors and CI systems
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::IsTerminal;
+use std::str::FromStr;
+
+use crate::analysis::Warning;
+use crate::error::{CompileError, LexErrorKind, ParseError, SemanticError};
+use crate::lexer::{self, Token};
+use crate::options::CompileOptions;
+
+/// A half-open range of byte offsets into the source code.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    /// Byte offset of the first character
+    pub start: usize,
+    /// Byte offset one past the last character
+    pub end: usize,
+}
+
+impl Span {
+    /// Creates a new span.
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Returns the 1-based line and column of the start of the span.
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        let before = &source[..self.start.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let column = before[line_start..].chars().count() + 1;
+        (line, column)
+    }
+}
+
+/// The severity of a diagnostic.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Severity {
+    /// A problem that prevents compilation
+    Error,
+    /// A likely mistake that does not prevent compilation
+    Warning,
+    /// Additional information
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// The format used to print diagnostics.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum MessageFormat {
+    /// Human-readable text
+    #[default]
+    Human,
+    /// Newline-delimited JSON records
+    Json,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(MessageFormat::Human),
+            "json" => Ok(MessageFormat::Json),
+            _ => Err(format!(
+                "invalid message format '{}': expected human or json",
+                s
+            )),
+        }
+    }
+}
+
+/// When to color human-readable diagnostics.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ColorChoice {
+    /// Color if stderr is a terminal and `NO_COLOR` is not set
+    #[default]
+    Auto,
+    /// Always color
+    Always,
+    /// Never color
+    Never,
+}
+
+impl ColorChoice {
+    /// Decides whether output written to stderr should be colored.
+    pub fn should_color(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none() && std::io::stderr().is_terminal()
+            }
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!(
+                "invalid color choice '{}': expected auto, always or never",
+                s
+            )),
+        }
+    }
+}
+
+/// ANSI escape sequences used when rendering colored diagnostics.
+mod ansi {
+    pub const RESET: &str = "\x1b[0m";
+    pub const BOLD: &str = "\x1b[1m";
+    pub const RED: &str = "\x1b[1;31m";
+    pub const YELLOW: &str = "\x1b[1;33m";
+    pub const CYAN: &str = "\x1b[1;36m";
+    pub const BLUE: &str = "\x1b[1;34m";
+}
+
+/// A message reported to the user.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    /// How serious the diagnostic is
+    pub severity: Severity,
+    /// Stable error code, e.g. `E0201`
+    pub code: &'static str,
+    /// The main message
+    pub message: String,
+    /// The source file the diagnostic refers to
+    pub file: Option<String>,
+    /// The location in the source file
+    pub span: Option<Span>,
+    /// Additional explanatory notes
+    pub notes: Vec<String>,
+    /// Suggested fixes
+    pub suggestions: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Creates an error diagnostic.
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            file: None,
+            span: None,
+            notes: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    /// Creates a warning diagnostic.
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(code, message)
+        }
+    }
+
+    /// Attaches a source span.
+    pub fn with_span(mut self, span: Option<Span>) -> Self {
+        self.span = span;
+        self
+    }
+
+    /// Attaches a source file name.
+    pub fn with_file(mut self, file: Option<&str>) -> Self {
+        self.file = file.map(str::to_string);
+        self
+    }
+
+    /// Renders the diagnostic as human-readable text in the style of rustc.
+    ///
+    /// When `source` is given and the diagnostic has a span, the location and
+    /// the offending line are shown with the span underlined.
+    pub fn render(&self, source: Option<&str>, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("{}{}{}", style, text, ansi::RESET)
+            } else {
+                text.to_string()
+            }
+        };
+        let severity_style = match self.severity {
+            Severity::Error => ansi::RED,
+            Severity::Warning => ansi::YELLOW,
+            Severity::Note => ansi::CYAN,
+        };
+
+        let mut out = format!(
+            "{}{}",
+            paint(severity_style, &format!("{}[{}]", self.severity, self.code)),
+            paint(ansi::BOLD, &format!(": {}", self.message))
+        );
+
+        let location = self.span.zip(source).map(|(span, source)| {
+            let (line, column) = span.line_col(source);
+            (span, source, line, column)
+        });
+        match (location, &self.file) {
+            (Some((span, source, line, column)), file) => {
+                let file = file.as_deref().unwrap_or("<source>");
+                let text = source.lines().nth(line - 1).unwrap_or("");
+                let gutter = " ".repeat(line.to_string().len());
+                let width = source[span.start.min(source.len())..span.end.min(source.len())]
+                    .chars()
+                    .take_while(|c| *c != '\n')
+                    .count()
+                    .max(1);
+                out.push_str(&format!(
+                    "\n{}{} {}:{}:{}",
+                    gutter,
+                    paint(ansi::BLUE, "-->"),
+                    file,
+                    line,
+                    column
+                ));
+                out.push_str(&format!("\n{} {}", gutter, paint(ansi::BLUE, "|")));
+                out.push_str(&format!(
+                    "\n{} {}",
+                    paint(ansi::BLUE, &format!("{} |", line)),
+                    text
+                ));
+                out.push_str(&format!(
+                    "\n{} {} {}{}",
+                    gutter,
+                    paint(ansi::BLUE, "|"),
+                    " ".repeat(column - 1),
+                    paint(severity_style, &"^".repeat(width))
+                ));
+            }
+            (None, Some(file)) => {
+                out.push_str(&format!("\n {} {}", paint(ansi::BLUE, "-->"), file));
+            }
+            (None, None) => {}
+        }
+
+        for note in &self.notes {
+            out.push_str(&format!("\n  {} {}", paint(ansi::BLUE, "="), note));
+        }
+        for suggestion in &self.suggestions {
+            out.push_str(&format!(
+                "\n  {} {}",
+                paint(ansi::BLUE, "= help:"),
+                suggestion
+            ));
+        }
+        out
+    }
+
+    /// Serializes the diagnostic as a single-line JSON object.
+    ///
+    /// When `source` is given, the span additionally includes the 1-based
+    /// line and column of its start.
+    pub fn to_json(&self, source: Option<&str>) -> String {
+        let span = match (self.span, source) {
+            (Some(span), Some(source)) => {
+                let (line, column) = span.line_col(source);
+                format!(
+                    "{{\"start\":{},\"end\":{},\"line\":{},\"column\":{}}}",
+                    span.start, span.end, line, column
+                )
+            }
+            (Some(span), None) => format!("{{\"start\":{},\"end\":{}}}", span.start, span.end),
+            (None, _) => "null".to_string(),
+        };
+        format!(
+            "{{\"severity\":{},\"code\":{},\"message\":{},\"file\":{},\"span\":{},\"notes\":{},\"suggestions\":{}}}",
+            json_string(&self.severity.to_string()),
+            json_string(self.code),
+            json_string(&self.message),
+            self.file.as_deref().map_or("null".to_string(), json_string),
+            span,
+            json_array(&self.notes),
+            json_array(&self.suggestions),
+        )
+    }
+}
+
+/// Returns the diagnostic code for a semantic error.
+fn semantic_code(error: &SemanticError) -> &'static str {
+    match error {
+        SemanticError::UndefinedVariable(..) => "E0201",
+        SemanticError::DuplicateVariable(_) => "E0202",
+        SemanticError::TypeMismatch(_) => "E0203",
+        SemanticError::UndefinedFunction(..) => "E0204",
+        SemanticError::WrongArgumentCount(..) => "E0205",
+        SemanticError::ReturnTypeMismatch(_) => "E0206",
+        SemanticError::InvalidFormat(_) => "E0207",
+        SemanticError::InvalidAssignmentTarget(_) => "E0208",
+        SemanticError::InvalidConstant(_) => "E0209",
+        SemanticError::MisplacedAssertion => "E0210",
+        SemanticError::DuplicateFunction(_) => "E0211",
+        SemanticError::ConflictingDeclaration(..) => "E0212",
+        SemanticError::InvalidEntry(..) => "E0213",
+        SemanticError::ConflictingDefinition(..) => "E0214",
+        SemanticError::IntegerOutOfRange(..) => "E0215",
+    }
+}
+
+impl From<&CompileError> for Vec<Diagnostic> {
+    fn from(error: &CompileError) -> Self {
+        match error {
+            CompileError::Lex(e) => {
+                let code = match e.kind {
+                    LexErrorKind::InvalidToken
+                    | LexErrorKind::NonAsciiCharacter(_)
+                    | LexErrorKind::NonAsciiIdentifier
+                    | LexErrorKind::InvalidUnicodeEscape => "E0001",
+                    LexErrorKind::IntegerOutOfRange | LexErrorKind::FloatOutOfRange => "E0002",
+                    LexErrorKind::IdentifierTooLong { .. } | LexErrorKind::StringTooLong { .. } => {
+                        "E0003"
+                    }
+                };
+                vec![Diagnostic::error(code, e.to_string()).with_span(Some(e.span))]
+            }
+            CompileError::Parse(e, span) => {
+                let code = match e {
+                    ParseError::Syntax { .. } => "E0101",
+                    ParseError::TooDeep { .. } => "E0102",
+                    ParseError::TooLarge { .. } => "E0103",
+                    ParseError::TooLong { .. } => "E0104",
+                };
+                let mut diagnostic = Diagnostic::error(code, e.to_string()).with_span(*span);
+                if let ParseError::Syntax {
+                    hint: Some(hint), ..
+                } = e
+                {
+                    diagnostic.suggestions.push(hint.clone());
+                }
+                vec![diagnostic]
+            }
+            CompileError::Semantic(errors, spans) => errors
+                .iter()
+                .enumerate()
+                .map(|(i, e)| {
+                    let mut diagnostic = Diagnostic::error(semantic_code(e), e.to_string())
+                        .with_span(spans.get(i).copied().flatten());
+                    if let Some(name) = e.suggestion() {
+                        diagnostic
+                            .suggestions
+                            .push(format!("did you mean '{}'?", name));
+                    }
+                    diagnostic
+                })
+                .collect(),
+            CompileError::Codegen(e) => {
+                let mut diagnostic = Diagnostic::error("E0301", e.to_string());
+                if let (Some(function), Some(ir)) = (&e.function, &e.function_ir) {
+                    diagnostic.notes.push(format!(
+                        "the IR of function '{}':\n{}",
+                        function,
+                        ir.trim_end()
+                    ));
+                }
+                match &e.module_ir {
+                    Some(ir) => diagnostic.notes.push(format!(
+                        "the IR of the module when the error occurred:\n{}",
+                        ir.trim_end()
+                    )),
+                    None => diagnostic.suggestions.push(
+                        "rerun with --dump-ir-on-error to see the IR of the whole module"
+                            .to_string(),
+                    ),
+                }
+                vec![diagnostic]
+            }
+            CompileError::Link(e) => {
+                let mut diagnostic = Diagnostic::error("E0401", e.to_string());
+                if !e.is_program_error() {
+                    diagnostic
+                        .notes
+                        .push("run `virtuc doctor` to check the build environment".to_string());
+                }
+                vec![diagnostic]
+            }
+            CompileError::Io(msg) => vec![Diagnostic::error("E0501", msg.clone())],
+            CompileError::Internal(e) => {
+                let mut diagnostic = Diagnostic::error("E0601", e.to_string());
+                diagnostic.notes.push(format!(
+                    "this is a bug in virtuc; please file an issue at {}",
+                    crate::ice::BUG_REPORT_URL
+                ));
+                vec![diagnostic]
+            }
+            CompileError::Import { file, error } => Vec::from(error.as_ref())
+                .into_iter()
+                // Errors of nested imports already name their file
+                .map(|diagnostic| match diagnostic.file {
+                    Some(_) => diagnostic,
+                    None => diagnostic.with_file(Some(file)),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Converts warnings about the program of `source` into diagnostics, each
+/// located at the name of the function it is about where `source` declares
+/// that function.
+pub fn locate_warnings(
+    warnings: &[Warning],
+    source: &str,
+    options: &CompileOptions,
+) -> Vec<Diagnostic> {
+    let declarations = declaration_spans(source, options);
+    warnings
+        .iter()
+        .map(|warning| {
+            Diagnostic::from(warning).with_span(declarations.get(warning.function()).copied())
+        })
+        .collect()
+}
+
+/// Returns the span of the name of each function that `source` declares:
+/// an identifier followed by `(` outside of any braces.
+fn declaration_spans(source: &str, options: &CompileOptions) -> HashMap<String, Span> {
+    let Ok(tokens) = lexer::lex_with_options(source, options) else {
+        return HashMap::new();
+    };
+    let mut spans = HashMap::new();
+    let mut depth = 0usize;
+    for (i, (token, span)) in tokens.iter().enumerate() {
+        match token {
+            Token::LBrace => depth += 1,
+            Token::RBrace => depth = depth.saturating_sub(1),
+            Token::Identifier(name)
+                if depth == 0 && matches!(tokens.get(i + 1), Some((Token::LParen, _))) =>
+            {
+                spans.entry(name.to_string()).or_insert(*span);
+            }
+            _ => {}
+        }
+    }
+    spans
+}
+
+impl From<&Warning> for Diagnostic {
+    fn from(warning: &Warning) -> Self {
+        Diagnostic::warning(warning.code(), warning.to_string())
+    }
+}
+
+/// Encodes a string as a JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Encodes a list of strings as a JSON array.
+fn json_array(items: &[String]) -> String {
+    let items: Vec<String> = items.iter().map(|s| json_string(s)).collect();
+    format!("[{}]", items.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{CodegenError, LexerError};
+
+    #[test]
+    fn test_span_line_col() {
+        let source = "int x;\nint y = @;";
+        let span = Span::new(15, 16);
+        assert_eq!(span.line_col(source), (2, 9));
+    }
+
+    #[test]
+    fn test_json_output() {
+        let diagnostic = Diagnostic::error("E0201", "Undefined variable: \"x\"")
+            .with_file(Some("main.c"))
+            .with_span(Some(Span::new(4, 5)));
+        assert_eq!(
+            diagnostic.to_json(Some("int x;")),
+            r#"{"severity":"error","code":"E0201","message":"Undefined variable: \"x\"","file":"main.c","span":{"start":4,"end":5,"line":1,"column":5},"notes":[],"suggestions":[]}"#
+        );
+    }
+
+    #[test]
+    fn test_render_plain() {
+        let source = "int main() {\n  return y;\n}";
+        let diagnostic = Diagnostic::error("E0201", "Undefined variable: y")
+            .with_file(Some("main.c"))
+            .with_span(Some(Span::new(22, 23)));
+        assert_eq!(
+            diagnostic.render(Some(source), false),
+            "error[E0201]: Undefined variable: y\n --> main.c:2:10\n  |\n2 |   return y;\n  |          ^"
+        );
+    }
+
+    #[test]
+    fn test_render_color() {
+        let diagnostic = Diagnostic::error("E0501", "I/O error");
+        let plain = diagnostic.render(None, false);
+        let colored = diagnostic.render(None, true);
+        assert_eq!(plain, "error[E0501]: I/O error");
+        assert!(colored.starts_with("\x1b[1;31merror[E0501]"));
+        assert!(colored.ends_with(ansi::RESET));
+    }
+
+    #[test]
+    fn test_color_choice() {
+        assert_eq!("always".parse::<ColorChoice>(), Ok(ColorChoice::Always));
+        assert!(ColorChoice::Always.should_color());
+        assert!(!ColorChoice::Never.should_color());
+        assert!("sometimes".parse::<ColorChoice>().is_err());
+    }
+
+    #[test]
+    fn test_compile_error_to_diagnostics() {
+        let error = CompileError::Semantic(
+            vec![
+                SemanticError::UndefinedVariable("x".to_string(), None),
+                SemanticError::UndefinedFunction("f".to_string(), Some("g".to_string())),
+            ],
+            vec![Some(Span::new(5, 8))],
+        );
+        let diagnostics: Vec<Diagnostic> = (&error).into();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].code, "E0201");
+        assert_eq!(diagnostics[1].code, "E0204");
+        assert!(diagnostics[0].suggestions.is_empty());
+        assert_eq!(diagnostics[1].suggestions, vec!["did you mean 'g'?"]);
+        assert_eq!(diagnostics[0].span, Some(Span::new(5, 8)));
+        assert_eq!(diagnostics[1].span, None);
+
+        let error = CompileError::Lex(LexerError::new(LexErrorKind::InvalidToken, Span::new(3, 4)));
+        let diagnostics: Vec<Diagnostic> = (&error).into();
+        assert_eq!(diagnostics[0].span, Some(Span::new(3, 4)));
+
+        let error = CompileError::Lex(LexerError::new(
+            LexErrorKind::IntegerOutOfRange,
+            Span::new(0, 20),
+        ));
+        let diagnostics: Vec<Diagnostic> = (&error).into();
+        assert_eq!(diagnostics[0].code, "E0002");
+
+        let mut codegen = CodegenError {
+            message: "function 'f' failed verification".to_string(),
+            function: Some("f".to_string()),
+            function_ir: Some("define i64 @f() {\n}\n".to_string()),
+            module_ir: None,
+        };
+        let diagnostics: Vec<Diagnostic> = (&CompileError::Codegen(codegen.clone())).into();
+        assert_eq!(
+            diagnostics[0].notes,
+            vec!["the IR of function 'f':\ndefine i64 @f() {\n}"]
+        );
+        assert!(diagnostics[0].suggestions[0].contains("--dump-ir-on-error"));
+        codegen.module_ir = Some("; ModuleID = 'main'\n".to_string());
+        let diagnostics: Vec<Diagnostic> = (&CompileError::Codegen(codegen)).into();
+        assert_eq!(diagnostics[0].notes.len(), 2);
+        assert!(diagnostics[0].suggestions.is_empty());
+    }
+
+    #[test]
+    fn test_semantic_errors_are_located() {
+        let source = "int main() {\n    for (int i = 1.5; i < 3; i = i + 1) {\n        if (1) { return x; }\n    }\n    return 0;\n}\n";
+        let error = crate::check(source).unwrap_err();
+        let lines: Vec<_> = Vec::from(&error)
+            .iter()
+            .map(|diagnostic| diagnostic.span.map(|span| span.line_col(source)))
+            .collect();
+        // The initializer of a loop is located at the loop
+        assert_eq!(lines, [Some((2, 5)), Some((3, 18))]);
+    }
+
+    #[test]
+    fn test_warnings_are_located_at_their_function() {
+        let source = "extern int abs(int);\nint helper() { return 1; }\nint main() { return 0; }";
+        let program = crate::check(source).unwrap();
+        let warnings = crate::analysis::lint(&program);
+        let diagnostics = locate_warnings(&warnings, source, &CompileOptions::default());
+        let located: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.span.unwrap().line_col(source)))
+            .collect();
+        assert_eq!(located, [("W0002", (2, 5)), ("W0003", (1, 12))]);
+    }
+}
@@ -0,0 +1,256 @@
+//! # Environment Diagnostics
+//!
+//! Most failures on a first run come from the environment rather than the
+//! program: a missing C compiler, an LLVM without the host target, or a
+//! linker that cannot find the C library. [`run_checks`] looks for each of
+//! these and suggests a fix, and backs `virtuc doctor`.
+
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::process::Command;
+
+use inkwell::OptimizationLevel;
+use inkwell::targets::{CodeModel, InitializationConfig, RelocMode, Target, TargetMachine};
+
+use crate::options::CompileOptions;
+
+/// The outcome of a check.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Status {
+    /// The check passed
+    Ok,
+    /// Something optional is missing
+    Warning,
+    /// Compilation will fail
+    Error,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Padded, so that checks line up
+        f.pad(match self {
+            Status::Ok => "ok",
+            Status::Warning => "warning",
+            Status::Error => "error",
+        })
+    }
+}
+
+/// A check of the environment.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Check {
+    /// What was checked, e.g. `C compiler`
+    pub name: &'static str,
+    /// Whether the check passed
+    pub status: Status,
+    /// What was found
+    pub detail: String,
+    /// How to fix a failed check
+    pub remedy: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, detail: String) -> Self {
+        Self {
+            name,
+            status: Status::Ok,
+            detail,
+            remedy: None,
+        }
+    }
+
+    fn failed(name: &'static str, status: Status, detail: String, remedy: String) -> Self {
+        Self {
+            name,
+            status,
+            detail,
+            remedy: Some(remedy),
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:<8}{}: {}", self.status, self.name, self.detail)?;
+        if let Some(remedy) = &self.remedy {
+            write!(f, "\n        help: {}", remedy)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that the environment can compile programs with the C compiler and
+/// archiver of `options`.
+pub fn run_checks(options: &CompileOptions) -> Vec<Check> {
+    let mut checks = vec![llvm(), target()];
+    let cc = c_compiler(options.cc());
+    let cc_found = cc.status == Status::Ok;
+    checks.push(cc);
+    if cc_found {
+        checks.push(link(options));
+    }
+    checks.push(archiver(options.ar()));
+    checks
+}
+
+/// The LLVM version inkwell is built against, as selected by its feature
+/// in Cargo.toml.
+const LLVM_BUILD_VERSION: (u32, u32) = (19, 1);
+
+/// Reports the LLVM version linked into virtuc, and whether it is the one
+/// inkwell was built against.
+fn llvm() -> Check {
+    let (major, minor, patch) = inkwell::support::get_llvm_version();
+    let (build_major, build_minor) = LLVM_BUILD_VERSION;
+    let detail = format!(
+        "{}.{}.{} (built against {}.{})",
+        major, minor, patch, build_major, build_minor
+    );
+    if major == build_major {
+        Check::ok("LLVM", detail)
+    } else {
+        Check::failed(
+            "LLVM",
+            Status::Warning,
+            detail,
+            format!(
+                "point LLVM_SYS_{}{}_PREFIX at an LLVM {}.{} installation and rebuild virtuc",
+                build_major, build_minor, build_major, build_minor
+            ),
+        )
+    }
+}
+
+/// Checks that LLVM can generate code for the host.
+fn target() -> Check {
+    let triple = TargetMachine::get_default_triple();
+    let name = triple.as_str().to_string_lossy().into_owned();
+    let machine = Target::initialize_native(&InitializationConfig::default())
+        .and_then(|()| Target::from_triple(&triple).map_err(|e| e.to_string()))
+        .and_then(|target| {
+            target
+                .create_target_machine(
+                    &triple,
+                    "generic",
+                    "",
+                    OptimizationLevel::Default,
+                    RelocMode::PIC,
+                    CodeModel::Default,
+                )
+                .ok_or_else(|| "cannot create a target machine".to_string())
+        });
+    match machine {
+        Ok(_) => Check::ok("target", name),
+        Err(e) => Check::failed(
+            "target",
+            Status::Error,
+            format!("{}: {}", name, e),
+            "use an LLVM build that includes the host target".to_string(),
+        ),
+    }
+}
+
+/// Checks that the C compiler runs.
+fn c_compiler(cc: &str) -> Check {
+    match Command::new(cc).arg("--version").output() {
+        Ok(out) if out.status.success() => {
+            let version = String::from_utf8_lossy(&out.stdout);
+            let version = version.lines().next().unwrap_or("").trim();
+            Check::ok("C compiler", format!("{} ({})", cc, version))
+        }
+        Ok(out) => Check::failed(
+            "C compiler",
+            Status::Error,
+            format!("'{}' failed to report its version ({})", cc, out.status),
+            format!("check that '{}' is a working C compiler", cc),
+        ),
+        Err(e) if e.kind() == ErrorKind::NotFound => Check::failed(
+            "C compiler",
+            Status::Error,
+            format!("'{}' not found", cc),
+            "install clang (e.g. `apt install clang`, or `xcode-select --install` on macOS), \
+             or select another compiler with --cc <path>"
+                .to_string(),
+        ),
+        Err(e) => Check::failed(
+            "C compiler",
+            Status::Error,
+            format!("cannot run '{}': {}", cc, e),
+            format!("check the permissions of '{}'", cc),
+        ),
+    }
+}
+
+/// Compiles and runs a minimal program.
+fn link(options: &CompileOptions) -> Check {
+    let dir = std::env::temp_dir().join(format!("virtuc-doctor-{}", std::process::id()));
+    let output = dir.join("hello");
+    let result = fs::create_dir_all(&dir)
+        .map_err(|e| e.to_string())
+        .and_then(|()| {
+            crate::compile_with_options("int main() { return 0; }", &output, options)
+                .map_err(|e| e.to_string())
+        })
+        .and_then(|_| {
+            let status = Command::new(&output).status().map_err(|e| e.to_string())?;
+            if status.success() {
+                Ok(())
+            } else {
+                Err(format!("the compiled program exited with {}", status))
+            }
+        });
+    let _ = fs::remove_dir_all(&dir);
+    match result {
+        Ok(()) => Check::ok("linking", "compiled and ran a test program".to_string()),
+        Err(e) => Check::failed(
+            "linking",
+            Status::Error,
+            e,
+            format!(
+                "check that '{}' can link C programs, e.g. that the C library is installed",
+                options.cc()
+            ),
+        ),
+    }
+}
+
+/// Checks that the archiver for static libraries runs.
+fn archiver(ar: &str) -> Check {
+    // Not every archiver has a --version flag, so only check that it runs
+    match Command::new(ar).arg("--version").output() {
+        Ok(_) => Check::ok("archiver", ar.to_string()),
+        Err(e) => Check::failed(
+            "archiver",
+            Status::Warning,
+            format!("cannot run '{}': {}", ar, e),
+            "install binutils or llvm-ar to build static libraries, \
+             or select another archiver with --ar <path>"
+                .to_string(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_tools() {
+        let options = CompileOptions {
+            cc: Some("virtuc-no-such-cc".to_string()),
+            ar: Some("virtuc-no-such-ar".to_string()),
+            ..CompileOptions::default()
+        };
+        let checks = run_checks(&options);
+        let names: Vec<_> = checks.iter().map(|check| check.name).collect();
+        // Linking is not attempted without a compiler
+        assert_eq!(names, ["LLVM", "target", "C compiler", "archiver"]);
+        assert_eq!(checks[2].status, Status::Error);
+        assert!(checks[2].remedy.as_ref().unwrap().contains("--cc"));
+        assert_eq!(checks[3].status, Status::Warning);
+        assert!(checks[2].to_string().starts_with(
+            "error   C compiler: 'virtuc-no-such-cc' not found\n        help: install clang"
+        ));
+    }
+}
@@ -0,0 +1,230 @@
+//! # Embedded Build Information
+//!
+//! Executables compiled with [`CompileOptions::embed_source`] carry a record
+//! of how they were built: the virtuc version, the options that affect code
+//! generation, and the source itself. `virtuc inspect` reads it back, so that
+//! a shipped binary can be traced to the program it came from.
+//!
+//! ## Format
+//!
+//! The record is stored in a `.virtuc` section (`__DATA,__virtuc` on Apple
+//! targets) as [`MAGIC`] followed by four fields, each a little-endian `u32`
+//! byte length and its bytes: the version, the source name (empty if
+//! unknown), and the options as UTF-8 text, and the source as UTF-8 text
+//! compressed with raw DEFLATE. The last byte of [`MAGIC`] is the format
+//! version; records of other versions are not recognized.
+//!
+//! Readers find the record by scanning for the magic bytes rather than by
+//! parsing the executable format, so any executable or object file works.
+
+use miniz_oxide::deflate::compress_to_vec;
+use miniz_oxide::inflate::decompress_to_vec_with_limit;
+
+use crate::options::CompileOptions;
+
+/// Bytes that start an embedded record.
+pub const MAGIC: &[u8] = b"\x7fVIRTUC-EMBED\x02";
+
+/// DEFLATE level of the source, the highest: sources are small, and
+/// compressed once per build.
+const COMPRESSION_LEVEL: u8 = 10;
+
+/// Longest source a record is decompressed to, so that a corrupt or crafted
+/// record cannot exhaust memory.
+const MAX_SOURCE_LEN: usize = 64 << 20;
+
+/// Name of the section holding the record on the given target.
+pub fn section(triple: &str) -> &'static str {
+    if triple.contains("apple") {
+        "__DATA,__virtuc"
+    } else {
+        ".virtuc"
+    }
+}
+
+/// How an executable was built.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Embedded {
+    /// Version of the compiler
+    pub version: String,
+    /// Name of the source file, if known
+    pub source_name: Option<String>,
+    /// The options that affect the program, as command-line flags
+    pub options: String,
+    /// The source of the program
+    pub source: String,
+}
+
+impl Embedded {
+    /// Describes a compilation of `source` by this compiler.
+    pub fn new(source: &str, options: &CompileOptions) -> Self {
+        Self {
+            version: crate::VERSION.to_string(),
+            source_name: options.recorded_source_name().map(str::to_string),
+            options: describe_options(options),
+            source: source.to_string(),
+        }
+    }
+
+    /// Encodes the record, magic bytes included.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        let source = compress_to_vec(self.source.as_bytes(), COMPRESSION_LEVEL);
+        let fields = [
+            self.version.as_bytes(),
+            self.source_name.as_deref().unwrap_or("").as_bytes(),
+            self.options.as_bytes(),
+            &source,
+        ];
+        for field in fields {
+            bytes.extend_from_slice(&(field.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(field);
+        }
+        bytes
+    }
+
+    /// Decodes a record from the start of `bytes`, magic bytes included.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut rest = bytes.strip_prefix(MAGIC)?;
+        let mut field = || {
+            let len = u32::from_le_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+            let bytes = rest.get(4..4 + len)?;
+            rest = &rest[4 + len..];
+            Some(bytes)
+        };
+        let text = |bytes: &[u8]| String::from_utf8(bytes.to_vec()).ok();
+        let version = text(field()?)?;
+        let source_name = Some(text(field()?)?).filter(|name| !name.is_empty());
+        let options = text(field()?)?;
+        let source = decompress_to_vec_with_limit(field()?, MAX_SOURCE_LEN).ok()?;
+        let source = text(&source)?;
+        Some(Self {
+            version,
+            source_name,
+            options,
+            source,
+        })
+    }
+}
+
+/// Finds the record embedded in an executable or object file.
+pub fn find(binary: &[u8]) -> Option<Embedded> {
+    // The magic bytes may also occur as data, e.g. in virtuc itself, so
+    // take the first occurrence that decodes
+    binary
+        .windows(MAGIC.len())
+        .enumerate()
+        .filter(|(_, window)| *window == MAGIC)
+        .find_map(|(i, _)| Embedded::decode(&binary[i..]))
+}
+
+/// Describes the options that affect the compiled program as `virtuc
+/// compile` flags, e.g. `--int-width 32 -O2 -DNDEBUG`.
+pub fn describe_options(options: &CompileOptions) -> String {
+    let mut flags = vec![
+        format!("--int-width {}", options.int_width),
+        format!("-O{}", options.opt_level),
+    ];
+    if options.ndebug {
+        flags.push("-DNDEBUG".to_string());
+    }
+    if let Some(limit) = options.recursion_limit {
+        flags.push(format!("--checked-recursion={}", limit));
+    }
+    for sanitizer in &options.sanitizers {
+        flags.push(format!("--sanitize={}", sanitizer));
+    }
+    for lib in &options.link_libs {
+        flags.push(format!("-l {}", lib));
+    }
+    if let Some(unroll) = options.unroll_loops {
+        flags.push(format!(
+            "--f{}unroll-loops",
+            if unroll { "" } else { "no-" }
+        ));
+    }
+    if let Some(vectorize) = options.vectorize {
+        flags.push(format!(
+            "--f{}vectorize",
+            if vectorize { "" } else { "no-" }
+        ));
+    }
+    if options.profile_generate.is_some() {
+        flags.push("--profile-generate".to_string());
+    }
+    if options.profile_use.is_some() {
+        flags.push("--profile-use".to_string());
+    }
+    if let Some(exports) = &options.exports {
+        flags.push(format!("--export={}", exports.join(",")));
+    }
+    if let Some(entry) = &options.entry {
+        flags.push(format!("--entry={}", entry));
+    }
+    if options.hardening {
+        flags.push("--hardening".to_string());
+    }
+    if options.strip {
+        flags.push("--strip".to_string());
+    }
+    if options.lto {
+        flags.push("--lto".to_string());
+    }
+    if options.uses_fast_math() {
+        flags.push("--ffast-math".to_string());
+    }
+    if options.strip_unused {
+        flags.push("--strip-unused".to_string());
+    }
+    if options.coverage_file.is_some() {
+        flags.push("--coverage".to_string());
+    }
+    flags.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::IntWidth;
+
+    #[test]
+    fn test_round_trip() {
+        let options = CompileOptions {
+            int_width: IntWidth::W32,
+            opt_level: 2,
+            ndebug: true,
+            source_name: Some("hello.c".to_string()),
+            ..CompileOptions::default()
+        };
+        let embedded = Embedded::new("int main() { return 0; }\n", &options);
+        assert_eq!(embedded.options, "--int-width 32 -O2 -DNDEBUG");
+
+        // Records are found anywhere in a binary, skipping stray magic bytes
+        let mut binary = b"\x00ELF".to_vec();
+        binary.extend_from_slice(MAGIC);
+        binary.extend_from_slice(&embedded.encode());
+        binary.extend_from_slice(b"\x00trailing");
+        assert_eq!(find(&binary), Some(embedded));
+        assert_eq!(find(b"no record here"), None);
+    }
+
+    #[test]
+    fn test_source_is_compressed() {
+        let source = "int main() { return 0; }\n".repeat(100);
+        let embedded = Embedded::new(&source, &CompileOptions::default());
+        let encoded = embedded.encode();
+        assert!(encoded.len() < source.len() / 10, "{} bytes", encoded.len());
+        assert_eq!(Embedded::decode(&encoded), Some(embedded));
+
+        // Records of the previous, uncompressed format are not misread
+        let mut old = encoded.clone();
+        old[MAGIC.len() - 1] = 1;
+        assert_eq!(Embedded::decode(&old), None);
+        // Nor are records whose source does not decompress
+        let source_len = compress_to_vec(source.as_bytes(), COMPRESSION_LEVEL).len();
+        let mut corrupt = encoded;
+        let start = corrupt.len() - source_len;
+        corrupt[start..].fill(0xff);
+        assert_eq!(Embedded::decode(&corrupt), None);
+    }
+}
@@ -11,6 +11,8 @@
 //! - **Syntax Errors**: Malformed syntax, parsing failures
 //! - **Semantic Errors**: Type mismatches, undefined variables, scope issues
 //! - **Code Generation Errors**: LLVM IR generation failures
+//! - **Link Errors**: Failures invoking the external C compiler/linker
+//! - **Internal Errors**: Panics inside the compiler, i.e. compiler bugs
 //!
 //! ## Design
 //!
@@ -20,6 +22,10 @@
 
 use std::fmt;
 
+use crate::ast::{Function, Program};
+use crate::diagnostics::Span;
+use crate::options::IntWidth;
+
 /// Represents errors that can occur during lexical analysis.
 ///
 /// This error is produced when the lexer encounters characters or sequences
@@ -31,11 +37,93 @@ use std::fmt;
 ///
 /// Returned by the [`lex`](crate::lexer::lex) function when tokenization fails.
 #[derive(Debug, PartialEq, Clone)]
-pub struct LexerError;
+pub struct LexerError {
+    /// Location of the offending input
+    pub span: Span,
+    /// What is wrong with it
+    pub kind: LexErrorKind,
+}
+
+impl LexerError {
+    /// Creates a lexer error of the given kind.
+    pub fn new(kind: LexErrorKind, span: Span) -> Self {
+        Self { span, kind }
+    }
+}
 
 impl fmt::Display for LexerError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Invalid token encountered")
+        write!(f, "{}", self.kind)
+    }
+}
+
+/// The ways in which a token can be invalid.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub enum LexErrorKind {
+    /// The input matches no token
+    #[default]
+    InvalidToken,
+    /// A non-ASCII character outside of string literals and comments
+    NonAsciiCharacter(char),
+    /// An identifier with non-ASCII characters, which are not allowed
+    NonAsciiIdentifier,
+    /// A `\u` or `\U` escape without enough hex digits, or naming no
+    /// character
+    InvalidUnicodeEscape,
+    /// An integer literal does not fit in 64 bits
+    IntegerOutOfRange,
+    /// A float literal is too large to be represented
+    FloatOutOfRange,
+    /// An identifier is longer than the limit, in bytes
+    IdentifierTooLong {
+        /// The maximum length
+        limit: usize,
+        /// The length of the identifier
+        length: usize,
+    },
+    /// A string literal is longer than the limit, in bytes after escapes
+    StringTooLong {
+        /// The maximum length
+        limit: usize,
+        /// The length of the string
+        length: usize,
+    },
+}
+
+impl fmt::Display for LexErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexErrorKind::InvalidToken => write!(f, "Invalid token encountered"),
+            LexErrorKind::NonAsciiCharacter(c) => write!(
+                f,
+                "Unexpected non-ASCII character '{}' (U+{:04X})",
+                c, *c as u32
+            ),
+            LexErrorKind::NonAsciiIdentifier => write!(
+                f,
+                "Identifiers must be ASCII unless Unicode identifiers are allowed"
+            ),
+            LexErrorKind::InvalidUnicodeEscape => write!(
+                f,
+                "Invalid Unicode escape: expected \\u and 4 hex digits or \\U and 8, naming a character"
+            ),
+            LexErrorKind::IntegerOutOfRange => write!(
+                f,
+                "Integer literal out of range: the largest integer is {}",
+                i64::MAX
+            ),
+            LexErrorKind::FloatOutOfRange => write!(f, "Float literal out of range"),
+            LexErrorKind::IdentifierTooLong { limit, length } => write!(
+                f,
+                "Identifier is {} bytes long, exceeding the limit of {}",
+                length, limit
+            ),
+            LexErrorKind::StringTooLong { limit, length } => write!(
+                f,
+                "String literal is {} bytes long, exceeding the limit of {}",
+                length, limit
+            ),
+        }
     }
 }
 
@@ -43,20 +131,83 @@ impl std::error::Error for LexerError {}
 
 /// Represents errors that can occur during parsing.
 ///
-/// This error wraps error messages from the parser combinator library
-/// when the source code cannot be parsed according to the C subset grammar.
-/// Common causes include missing semicolons, unmatched parentheses, or
-/// malformed expressions/statements.
+/// Most parse errors are syntax errors, which wrap error messages from the
+/// parser combinator library when the source code cannot be parsed according
+/// to the C subset grammar. Common causes include missing semicolons,
+/// unmatched parentheses, or malformed expressions/statements.
+///
+/// Inputs that exceed the parser's resource limits are rejected with
+/// [`ParseError::TooDeep`], [`ParseError::TooLong`], or
+/// [`ParseError::TooLarge`] instead of exhausting the stack.
 ///
 /// # Usage
 ///
 /// Returned by the [`parse`](crate::parser::parse) function when AST construction fails.
 #[derive(Debug, PartialEq, Clone)]
-pub struct ParseError(pub String);
+pub enum ParseError {
+    /// The tokens do not match the grammar
+    Syntax {
+        /// Description of the failure
+        message: String,
+        /// Index of the token at which parsing failed, if known
+        position: Option<usize>,
+        /// How to fix the error, if known
+        hint: Option<String>,
+    },
+    /// Expressions or statements are nested more deeply than the limit
+    TooDeep {
+        /// The maximum nesting depth
+        limit: usize,
+        /// Index of the token at which the limit was exceeded
+        position: usize,
+    },
+    /// An expression chains more operators than the limit
+    TooLong {
+        /// The maximum number of chained operators
+        limit: usize,
+        /// Index of the token at which the limit was exceeded
+        position: usize,
+    },
+    /// The program has more tokens than the limit
+    TooLarge {
+        /// The maximum number of tokens
+        limit: usize,
+        /// The number of tokens in the program
+        size: usize,
+    },
+}
+
+impl ParseError {
+    /// Returns the index of the token at which parsing failed, if known.
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            ParseError::Syntax { position, .. } => *position,
+            ParseError::TooDeep { position, .. } | ParseError::TooLong { position, .. } => {
+                Some(*position)
+            }
+            ParseError::TooLarge { .. } => None,
+        }
+    }
+}
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Parse error: {}", self.0)
+        match self {
+            ParseError::Syntax { message, .. } => write!(f, "Parse error: {}", message),
+            ParseError::TooDeep { limit, .. } => {
+                write!(f, "Parse error: nesting exceeds the limit of {}", limit)
+            }
+            ParseError::TooLong { limit, .. } => write!(
+                f,
+                "Parse error: expression too long, chaining more than {} operators",
+                limit
+            ),
+            ParseError::TooLarge { limit, size } => write!(
+                f,
+                "Parse error: program has {} tokens, exceeding the limit of {}",
+                size, limit
+            ),
+        }
     }
 }
 
@@ -75,33 +226,72 @@ impl std::error::Error for ParseError {}
 /// validation fails.
 #[derive(Debug, PartialEq, Clone)]
 pub enum SemanticError {
-    /// Variable is used but not declared
-    UndefinedVariable(String),
+    /// Variable is used but not declared, with the closest in-scope name
+    UndefinedVariable(String, Option<String>),
     /// Variable is declared multiple times in the same scope
     DuplicateVariable(String),
+    /// Function is defined more than once
+    DuplicateFunction(String),
+    /// Extern declaration conflicts with the declaration of an included
+    /// header: the function name and the header
+    ConflictingDeclaration(String, String),
     /// Type mismatch in assignment or operation
     TypeMismatch(String),
-    /// Function is called but not declared
-    UndefinedFunction(String),
+    /// An integer literal does not fit in the `int` type of the width
+    /// compiled for
+    IntegerOutOfRange(i64, IntWidth),
+    /// Function is called but not declared, with the closest known function
+    UndefinedFunction(String, Option<String>),
     /// Wrong number of arguments in function call
     WrongArgumentCount(String, usize, usize),
     /// Return type mismatch
     ReturnTypeMismatch(String),
+    /// printf format string does not match its arguments
+    InvalidFormat(String),
+    /// The target of an assignment is not an lvalue
+    InvalidAssignmentTarget(String),
+    /// A constant expression has no defined value
+    InvalidConstant(ConstEvalError),
+    /// The builtin `assert` is used as a value rather than a statement
+    MisplacedAssertion,
+    /// The chosen entry point cannot start the program: the function name
+    /// and the reason
+    InvalidEntry(String, String),
+    /// Extern declaration conflicts with the definition of a file compiled
+    /// with it: the function name and the file
+    ConflictingDefinition(String, String),
 }
 
 impl fmt::Display for SemanticError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            SemanticError::UndefinedVariable(name) => {
+            SemanticError::UndefinedVariable(name, _) => {
                 write!(f, "Undefined variable: {}", name)
             }
             SemanticError::DuplicateVariable(name) => {
                 write!(f, "Duplicate variable declaration: {}", name)
             }
+            SemanticError::DuplicateFunction(name) => {
+                write!(f, "Duplicate function definition: {}", name)
+            }
+            SemanticError::ConflictingDeclaration(name, header) => {
+                write!(
+                    f,
+                    "Conflicting declaration of {}: does not match its declaration in <{}>",
+                    name, header
+                )
+            }
             SemanticError::TypeMismatch(msg) => {
                 write!(f, "Type mismatch: {}", msg)
             }
-            SemanticError::UndefinedFunction(name) => {
+            SemanticError::IntegerOutOfRange(value, width) => {
+                write!(
+                    f,
+                    "Integer literal {} does not fit in {}-bit int",
+                    value, width
+                )
+            }
+            SemanticError::UndefinedFunction(name, _) => {
                 write!(f, "Undefined function: {}", name)
             }
             SemanticError::WrongArgumentCount(func, expected, got) => {
@@ -114,28 +304,477 @@ impl fmt::Display for SemanticError {
             SemanticError::ReturnTypeMismatch(msg) => {
                 write!(f, "Return type mismatch: {}", msg)
             }
+            SemanticError::InvalidFormat(msg) => {
+                write!(f, "Invalid format string: {}", msg)
+            }
+            SemanticError::InvalidAssignmentTarget(msg) => {
+                write!(f, "Invalid assignment target: {}", msg)
+            }
+            SemanticError::InvalidConstant(e) => {
+                write!(f, "Invalid constant expression: {}", e)
+            }
+            SemanticError::MisplacedAssertion => {
+                write!(
+                    f,
+                    "Misplaced assertion: assert can only be used as a statement"
+                )
+            }
+            SemanticError::InvalidEntry(name, reason) => {
+                write!(f, "Invalid entry point {}: {}", name, reason)
+            }
+            SemanticError::ConflictingDefinition(name, file) => {
+                write!(
+                    f,
+                    "Conflicting declaration of {}: does not match its definition in {}",
+                    name, file
+                )
+            }
+        }
+    }
+}
+
+impl SemanticError {
+    /// Returns the suggested replacement name, if there is one.
+    pub fn suggestion(&self) -> Option<&str> {
+        match self {
+            SemanticError::UndefinedVariable(_, suggestion)
+            | SemanticError::UndefinedFunction(_, suggestion) => suggestion.as_deref(),
+            _ => None,
         }
     }
 }
 
 impl std::error::Error for SemanticError {}
 
+/// Represents the reasons a constant expression has no value.
+///
+/// # Usage
+///
+/// Returned by [`consteval::eval`](crate::consteval::eval); semantic
+/// analysis reports all but [`NotConstant`](ConstEvalError::NotConstant).
+#[derive(Debug, PartialEq, Clone)]
+pub enum ConstEvalError {
+    /// The expression depends on values only known at run time
+    NotConstant,
+    /// Integer arithmetic overflows the `int` type
+    Overflow,
+    /// Integer division by zero
+    DivisionByZero,
+}
+
+impl fmt::Display for ConstEvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConstEvalError::NotConstant => write!(f, "expression is not constant"),
+            ConstEvalError::Overflow => write!(f, "integer overflow"),
+            ConstEvalError::DivisionByZero => write!(f, "division by zero"),
+        }
+    }
+}
+
+impl std::error::Error for ConstEvalError {}
+
 /// Represents errors that can occur during code generation.
 ///
 /// This error wraps error messages from LLVM IR generation failures.
 /// Common causes include invalid operations, unsupported constructs, or
-/// issues with the inkwell LLVM bindings.
+/// issues with the inkwell LLVM bindings. When LLVM rejects a generated
+/// function, the error carries the verifier's explanation, the function, and
+/// its IR, since the failure is a compiler bug that the IR helps to find.
 ///
 /// # Usage
 ///
 /// Returned by code generation functions when LLVM IR emission fails.
 #[derive(Debug, PartialEq, Clone)]
-pub struct CodegenError(pub String);
+pub struct CodegenError {
+    /// What went wrong, including the verifier's output
+    pub message: String,
+    /// The function being generated, if the error is about one
+    pub function: Option<String>,
+    /// The IR of that function
+    pub function_ir: Option<String>,
+    /// The IR of the whole module at the time of the error, kept when
+    /// [`CompileOptions::dump_ir_on_error`](crate::options::CompileOptions::dump_ir_on_error)
+    /// is set
+    pub module_ir: Option<String>,
+}
+
+impl CodegenError {
+    /// Creates an error with only a message.
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            function: None,
+            function_ir: None,
+            module_ir: None,
+        }
+    }
+}
 
 impl fmt::Display for CodegenError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Code generation error: {}", self.0)
+        write!(f, "Code generation error: {}", self.message)
     }
 }
 
 impl std::error::Error for CodegenError {}
+
+/// Represents errors that can occur while linking the generated IR.
+///
+/// The final executable is produced by an external C compiler (`clang` by
+/// default). These errors carry the compiler's own diagnostics so that users
+/// see why linking failed rather than a generic message.
+///
+/// # Usage
+///
+/// Returned by [`compile`](crate::compile) when the link step fails, and when
+/// archiving a static library fails.
+#[derive(Debug, PartialEq, Clone)]
+pub enum LinkError {
+    /// The C compiler could not be found
+    CompilerNotFound(String),
+    /// The archiver could not be found
+    ArchiverNotFound(String),
+    /// The C compiler ran but failed: (compiler, exit code, captured output)
+    Failed(String, Option<i32>, String),
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LinkError::CompilerNotFound(cc) => {
+                write!(
+                    f,
+                    "C compiler '{}' not found; install clang or select another compiler with --cc <path>",
+                    cc
+                )
+            }
+            LinkError::ArchiverNotFound(ar) => {
+                write!(
+                    f,
+                    "Archiver '{}' not found; install binutils or select another archiver with --ar <path>",
+                    ar
+                )
+            }
+            LinkError::Failed(cc, code, output) => {
+                match code {
+                    Some(code) => write!(f, "Linking with '{}' failed (exit code {})", cc, code)?,
+                    None => write!(f, "Linking with '{}' failed", cc)?,
+                }
+                if !output.trim().is_empty() {
+                    write!(f, ":\n{}", output.trim_end())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+impl LinkError {
+    /// Returns true if linking failed because of the program rather than the
+    /// toolchain, such as when it calls an `extern` function that no library
+    /// defines, or defines a function a library also does.
+    pub fn is_program_error(&self) -> bool {
+        // The wordings of GNU ld, lld, and Apple's linker
+        const SYMBOL_ERRORS: &[&str] = &[
+            "undefined reference to",
+            "undefined symbol",
+            "Undefined symbols",
+            "multiple definition of",
+            "duplicate symbol",
+        ];
+        match self {
+            LinkError::Failed(_, Some(_), output) => {
+                SYMBOL_ERRORS.iter().any(|error| output.contains(error))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Represents an internal compiler error: a panic inside the compiler.
+///
+/// These always indicate a bug in `virtuc` rather than in the program. They
+/// carry what is needed to report the bug: the function being compiled when
+/// the panic occurred and a minimized program that contains it.
+///
+/// # Usage
+///
+/// Produced by [`ice::catch`](crate::ice::catch) around code generation.
+#[derive(Debug, PartialEq, Clone)]
+pub struct InternalError {
+    /// The panic message
+    pub message: String,
+    /// The function being compiled, if known
+    pub function: Option<Box<Function>>,
+    /// A minimized program containing the function, if known
+    pub reproducer: Option<Box<Program>>,
+}
+
+impl fmt::Display for InternalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "internal compiler error: {}", self.message)?;
+        if let Some(function) = &self.function {
+            write!(f, " (while compiling function '{}')", function.name)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InternalError {}
+
+/// Represents malformed LLVM IR text.
+///
+/// # Usage
+///
+/// Returned by [`ir::parse`](crate::ir::parse).
+#[derive(Debug, PartialEq, Clone)]
+pub struct IrParseError {
+    /// The line of the text, counting from 1
+    pub line: usize,
+    /// What is wrong with it
+    pub message: String,
+}
+
+impl fmt::Display for IrParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid IR at line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for IrParseError {}
+
+/// Represents an invalid rewrite of a program template.
+///
+/// # Usage
+///
+/// Returned by the methods of [`Template`](crate::template::Template).
+#[derive(Debug, PartialEq, Clone)]
+pub enum TemplateError {
+    /// The template does not define the function
+    UndefinedFunction(String),
+    /// The template already defines the function
+    DuplicateFunction(String),
+    /// The function has no such parameter: (function, parameter)
+    UndefinedParameter(String, String),
+    /// The value bound to a parameter has another type: (function,
+    /// parameter)
+    MismatchedBinding(String, String),
+    /// The source of a function does not parse
+    Source(Box<CompileError>),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TemplateError::UndefinedFunction(name) => {
+                write!(f, "template has no function '{}'", name)
+            }
+            TemplateError::DuplicateFunction(name) => {
+                write!(f, "template already defines function '{}'", name)
+            }
+            TemplateError::UndefinedParameter(function, param) => {
+                write!(f, "function '{}' has no parameter '{}'", function, param)
+            }
+            TemplateError::MismatchedBinding(function, param) => write!(
+                f,
+                "value bound to parameter '{}' of function '{}' has the wrong type",
+                param, function
+            ),
+            TemplateError::Source(e) => write!(f, "invalid function source: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// Represents errors in a `virtuc.toml` project manifest.
+///
+/// # Usage
+///
+/// Returned by [`Manifest::load`](crate::manifest::Manifest::load) and
+/// [`Manifest::from_toml`](crate::manifest::Manifest::from_toml).
+#[derive(Debug, PartialEq, Clone)]
+pub enum ManifestError {
+    /// The manifest could not be read: (path, reason)
+    Io(String, String),
+    /// The manifest is not valid TOML or has unknown keys
+    Parse(String),
+    /// The manifest is well-formed but its settings are invalid
+    Invalid(String),
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ManifestError::Io(path, reason) => {
+                write!(f, "failed to read manifest '{}': {}", path, reason)
+            }
+            ManifestError::Parse(message) => write!(f, "invalid manifest: {}", message),
+            ManifestError::Invalid(message) => write!(f, "invalid manifest: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+/// Represents errors in a grading spec.
+///
+/// # Usage
+///
+/// Returned by [`GradeSpec::load`](crate::grade::GradeSpec::load) and
+/// [`GradeSpec::from_toml`](crate::grade::GradeSpec::from_toml).
+#[derive(Debug, PartialEq, Clone)]
+pub enum GradeSpecError {
+    /// The spec could not be read: (path, reason)
+    Io(String, String),
+    /// The spec is not valid TOML or has unknown keys
+    Parse(String),
+    /// The spec is well-formed but its settings are invalid
+    Invalid(String),
+}
+
+impl fmt::Display for GradeSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GradeSpecError::Io(path, reason) => {
+                write!(f, "failed to read grading spec '{}': {}", path, reason)
+            }
+            GradeSpecError::Parse(message) | GradeSpecError::Invalid(message) => {
+                write!(f, "invalid grading spec: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GradeSpecError {}
+
+/// Represents any error produced while compiling a program.
+///
+/// This unifies the errors of the individual phases so that callers can
+/// handle them uniformly, e.g. by converting them into
+/// [`Diagnostic`](crate::diagnostics::Diagnostic)s.
+///
+/// # Usage
+///
+/// Returned by [`compile_with_options`](crate::compile_with_options).
+#[derive(Debug, PartialEq, Clone)]
+pub enum CompileError {
+    /// Lexical analysis failed
+    Lex(LexerError),
+    /// Parsing failed; the span locates the offending token when known
+    Parse(ParseError, Option<Span>),
+    /// Semantic analysis reported one or more errors; the spans, in the same
+    /// order, locate the statement each error was found in when known
+    Semantic(Vec<SemanticError>, Vec<Option<Span>>),
+    /// LLVM IR generation failed
+    Codegen(CodegenError),
+    /// Linking the executable failed
+    Link(LinkError),
+    /// Reading or writing a file failed
+    Io(String),
+    /// The compiler panicked
+    Internal(InternalError),
+    /// An imported source file failed to compile; spans in the error
+    /// locate it in that file
+    Import {
+        /// Path of the imported file
+        file: String,
+        /// The error in the imported file
+        error: Box<CompileError>,
+    },
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileError::Lex(e) => write!(f, "{}", e),
+            CompileError::Parse(e, _) => write!(f, "{}", e),
+            CompileError::Semantic(errors, _) => {
+                write!(f, "Semantic errors:")?;
+                for e in errors {
+                    write!(f, "\n{}", e)?;
+                }
+                Ok(())
+            }
+            CompileError::Codegen(e) => write!(f, "{}", e),
+            CompileError::Link(e) => write!(f, "{}", e),
+            CompileError::Io(msg) => write!(f, "I/O error: {}", msg),
+            CompileError::Internal(e) => write!(f, "{}", e),
+            CompileError::Import { file, error } => write!(f, "{}: {}", file, error),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+impl CompileError {
+    /// Returns the process exit code that reports this error.
+    ///
+    /// Errors in the program are [`COMPILE_ERROR`](exit_code::COMPILE_ERROR),
+    /// including link failures it causes (see
+    /// [`LinkError::is_program_error`]). Failing to generate code for a
+    /// program that passed semantic analysis is a bug in the compiler, and
+    /// other failures of the file system or the C compiler are problems with
+    /// the environment.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CompileError::Lex(_) | CompileError::Parse(..) | CompileError::Semantic(..) => {
+                exit_code::COMPILE_ERROR
+            }
+            CompileError::Link(e) if e.is_program_error() => exit_code::COMPILE_ERROR,
+            CompileError::Codegen(_) | CompileError::Internal(_) => exit_code::INTERNAL_ERROR,
+            CompileError::Link(_) | CompileError::Io(_) => exit_code::ENVIRONMENT,
+            CompileError::Import { error, .. } => error.exit_code(),
+        }
+    }
+}
+
+/// Process exit codes of the `virtuc` command-line tool.
+///
+/// Scripts and services driving `virtuc` can rely on these to tell a
+/// program with errors apart from a broken installation or a compiler bug.
+pub mod exit_code {
+    /// Compilation succeeded
+    pub const SUCCESS: u8 = 0;
+    /// The program has errors
+    pub const COMPILE_ERROR: u8 = 1;
+    /// The command line is invalid
+    pub const USAGE: u8 = 2;
+    /// Reading input, writing output, or running the C compiler failed
+    pub const ENVIRONMENT: u8 = 3;
+    /// The compiler crashed or produced invalid code; this is a bug
+    pub const INTERNAL_ERROR: u8 = 101;
+}
+
+impl From<LexerError> for CompileError {
+    fn from(e: LexerError) -> Self {
+        CompileError::Lex(e)
+    }
+}
+
+impl From<CodegenError> for CompileError {
+    fn from(e: CodegenError) -> Self {
+        CompileError::Codegen(e)
+    }
+}
+
+impl From<InternalError> for CompileError {
+    fn from(e: InternalError) -> Self {
+        CompileError::Internal(e)
+    }
+}
+
+impl From<LinkError> for CompileError {
+    fn from(e: LinkError) -> Self {
+        CompileError::Link(e)
+    }
+}
+
+impl From<std::io::Error> for CompileError {
+    fn from(e: std::io::Error) -> Self {
+        CompileError::Io(e.to_string())
+    }
+}
@@ -0,0 +1,620 @@
+//! # printf Formatting
+//!
+//! This module parses printf format strings, for the semantic checks of
+//! `printf` calls, and formats arguments exactly as C's printf does. Compiled
+//! programs print with the C library; [`printf`] predicts their output in
+//! Rust, so that tests and tools can check what a program prints without
+//! running it.
+//!
+//! ## Supported Conversions
+//!
+//! - `%d`, `%i`, `%u`, `%o`, `%x`, `%X`, and `%c` take an `int`. Without a
+//!   64-bit length modifier (`l`, `ll`, `j`, `z`, `t`) they read a C `int`,
+//!   the low 32 bits of the argument.
+//! - `%f`, `%F`, `%e`, `%E`, `%g`, and `%G` take a `float`, printed like a C
+//!   `double`, including `inf`, `nan`, and negative zero.
+//! - `%s` takes a `string`.
+//!
+//! Flags, width, precision, and `*` arguments are supported. `%a`, `%A`, and
+//! `%p` pass the semantic checks, but [`printf`] cannot predict their output:
+//! hexadecimal floats are not implemented, and pointers depend on the run.
+
+/// The argument category a printf conversion consumes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ConversionKind {
+    Int,
+    Float,
+    String,
+}
+
+/// A width or precision.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Count {
+    /// Written in the format
+    Fixed(usize),
+    /// Given by an `int` argument, written `*`
+    Star,
+}
+
+/// A conversion specification of a format string.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Spec {
+    /// The specification as written, without the leading '%'
+    pub text: String,
+    /// The `-` flag: pad on the right
+    pub left: bool,
+    /// The `+` flag: always print a sign
+    pub plus: bool,
+    /// The ` ` flag: print a space instead of a `+` sign
+    pub space: bool,
+    /// The `#` flag: the alternate form
+    pub alternate: bool,
+    /// The `0` flag: pad numbers with zeros
+    pub zero: bool,
+    /// The minimum field width
+    pub width: Option<Count>,
+    /// The precision
+    pub precision: Option<Count>,
+    /// Whether a 64-bit length modifier (`l`, `ll`, `j`, `z`, `t`) was given
+    pub long: bool,
+    /// The conversion character, e.g. `d`
+    pub conversion: char,
+}
+
+impl Spec {
+    /// Returns the category of argument the conversion consumes.
+    pub fn kind(&self) -> ConversionKind {
+        match self.conversion {
+            'f' | 'F' | 'e' | 'E' | 'g' | 'G' | 'a' | 'A' => ConversionKind::Float,
+            's' | 'p' => ConversionKind::String,
+            _ => ConversionKind::Int,
+        }
+    }
+}
+
+/// A part of a format string.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Piece {
+    /// Text printed as is, with `%%` replaced by `%`
+    Text(String),
+    /// A conversion specification
+    Spec(Spec),
+}
+
+/// A conversion of a format string, as checked against an argument.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Conversion {
+    /// The specification as written, without the leading '%'
+    pub spec: String,
+    pub kind: ConversionKind,
+    /// Whether a 64-bit length modifier was given
+    pub long: bool,
+}
+
+/// Parses a format string into text and conversion specifications.
+pub fn parse(format: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            text.push(c);
+            continue;
+        }
+        let mut spec = Spec {
+            text: String::new(),
+            left: false,
+            plus: false,
+            space: false,
+            alternate: false,
+            zero: false,
+            width: None,
+            precision: None,
+            long: false,
+            conversion: '%',
+        };
+        // Flags
+        while let Some(&c) = chars.peek() {
+            match c {
+                '-' => spec.left = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                '#' => spec.alternate = true,
+                '0' => spec.zero = true,
+                _ => break,
+            }
+            spec.text.push(c);
+            chars.next();
+        }
+        // Width and precision
+        spec.width = parse_count(&mut chars, &mut spec.text);
+        if chars.peek() == Some(&'.') {
+            spec.text.push('.');
+            chars.next();
+            spec.precision =
+                Some(parse_count(&mut chars, &mut spec.text).unwrap_or(Count::Fixed(0)));
+        }
+        // Length modifier
+        while let Some(&c) = chars.peek() {
+            if "hljztL".contains(c) {
+                spec.long |= "ljzt".contains(c);
+                spec.text.push(c);
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        let conversion = chars
+            .next()
+            .ok_or_else(|| "incomplete format specification at end of string".to_string())?;
+        spec.text.push(conversion);
+        match conversion {
+            '%' if spec.text == "%" => {
+                text.push('%');
+                continue;
+            }
+            'd' | 'i' | 'o' | 'u' | 'x' | 'X' | 'c' | 'f' | 'F' | 'e' | 'E' | 'g' | 'G' | 'a'
+            | 'A' | 's' | 'p' => spec.conversion = conversion,
+            other => return Err(format!("unsupported conversion '%{}'", other)),
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(std::mem::take(&mut text)));
+        }
+        pieces.push(Piece::Spec(spec));
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+/// Parses a width or precision, appending it to `text`.
+fn parse_count(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    text: &mut String,
+) -> Option<Count> {
+    if chars.peek() == Some(&'*') {
+        text.push('*');
+        chars.next();
+        return Some(Count::Star);
+    }
+    let mut digits = String::new();
+    while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+        digits.push(c);
+        chars.next();
+    }
+    text.push_str(&digits);
+    // Absurd widths saturate rather than fail
+    (!digits.is_empty()).then(|| Count::Fixed(digits.parse().unwrap_or(usize::MAX)))
+}
+
+/// Returns the arguments a format string consumes, in order. A `*` width or
+/// precision consumes an extra `int` argument.
+pub fn conversions(format: &str) -> Result<Vec<Conversion>, String> {
+    let mut conversions = Vec::new();
+    for piece in parse(format)? {
+        let Piece::Spec(spec) = piece else { continue };
+        for count in [spec.width, spec.precision] {
+            if count == Some(Count::Star) {
+                conversions.push(Conversion {
+                    spec: "*".to_string(),
+                    kind: ConversionKind::Int,
+                    long: false,
+                });
+            }
+        }
+        conversions.push(Conversion {
+            kind: spec.kind(),
+            spec: spec.text,
+            long: spec.long,
+        });
+    }
+    Ok(conversions)
+}
+
+/// An argument of [`printf`].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Arg<'a> {
+    Int(i64),
+    Float(f64),
+    Str(&'a str),
+}
+
+/// Formats `args` as C's printf does, returning the bytes it would print.
+///
+/// # Errors
+///
+/// Returns a message if the format is invalid, does not match the
+/// arguments, or uses a conversion whose output cannot be predicted.
+pub fn printf(format: &str, args: &[Arg]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut args = args.iter();
+    let mut next_arg = |spec: &str| {
+        args.next()
+            .copied()
+            .ok_or_else(|| format!("missing argument for '%{}'", spec))
+    };
+    for piece in parse(format)? {
+        let spec = match piece {
+            Piece::Text(text) => {
+                out.extend_from_slice(text.as_bytes());
+                continue;
+            }
+            Piece::Spec(spec) => spec,
+        };
+        let mut left = spec.left;
+        let width = match spec.width {
+            Some(Count::Star) => {
+                let width = int_arg(next_arg(&spec.text)?, &spec.text)? as i32;
+                // A negative width is a `-` flag
+                left |= width < 0;
+                width.unsigned_abs() as usize
+            }
+            Some(Count::Fixed(width)) => width,
+            None => 0,
+        };
+        let precision = match spec.precision {
+            Some(Count::Star) => {
+                let precision = int_arg(next_arg(&spec.text)?, &spec.text)? as i32;
+                // A negative precision is taken as if it were omitted
+                usize::try_from(precision).ok()
+            }
+            Some(Count::Fixed(precision)) => Some(precision),
+            None => None,
+        };
+        let arg = next_arg(&spec.text)?;
+        let field = match spec.kind() {
+            ConversionKind::Int => format_int(&spec, int_arg(arg, &spec.text)?, precision),
+            ConversionKind::Float => format_float(&spec, float_arg(arg, &spec.text)?, precision)?,
+            ConversionKind::String => match (spec.conversion, arg) {
+                ('s', Arg::Str(s)) => {
+                    let bytes = s.as_bytes();
+                    let len = precision.map_or(bytes.len(), |p| p.min(bytes.len()));
+                    Field::text(bytes[..len].to_vec())
+                }
+                ('s', _) => return Err(mismatch(&spec.text)),
+                (_, _) => return Err(unpredictable(&spec.text)),
+            },
+        };
+        field.write(&mut out, width, left);
+    }
+    if args.next().is_some() {
+        return Err("more arguments than the format uses".to_string());
+    }
+    Ok(out)
+}
+
+/// A formatted conversion before padding to its width.
+struct Field {
+    /// Sign or base prefix, which zero padding follows
+    prefix: String,
+    body: Vec<u8>,
+    /// Whether to pad with zeros between prefix and body
+    zero_pad: bool,
+}
+
+impl Field {
+    fn text(body: Vec<u8>) -> Self {
+        Self {
+            prefix: String::new(),
+            body,
+            zero_pad: false,
+        }
+    }
+
+    fn write(self, out: &mut Vec<u8>, width: usize, left: bool) {
+        let len = self.prefix.len() + self.body.len();
+        let padding = width.saturating_sub(len);
+        if left {
+            out.extend_from_slice(self.prefix.as_bytes());
+            out.extend_from_slice(&self.body);
+            out.resize(out.len() + padding, b' ');
+        } else if self.zero_pad {
+            out.extend_from_slice(self.prefix.as_bytes());
+            out.resize(out.len() + padding, b'0');
+            out.extend_from_slice(&self.body);
+        } else {
+            out.resize(out.len() + padding, b' ');
+            out.extend_from_slice(self.prefix.as_bytes());
+            out.extend_from_slice(&self.body);
+        }
+    }
+}
+
+fn int_arg(arg: Arg, spec: &str) -> Result<i64, String> {
+    match arg {
+        Arg::Int(value) => Ok(value),
+        _ => Err(mismatch(spec)),
+    }
+}
+
+fn float_arg(arg: Arg, spec: &str) -> Result<f64, String> {
+    match arg {
+        Arg::Float(value) => Ok(value),
+        _ => Err(mismatch(spec)),
+    }
+}
+
+fn mismatch(spec: &str) -> String {
+    format!("'%{}' does not match its argument", spec)
+}
+
+fn unpredictable(spec: &str) -> String {
+    format!("the output of '%{}' cannot be predicted", spec)
+}
+
+/// Returns the sign prefix of a signed number.
+fn sign(spec: &Spec, negative: bool) -> &'static str {
+    if negative {
+        "-"
+    } else if spec.plus {
+        "+"
+    } else if spec.space {
+        " "
+    } else {
+        ""
+    }
+}
+
+/// Formats an integer conversion.
+fn format_int(spec: &Spec, value: i64, precision: Option<usize>) -> Field {
+    // Without a length modifier, the conversion reads a 32-bit C `int`
+    let (signed, unsigned) = if spec.long {
+        (value, value as u64)
+    } else {
+        (value as i32 as i64, value as i32 as u32 as u64)
+    };
+    if spec.conversion == 'c' {
+        return Field::text(vec![value as u8]);
+    }
+    let mut prefix = "";
+    let mut digits = match spec.conversion {
+        'd' | 'i' => {
+            prefix = sign(spec, signed < 0);
+            signed.unsigned_abs().to_string()
+        }
+        'o' => format!("{:o}", unsigned),
+        'x' => format!("{:x}", unsigned),
+        'X' => format!("{:X}", unsigned),
+        _ => unsigned.to_string(),
+    };
+    if precision == Some(0) && unsigned == 0 {
+        digits.clear();
+    }
+    if let Some(precision) = precision
+        && digits.len() < precision
+    {
+        digits.insert_str(0, &"0".repeat(precision - digits.len()));
+    }
+    if spec.alternate {
+        match spec.conversion {
+            'o' if !digits.starts_with('0') => digits.insert(0, '0'),
+            'x' if unsigned != 0 => prefix = "0x",
+            'X' if unsigned != 0 => prefix = "0X",
+            _ => {}
+        }
+    }
+    Field {
+        prefix: prefix.to_string(),
+        body: digits.into_bytes(),
+        // A precision turns off zero padding
+        zero_pad: spec.zero && !spec.left && precision.is_none(),
+    }
+}
+
+/// Formats a floating-point conversion.
+fn format_float(spec: &Spec, value: f64, precision: Option<usize>) -> Result<Field, String> {
+    let upper = spec.conversion.is_ascii_uppercase();
+    let prefix = sign(spec, value.is_sign_negative()).to_string();
+    if !value.is_finite() {
+        let body = if value.is_nan() { "nan" } else { "inf" };
+        let body = if upper {
+            body.to_uppercase()
+        } else {
+            body.to_string()
+        };
+        return Ok(Field {
+            prefix,
+            body: body.into_bytes(),
+            zero_pad: false,
+        });
+    }
+    let value = value.abs();
+    let precision = precision.unwrap_or(6);
+    let body = match spec.conversion.to_ascii_lowercase() {
+        'f' => fixed(value, precision, spec.alternate),
+        'e' => exponential(value, precision, spec.alternate, upper),
+        'g' => general(value, precision, spec.alternate, upper),
+        _ => return Err(unpredictable(&spec.text)),
+    };
+    Ok(Field {
+        prefix,
+        body: body.into_bytes(),
+        zero_pad: spec.zero && !spec.left,
+    })
+}
+
+/// Formats a non-negative number as `%f` does.
+fn fixed(value: f64, precision: usize, alternate: bool) -> String {
+    let mut body = format!("{:.*}", precision, value);
+    if alternate && precision == 0 {
+        body.push('.');
+    }
+    body
+}
+
+/// Formats a non-negative number as `%e` does.
+fn exponential(value: f64, precision: usize, alternate: bool, upper: bool) -> String {
+    let (mut mantissa, exponent) = split_exponential(value, precision);
+    if alternate && precision == 0 {
+        mantissa.push('.');
+    }
+    with_exponent(mantissa, exponent, upper)
+}
+
+/// Formats a non-negative number as `%g` does: in the style of `%f` or
+/// `%e` depending on its exponent, without trailing zeros.
+fn general(value: f64, precision: usize, alternate: bool, upper: bool) -> String {
+    let precision = precision.max(1);
+    let (_, exponent) = split_exponential(value, precision - 1);
+    let (mut body, exponent) = if exponent >= -4 && (exponent as i64) < precision as i64 {
+        let digits = (precision as i64 - 1 - exponent as i64) as usize;
+        (fixed(value, digits, alternate), None)
+    } else {
+        let (mantissa, exponent) = split_exponential(value, precision - 1);
+        (mantissa, Some(exponent))
+    };
+    if alternate {
+        if !body.contains('.') {
+            body.push('.');
+        }
+    } else if body.contains('.') {
+        body.truncate(body.trim_end_matches('0').trim_end_matches('.').len());
+    }
+    match exponent {
+        Some(exponent) => with_exponent(body, exponent, upper),
+        None => body,
+    }
+}
+
+/// Splits the `%e` form of a non-negative number into its mantissa and
+/// exponent.
+fn split_exponential(value: f64, precision: usize) -> (String, i32) {
+    let formatted = format!("{:.*e}", precision, value);
+    let (mantissa, exponent) = formatted.split_once('e').unwrap();
+    (mantissa.to_string(), exponent.parse().unwrap())
+}
+
+/// Appends an exponent with a sign and at least two digits, e.g. `e+05`.
+fn with_exponent(mantissa: String, exponent: i32, upper: bool) -> String {
+    let e = if upper { 'E' } else { 'e' };
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{}{}{}{:02}", mantissa, e, sign, exponent.unsigned_abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(format: &str, args: &[Arg]) -> String {
+        String::from_utf8(printf(format, args).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_conversions() {
+        let conversions = conversions("%-*.*ld %s 100%%").unwrap();
+        let specs: Vec<_> = conversions.iter().map(|c| c.spec.as_str()).collect();
+        assert_eq!(specs, ["*", "*", "-*.*ld", "s"]);
+        assert!(conversions[2].long);
+        assert_eq!(conversions[3].kind, ConversionKind::String);
+        assert_eq!(parse("%q"), Err("unsupported conversion '%q'".to_string()));
+        assert!(parse("50%").is_err());
+    }
+
+    #[test]
+    fn test_format_ints() {
+        assert_eq!(
+            format(
+                "%d|%5d|%-5d|%05d|%+d|% d",
+                &[
+                    Arg::Int(42),
+                    Arg::Int(-42),
+                    Arg::Int(7),
+                    Arg::Int(-7),
+                    Arg::Int(3),
+                    Arg::Int(3)
+                ]
+            ),
+            "42|  -42|7    |-0007|+3| 3"
+        );
+        assert_eq!(
+            format(
+                "%x %#X %#o %u %.3d %.0d|",
+                &[
+                    Arg::Int(255),
+                    Arg::Int(255),
+                    Arg::Int(8),
+                    Arg::Int(-1),
+                    Arg::Int(5),
+                    Arg::Int(0)
+                ]
+            ),
+            "ff 0XFF 010 4294967295 005 |"
+        );
+        // Without a length modifier, only the low 32 bits are read
+        assert_eq!(
+            format(
+                "%d %ld %c",
+                &[Arg::Int(1 << 32 | 5), Arg::Int(1 << 32), Arg::Int(65)]
+            ),
+            "5 4294967296 A"
+        );
+        assert_eq!(
+            format(
+                "%*d|%-*d|",
+                &[Arg::Int(4), Arg::Int(1), Arg::Int(-3), Arg::Int(2)]
+            ),
+            "   1|2  |"
+        );
+    }
+
+    #[test]
+    fn test_format_floats() {
+        assert_eq!(
+            format(
+                "%f %.2f %8.3f %-8.1f| %+.0f %#.0f",
+                &[
+                    Arg::Float(1.23456),
+                    Arg::Float(2.5),
+                    Arg::Float(-1.0),
+                    Arg::Float(0.25),
+                    Arg::Float(2.0),
+                    Arg::Float(3.0)
+                ]
+            ),
+            "1.234560 2.50   -1.000 0.2     | +2 3."
+        );
+        assert_eq!(
+            format(
+                "%e %.2E %g %g %g %G",
+                &[
+                    Arg::Float(1234.5),
+                    Arg::Float(0.000123),
+                    Arg::Float(100000.0),
+                    Arg::Float(1000000.0),
+                    Arg::Float(0.0001),
+                    Arg::Float(1e-5)
+                ]
+            ),
+            "1.234500e+03 1.23E-04 100000 1e+06 0.0001 1E-05"
+        );
+        assert_eq!(
+            format(
+                "%f %F %f %05.1f %g",
+                &[
+                    Arg::Float(f64::INFINITY),
+                    Arg::Float(f64::NEG_INFINITY),
+                    Arg::Float(f64::NAN),
+                    Arg::Float(-0.0),
+                    Arg::Float(0.0)
+                ]
+            ),
+            "inf -INF nan -00.0 0"
+        );
+    }
+
+    #[test]
+    fn test_format_strings_and_errors() {
+        assert_eq!(
+            format(
+                "[%5s|%-4s|%.2s]",
+                &[Arg::Str("ab"), Arg::Str("c"), Arg::Str("xyz")]
+            ),
+            "[   ab|c   |xy]"
+        );
+        assert!(printf("%d", &[Arg::Float(1.0)]).is_err());
+        assert!(printf("%d %d", &[Arg::Int(1)]).is_err());
+        assert!(printf("%d", &[Arg::Int(1), Arg::Int(2)]).is_err());
+        assert!(printf("%a", &[Arg::Float(1.0)]).is_err());
+    }
+}
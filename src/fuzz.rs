@@ -0,0 +1,143 @@
+//! # Fuzzing Entry Points
+//!
+//! This module exposes functions that feed arbitrary input to the front end
+//! of the compiler. They are the bodies of the `cargo fuzz` targets in the
+//! `fuzz/` directory, and are also exercised by unit tests on a fixed
+//! pseudo-random corpus so that regressions show up in `cargo test`.
+//!
+//! Every entry point accepts any input and must never panic: malformed input
+//! has to be reported through the regular error types.
+//!
+//! ## Targets
+//!
+//! - [`fuzz_lex`]: arbitrary bytes into the lexer
+//! - [`fuzz_parse`]: arbitrary token sequences into the parser
+//! - [`fuzz_front_end`]: arbitrary source through lexing, parsing, semantic
+//!   analysis, and the printer round trip
+
+use crate::lexer::{Token, lex};
+use crate::parser::parse;
+use crate::printer::print_program;
+use crate::semantic::analyze;
+
+/// Lexes arbitrary bytes, interpreted as UTF-8 where valid.
+pub fn fuzz_lex(data: &[u8]) {
+    let source = String::from_utf8_lossy(data);
+    let _ = lex(&source);
+}
+
+/// Parses a token sequence decoded from arbitrary bytes.
+///
+/// Decoding every byte to a token reaches far more parser states than
+/// mutating source text, where most inputs fail to lex.
+pub fn fuzz_parse(data: &[u8]) {
+    let tokens = tokens_from_bytes(data);
+    if let Ok(program) = parse(&tokens) {
+        let _ = analyze(&program);
+    }
+}
+
+/// Runs arbitrary source through the front end. Programs that parse must
+/// print to source that parses back to the same AST.
+pub fn fuzz_front_end(data: &[u8]) {
+    let source = String::from_utf8_lossy(data);
+    let Ok(tokens) = lex(&source) else {
+        return;
+    };
+    let Ok(program) = parse(&tokens) else {
+        return;
+    };
+    let _ = analyze(&program);
+    let printed = print_program(&program);
+    let reparsed = lex(&printed).ok().and_then(|tokens| parse(&tokens).ok());
+    assert_eq!(
+        reparsed.as_ref(),
+        Some(&program),
+        "printed program did not round-trip:\n{}",
+        printed
+    );
+}
+
+/// Decodes each byte into a token, drawing names and literals from small
+/// pools so that identifiers repeat and declarations are found by uses.
+fn tokens_from_bytes(data: &[u8]) -> Vec<Token> {
+    const NAMES: [&str; 4] = ["main", "x", "y", "printf"];
+    data.iter()
+        .map(|byte| match byte % 34 {
+            0 => Token::Int,
+            1 => Token::Float,
+            2 => Token::StringType,
+            3 => Token::If,
+            4 => Token::Else,
+            5 => Token::For,
+            6 => Token::Return,
+            7 => Token::Extern,
+            8 => Token::Identifier(NAMES[(byte / 34) as usize % NAMES.len()].into()),
+            9 => Token::FloatLiteral(f64::from(byte / 34) + 0.5),
+            10 => Token::IntLiteral(i64::from(byte / 34)),
+            11 => Token::StringLiteral("%d\n".to_string()),
+            12 => Token::Include("stdio.h".to_string()),
+            13 => Token::LessEqual,
+            14 => Token::GreaterEqual,
+            15 => Token::Equal,
+            16 => Token::NotEqual,
+            17 => Token::LessThan,
+            18 => Token::GreaterThan,
+            19 => Token::Assign,
+            20 => Token::Plus,
+            21 => Token::Minus,
+            22 => Token::Multiply,
+            23 => Token::Divide,
+            24 => Token::Semicolon,
+            25 => Token::Comma,
+            26 | 27 => Token::LParen,
+            28 | 29 => Token::RParen,
+            30 | 31 => Token::LBrace,
+            _ => Token::RBrace,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small deterministic corpus of pseudo-random inputs.
+    fn corpus() -> Vec<Vec<u8>> {
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        (0..500)
+            .map(|i| {
+                (0..(i % 64))
+                    .map(|_| {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        state as u8
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fuzz_targets_do_not_panic() {
+        for input in corpus() {
+            fuzz_lex(&input);
+            fuzz_parse(&input);
+            fuzz_front_end(&input);
+        }
+    }
+
+    #[test]
+    fn test_known_crashers() {
+        // Integer literal overflow used to panic in the lexer
+        fuzz_lex(b"int main() { return 99999999999999999999; }");
+        assert!(lex("99999999999999999999").is_err());
+        assert!(lex(&format!("{}.0", "9".repeat(400))).is_err());
+        // Deep nesting used to overflow the stack
+        fuzz_front_end(format!("int main() {{ return {}1; }}", "(".repeat(50_000)).as_bytes());
+        fuzz_parse(&[30; 50_000]);
+        // Externs declared before an include must keep their order
+        fuzz_front_end(b"extern int printf(string, ...); extern int abs(int); #include <stdio.h>");
+    }
+}
@@ -0,0 +1,476 @@
+//! # Batch Grading
+//!
+//! This module grades a directory of submissions, `virtuc grade`: every C
+//! file in it is compiled and run against the test cases of a spec, and the
+//! results are collected into a [`Report`] that serializes to JSON.
+//!
+//! ## Spec Format
+//!
+//! ```toml
+//! [build]
+//! opt-level = 1           # 0 to 3, defaults to 0
+//! int-width = 32          # 32 or 64, defaults to 64
+//!
+//! [limits]
+//! timeout-ms = 2000       # per test, defaults to 10000
+//! max-output = 65536      # bytes per stream, defaults to 1 MiB
+//! memory-mb = 64          # address space per test, defaults to 512
+//!
+//! [[test]]
+//! name = "adds"
+//! args = ["3", "4"]       # defaults to none
+//! stdin = "1 2\n"         # defaults to empty
+//! stdout = "3\n"          # exact output expected; not compared if omitted
+//! exit-code = 0           # defaults to 0
+//! ```
+//!
+//! A test passes if the program exits with the expected code and output
+//! within the limits. Programs run with an empty environment in a scratch
+//! directory. The memory limit caps the address space on Unix, and is not
+//! enforced elsewhere; see [`crate::run`] for how it is reported.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::diagnostics::Diagnostic;
+use crate::error::GradeSpecError;
+use crate::options::{CompileOptions, IntWidth};
+use crate::run::{RunConfig, RunResult, run_program};
+
+/// A parsed and validated grading spec.
+#[derive(Debug, PartialEq, Clone)]
+pub struct GradeSpec {
+    /// Options for compiling each submission
+    pub options: CompileOptions,
+    /// Longest each test may run
+    pub timeout: Duration,
+    /// Most bytes each test may write to each of stdout and stderr
+    pub max_output: usize,
+    /// Most bytes of address space each test may use
+    pub max_memory: u64,
+    /// The test cases, in order
+    pub tests: Vec<TestCase>,
+}
+
+/// A run of a submission and what it must do.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TestCase {
+    /// Name, unique within the spec
+    pub name: String,
+    /// Command-line arguments
+    pub args: Vec<String>,
+    /// Text written to stdin
+    pub stdin: String,
+    /// The exact stdout expected, if it is compared
+    pub stdout: Option<String>,
+    /// The exit code expected
+    pub exit_code: i32,
+}
+
+/// The spec as written, before validation.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSpec {
+    #[serde(default)]
+    build: RawBuild,
+    #[serde(default)]
+    limits: RawLimits,
+    #[serde(default, rename = "test")]
+    tests: Vec<RawTest>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct RawBuild {
+    #[serde(default)]
+    opt_level: u8,
+    int_width: Option<u32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct RawLimits {
+    timeout_ms: Option<u64>,
+    max_output: Option<usize>,
+    memory_mb: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct RawTest {
+    name: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    stdin: String,
+    stdout: Option<String>,
+    #[serde(default)]
+    exit_code: i32,
+}
+
+/// Default time limit of a test.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default output limit of a test, in bytes.
+const DEFAULT_MAX_OUTPUT: usize = 1 << 20;
+
+/// Default memory limit of a test, in MiB.
+const DEFAULT_MEMORY_MB: u64 = 512;
+
+impl GradeSpec {
+    /// Reads and validates the spec at `path`.
+    pub fn load(path: &Path) -> Result<Self, GradeSpecError> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| GradeSpecError::Io(path.display().to_string(), e.to_string()))?;
+        Self::from_toml(&text)
+    }
+
+    /// Parses and validates spec text.
+    pub fn from_toml(text: &str) -> Result<Self, GradeSpecError> {
+        let raw: RawSpec =
+            toml::from_str(text).map_err(|e| GradeSpecError::Parse(e.message().to_string()))?;
+        if raw.build.opt_level > 3 {
+            return Err(GradeSpecError::Invalid(format!(
+                "invalid opt-level {}: expected 0 to 3",
+                raw.build.opt_level
+            )));
+        }
+        let int_width = match raw.build.int_width {
+            Some(bits) => bits.to_string().parse().map_err(GradeSpecError::Invalid)?,
+            None => IntWidth::default(),
+        };
+        let memory_mb = raw.limits.memory_mb.unwrap_or(DEFAULT_MEMORY_MB);
+        if memory_mb == 0 || memory_mb > u64::MAX >> 20 {
+            return Err(GradeSpecError::Invalid(format!(
+                "invalid memory-mb {}: expected a positive size",
+                memory_mb
+            )));
+        }
+        if raw.tests.is_empty() {
+            return Err(GradeSpecError::Invalid("no tests given".into()));
+        }
+        for (i, test) in raw.tests.iter().enumerate() {
+            if raw.tests[..i].iter().any(|t| t.name == test.name) {
+                return Err(GradeSpecError::Invalid(format!(
+                    "duplicate test '{}'",
+                    test.name
+                )));
+            }
+        }
+        Ok(GradeSpec {
+            options: CompileOptions {
+                opt_level: raw.build.opt_level,
+                int_width,
+                ..CompileOptions::default()
+            },
+            timeout: raw
+                .limits
+                .timeout_ms
+                .map_or(DEFAULT_TIMEOUT, Duration::from_millis),
+            max_output: raw.limits.max_output.unwrap_or(DEFAULT_MAX_OUTPUT),
+            max_memory: memory_mb << 20,
+            tests: raw
+                .tests
+                .into_iter()
+                .map(|test| TestCase {
+                    name: test.name,
+                    args: test.args,
+                    stdin: test.stdin,
+                    stdout: test.stdout,
+                    exit_code: test.exit_code,
+                })
+                .collect(),
+        })
+    }
+}
+
+/// The results of grading every submission.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Report {
+    /// The results of each submission, by file name
+    pub submissions: Vec<Submission>,
+}
+
+/// The results of grading a submission.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Submission {
+    /// File name of the submission
+    pub file: String,
+    /// Whether the submission compiled
+    pub compiled: bool,
+    /// The compile errors, as `CODE: message`
+    pub errors: Vec<String>,
+    /// Number of tests passed
+    pub passed: usize,
+    /// Number of tests
+    pub total: usize,
+    /// The result of each test; empty if the submission did not compile
+    pub tests: Vec<TestResult>,
+}
+
+/// The result of a test.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct TestResult {
+    /// Name of the test
+    pub name: String,
+    /// Whether the program did what the test expects
+    pub passed: bool,
+    /// The exit code, or `None` if the program was killed
+    pub exit_code: Option<i32>,
+    /// The limit the program exceeded, such as `timed out`
+    pub exceeded: Option<String>,
+    /// Wall-clock time of the run, in milliseconds
+    pub duration_ms: u64,
+    /// The first line where stdout differs from the expected output
+    pub mismatch: Option<Mismatch>,
+}
+
+/// The first difference between the expected and actual output.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct Mismatch {
+    /// The line, counting from 1
+    pub line: usize,
+    /// The expected line, or `None` past the end of the expected output
+    pub expected: Option<String>,
+    /// The actual line, or `None` past the end of the actual output
+    pub actual: Option<String>,
+}
+
+impl Report {
+    /// Serializes the report as indented JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("reports serialize")
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for submission in &self.submissions {
+            if submission.compiled {
+                writeln!(
+                    f,
+                    "{}: {}/{} tests passed",
+                    submission.file, submission.passed, submission.total
+                )?;
+            } else {
+                writeln!(f, "{}: does not compile", submission.file)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Grades every `.c` file directly in `dir`, in file name order.
+///
+/// # Errors
+///
+/// Returns an error if the directory cannot be read, or a scratch directory
+/// cannot be created. Submissions that fail to compile or run are reported,
+/// not errors.
+pub fn grade_dir(spec: &GradeSpec, dir: &Path) -> io::Result<Report> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<_>>()?;
+    files.retain(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "c"));
+    files.sort();
+
+    let scratch = std::env::temp_dir().join(format!("virtuc-grade-{}", process::id()));
+    fs::create_dir_all(&scratch)?;
+    let submissions = files
+        .iter()
+        .map(|path| {
+            let file = path
+                .file_name()
+                .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+            match fs::read_to_string(path) {
+                Ok(source) => grade_source(spec, &file, &source, &scratch),
+                Err(e) => Submission {
+                    errors: vec![format!("cannot read {}: {}", file, e)],
+                    ..failed(spec, file)
+                },
+            }
+        })
+        .collect();
+    let _ = fs::remove_dir_all(&scratch);
+    Ok(Report { submissions })
+}
+
+/// Grades a submission named `file`, building and running it in the
+/// directory `scratch`.
+pub fn grade_source(spec: &GradeSpec, file: &str, source: &str, scratch: &Path) -> Submission {
+    let executable = scratch.join(Path::new(file).with_extension("out"));
+    let options = CompileOptions {
+        source_name: Some(file.to_string()),
+        ..spec.options.clone()
+    };
+    if let Err(e) = crate::compile_with_options(source, &executable, &options) {
+        let diagnostics: Vec<Diagnostic> = (&e).into();
+        return Submission {
+            errors: diagnostics
+                .iter()
+                .map(|d| format!("{}: {}", d.code, d.message))
+                .collect(),
+            ..failed(spec, file.to_string())
+        };
+    }
+    let tests: Vec<TestResult> = spec
+        .tests
+        .iter()
+        .map(|test| run_test(spec, test, &executable, scratch))
+        .collect();
+    let _ = fs::remove_file(&executable);
+    Submission {
+        file: file.to_string(),
+        compiled: true,
+        errors: Vec::new(),
+        passed: tests.iter().filter(|t| t.passed).count(),
+        total: spec.tests.len(),
+        tests,
+    }
+}
+
+/// The results of a submission that did not compile.
+fn failed(spec: &GradeSpec, file: String) -> Submission {
+    Submission {
+        file,
+        compiled: false,
+        errors: Vec::new(),
+        passed: 0,
+        total: spec.tests.len(),
+        tests: Vec::new(),
+    }
+}
+
+/// Runs a test against a compiled submission.
+fn run_test(spec: &GradeSpec, test: &TestCase, executable: &Path, scratch: &Path) -> TestResult {
+    let config = RunConfig {
+        args: test.args.clone(),
+        stdin: Some(test.stdin.clone().into_bytes()),
+        timeout: Some(spec.timeout),
+        max_output: Some(spec.max_output),
+        max_memory: Some(spec.max_memory),
+        clear_env: true,
+        current_dir: Some(scratch.to_path_buf()),
+        ..RunConfig::default()
+    };
+    let run = match run_program(executable, &config) {
+        Ok(run) => run,
+        Err(_) => RunResult {
+            exit_code: None,
+            exceeded: None,
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            duration: Duration::ZERO,
+        },
+    };
+    let mismatch = test
+        .stdout
+        .as_deref()
+        .and_then(|expected| first_difference(expected, &run.stdout_text()));
+    TestResult {
+        name: test.name.clone(),
+        passed: run.exceeded.is_none()
+            && run.exit_code == Some(test.exit_code)
+            && mismatch.is_none(),
+        exit_code: run.exit_code,
+        exceeded: run.exceeded.map(|limit| limit.to_string()),
+        duration_ms: run.duration.as_millis() as u64,
+        mismatch,
+    }
+}
+
+/// Returns the first line where `actual` differs from `expected`.
+fn first_difference(expected: &str, actual: &str) -> Option<Mismatch> {
+    if expected == actual {
+        return None;
+    }
+    let mut expected_lines = expected.split_inclusive('\n');
+    let mut actual_lines = actual.split_inclusive('\n');
+    for line in 1.. {
+        let (e, a) = (expected_lines.next(), actual_lines.next());
+        if e != a {
+            let text = |line: &str| line.trim_end_matches('\n').to_string();
+            return Some(Mismatch {
+                line,
+                expected: e.map(text),
+                actual: a.map(text),
+            });
+        }
+    }
+    unreachable!("the outputs differ, so some line does")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spec() {
+        let spec = GradeSpec::from_toml(
+            r#"
+            [limits]
+            timeout-ms = 500
+            memory-mb = 64
+
+            [[test]]
+            name = "echo"
+            args = ["a"]
+            stdout = "a\n"
+
+            [[test]]
+            name = "fails"
+            exit-code = 1
+            "#,
+        )
+        .unwrap();
+        assert_eq!(spec.timeout, Duration::from_millis(500));
+        assert_eq!(spec.max_output, DEFAULT_MAX_OUTPUT);
+        assert_eq!(spec.max_memory, 64 << 20);
+        assert_eq!(spec.tests[0].args, ["a"]);
+        assert_eq!(spec.tests[1].stdout, None);
+        assert_eq!(spec.tests[1].exit_code, 1);
+
+        let invalid = |text| GradeSpec::from_toml(text).unwrap_err();
+        assert!(matches!(invalid(""), GradeSpecError::Invalid(_)));
+        assert!(matches!(
+            invalid("[limits]\nmemory-mb = 0\n[[test]]\nname = \"a\"\n"),
+            GradeSpecError::Invalid(_)
+        ));
+        assert!(matches!(
+            invalid("[[test]]\nname = \"a\"\n[[test]]\nname = \"a\"\n"),
+            GradeSpecError::Invalid(_)
+        ));
+        assert!(matches!(
+            invalid("[[test]]\nname = \"a\"\nstdot = \"\"\n"),
+            GradeSpecError::Parse(_)
+        ));
+    }
+
+    #[test]
+    fn test_first_difference() {
+        assert_eq!(first_difference("a\nb\n", "a\nb\n"), None);
+        assert_eq!(
+            first_difference("a\nb\n", "a\nc\n"),
+            Some(Mismatch {
+                line: 2,
+                expected: Some("b".to_string()),
+                actual: Some("c".to_string()),
+            })
+        );
+        // A missing final newline is a difference too
+        assert_eq!(first_difference("a\n", "a").unwrap().line, 1);
+        assert_eq!(
+            first_difference("a\n", "a\nextra\n"),
+            Some(Mismatch {
+                line: 2,
+                expected: None,
+                actual: Some("extra".to_string()),
+            })
+        );
+    }
+}
@@ -5,13 +5,23 @@
 //! this registry automatically injects the appropriate function declarations.
 //!
 //! This allows code to use standard library functions without explicit extern declarations.
+//! Only the functions a program calls are declared, so that including a header
+//! costs nothing for the functions it does not use.
 //!
 //! ## Supported Headers
 //!
 //! Currently supports:
 //! - `stdio.h` - Standard I/O functions (printf, etc.)
+//! - `assert.h` - The `assert` builtin, declared as `int assert(int)` (see
+//!   [`assertions`](crate::assertions))
+//! - `virtuc/random.h` - The `rand_seeded` and `rand_next` builtins (see
+//!   [`random`](crate::random))
 
-use crate::ast::{ExternFunction, Type};
+use std::collections::HashSet;
+
+use crate::analysis;
+use crate::ast::{ExternFunction, Program, Type};
+use crate::random;
 
 /// Returns the list of extern functions that should be automatically available for a header.
 ///
@@ -28,13 +38,100 @@ pub fn externs_for_header(header: &str) -> Vec<ExternFunction> {
             param_types: vec![Type::String],
             is_variadic: true,
         }],
+        "assert.h" => vec![ExternFunction {
+            return_ty: Type::Int,
+            name: "assert".to_string(),
+            param_types: vec![Type::Int],
+            is_variadic: false,
+        }],
+        random::HEADER => vec![
+            ExternFunction {
+                return_ty: Type::Int,
+                name: random::SEED.to_string(),
+                param_types: vec![Type::Int],
+                is_variadic: false,
+            },
+            ExternFunction {
+                return_ty: Type::Int,
+                name: random::NEXT.to_string(),
+                param_types: Vec::new(),
+                is_variadic: false,
+            },
+        ],
         _ => Vec::new(),
     }
 }
 
+/// Declares the extern functions of the headers a program includes that it
+/// calls, unless it declares or defines them itself. They follow the
+/// program's own extern declarations.
+pub fn declare_used_externs(program: &mut Program) {
+    let graph = analysis::call_graph(program);
+    let called: HashSet<&str> = program
+        .functions
+        .iter()
+        .flat_map(|function| graph.callees(&function.name))
+        .map(String::as_str)
+        .collect();
+    let mut externs: Vec<ExternFunction> = Vec::new();
+    for ext in program.includes.iter().flat_map(|h| externs_for_header(h)) {
+        let declared = program.extern_functions.iter().any(|e| e.name == ext.name)
+            || program.functions.iter().any(|f| f.name == ext.name)
+            || externs.iter().any(|e| e.name == ext.name);
+        if called.contains(ext.name.as_str()) && !declared {
+            externs.push(ext);
+        }
+    }
+    program.extern_functions.extend(externs);
+}
+
+/// Returns the included header that declares `ext` with another signature,
+/// if there is one.
+pub fn conflicting_header<'a>(includes: &'a [String], ext: &ExternFunction) -> Option<&'a str> {
+    includes
+        .iter()
+        .find(|header| {
+            externs_for_header(header)
+                .iter()
+                .any(|e| e.name == ext.name && e != ext)
+        })
+        .map(String::as_str)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lexer::lex;
+    use crate::parser::parse;
+
+    #[test]
+    fn injects_only_called_functions() {
+        let program = parse(
+            &lex(
+                "#include <stdio.h>\n#include <assert.h>\nint main() { printf(\"hi\"); return 0; }",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let names: Vec<_> = program
+            .extern_functions
+            .iter()
+            .map(|e| e.name.as_str())
+            .collect();
+        assert_eq!(names, ["printf"]);
+
+        let ext = ExternFunction {
+            return_ty: Type::Int,
+            name: "printf".to_string(),
+            param_types: vec![Type::Int],
+            is_variadic: false,
+        };
+        assert_eq!(conflicting_header(&program.includes, &ext), Some("stdio.h"));
+        assert_eq!(
+            conflicting_header(&program.includes, &externs_for_header("stdio.h")[0]),
+            None
+        );
+    }
 
     #[test]
     fn stdio_injects_printf() {
@@ -0,0 +1,388 @@
+//! # Semantic Highlighting
+//!
+//! This module classifies the tokens of a source file for syntax
+//! highlighting, e.g. as LSP semantic tokens. Unlike a purely lexical
+//! highlighter, it tells parameters and locals apart and recognizes
+//! function names, using the parsed program.
+//!
+//! ## Classification
+//!
+//! Keywords, types, literals, and `#include` directives are classified from
+//! their tokens alone. Identifiers are matched, in source order, with the
+//! names in the AST of their top-level item and resolved through the
+//! scopes of their function. Operators and punctuation are not classified.
+//!
+//! Source that does not parse is still highlighted: identifiers followed by
+//! `(` are taken to be functions, and all others to be locals. Invalid
+//! tokens are skipped.
+//!
+//! ## Rendering
+//!
+//! [`render_html`] and [`render_ansi`] print a whole source file with its
+//! classified tokens marked up, leaving everything between them, such as
+//! whitespace and comments, as it is. HTML output marks tokens with `<span>`
+//! elements whose class is the [`SemanticKind::name`], for styling with CSS.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::{Arena, Expr, ExprId, Function, Stmt, StmtId};
+use crate::diagnostics::Span;
+use crate::interner::Symbol;
+use crate::lexer::{Token, TokenStream};
+use crate::options::CompileOptions;
+use crate::parser::{self, TopLevel};
+
+/// What a token means to the program.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SemanticKind {
+    /// A keyword such as `if` or `return`
+    Keyword,
+    /// A type name
+    Type,
+    /// The name of a function, where defined, declared, or called
+    Function,
+    /// A function parameter
+    Parameter,
+    /// A local variable
+    Local,
+    /// An integer, float, or string literal
+    Literal,
+    /// An `#include` directive
+    Directive,
+}
+
+impl SemanticKind {
+    /// Returns the name of the kind, as used for CSS classes.
+    pub fn name(self) -> &'static str {
+        match self {
+            SemanticKind::Keyword => "keyword",
+            SemanticKind::Type => "type",
+            SemanticKind::Function => "function",
+            SemanticKind::Parameter => "parameter",
+            SemanticKind::Local => "local",
+            SemanticKind::Literal => "literal",
+            SemanticKind::Directive => "directive",
+        }
+    }
+}
+
+impl fmt::Display for SemanticKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Classifies the tokens of `source`, returning the span and kind of every
+/// token that has one, in source order.
+pub fn semantic_tokens(source: &str) -> Vec<(Span, SemanticKind)> {
+    let (tokens, spans): (Vec<_>, Vec<_>) = TokenStream::new(source).flatten().unzip();
+
+    // The kind of each identifier, if the program parses
+    let mut identifiers = vec![None; tokens.len()];
+    if let Ok(items) = parser::parse_items(&tokens, 0, &CompileOptions::default()) {
+        for (range, item) in items {
+            let mut resolver = Resolver::default();
+            resolver.item(&item);
+            let positions: Vec<usize> = range
+                .filter(|&i| matches!(tokens[i], Token::Identifier(_)))
+                .collect();
+            if positions.len() == resolver.kinds.len() {
+                for (i, kind) in positions.into_iter().zip(resolver.kinds) {
+                    identifiers[i] = Some(kind);
+                }
+            }
+        }
+    }
+
+    tokens
+        .iter()
+        .enumerate()
+        .filter_map(|(i, token)| {
+            let kind = match token {
+                Token::If | Token::Else | Token::For | Token::Return | Token::Extern => {
+                    SemanticKind::Keyword
+                }
+                Token::Int | Token::Float | Token::StringType => SemanticKind::Type,
+                Token::IntLiteral(_) | Token::FloatLiteral(_) | Token::StringLiteral(_) => {
+                    SemanticKind::Literal
+                }
+                Token::Include(_) | Token::LocalInclude(_) => SemanticKind::Directive,
+                Token::Identifier(_) => identifiers[i].unwrap_or_else(|| match tokens.get(i + 1) {
+                    Some(Token::LParen) => SemanticKind::Function,
+                    _ => SemanticKind::Local,
+                }),
+                _ => return None,
+            };
+            Some((spans[i], kind))
+        })
+        .collect()
+}
+
+/// Renders `source` as an HTML `<pre>` element.
+pub fn render_html(source: &str) -> String {
+    let mut out = String::from("<pre class=\"virtuc\"><code>");
+    render(source, &mut out, |out, text, kind| match kind {
+        Some(kind) => {
+            out.push_str(&format!("<span class=\"{}\">", kind));
+            escape_html(text, out);
+            out.push_str("</span>");
+        }
+        None => escape_html(text, out),
+    });
+    out.push_str("</code></pre>\n");
+    out
+}
+
+/// Renders `source` with ANSI color escape sequences for terminals.
+pub fn render_ansi(source: &str) -> String {
+    let mut out = String::new();
+    render(source, &mut out, |out, text, kind| {
+        let style = match kind {
+            Some(SemanticKind::Keyword) => "1;35",
+            Some(SemanticKind::Type) => "36",
+            Some(SemanticKind::Function) => "34",
+            Some(SemanticKind::Parameter) => "3",
+            Some(SemanticKind::Literal) => "32",
+            Some(SemanticKind::Directive) => "35",
+            Some(SemanticKind::Local) | None => {
+                out.push_str(text);
+                return;
+            }
+        };
+        out.push_str(&format!("\x1b[{}m{}\x1b[0m", style, text));
+    });
+    out
+}
+
+/// Calls `emit` on each classified token of `source` with its kind, and on
+/// the text between them without one.
+fn render(source: &str, out: &mut String, emit: impl Fn(&mut String, &str, Option<SemanticKind>)) {
+    let mut at = 0;
+    for (span, kind) in semantic_tokens(source) {
+        emit(out, &source[at..span.start], None);
+        emit(out, &source[span.start..span.end], Some(kind));
+        at = span.end;
+    }
+    emit(out, &source[at..], None);
+}
+
+/// Appends `text` to `out`, escaping the characters special to HTML.
+fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Lists the kinds of the identifiers of a top-level item in source order.
+#[derive(Default)]
+struct Resolver {
+    /// Variables in scope, innermost scope last
+    scopes: Vec<HashMap<Symbol, SemanticKind>>,
+    kinds: Vec<SemanticKind>,
+}
+
+impl Resolver {
+    fn item(&mut self, item: &TopLevel) {
+        match item {
+            TopLevel::Include(_) | TopLevel::Import(_) => {}
+            TopLevel::Extern(_) => self.kinds.push(SemanticKind::Function),
+            TopLevel::Function(function) => self.function(function),
+        }
+    }
+
+    fn function(&mut self, function: &Function) {
+        self.kinds.push(SemanticKind::Function);
+        self.scopes.push(HashMap::new());
+        for (_, name) in &function.params {
+            self.declare(Symbol::intern(name), SemanticKind::Parameter);
+        }
+        self.stmt(&function.arena, function.body);
+        self.scopes.pop();
+    }
+
+    /// Declares a variable whose name is the next identifier.
+    fn declare(&mut self, name: Symbol, kind: SemanticKind) {
+        self.kinds.push(kind);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, kind);
+        }
+    }
+
+    fn stmt(&mut self, arena: &Arena, stmt: StmtId) {
+        match &arena[stmt] {
+            Stmt::Declaration { name, init, .. } => {
+                // The name comes before the initializer, but is only in
+                // scope after it
+                self.kinds.push(SemanticKind::Local);
+                if let Some(init) = init {
+                    self.expr(arena, *init);
+                }
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.insert(*name, SemanticKind::Local);
+                }
+            }
+            Stmt::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(arena, *value);
+                }
+            }
+            Stmt::Block(stmts) => {
+                self.scopes.push(HashMap::new());
+                for stmt in stmts {
+                    self.stmt(arena, *stmt);
+                }
+                self.scopes.pop();
+            }
+            Stmt::If { cond, then, else_ } => {
+                self.expr(arena, *cond);
+                self.stmt(arena, *then);
+                if let Some(else_) = else_ {
+                    self.stmt(arena, *else_);
+                }
+            }
+            Stmt::For {
+                init,
+                cond,
+                update,
+                body,
+            } => {
+                self.scopes.push(HashMap::new());
+                if let Some(init) = init {
+                    self.stmt(arena, *init);
+                }
+                for expr in cond.iter().chain(update) {
+                    self.expr(arena, *expr);
+                }
+                self.stmt(arena, *body);
+                self.scopes.pop();
+            }
+            Stmt::Expr(expr) => self.expr(arena, *expr),
+            Stmt::Empty => {}
+        }
+    }
+
+    fn expr(&mut self, arena: &Arena, expr: ExprId) {
+        match &arena[expr] {
+            Expr::Literal(_) => {}
+            Expr::Identifier(name) => {
+                let kind = self
+                    .scopes
+                    .iter()
+                    .rev()
+                    .find_map(|scope| scope.get(name).copied())
+                    .unwrap_or(SemanticKind::Local);
+                self.kinds.push(kind);
+            }
+            Expr::Binary { left, right, .. } => {
+                self.expr(arena, *left);
+                self.expr(arena, *right);
+            }
+            Expr::Call { args, .. } => {
+                self.kinds.push(SemanticKind::Function);
+                for arg in args {
+                    self.expr(arena, *arg);
+                }
+            }
+            Expr::Assignment { target, value } => {
+                self.expr(arena, *target);
+                self.expr(arena, *value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the text and kind of each classified token.
+    fn classify(source: &str) -> Vec<(&str, SemanticKind)> {
+        semantic_tokens(source)
+            .into_iter()
+            .map(|(span, kind)| (&source[span.start..span.end], kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_semantic_tokens() {
+        use SemanticKind::*;
+        let source = "#include <stdio.h>
+int scale(int x) {
+    int y = x * 2;
+    for (int x = 0; x < y; x = x + 1) { y = y - 1; }
+    return scale(y) + x;
+}";
+        assert_eq!(
+            classify(source),
+            [
+                ("#include <stdio.h>", Directive),
+                ("int", Type),
+                ("scale", Function),
+                ("int", Type),
+                ("x", Parameter),
+                ("int", Type),
+                ("y", Local),
+                ("x", Parameter),
+                ("2", Literal),
+                ("for", Keyword),
+                ("int", Type),
+                ("x", Local),
+                ("0", Literal),
+                ("x", Local),
+                ("y", Local),
+                ("x", Local),
+                ("x", Local),
+                ("1", Literal),
+                ("y", Local),
+                ("y", Local),
+                ("1", Literal),
+                ("return", Keyword),
+                ("scale", Function),
+                ("y", Local),
+                ("x", Parameter),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_semantic_tokens_of_invalid_source() {
+        use SemanticKind::*;
+        assert_eq!(
+            classify("int f(int a) { return g(a) @ }"),
+            [
+                ("int", Type),
+                ("f", Function),
+                ("int", Type),
+                ("a", Local),
+                ("return", Keyword),
+                ("g", Function),
+                ("a", Local),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let source = "#include <stdio.h>\nint f(int a) { return a < 1; } // done\n";
+        assert_eq!(
+            render_html(source),
+            "<pre class=\"virtuc\"><code>\
+             <span class=\"directive\">#include &lt;stdio.h&gt;</span>\n\
+             <span class=\"type\">int</span> <span class=\"function\">f</span>(\
+             <span class=\"type\">int</span> <span class=\"parameter\">a</span>) { \
+             <span class=\"keyword\">return</span> <span class=\"parameter\">a</span> &lt; \
+             <span class=\"literal\">1</span>; } // done\n\
+             </code></pre>\n"
+        );
+
+        let ansi = render_ansi("int x;");
+        assert_eq!(ansi, "\x1b[36mint\x1b[0m x;");
+    }
+}
@@ -0,0 +1,116 @@
+//! # Internal Compiler Errors
+//!
+//! This module turns panics inside the compiler into [`InternalError`]s that
+//! make bug reports actionable. Code generation records the function it is
+//! working on with [`enter_function`]; when a panic escapes, [`catch`]
+//! reports that function together with a minimized program that still
+//! contains it.
+//!
+//! ## Minimization
+//!
+//! The reproducer keeps the includes and extern declarations of the original
+//! program, the offending function, and every function it calls directly or
+//! indirectly, so that it remains a valid program. All other functions are
+//! dropped.
+
+use std::cell::RefCell;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::analysis;
+use crate::ast::Program;
+use crate::error::InternalError;
+
+/// Where users should report internal compiler errors.
+pub const BUG_REPORT_URL: &str = "https://github.com/huangsam/virtuc/issues";
+
+thread_local! {
+    /// The function the compiler is working on in this thread, if any
+    static CURRENT_FUNCTION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Records that the compiler is working on the function `name`, or on no
+/// function in particular, so that an internal error can name it.
+pub fn enter_function(name: Option<&str>) {
+    CURRENT_FUNCTION.with(|current| *current.borrow_mut() = name.map(str::to_string));
+}
+
+/// Runs `f`, converting a panic into an [`InternalError`] about `program`.
+pub fn catch<T>(program: &Program, f: impl FnOnce() -> T) -> Result<T, InternalError> {
+    enter_function(None);
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    let name = CURRENT_FUNCTION.with(|current| current.borrow_mut().take());
+    result.map_err(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        let function = name.and_then(|name| program.functions.iter().find(|f| f.name == name));
+        InternalError {
+            message,
+            function: function.cloned().map(Box::new),
+            reproducer: function.map(|f| Box::new(minimize(program, &f.name))),
+        }
+    })
+}
+
+/// Reduces `program` to the function `name` and the functions it calls.
+pub fn minimize(program: &Program, name: &str) -> Program {
+    let graph = analysis::call_graph(program);
+    let kept = graph.reachable_from(name);
+    Program {
+        includes: program.includes.clone(),
+        imports: program.imports.clone(),
+        extern_functions: program.extern_functions.clone(),
+        functions: program
+            .functions
+            .iter()
+            .filter(|f| kept.contains(&f.name.as_str()))
+            .cloned()
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::parse;
+
+    fn program(source: &str) -> Program {
+        parse(&lex(source).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_minimize_keeps_callees() {
+        let program = program(
+            "int leaf() { return 1; }
+             int unused() { return 2; }
+             int helper() { return leaf(); }
+             int main() { return helper(); }",
+        );
+        let names = |program: &Program| -> Vec<String> {
+            program.functions.iter().map(|f| f.name.clone()).collect()
+        };
+        assert_eq!(names(&minimize(&program, "helper")), ["leaf", "helper"]);
+        assert_eq!(
+            names(&minimize(&program, "main")),
+            ["leaf", "helper", "main"]
+        );
+    }
+
+    #[test]
+    fn test_catch_reports_current_function() {
+        let program = program("int f() { return 1; } int main() { return f(); }");
+        let error = catch(&program, || {
+            enter_function(Some("f"));
+            panic!("boom");
+        })
+        .unwrap_err();
+        assert_eq!(error.message, "boom");
+        assert_eq!(error.function.unwrap().name, "f");
+        assert_eq!(error.reproducer.unwrap().functions.len(), 1);
+
+        assert_eq!(catch(&program, || 7), Ok(7));
+    }
+}
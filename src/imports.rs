@@ -0,0 +1,238 @@
+//! # Source Imports
+//!
+//! A program may import another source file with `#include "file.c"`. Unlike
+//! a header include, which declares the extern functions of a known C header,
+//! an import merges the program of the imported file into the importing one:
+//! - Paths are relative to the directory of the importing file. For the main
+//!   file that is the directory of [`CompileOptions::source_name`], or the
+//!   current directory when there is none
+//! - Each file is imported once, however many files import it, so imports
+//!   may be repeated or cyclic
+//! - Header includes and extern declarations are merged, declaring identical
+//!   extern functions once. Headers included by one file declare their
+//!   functions for all of them
+//! - Functions follow those of the importing file, in import order, and a
+//!   function defined in two files is an error
+//!
+//! Errors in an imported file are reported as [`CompileError::Import`],
+//! naming the file their spans belong to.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ast::Program;
+use crate::error::{CompileError, SemanticError};
+use crate::header_registry;
+use crate::lexer::TokenStream;
+use crate::options::CompileOptions;
+use crate::parser;
+
+/// Merges the files imported by `program`, and the files they import, into
+/// it. The result has no imports left.
+pub fn merge(program: Program, options: &CompileOptions) -> Result<Program, CompileError> {
+    if program.imports.is_empty() {
+        return Ok(program);
+    }
+    let main = Path::new(options.source_name.as_deref().unwrap_or(""));
+    let mut merger = Merger {
+        options,
+        seen: HashSet::new(),
+        program: Program {
+            includes: Vec::new(),
+            imports: Vec::new(),
+            extern_functions: Vec::new(),
+            functions: Vec::new(),
+        },
+    };
+    // A cycle back to the main file imports nothing
+    if let Ok(path) = main.canonicalize() {
+        merger.seen.insert(path);
+    }
+    merger.add(program, None, main.parent().unwrap_or(Path::new("")))?;
+    // Headers included by any file declare the functions called by all
+    header_registry::declare_used_externs(&mut merger.program);
+    Ok(merger.program)
+}
+
+/// Returns the files that the main file, whose source is `source`, imports
+/// directly or through other files, as far as they can be read and parsed.
+/// Files that do not exist are included, so that tools watching for changes
+/// notice when they are created.
+pub fn imported_files(source: &str, options: &CompileOptions) -> Vec<PathBuf> {
+    let main = Path::new(options.source_name.as_deref().unwrap_or(""));
+    let mut seen: HashSet<PathBuf> = main.canonicalize().into_iter().collect();
+    let mut files = Vec::new();
+    let mut pending = vec![(
+        source.to_string(),
+        main.parent().unwrap_or(Path::new("")).to_path_buf(),
+    )];
+    while let Some((source, dir)) = pending.pop() {
+        let Ok(program) =
+            parser::parse_stream(TokenStream::with_options(&source, options), options)
+        else {
+            continue;
+        };
+        for import in program.imports {
+            let path = dir.join(&import);
+            if !seen.insert(path.canonicalize().unwrap_or_else(|_| path.clone())) {
+                continue;
+            }
+            if let Ok(source) = fs::read_to_string(&path) {
+                let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+                pending.push((source, dir));
+            }
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// Accumulates the merged program.
+struct Merger<'a> {
+    options: &'a CompileOptions,
+    /// Canonical paths of the files imported so far
+    seen: HashSet<PathBuf>,
+    program: Program,
+}
+
+impl Merger<'_> {
+    /// Adds the program of `file` (the main file if `None`) and its imports,
+    /// which are relative to `dir`.
+    fn add(
+        &mut self,
+        program: Program,
+        file: Option<&str>,
+        dir: &Path,
+    ) -> Result<(), CompileError> {
+        for header in program.includes {
+            if !self.program.includes.contains(&header) {
+                self.program.includes.push(header);
+            }
+        }
+        // Conflicting declarations are kept for semantic analysis to report
+        for ext in program.extern_functions {
+            if !self.program.extern_functions.contains(&ext) {
+                self.program.extern_functions.push(ext);
+            }
+        }
+        for function in program.functions {
+            if let Some(file) = file
+                && self
+                    .program
+                    .functions
+                    .iter()
+                    .any(|f| f.name == function.name)
+            {
+                return Err(CompileError::Import {
+                    file: file.to_string(),
+                    error: Box::new(CompileError::Semantic(
+                        vec![SemanticError::DuplicateFunction(function.name)],
+                        Vec::new(),
+                    )),
+                });
+            }
+            self.program.functions.push(function);
+        }
+
+        for import in program.imports {
+            let path = dir.join(&import);
+            let name = path.display().to_string();
+            let io_error =
+                |e: std::io::Error| CompileError::Io(format!("cannot import {}: {}", name, e));
+            if !self.seen.insert(path.canonicalize().map_err(io_error)?) {
+                continue;
+            }
+            let source = fs::read_to_string(&path).map_err(io_error)?;
+            let imported = parser::parse_stream(
+                TokenStream::with_options(&source, self.options),
+                self.options,
+            )
+            .map_err(|error| CompileError::Import {
+                file: name.clone(),
+                error: Box::new(error),
+            })?;
+            self.add(
+                imported,
+                Some(&name),
+                path.parent().unwrap_or(Path::new("")),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::parse;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_merge_imports() {
+        let dir = TempDir::new().unwrap();
+        let write = |name: &str, source: &str| fs::write(dir.path().join(name), source).unwrap();
+        write(
+            "util.c",
+            "#include \"main.c\"\n#include <stdio.h>\nint twice(int x) { return x * 2; }\n",
+        );
+        write(
+            "math.c",
+            "#include \"util.c\"\nint quad(int x) { return twice(twice(x)); }\n",
+        );
+        let options = CompileOptions {
+            source_name: Some(dir.path().join("main.c").display().to_string()),
+            ..CompileOptions::default()
+        };
+        let main = "#include \"math.c\"\n#include \"util.c\"\nint main() { return quad(1); }";
+        write("main.c", main);
+
+        // Repeated and cyclic imports are merged once, after the importer
+        let program = merge(parse(&lex(main).unwrap()).unwrap(), &options).unwrap();
+        let names: Vec<_> = program.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["main", "quad", "twice"]);
+        assert_eq!(program.includes, ["stdio.h"]);
+        assert!(program.imports.is_empty());
+
+        // Errors name the imported file
+        write("util.c", "int main() { return 0; }\n");
+        let error = merge(parse(&lex(main).unwrap()).unwrap(), &options).unwrap_err();
+        let util = dir.path().join("util.c").display().to_string();
+        assert_eq!(
+            error,
+            CompileError::Import {
+                file: util.clone(),
+                error: Box::new(CompileError::Semantic(
+                    vec![SemanticError::DuplicateFunction("main".to_string())],
+                    Vec::new()
+                )),
+            }
+        );
+        write("util.c", "int twice(int x) { return x @ 2; }\n");
+        let error = merge(parse(&lex(main).unwrap()).unwrap(), &options).unwrap_err();
+        assert!(matches!(error, CompileError::Import { file, .. } if file == util));
+    }
+
+    #[test]
+    fn test_imported_files() {
+        let dir = TempDir::new().unwrap();
+        let write = |name: &str, source: &str| fs::write(dir.path().join(name), source).unwrap();
+        write("util.c", "#include \"main.c\"\n#include \"missing.c\"\n");
+        write("math.c", "#include \"util.c\"\nint quad(int x) {");
+        let options = CompileOptions {
+            source_name: Some(dir.path().join("main.c").display().to_string()),
+            ..CompileOptions::default()
+        };
+        let main = "#include \"math.c\"\n#include \"util.c\"\nint main() { return 0; }";
+        write("main.c", main);
+
+        // Each file once, including missing ones, but not the main file;
+        // files that do not parse contribute no imports
+        let mut files = imported_files(main, &options);
+        files.sort();
+        let path = |name: &str| dir.path().join(name);
+        assert_eq!(files, [path("math.c"), path("missing.c"), path("util.c")]);
+        assert!(imported_files("int main() {", &options).is_empty());
+    }
+}
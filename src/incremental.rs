@@ -0,0 +1,279 @@
+//! # Incremental Parsing
+//!
+//! This module keeps a parsed source file up to date as it is edited, for
+//! tools such as editors and watch mode that re-parse after every change.
+//! Applying a [`TextEdit`] to a [`ParsedSource`] re-lexes and re-parses only
+//! the top-level items the edit touches.
+//!
+//! ## Reuse
+//!
+//! Lexing restarts at the end of the last item before the edit, where the
+//! lexer is known to be between tokens. The lexer keeps no state from one
+//! token to the next, so once it produces a token past the edit that starts
+//! exactly where an old item started, every later token is unchanged apart
+//! from its position: lexing stops there, and the old tokens and items are
+//! reused. Top-level items parse independently of each other, so only the
+//! re-lexed tokens are parsed again.
+
+use std::ops::Range;
+
+use crate::ast::Program;
+use crate::diagnostics::Span;
+use crate::error::{CompileError, LexerError};
+use crate::lexer::{self, Token, TokenStream};
+use crate::options::CompileOptions;
+use crate::parser::{self, TopLevel};
+
+/// A change to a source file: the bytes in `range` are replaced by `text`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TextEdit {
+    /// Byte range of the replaced text
+    pub range: Range<usize>,
+    /// The replacement text
+    pub text: String,
+}
+
+impl TextEdit {
+    /// Creates an edit replacing `range` with `text`.
+    pub fn new(range: Range<usize>, text: impl Into<String>) -> Self {
+        Self {
+            range,
+            text: text.into(),
+        }
+    }
+}
+
+/// A source file together with its tokens and top-level items.
+#[derive(Debug, Clone)]
+pub struct ParsedSource {
+    source: String,
+    tokens: Vec<Token>,
+    spans: Vec<Span>,
+    items: Vec<Item>,
+    options: CompileOptions,
+    /// Number of items carried over from the previous parse
+    reused: usize,
+}
+
+/// A parsed top-level item.
+#[derive(Debug, Clone)]
+struct Item {
+    /// Range of the item's tokens
+    tokens: Range<usize>,
+    node: TopLevel,
+}
+
+impl ParsedSource {
+    /// Lexes and parses a whole source file.
+    pub fn new(source: &str, options: &CompileOptions) -> Result<Self, CompileError> {
+        let (tokens, spans): (Vec<_>, Vec<_>) = lexer::lex_with_options(source, options)?
+            .into_iter()
+            .unzip();
+        let items = parser::parse_items(&tokens, 0, options)
+            .map_err(|e| parser::locate_error(e, source, &spans))?;
+        Ok(Self {
+            source: source.to_string(),
+            tokens,
+            spans,
+            items: items
+                .into_iter()
+                .map(|(tokens, node)| Item { tokens, node })
+                .collect(),
+            options: options.clone(),
+            reused: 0,
+        })
+    }
+
+    /// Returns the parse of the source with `edit` applied, reusing the
+    /// items the edit does not touch.
+    ///
+    /// An edit that breaks the program leaves `self` untouched, so callers
+    /// can keep the last successful parse and re-parse the edited text with
+    /// [`ParsedSource::new`] once it is valid again.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range of the edit is out of bounds or does not lie on
+    /// character boundaries.
+    pub fn edit(&self, edit: &TextEdit) -> Result<Self, CompileError> {
+        let Range { start, end } = edit.range.clone();
+        let mut source = self.source.clone();
+        source.replace_range(start..end, &edit.text);
+        let inserted_end = start + edit.text.len();
+        let shift = |offset: usize| offset + inserted_end - end;
+
+        // Restart after the last item that ends before the edit; an edit
+        // right after an item may extend its last token
+        let first = self
+            .items
+            .iter()
+            .position(|item| self.spans[item.tokens.end - 1].end >= start)
+            .unwrap_or(self.items.len());
+        let (relex_token, relex_byte) = match first {
+            0 => (0, 0),
+            _ => {
+                let last = self.items[first - 1].tokens.end;
+                (last, self.spans[last - 1].end)
+            }
+        };
+
+        // Re-lex until a token lines up with an old item past the edit
+        let mut tokens = self.tokens[..relex_token].to_vec();
+        let mut spans = self.spans[..relex_token].to_vec();
+        let mut resync = self.items.len();
+        for token in TokenStream::with_options(&source[relex_byte..], &self.options) {
+            let (token, span) = token.map_err(|e| {
+                LexerError::new(
+                    e.kind,
+                    Span::new(e.span.start + relex_byte, e.span.end + relex_byte),
+                )
+            })?;
+            let span = Span::new(span.start + relex_byte, span.end + relex_byte);
+            if span.start >= inserted_end {
+                let old_start = span.start + end - inserted_end;
+                if let Ok(i) = self.items[first..]
+                    .binary_search_by_key(&old_start, |item| self.spans[item.tokens.start].start)
+                {
+                    resync = first + i;
+                    break;
+                }
+            }
+            tokens.push(token);
+            spans.push(span);
+        }
+
+        // Parse the re-lexed tokens, then append the reused tail
+        let parsed_end = tokens.len();
+        let parsed = parser::parse_items(&tokens, relex_token, &self.options)
+            .map_err(|e| parser::locate_error(e, &source, &spans))?;
+        let mut items = self.items[..first].to_vec();
+        items.extend(
+            parsed
+                .into_iter()
+                .map(|(tokens, node)| Item { tokens, node }),
+        );
+        if let Some(item) = self.items.get(resync) {
+            let tail = item.tokens.start;
+            tokens.extend_from_slice(&self.tokens[tail..]);
+            spans.extend(
+                self.spans[tail..]
+                    .iter()
+                    .map(|span| Span::new(shift(span.start), shift(span.end))),
+            );
+            items.extend(self.items[resync..].iter().map(|item| Item {
+                tokens: item.tokens.start - tail + parsed_end..item.tokens.end - tail + parsed_end,
+                node: item.node.clone(),
+            }));
+        }
+
+        Ok(Self {
+            source,
+            tokens,
+            spans,
+            items,
+            options: self.options.clone(),
+            reused: first + self.items.len() - resync,
+        })
+    }
+
+    /// Returns the source text.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns the tokens of the source.
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// Returns the source span of each token.
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    /// Returns the number of top-level items the last edit did not have to
+    /// re-parse.
+    pub fn reused_items(&self) -> usize {
+        self.reused
+    }
+
+    /// Returns the parsed program.
+    pub fn program(&self) -> Program {
+        parser::assemble(self.items.iter().map(|item| item.node.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "#include <stdio.h>
+int one() { return 1; }
+int two() { return 2; }
+int main() { return one() + two(); }
+";
+
+    /// Applies `edit` incrementally, checking the result against a full
+    /// parse of the edited source.
+    fn check_edit(parsed: &ParsedSource, edit: TextEdit) -> ParsedSource {
+        let edited = parsed.edit(&edit).unwrap();
+        let full = ParsedSource::new(edited.source(), &CompileOptions::default()).unwrap();
+        assert_eq!(edited.program(), full.program());
+        assert_eq!(edited.tokens(), full.tokens());
+        assert_eq!(edited.spans(), full.spans());
+        edited
+    }
+
+    #[test]
+    fn test_edit_reuses_untouched_items() {
+        let parsed = ParsedSource::new(SOURCE, &CompileOptions::default()).unwrap();
+        let at = SOURCE.find("2;").unwrap();
+        let edited = check_edit(&parsed, TextEdit::new(at..at + 1, "20 + 2"));
+        assert!(edited.source().contains("return 20 + 2;"));
+        assert_eq!(edited.reused_items(), 3);
+
+        // Later edits build on earlier ones
+        let at = edited.source().find("int main").unwrap();
+        let edited = check_edit(
+            &edited,
+            TextEdit::new(at..at, "int three() { return 3; }\n"),
+        );
+        assert_eq!(edited.program().functions.len(), 4);
+        assert_eq!(edited.reused_items(), 4);
+    }
+
+    #[test]
+    fn test_edit_that_changes_lexing_past_the_edit() {
+        let parsed = ParsedSource::new(SOURCE, &CompileOptions::default()).unwrap();
+
+        // Extending an identifier at the end of an item
+        let at = SOURCE.find("one()").unwrap() + 3;
+        check_edit(&parsed, TextEdit::new(at..at, "_more"));
+
+        // Commenting out the rest of a line swallows tokens after the edit
+        let source = "int one() { return 1; } int two() { return 2; }\nint main() { return 0; }\n";
+        let parsed = ParsedSource::new(source, &CompileOptions::default()).unwrap();
+        let at = source.find("int two").unwrap();
+        let edited = check_edit(&parsed, TextEdit::new(at..at, "// "));
+        assert_eq!(edited.program().functions.len(), 2);
+
+        // Deleting everything
+        check_edit(&parsed, TextEdit::new(0..source.len(), ""));
+    }
+
+    #[test]
+    fn test_edit_errors_are_located() {
+        let parsed = ParsedSource::new(SOURCE, &CompileOptions::default()).unwrap();
+        let at = SOURCE.find("return 2;").unwrap();
+        let Err(CompileError::Parse(_, Some(span))) =
+            parsed.edit(&TextEdit::new(at..at + "return".len(), "retur"))
+        else {
+            panic!("expected a located parse error");
+        };
+        assert_eq!(span.start, at + "retur ".len());
+        assert!(matches!(
+            parsed.edit(&TextEdit::new(at..at, "@")),
+            Err(CompileError::Lex(e)) if e.span == Span::new(at, at + 1)
+        ));
+    }
+}
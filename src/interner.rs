@@ -0,0 +1,134 @@
+//! # String Interning
+//!
+//! This module maps names to small integer handles. The lexer interns each
+//! identifier it reads, and tokens and the AST hold the resulting [`Symbol`]
+//! rather than a `String`, so that names are copied, hashed, and compared
+//! as integers and each distinct name is stored once.
+//!
+//! Symbols come from a single interner shared by every thread, so a
+//! symbol means the same name wherever it is used, including in programs
+//! built outside the parser, such as by [`Template`]. Interned names are
+//! never freed: a long-running process such as `serve` keeps every distinct
+//! name it has seen, which is bounded by the identifier length limit times
+//! the number of distinct names.
+//!
+//! [`Template`]: crate::template::Template
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{LazyLock, RwLock};
+
+/// An interned string.
+///
+/// Symbols print as their string, and compare equal to it.
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// Returns the symbol of `name`, interning it if it is new.
+    pub fn intern(name: &str) -> Self {
+        if let Some(&symbol) = INTERNER.read().unwrap().symbols.get(name) {
+            return symbol;
+        }
+        INTERNER.write().unwrap().intern(name)
+    }
+
+    /// Returns the string of the symbol.
+    pub fn as_str(self) -> &'static str {
+        INTERNER.read().unwrap().strings[self.0 as usize]
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Self::intern(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Self {
+        Self::intern(&name)
+    }
+}
+
+impl From<Symbol> for String {
+    fn from(symbol: Symbol) -> Self {
+        symbol.as_str().to_string()
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// The interner shared by every thread.
+static INTERNER: LazyLock<RwLock<Interner>> = LazyLock::new(Default::default);
+
+/// A table of interned strings.
+#[derive(Default)]
+struct Interner {
+    /// Symbol of each string
+    symbols: HashMap<&'static str, Symbol>,
+    /// String of each symbol, indexed by symbol
+    strings: Vec<&'static str>,
+}
+
+impl Interner {
+    /// Returns the symbol of `name`, interning it if it is new.
+    fn intern(&mut self, name: &str) -> Symbol {
+        // Another thread may have interned it since the caller looked
+        if let Some(&symbol) = self.symbols.get(name) {
+            return symbol;
+        }
+        let symbol = Symbol(u32::try_from(self.strings.len()).expect("too many names"));
+        let name: &'static str = Box::leak(name.into());
+        self.strings.push(name);
+        self.symbols.insert(name, symbol);
+        symbol
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        let x = Symbol::intern("x");
+        let y = Symbol::intern("y");
+        assert_ne!(x, y);
+        assert_eq!(Symbol::intern("x"), x);
+        assert_eq!(y.as_str(), "y");
+        assert_eq!(y, "y");
+        assert_eq!(y.to_string(), "y");
+        assert_eq!(format!("{y:?}"), "\"y\"");
+    }
+
+    #[test]
+    fn test_intern_across_threads() {
+        let symbol = std::thread::spawn(|| Symbol::intern("shared"))
+            .join()
+            .unwrap();
+        assert_eq!(Symbol::intern("shared"), symbol);
+    }
+}
@@ -0,0 +1,395 @@
+//! # LLVM IR Reader
+//!
+//! This module reads back the subset of LLVM IR text that the compiler emits,
+//! so that tests can assert on the structure of generated code (which
+//! functions exist, their linkage, the instructions of each block) rather
+//! than on substrings of the IR, which break whenever an unrelated detail of
+//! the output changes.
+//!
+//! The reader is deliberately shallow. It splits a module into functions,
+//! blocks and instructions, and each instruction into its result, opcode and
+//! operand text. Types and operands are kept as text, and module-level items
+//! other than functions (globals, attribute groups, metadata) are skipped.
+//!
+//! ```
+//! let module = virtuc::ir::parse(
+//!     "define i64 @main() {\nentry:\n  %x = add i64 1, 2\n  ret i64 %x\n}\n",
+//! )
+//! .unwrap();
+//! let main = module.function("main").unwrap();
+//! assert_eq!(main.opcodes(), ["add", "ret"]);
+//! ```
+
+use crate::error::IrParseError;
+
+/// A module: its functions, defined and declared, in order.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Module {
+    /// Functions, in the order they appear
+    pub functions: Vec<Function>,
+}
+
+impl Module {
+    /// Returns the function `name`, if the module defines or declares it.
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+
+    /// Returns the functions the module defines, skipping declarations.
+    pub fn definitions(&self) -> impl Iterator<Item = &Function> {
+        self.functions.iter().filter(|f| !f.is_declaration())
+    }
+}
+
+/// A function definition or declaration.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Function {
+    /// Name, without the `@`
+    pub name: String,
+    /// Linkage, such as `internal`, if not the default external linkage
+    pub linkage: Option<String>,
+    /// Return type
+    pub return_type: String,
+    /// Parameter types, with their attributes
+    pub params: Vec<String>,
+    /// Basic blocks; empty for a declaration
+    pub blocks: Vec<Block>,
+}
+
+impl Function {
+    /// Returns true if the function is declared but not defined.
+    pub fn is_declaration(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Returns true if the function is visible outside its module.
+    pub fn is_external(&self) -> bool {
+        !matches!(self.linkage.as_deref(), Some("internal" | "private"))
+    }
+
+    /// Returns the block labelled `label`.
+    pub fn block(&self, label: &str) -> Option<&Block> {
+        self.blocks.iter().find(|b| b.label == label)
+    }
+
+    /// Returns every instruction, in block order.
+    pub fn instructions(&self) -> impl Iterator<Item = &Instruction> {
+        self.blocks.iter().flat_map(|b| &b.instructions)
+    }
+
+    /// Returns the opcode of every instruction, in block order.
+    pub fn opcodes(&self) -> Vec<&str> {
+        self.instructions().map(|i| i.opcode.as_str()).collect()
+    }
+
+    /// Returns the number of instructions with the given opcode.
+    pub fn count(&self, opcode: &str) -> usize {
+        self.instructions().filter(|i| i.opcode == opcode).count()
+    }
+
+    /// Returns the functions called directly, in order of the calls.
+    pub fn callees(&self) -> Vec<&str> {
+        self.instructions()
+            .filter_map(Instruction::callee)
+            .collect()
+    }
+}
+
+/// A basic block.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Block {
+    /// Label, without the `:`; empty for an unlabelled entry block
+    pub label: String,
+    /// Instructions, ending with the terminator
+    pub instructions: Vec<Instruction>,
+}
+
+/// An instruction.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Instruction {
+    /// The value defined, without the `%`, if any
+    pub result: Option<String>,
+    /// Opcode, such as `add` or `call`; `tail` and similar markers of calls
+    /// are dropped
+    pub opcode: String,
+    /// The rest of the instruction, from the types and flags on
+    pub operands: String,
+}
+
+impl Instruction {
+    /// Returns the function a `call` calls directly.
+    pub fn callee(&self) -> Option<&str> {
+        if self.opcode != "call" {
+            return None;
+        }
+        let start = self.operands.find('@')? + 1;
+        let len = self.operands[start..].find('(')?;
+        Some(&self.operands[start..start + len])
+    }
+}
+
+/// Markers that may precede the opcode of a call.
+const CALL_MARKERS: &[&str] = &["tail", "musttail", "notail"];
+
+/// Linkage types that may follow `define` or `declare`.
+const LINKAGES: &[&str] = &[
+    "private",
+    "internal",
+    "available_externally",
+    "linkonce",
+    "weak",
+    "common",
+    "appending",
+    "extern_weak",
+    "linkonce_odr",
+    "weak_odr",
+    "external",
+];
+
+/// Parses the text of an LLVM module.
+///
+/// # Errors
+///
+/// Returns an error naming the line if a function is malformed, such as a
+/// definition that is never closed.
+pub fn parse(text: &str) -> Result<Module, IrParseError> {
+    let mut module = Module::default();
+    let mut current: Option<Function> = None;
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let error = |message: &str| IrParseError {
+            line: number,
+            message: message.to_string(),
+        };
+        let code = strip_comment(line).trim_end();
+        if code.trim().is_empty() {
+            continue;
+        }
+        let Some(function) = current.as_mut() else {
+            if let Some(header) = code.strip_prefix("define ") {
+                let function = parse_header(header).ok_or_else(|| error("malformed define"))?;
+                if !code.ends_with('{') {
+                    return Err(error("expected '{' after the function header"));
+                }
+                current = Some(function);
+            } else if let Some(header) = code.strip_prefix("declare ") {
+                let function = parse_header(header).ok_or_else(|| error("malformed declare"))?;
+                module.functions.push(function);
+            }
+            continue;
+        };
+        if code == "}" {
+            module.functions.extend(current.take());
+        } else if !code.starts_with(char::is_whitespace) {
+            let label = code
+                .strip_suffix(':')
+                .ok_or_else(|| error("expected a block label"))?;
+            function.blocks.push(Block {
+                label: label.to_string(),
+                instructions: Vec::new(),
+            });
+        } else {
+            let instruction =
+                parse_instruction(code.trim()).ok_or_else(|| error("malformed instruction"))?;
+            if function.blocks.is_empty() {
+                function.blocks.push(Block {
+                    label: String::new(),
+                    instructions: Vec::new(),
+                });
+            }
+            function
+                .blocks
+                .last_mut()
+                .expect("a block was just ensured")
+                .instructions
+                .push(instruction);
+        }
+    }
+    match current {
+        Some(function) => Err(IrParseError {
+            line: text.lines().count(),
+            message: format!("function '{}' is not closed", function.name),
+        }),
+        None => Ok(module),
+    }
+}
+
+/// Removes a `;` comment, leaving semicolons inside string constants.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Parses a function header after `define` or `declare`, up to and
+/// including its parameter list.
+fn parse_header(header: &str) -> Option<Function> {
+    let at = header.find('@')?;
+    let prefix: Vec<&str> = header[..at].split_whitespace().collect();
+    let return_type = prefix.last()?.to_string();
+    let linkage = prefix
+        .first()
+        .filter(|word| LINKAGES.contains(word))
+        .map(|word| word.to_string());
+    let rest = &header[at + 1..];
+    let open = rest.find('(')?;
+    let name = rest[..open].trim_matches('"').to_string();
+    let close = matching_paren(rest, open)?;
+    let params = split_top_level(&rest[open + 1..close])
+        .into_iter()
+        .map(|param| {
+            // Drop the parameter name, keeping the type and its attributes
+            let param = param.trim();
+            match param.rsplit_once(' ') {
+                Some((ty, name)) if name.starts_with('%') => ty.to_string(),
+                _ => param.to_string(),
+            }
+        })
+        .filter(|param| !param.is_empty())
+        .collect();
+    Some(Function {
+        name,
+        linkage,
+        return_type,
+        params,
+        blocks: Vec::new(),
+    })
+}
+
+/// Returns the index of the parenthesis closing the one at `open`.
+fn matching_paren(text: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text[open..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits `text` at the commas outside any brackets.
+fn split_top_level(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' | '[' | '{' | '<' => depth += 1,
+            ')' | ']' | '}' | '>' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Parses an instruction such as `%x = add i64 %a, %b`.
+fn parse_instruction(code: &str) -> Option<Instruction> {
+    let (result, rest) = match code.split_once(" = ") {
+        Some((result, rest)) if result.starts_with('%') => {
+            (Some(result[1..].trim_matches('"').to_string()), rest)
+        }
+        _ => (None, code),
+    };
+    let mut words = rest.splitn(2, ' ');
+    let mut opcode = words.next()?;
+    let mut operands = words.next().unwrap_or("");
+    if CALL_MARKERS.contains(&opcode) {
+        let mut words = operands.splitn(2, ' ');
+        opcode = words.next()?;
+        operands = words.next().unwrap_or("");
+    }
+    Some(Instruction {
+        result,
+        opcode: opcode.to_string(),
+        operands: operands.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODULE: &str = r#"; ModuleID = 'virtuc'
+source_filename = "virtuc"
+
+@.str = private unnamed_addr constant [4 x i8] c"a;b\00", align 1
+
+declare i32 @printf(ptr, ...)
+
+define internal i64 @square(i64 %0) #0 {
+entry:
+  %x = alloca i64, align 8
+  store i64 %0, ptr %x, align 8
+  %x1 = load i64, ptr %x, align 8
+  %mul = mul i64 %x1, %x1
+  ret i64 %mul
+}
+
+define signext i32 @main() {
+  %r = tail call i64 @square(i64 3)
+  br label %done
+
+done:                                             ; preds = %0
+  %p = call i32 (ptr, ...) @printf(ptr @.str)
+  ret i32 0
+}
+
+attributes #0 = { "frame-pointer"="all" }
+"#;
+
+    #[test]
+    fn test_parse_module() {
+        let module = parse(MODULE).unwrap();
+        let names: Vec<_> = module.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["printf", "square", "main"]);
+        assert_eq!(module.definitions().count(), 2);
+
+        let printf = module.function("printf").unwrap();
+        assert!(printf.is_declaration());
+        assert_eq!(printf.params, ["ptr", "..."]);
+
+        let square = module.function("square").unwrap();
+        assert_eq!(square.linkage.as_deref(), Some("internal"));
+        assert!(!square.is_external());
+        assert_eq!(square.return_type, "i64");
+        assert_eq!(square.params, ["i64"]);
+        assert_eq!(square.opcodes(), ["alloca", "store", "load", "mul", "ret"]);
+        let mul = &square.block("entry").unwrap().instructions[3];
+        assert_eq!(mul.result.as_deref(), Some("mul"));
+        assert_eq!(mul.operands, "i64 %x1, %x1");
+
+        let main = module.function("main").unwrap();
+        assert!(main.is_external());
+        assert_eq!(main.return_type, "i32");
+        assert_eq!(main.blocks.len(), 2);
+        assert_eq!(main.blocks[0].label, "");
+        assert_eq!(main.count("call"), 2);
+        assert_eq!(main.callees(), ["square", "printf"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let e = parse("define i64 @f() {\nentry:\n  ret i64 0\n").unwrap_err();
+        assert_eq!(e.message, "function 'f' is not closed");
+        let e = parse("define i64 @f() {\nnot a label\n}\n").unwrap_err();
+        assert_eq!(e.line, 2);
+    }
+}
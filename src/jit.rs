@@ -0,0 +1,427 @@
+//! # JIT Sessions
+//!
+//! This module compiles functions to machine code in the running process, a
+//! few at a time, for front ends such as a REPL that define functions one
+//! input after another and call them between inputs. A [`JitSession`]
+//! keeps what was compiled: each input only compiles the functions it
+//! defines and those that depend on them.
+//!
+//! ## Units
+//!
+//! Functions are compiled in units, each a module of its own in an LLVM
+//! execution engine of its own. A unit holds one function, or a group of
+//! functions that call each other in a cycle, which must be compiled
+//! together. The other functions a unit calls are only declared in its
+//! module, and bound to the addresses of their compiled code, so callees
+//! are compiled before their callers.
+//!
+//! ## Redefinition
+//!
+//! Defining a function again replaces it. A caller's code is bound to the
+//! address of its callee's code, so every function that reaches a replaced
+//! one through calls is compiled again, and the units they were in are
+//! dropped along with their code. Functions that do not reach it keep
+//! their code.
+//!
+//! Extern functions such as `printf` are looked up in the running process.
+
+use std::collections::{HashMap, HashSet};
+
+use inkwell::context::Context;
+use inkwell::execution_engine::ExecutionEngine;
+
+use crate::analysis::{self, CallGraph};
+use crate::ast::{Program, Type};
+use crate::codegen;
+use crate::consteval::ConstValue;
+use crate::error::{CodegenError, CompileError};
+use crate::imports;
+use crate::lexer;
+use crate::options::{CompileOptions, IntWidth};
+use crate::parser;
+use crate::semantic;
+
+/// Functions compiled together, and the engine that holds their code.
+struct Unit<'ctx> {
+    /// Owns the unit's code, which is freed once the engine is dropped
+    _engine: ExecutionEngine<'ctx>,
+    /// The functions the unit defines
+    functions: Vec<String>,
+}
+
+/// A set of functions compiled in the running process, to which inputs add
+/// functions and replace them.
+///
+/// # Examples
+///
+/// ```
+/// use inkwell::context::Context;
+/// use virtuc::ConstValue;
+/// use virtuc::jit::JitSession;
+///
+/// let context = Context::create();
+/// let mut session = JitSession::new(&context);
+/// session.define("int two() { return 2; }").unwrap();
+/// session.define("int four() { return two() * 2; }").unwrap();
+/// assert_eq!(session.call("four").unwrap(), ConstValue::Int(4));
+///
+/// session.define("int two() { return 3; }").unwrap();
+/// assert_eq!(session.call("four").unwrap(), ConstValue::Int(6));
+/// ```
+pub struct JitSession<'ctx> {
+    context: &'ctx Context,
+    options: CompileOptions,
+    /// Every function and declaration defined so far, with the latest
+    /// definition of each function
+    program: Program,
+    units: Vec<Unit<'ctx>>,
+    /// Address of the compiled code of each function
+    addresses: HashMap<String, usize>,
+}
+
+impl<'ctx> JitSession<'ctx> {
+    /// Creates an empty session.
+    pub fn new(context: &'ctx Context) -> Self {
+        Self::with_options(context, &CompileOptions::default())
+    }
+
+    /// Creates an empty session compiling as configured by the compile
+    /// options. Every function is kept and visible to the session, so
+    /// exports, stripping of unused functions, and coverage are ignored.
+    pub fn with_options(context: &'ctx Context, options: &CompileOptions) -> Self {
+        let options = CompileOptions {
+            exports: None,
+            strip_unused: false,
+            coverage_file: None,
+            ..options.clone()
+        };
+        Self {
+            context,
+            options,
+            program: Program {
+                includes: Vec::new(),
+                imports: Vec::new(),
+                extern_functions: Vec::new(),
+                functions: Vec::new(),
+            },
+            units: Vec::new(),
+            addresses: HashMap::new(),
+        }
+    }
+
+    /// Compiles the functions, extern declarations, and includes of
+    /// `source`, returning the names of the functions it defines. Functions
+    /// defined before under the same names are replaced.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `source` does not compile together with what
+    /// the session already defines, in which case the session is unchanged.
+    pub fn define(&mut self, source: &str) -> Result<Vec<String>, CompileError> {
+        let (tokens, spans): (Vec<_>, Vec<_>) = lexer::lex_with_options(source, &self.options)?
+            .into_iter()
+            .unzip();
+        let input = parser::parse_with_options(&tokens, &self.options)
+            .map_err(|e| parser::locate_error(e, source, &spans))?;
+        let input = imports::merge(input, &self.options)?;
+
+        let mut program = self.program.clone();
+        for include in input.includes {
+            if !program.includes.contains(&include) {
+                program.includes.push(include);
+            }
+        }
+        for extern_func in input.extern_functions {
+            match program
+                .extern_functions
+                .iter_mut()
+                .find(|e| e.name == extern_func.name)
+            {
+                Some(declared) => *declared = extern_func,
+                None => program.extern_functions.push(extern_func),
+            }
+        }
+        let defined: Vec<String> = input.functions.iter().map(|f| f.name.clone()).collect();
+        for function in input.functions {
+            match program
+                .functions
+                .iter_mut()
+                .find(|f| f.name == function.name)
+            {
+                Some(previous) => *previous = function,
+                None => program.functions.push(function),
+            }
+        }
+
+        let errors = semantic::analyze_with_options(&program, &self.options);
+        if !errors.is_empty() {
+            let spans = vec![None; errors.len()];
+            return Err(CompileError::Semantic(errors, spans));
+        }
+        self.compile(program, &defined)?;
+        Ok(defined)
+    }
+
+    /// Returns the names of the functions defined so far, in the order they
+    /// were first defined.
+    pub fn functions(&self) -> impl Iterator<Item = &str> {
+        self.program.functions.iter().map(|f| f.name.as_str())
+    }
+
+    /// Calls the function `name`, which must take no parameters and return
+    /// an `int` or a `float`, and returns its result.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the session does not define `name`, or if it
+    /// takes parameters or returns a `string`.
+    pub fn call(&self, name: &str) -> Result<ConstValue, CodegenError> {
+        let function = self
+            .program
+            .functions
+            .iter()
+            .find(|f| f.name == name)
+            .ok_or_else(|| CodegenError::new(format!("Undefined function: {}", name)))?;
+        if !function.params.is_empty() {
+            return Err(CodegenError::new(format!(
+                "Cannot call {} without arguments: it takes {} parameters",
+                name,
+                function.params.len()
+            )));
+        }
+        let address = self.addresses[name];
+        // SAFETY: the address is that of the compiled code of `name`, which
+        // its unit keeps alive, and the function type matches its signature
+        unsafe {
+            match (function.return_ty, self.options.int_width) {
+                (Type::Int, IntWidth::W32) => {
+                    let f: extern "C" fn() -> i32 = std::mem::transmute(address);
+                    Ok(ConstValue::Int(i64::from(f())))
+                }
+                (Type::Int, IntWidth::W64) => {
+                    let f: extern "C" fn() -> i64 = std::mem::transmute(address);
+                    Ok(ConstValue::Int(f()))
+                }
+                (Type::Float, _) => {
+                    let f: extern "C" fn() -> f64 = std::mem::transmute(address);
+                    Ok(ConstValue::Float(f()))
+                }
+                (Type::String, _) => Err(CodegenError::new(format!(
+                    "Cannot call {}: it returns a string",
+                    name
+                ))),
+            }
+        }
+    }
+
+    /// Compiles the functions of `program` that reach one of `defined`,
+    /// and replaces the session's program with it once all of them compile.
+    fn compile(&mut self, program: Program, defined: &[String]) -> Result<(), CodegenError> {
+        let graph = analysis::call_graph(&program);
+        let reaches_defined = |name: &str| {
+            graph
+                .reachable_from(name)
+                .iter()
+                .any(|f| defined.iter().any(|d| d == f))
+        };
+        let mut stale: HashSet<String> = program
+            .functions
+            .iter()
+            .map(|f| f.name.clone())
+            .filter(|name| reaches_defined(name))
+            .collect();
+        // A unit's code goes once it is dropped, so all of its functions are
+        // compiled again
+        for unit in &self.units {
+            if unit.functions.iter().any(|f| stale.contains(f.as_str())) {
+                stale.extend(unit.functions.iter().cloned());
+            }
+        }
+
+        let mut addresses = self.addresses.clone();
+        addresses.retain(|name, _| !stale.contains(name.as_str()));
+        let mut units = Vec::new();
+        let mut pending: Vec<&str> = graph
+            .functions()
+            .iter()
+            .map(String::as_str)
+            .filter(|name| stale.contains(*name))
+            .collect();
+        while !pending.is_empty() {
+            // The first function whose callees outside its cycle are
+            // compiled, with the functions in that cycle
+            let group = pending
+                .iter()
+                .map(|name| cycle(&graph, name))
+                .find(|group| {
+                    group.iter().all(|name| {
+                        graph.callees(name).iter().all(|callee| {
+                            group.contains(&callee.as_str())
+                                || addresses.contains_key(callee)
+                                || !stale.contains(callee.as_str())
+                        })
+                    })
+                })
+                .expect("the cycles of a call graph form a DAG");
+            let unit = self.compile_unit(&program, &group, &mut addresses)?;
+            pending.retain(|name| !group.contains(name));
+            units.push(unit);
+        }
+
+        self.units
+            .retain(|unit| !unit.functions.iter().any(|f| stale.contains(f.as_str())));
+        self.units.extend(units);
+        self.addresses = addresses;
+        self.program = program;
+        Ok(())
+    }
+
+    /// Compiles the functions `group` of `program` in a unit of their own,
+    /// binding the functions they call to `addresses`, and adds their own
+    /// addresses to it.
+    fn compile_unit(
+        &self,
+        program: &Program,
+        group: &[&str],
+        addresses: &mut HashMap<String, usize>,
+    ) -> Result<Unit<'ctx>, CodegenError> {
+        let module =
+            codegen::generate_module_of(self.context, program, &self.options, Some(group))?;
+        let engine = module
+            .create_jit_execution_engine(codegen::optimization_level(self.options.opt_level))
+            .map_err(|e| CodegenError::new(e.to_string()))?;
+        for function in module.get_functions() {
+            let name = function.get_name().to_string_lossy();
+            if function.count_basic_blocks() == 0
+                && let Some(&address) = addresses.get(name.as_ref())
+            {
+                engine.add_global_mapping(&function, address);
+            }
+        }
+        for name in group {
+            let address = engine
+                .get_function_address(name)
+                .map_err(|e| CodegenError::new(format!("Cannot compile {}: {:?}", name, e)))?;
+            addresses.insert(name.to_string(), address);
+        }
+        Ok(Unit {
+            _engine: engine,
+            functions: group.iter().map(|name| name.to_string()).collect(),
+        })
+    }
+}
+
+/// Returns the functions that both reach and are reached from `name`,
+/// including `name` itself.
+fn cycle<'a>(graph: &'a CallGraph, name: &str) -> Vec<&'a str> {
+    graph
+        .reachable_from(name)
+        .into_iter()
+        .filter(|f| graph.reachable_from(f).contains(&name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_define_and_call() {
+        let context = Context::create();
+        let mut session = JitSession::new(&context);
+        let defined = session
+            .define("int square(int x) { return x * x; } int nine() { return square(3); }")
+            .unwrap();
+        assert_eq!(defined, ["square", "nine"]);
+        assert_eq!(session.call("nine").unwrap(), ConstValue::Int(9));
+
+        // Later inputs call what earlier ones defined
+        session
+            .define("float half() { return 1.0 / 2.0; }")
+            .unwrap();
+        session
+            .define("int sixteen() { return square(4); }")
+            .unwrap();
+        assert_eq!(session.call("half").unwrap(), ConstValue::Float(0.5));
+        assert_eq!(session.call("sixteen").unwrap(), ConstValue::Int(16));
+        assert_eq!(
+            session.functions().collect::<Vec<_>>(),
+            ["square", "nine", "half", "sixteen"]
+        );
+    }
+
+    #[test]
+    fn test_redefinition_recompiles_callers() {
+        let context = Context::create();
+        let mut session = JitSession::new(&context);
+        session
+            .define(
+                "int base() { return 1; }
+                 int twice() { return base() * 2; }
+                 int other() { return 7; }",
+            )
+            .unwrap();
+        assert_eq!(session.call("twice").unwrap(), ConstValue::Int(2));
+        let other = session.addresses["other"];
+
+        session.define("int base() { return 5; }").unwrap();
+        assert_eq!(session.call("base").unwrap(), ConstValue::Int(5));
+        assert_eq!(session.call("twice").unwrap(), ConstValue::Int(10));
+        // Functions that do not call it keep their code
+        assert_eq!(session.addresses["other"], other);
+        assert_eq!(session.units.len(), 3);
+    }
+
+    #[test]
+    fn test_recursive_functions() {
+        let context = Context::create();
+        let mut session = JitSession::new(&context);
+        session
+            .define(
+                "int is_even(int n) { if (n == 0) { return 1; } return is_odd(n - 1); }
+                 int is_odd(int n) { if (n == 0) { return 0; } return is_even(n - 1); }
+                 int fact(int n) { if (n < 2) { return 1; } return n * fact(n - 1); }",
+            )
+            .unwrap();
+        session
+            .define("int check() { return is_even(10) * 100 + fact(5); }")
+            .unwrap();
+        assert_eq!(session.call("check").unwrap(), ConstValue::Int(220));
+        // The mutually recursive pair is one unit
+        assert_eq!(session.units.len(), 3);
+    }
+
+    #[test]
+    fn test_failed_input_leaves_session_unchanged() {
+        let context = Context::create();
+        let mut session = JitSession::new(&context);
+        session.define("int one() { return 1; }").unwrap();
+        assert!(matches!(
+            session.define("int one() { return missing(); }"),
+            Err(CompileError::Semantic(..))
+        ));
+        assert!(matches!(
+            session.define("int one() { return"),
+            Err(CompileError::Parse(..))
+        ));
+        assert_eq!(session.call("one").unwrap(), ConstValue::Int(1));
+        assert!(session.call("two").is_err());
+    }
+
+    #[test]
+    fn test_call_checks_signature() {
+        let context = Context::create();
+        let mut session = JitSession::with_options(
+            &context,
+            &CompileOptions {
+                int_width: IntWidth::W32,
+                ..CompileOptions::default()
+            },
+        );
+        session
+            .define("int negative() { return 0 - 3; } int id(int x) { return x; }")
+            .unwrap();
+        assert_eq!(session.call("negative").unwrap(), ConstValue::Int(-3));
+        assert!(session.call("id").is_err());
+    }
+}
@@ -17,13 +17,24 @@
 //!
 //! Uses the `logos` procedural macro to define token patterns and generate
 //! the lexer automatically. Handles whitespace, comments, and error recovery.
-
-use crate::error::LexerError;
+//! [`TokenStream`] exposes the lexer to external tools one token at a time,
+//! with lookahead; [`lex`] and [`lex_with_spans`] collect it for the parser.
+//! [`parse_stream`](crate::parser::parse_stream) consumes it lazily instead,
+//! for large inputs.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::diagnostics::Span;
+use crate::error::{LexErrorKind, LexerError};
+use crate::interner::Symbol;
+use crate::options::CompileOptions;
 use logos::Logos;
 
 /// Represents the tokens produced by the lexer.
 #[derive(Logos, Debug, PartialEq, Clone)]
-#[logos(skip r"[ \t\n\f]+")] // Skip whitespace
+#[logos(error = LexErrorKind)]
+#[logos(skip r"[ \t\r\n\f]+")] // Skip whitespace, including CRLF line endings
 #[logos(skip r"//[^\n]*")] // Skip single-line comments
 pub enum Token {
     /// Integer keyword
@@ -57,23 +68,30 @@ pub enum Token {
     /// Extern keyword
     #[token("extern")]
     Extern,
-    #[regex(r"[a-zA-Z_][a-zA-Z0-9_]*", |lex| lex.slice().to_owned())]
-    Identifier(String),
-
-    /// Float literal
-    #[regex(r"\d+\.\d+", |lex| lex.slice().parse::<f64>().unwrap())]
+    /// Identifier; non-ASCII identifiers are lexing errors unless allowed
+    #[regex(r"[\p{XID_Start}_]\p{XID_Continue}*", |lex| Symbol::intern(lex.slice()))]
+    Identifier(Symbol),
+
+    /// Float literal; literals that overflow `f64` are lexing errors
+    #[regex(r"\d+\.\d+", |lex| {
+        lex.slice()
+            .parse::<f64>()
+            .ok()
+            .filter(|f| f.is_finite())
+            .ok_or(LexErrorKind::FloatOutOfRange)
+    })]
     FloatLiteral(f64),
 
-    /// Integer literal
-    #[regex(r"\d+", |lex| lex.slice().parse::<i64>().unwrap())]
+    /// Integer literal; literals that overflow `i64` are lexing errors
+    #[regex(r"\d+", |lex| lex.slice().parse::<i64>().map_err(|_| LexErrorKind::IntegerOutOfRange))]
     IntLiteral(i64),
 
-    /// String literal
+    /// String literal; adjacent literals are concatenated by the parser
     #[regex(r#""([^"\\]|\\.)*""#, |lex| {
         // Strip surrounding quotes and unescape common C-style escapes
         let s = lex.slice();
         let inner = &s[1..s.len()-1];
-        unescape_c_string(inner)
+        unescape_c_string(inner).ok_or(LexErrorKind::InvalidUnicodeEscape)
     })]
     StringLiteral(String),
 
@@ -86,6 +104,14 @@ pub enum Token {
     })]
     Include(String),
 
+    /// Import of another source file: #include "file.c"
+    #[regex(r#"#include\s*"[^"\n]+""#, |lex| {
+        let s = lex.slice();
+        let start = s.find('"').map(|i| i+1).unwrap_or(0);
+        s[start..s.len()-1].to_string()
+    })]
+    LocalInclude(String),
+
     /// Less than or equal operator
     #[token("<=")]
     LessEqual,
@@ -159,6 +185,47 @@ pub enum Token {
     Ellipsis,
 }
 
+impl fmt::Display for Token {
+    /// Writes the token as it appears in source code.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Token::Int => "int",
+            Token::Float => "float",
+            Token::StringType => "string",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::For => "for",
+            Token::Return => "return",
+            Token::Extern => "extern",
+            Token::Identifier(name) => return write!(f, "{}", name),
+            Token::FloatLiteral(value) => return write!(f, "{:?}", value),
+            Token::IntLiteral(value) => return write!(f, "{}", value),
+            Token::StringLiteral(value) => return write!(f, "{:?}", value),
+            Token::Include(header) => return write!(f, "#include <{}>", header),
+            Token::LocalInclude(file) => return write!(f, "#include \"{}\"", file),
+            Token::LessEqual => "<=",
+            Token::GreaterEqual => ">=",
+            Token::Equal => "==",
+            Token::NotEqual => "!=",
+            Token::LessThan => "<",
+            Token::GreaterThan => ">",
+            Token::Assign => "=",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Multiply => "*",
+            Token::Divide => "/",
+            Token::Semicolon => ";",
+            Token::Comma => ",",
+            Token::LParen => "(",
+            Token::RParen => ")",
+            Token::LBrace => "{",
+            Token::RBrace => "}",
+            Token::Ellipsis => "...",
+        };
+        write!(f, "{}", text)
+    }
+}
+
 /// Lexes the input source code into a vector of tokens.
 ///
 /// # Arguments
@@ -169,21 +236,155 @@ pub enum Token {
 ///
 /// A `Result` containing a vector of tokens or a lexing error.
 pub fn lex(input: &str) -> Result<Vec<Token>, LexerError> {
-    let lexer = Token::lexer(input);
-    let mut tokens = Vec::new();
+    Ok(lex_with_spans(input)?
+        .into_iter()
+        .map(|(token, _)| token)
+        .collect())
+}
+
+/// Lexes the input source code into tokens paired with their source spans.
+///
+/// # Arguments
+///
+/// * `input` - The source code string to tokenize.
+///
+/// # Returns
+///
+/// A `Result` containing a vector of (token, span) pairs or a lexing error.
+pub fn lex_with_spans(input: &str) -> Result<Vec<(Token, Span)>, LexerError> {
+    TokenStream::new(input).collect()
+}
 
-    for token in lexer {
-        match token {
-            Ok(t) => tokens.push(t),
-            Err(_) => return Err(LexerError),
+/// Lexes the input source code into tokens paired with their source spans,
+/// enforcing the length limits of the options.
+pub fn lex_with_options(
+    input: &str,
+    options: &CompileOptions,
+) -> Result<Vec<(Token, Span)>, LexerError> {
+    TokenStream::with_options(input, options).collect()
+}
+
+/// The byte order mark, which is skipped at the start of a source file.
+const BYTE_ORDER_MARK: char = '\u{feff}';
+
+/// A stream of tokens lexed on demand, with lookahead.
+///
+/// Unlike [`lex`], the stream keeps going after an invalid token, which
+/// makes it suitable for tools such as syntax highlighters that must cope
+/// with code being edited.
+pub struct TokenStream<'source> {
+    lexer: logos::Lexer<'source, Token>,
+    /// Tokens that have been peeked at but not consumed
+    lookahead: VecDeque<Result<(Token, Span), LexerError>>,
+    /// Maximum length of an identifier
+    max_identifier_len: usize,
+    /// Maximum length of a string literal
+    max_string_len: usize,
+    /// Whether identifiers may be non-ASCII
+    allow_unicode_ids: bool,
+}
+
+impl<'source> TokenStream<'source> {
+    /// Creates a stream over the tokens of `source`, with the default
+    /// length limits.
+    pub fn new(source: &'source str) -> Self {
+        Self::with_options(source, &CompileOptions::default())
+    }
+
+    /// Creates a stream over the tokens of `source`, enforcing the length
+    /// limits of the options.
+    pub fn with_options(source: &'source str, options: &CompileOptions) -> Self {
+        let mut lexer = Token::lexer(source);
+        // Skip a byte order mark, keeping spans relative to the source
+        if source.starts_with(BYTE_ORDER_MARK) {
+            lexer.bump(BYTE_ORDER_MARK.len_utf8());
+        }
+        Self {
+            lexer,
+            lookahead: VecDeque::new(),
+            max_identifier_len: options.max_identifier_len(),
+            max_string_len: options.max_string_len(),
+            allow_unicode_ids: options.allow_unicode_ids,
         }
     }
 
-    Ok(tokens)
+    /// Returns the next token without consuming it.
+    pub fn peek(&mut self) -> Option<&Result<(Token, Span), LexerError>> {
+        self.peek_nth(0)
+    }
+
+    /// Returns the token `n` positions ahead without consuming anything, so
+    /// that `peek_nth(0)` is the next token.
+    pub fn peek_nth(&mut self, n: usize) -> Option<&Result<(Token, Span), LexerError>> {
+        while self.lookahead.len() <= n {
+            let token = self.lex_next()?;
+            self.lookahead.push_back(token);
+        }
+        self.lookahead.get(n)
+    }
+
+    /// Returns the span of the next token, or an empty span at the end of
+    /// the source once the stream is exhausted.
+    pub fn span(&mut self) -> Span {
+        let end = self.lexer.source().len();
+        match self.peek() {
+            Some(Ok((_, span))) => *span,
+            Some(Err(e)) => e.span,
+            None => Span::new(end, end),
+        }
+    }
+
+    /// Lexes the token after those in the lookahead buffer.
+    fn lex_next(&mut self) -> Option<Result<(Token, Span), LexerError>> {
+        let token = self.lexer.next()?;
+        let span = Span::new(self.lexer.span().start, self.lexer.span().end);
+        let token = token.and_then(|token| {
+            if let Token::Identifier(name) = &token
+                && !name.as_str().is_ascii()
+                && !self.allow_unicode_ids
+            {
+                return Err(LexErrorKind::NonAsciiIdentifier);
+            }
+            let (limit, length) = match &token {
+                Token::Identifier(name) => (self.max_identifier_len, name.as_str().len()),
+                Token::StringLiteral(value) => (self.max_string_len, value.len()),
+                _ => return Ok(token),
+            };
+            if length <= limit {
+                Ok(token)
+            } else if let Token::Identifier(_) = token {
+                Err(LexErrorKind::IdentifierTooLong { limit, length })
+            } else {
+                Err(LexErrorKind::StringTooLong { limit, length })
+            }
+        });
+        let token = token.map_err(|kind| match kind {
+            // Name the character, which may be invisible or look like ASCII
+            LexErrorKind::InvalidToken => match self.lexer.slice().chars().next() {
+                Some(c) if !c.is_ascii() => LexErrorKind::NonAsciiCharacter(c),
+                _ => kind,
+            },
+            kind => kind,
+        });
+        Some(
+            token
+                .map(|t| (t, span))
+                .map_err(|kind| LexerError::new(kind, span)),
+        )
+    }
+}
+
+impl Iterator for TokenStream<'_> {
+    type Item = Result<(Token, Span), LexerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lookahead.pop_front().or_else(|| self.lex_next())
+    }
 }
 
-// Helper: Unescape a C-style string body (no surrounding quotes)
-fn unescape_c_string(s: &str) -> String {
+// Helper: Unescape a C-style string body (no surrounding quotes). Returns
+// None for a malformed universal character name (`\u` or `\U` escape).
+fn unescape_c_string(s: &str) -> Option<String> {
     let mut out = String::with_capacity(s.len());
     let mut chars = s.chars();
     while let Some(c) = chars.next() {
@@ -222,6 +423,14 @@ fn unescape_c_string(s: &str) -> String {
                         }
                     }
                 }
+                Some(u @ ('u' | 'U')) => {
+                    let digits = if u == 'u' { 4 } else { 8 };
+                    let hex: String = chars.by_ref().take(digits).collect();
+                    if hex.len() != digits || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return None;
+                    }
+                    out.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                }
                 Some(other) => {
                     // Unknown escape, keep as-is
                     out.push(other);
@@ -232,19 +441,49 @@ fn unescape_c_string(s: &str) -> String {
             out.push(c);
         }
     }
-    out
+    Some(out)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_token_stream_lookahead() {
+        let mut stream = TokenStream::new("x = @ 1");
+        assert_eq!(stream.span(), Span::new(0, 1));
+        assert_eq!(
+            stream.peek_nth(1),
+            Some(&Ok((Token::Assign, Span::new(2, 3))))
+        );
+        assert_eq!(
+            stream.next(),
+            Some(Ok((Token::Identifier("x".into()), Span::new(0, 1))))
+        );
+        assert_eq!(stream.next(), Some(Ok((Token::Assign, Span::new(2, 3)))));
+        // Lexing continues after an invalid token
+        assert_eq!(
+            stream.next(),
+            Some(Err(LexerError::new(
+                LexErrorKind::InvalidToken,
+                Span::new(4, 5)
+            )))
+        );
+        assert_eq!(
+            stream.peek(),
+            Some(&Ok((Token::IntLiteral(1), Span::new(6, 7))))
+        );
+        assert!(stream.next().is_some());
+        assert_eq!(stream.peek(), None);
+        assert_eq!(stream.span(), Span::new(7, 7));
+    }
+
     #[test]
     fn test_simple_declaration() {
         let input = "int x = 5;";
         let expected = vec![
             Token::Int,
-            Token::Identifier("x".to_string()),
+            Token::Identifier("x".into()),
             Token::Assign,
             Token::IntLiteral(5),
             Token::Semicolon,
@@ -253,11 +492,12 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_float_declaration() {
         let input = "float y = 3.14;";
         let expected = vec![
             Token::Float,
-            Token::Identifier("y".to_string()),
+            Token::Identifier("y".into()),
             Token::Assign,
             Token::FloatLiteral(3.14),
             Token::Semicolon,
@@ -269,9 +509,9 @@ mod tests {
     fn test_arithmetic_expression() {
         let input = "x + y * 2";
         let expected = vec![
-            Token::Identifier("x".to_string()),
+            Token::Identifier("x".into()),
             Token::Plus,
-            Token::Identifier("y".to_string()),
+            Token::Identifier("y".into()),
             Token::Multiply,
             Token::IntLiteral(2),
         ];
@@ -282,9 +522,9 @@ mod tests {
     fn test_comparison() {
         let input = "a == b";
         let expected = vec![
-            Token::Identifier("a".to_string()),
+            Token::Identifier("a".into()),
             Token::Equal,
-            Token::Identifier("b".to_string()),
+            Token::Identifier("b".into()),
         ];
         assert_eq!(lex(input).unwrap(), expected);
     }
@@ -295,6 +535,92 @@ mod tests {
         assert!(lex(input).is_err());
     }
 
+    #[test]
+    fn test_literal_and_length_limits() {
+        let err = lex("int x = 99999999999999999999;").unwrap_err();
+        assert_eq!(err.kind, LexErrorKind::IntegerOutOfRange);
+        assert_eq!(err.span, Span::new(8, 28));
+        assert_eq!(
+            lex("9223372036854775807").unwrap(),
+            [Token::IntLiteral(i64::MAX)]
+        );
+
+        let huge = format!("{}.0", "9".repeat(400));
+        assert_eq!(lex(&huge).unwrap_err().kind, LexErrorKind::FloatOutOfRange);
+
+        let options = CompileOptions {
+            max_identifier_len: Some(3),
+            max_string_len: Some(2),
+            ..CompileOptions::default()
+        };
+        assert!(lex_with_options("abc \"\\n\\t\"", &options).is_ok());
+        let err = lex_with_options("int abcd;", &options).unwrap_err();
+        assert_eq!(
+            err,
+            LexerError::new(
+                LexErrorKind::IdentifierTooLong {
+                    limit: 3,
+                    length: 4
+                },
+                Span::new(4, 8)
+            )
+        );
+        assert_eq!(
+            lex_with_options("\"abc\"", &options).unwrap_err().kind,
+            LexErrorKind::StringTooLong {
+                limit: 2,
+                length: 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_unicode() {
+        // UTF-8 is fine in strings and comments, and a byte order mark is
+        // skipped
+        let source = "\u{feff}int x; // café\nstring s = \"héllo → 世界\";";
+        let tokens = lex_with_spans(source).unwrap();
+        assert_eq!(tokens[0], (Token::Int, Span::new(3, 6)));
+        assert_eq!(
+            tokens[6].0,
+            Token::StringLiteral("héllo → 世界".to_string())
+        );
+
+        // Stray characters are named, with the span of the whole character
+        let err = lex("int x = 1 × 2;").unwrap_err();
+        assert_eq!(err.kind, LexErrorKind::NonAsciiCharacter('×'));
+        assert_eq!(err.span, Span::new(10, 12));
+
+        // Windows line endings are whitespace, and lines still count from
+        // the line feeds
+        let source = "\u{feff}int x; // café\r\nint y;\r\n";
+        let tokens = lex_with_spans(source).unwrap();
+        assert_eq!(tokens.len(), 6);
+        assert_eq!(tokens[4].0, Token::Identifier("y".into()));
+        assert_eq!(tokens[4].1.line_col(source), (2, 5));
+
+        let err = lex("int größe;").unwrap_err();
+        assert_eq!(err.kind, LexErrorKind::NonAsciiIdentifier);
+        assert_eq!(err.span, Span::new(4, 11));
+        let options = CompileOptions {
+            allow_unicode_ids: true,
+            ..CompileOptions::default()
+        };
+        assert_eq!(
+            lex_with_options("int größe;", &options).unwrap()[1].0,
+            Token::Identifier("größe".into())
+        );
+    }
+
+    #[test]
+    fn test_spans() {
+        let input = "int x = @;";
+        let err = lex(input).unwrap_err();
+        assert_eq!(err.span, Span::new(8, 9));
+        let tokens = lex_with_spans("int xy;").unwrap();
+        assert_eq!(tokens[1], (Token::Identifier("xy".into()), Span::new(4, 6)));
+    }
+
     #[test]
     fn test_string_literal_unescape() {
         let input = r#"int main() { printf("Hello\n"); }"#;
@@ -307,24 +633,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_unicode_escapes() {
+        assert_eq!(
+            lex(r#""caf\u00e9 \U0001F600""#).unwrap(),
+            [Token::StringLiteral("café 😀".to_string())]
+        );
+        for invalid in [
+            r#""\u00e""#,
+            r#""\u00eg""#,
+            r#""\ud800""#,
+            r#""\U00110000""#,
+        ] {
+            assert_eq!(
+                lex(invalid).unwrap_err().kind,
+                LexErrorKind::InvalidUnicodeEscape,
+                "{}",
+                invalid
+            );
+        }
+    }
+
     #[test]
     fn test_function_declaration() {
         let input = "int add(int a, int b) { return a + b; }";
         let expected = vec![
             Token::Int,
-            Token::Identifier("add".to_string()),
+            Token::Identifier("add".into()),
             Token::LParen,
             Token::Int,
-            Token::Identifier("a".to_string()),
+            Token::Identifier("a".into()),
             Token::Comma,
             Token::Int,
-            Token::Identifier("b".to_string()),
+            Token::Identifier("b".into()),
             Token::RParen,
             Token::LBrace,
             Token::Return,
-            Token::Identifier("a".to_string()),
+            Token::Identifier("a".into()),
             Token::Plus,
-            Token::Identifier("b".to_string()),
+            Token::Identifier("b".into()),
             Token::Semicolon,
             Token::RBrace,
         ];
@@ -337,13 +684,13 @@ mod tests {
         let expected = vec![
             Token::If,
             Token::LParen,
-            Token::Identifier("x".to_string()),
+            Token::Identifier("x".into()),
             Token::GreaterThan,
             Token::IntLiteral(0),
             Token::RParen,
             Token::LBrace,
             Token::Return,
-            Token::Identifier("x".to_string()),
+            Token::Identifier("x".into()),
             Token::Semicolon,
             Token::RBrace,
             Token::Else,
@@ -363,26 +710,26 @@ mod tests {
             Token::For,
             Token::LParen,
             Token::Int,
-            Token::Identifier("i".to_string()),
+            Token::Identifier("i".into()),
             Token::Assign,
             Token::IntLiteral(0),
             Token::Semicolon,
-            Token::Identifier("i".to_string()),
+            Token::Identifier("i".into()),
             Token::LessThan,
             Token::IntLiteral(10),
             Token::Semicolon,
-            Token::Identifier("i".to_string()),
+            Token::Identifier("i".into()),
             Token::Assign,
-            Token::Identifier("i".to_string()),
+            Token::Identifier("i".into()),
             Token::Plus,
             Token::IntLiteral(1),
             Token::RParen,
             Token::LBrace,
-            Token::Identifier("x".to_string()),
+            Token::Identifier("x".into()),
             Token::Assign,
-            Token::Identifier("x".to_string()),
+            Token::Identifier("x".into()),
             Token::Plus,
-            Token::Identifier("i".to_string()),
+            Token::Identifier("i".into()),
             Token::Semicolon,
             Token::RBrace,
         ];
@@ -394,12 +741,12 @@ mod tests {
         let input = "int x = 5; // this is a comment\nfloat y;";
         let expected = vec![
             Token::Int,
-            Token::Identifier("x".to_string()),
+            Token::Identifier("x".into()),
             Token::Assign,
             Token::IntLiteral(5),
             Token::Semicolon,
             Token::Float,
-            Token::Identifier("y".to_string()),
+            Token::Identifier("y".into()),
             Token::Semicolon,
         ];
         assert_eq!(lex(input).unwrap(), expected);
@@ -12,19 +12,218 @@
 //! 3. **Semantic Analysis**: AST validation and type checking
 //! 4. **Code Generation**: AST → LLVM Intermediate Representation (IR)
 //! 5. **Execution**: IR → Native executable
+//!
+//! ## Features
+//!
+//! Code generation and native compilation depend on LLVM and are enabled by
+//! the default `llvm` feature. Without it, the crate still provides lexing,
+//! parsing, and semantic analysis, with [`check`] running all three.
+//!
+//! ## Thread Safety
+//!
+//! Each compilation creates its own LLVM context, and the parser's state is
+//! thread-local. The only global state is the [`interner`] of identifiers,
+//! which every thread shares behind a lock. Compilations may therefore run
+//! concurrently on separate threads, and the intermediate types (tokens,
+//! ASTs, errors, and timings) are `Send` and `Sync`.
 
+pub mod analysis;
+pub mod artifacts;
+pub mod assertions;
 pub mod ast;
+pub mod builder;
+pub mod cheader;
+#[cfg(feature = "llvm")]
 pub mod codegen;
+pub mod consteval;
+pub mod coverage;
+pub mod cst;
+pub mod dataflow;
+pub mod diagnostics;
+#[cfg(feature = "llvm")]
+pub mod doctor;
+pub mod embed;
 pub mod error;
+pub mod format;
+pub mod fuzz;
+#[cfg(feature = "llvm")]
+pub mod grade;
 pub mod header_registry;
+pub mod highlight;
+pub mod ice;
+pub mod imports;
+pub mod incremental;
+pub mod interner;
+pub mod ir;
+#[cfg(feature = "llvm")]
+pub mod jit;
 pub mod lexer;
+pub mod manifest;
+pub mod mir;
+pub mod optimize;
+pub mod options;
 pub mod parser;
+pub mod printer;
+pub mod profile;
+pub mod random;
+pub mod resolve;
+pub mod run;
+#[cfg(feature = "llvm")]
+pub mod runtime;
 pub mod semantic;
+#[cfg(feature = "llvm")]
+pub mod serve;
+#[cfg(feature = "llvm")]
+pub mod size;
+pub mod template;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod timings;
 
+use std::collections::HashMap;
+#[cfg(feature = "llvm")]
 use std::fs;
-use std::path::Path;
+#[cfg(feature = "llvm")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "llvm")]
 use std::process::Command;
 
+#[cfg(feature = "llvm")]
+use inkwell::context::Context;
+#[cfg(feature = "llvm")]
+use inkwell::module::Module;
+
+pub use artifacts::CompileArtifacts;
+use ast::{Program, Stmt, StmtId};
+pub use consteval::ConstValue;
+use diagnostics::Span;
+#[cfg(feature = "llvm")]
+use error::LinkError;
+use error::{CompileError, SemanticError};
+pub use options::{CompileOptions, IntWidth, Sanitizer};
+pub use timings::{Phase, Timings};
+
+/// The version of the compiler, as recorded in emitted artifacts.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Checks a C subset source string, running every phase before code
+/// generation.
+///
+/// # Returns
+///
+/// * `Result<Program, CompileError>` - The validated AST if the source is a
+///   valid program, the error of the failing phase otherwise.
+pub fn check(source: &str) -> Result<Program, CompileError> {
+    check_with_options(source, &CompileOptions::default())
+}
+
+/// Checks a C subset source string using the given options.
+pub fn check_with_options(source: &str, options: &CompileOptions) -> Result<Program, CompileError> {
+    front_end(source, options, &mut Timings::new()).map(|(program, _)| program)
+}
+
+/// Generates a C header declaring the functions of a C subset source
+/// string, as [`cheader::generate`] does. `name` is the file name of the
+/// header, from which its include guard is derived.
+///
+/// Only the front end runs, so headers can be generated without LLVM.
+pub fn emit_header_with_options(
+    source: &str,
+    name: &str,
+    options: &CompileOptions,
+) -> Result<String, CompileError> {
+    let program = check_with_options(source, options)?;
+    Ok(cheader::generate(
+        &exported(&program, options),
+        name,
+        options.int_width,
+    ))
+}
+
+/// Lowers a C subset source string to [`mir`], as printed by
+/// `virtuc emit --emit=mir`. The program is first rewritten by the passes
+/// of [`optimize::pipeline`], as it is for code generation.
+///
+/// Only the front end runs, so MIR can be emitted without LLVM.
+pub fn emit_mir_with_options(
+    source: &str,
+    options: &CompileOptions,
+) -> Result<String, CompileError> {
+    let program = check_with_options(source, options)?;
+    Ok(mir::lower(&optimize::optimize_program(&program, options)).to_string())
+}
+
+/// Prints the program after each pass of [`optimize::pipeline`], as
+/// `virtuc opt-view` does: as formatted source, or as [`mir`] when `mir` is
+/// true. Each pass is introduced by a `// *** after NAME: DESCRIPTION ***`
+/// comment, and passes that leave the program unchanged are listed without
+/// it.
+///
+/// Only the front end runs, so this works without LLVM.
+pub fn opt_view_with_options(
+    source: &str,
+    options: &CompileOptions,
+    mir: bool,
+) -> Result<String, CompileError> {
+    let program = check_with_options(source, options)?;
+    let show = |program: &Program| {
+        if mir {
+            mir::lower(program).to_string()
+        } else {
+            printer::print_program(program)
+        }
+    };
+    let mut text = format!("// *** input ***\n{}", show(&program));
+    let mut previous = program.clone();
+    optimize::run_pipeline(&program, options, |pass, program| {
+        if *program == previous {
+            text.push_str(&format!("\n// *** after {} (no changes) ***\n", pass.name));
+        } else {
+            text.push_str(&format!(
+                "\n// *** after {}: {} ***\n{}",
+                pass.name,
+                pass.description,
+                show(program)
+            ));
+            previous = program.clone();
+        }
+    });
+    Ok(text)
+}
+
+/// Evaluates a constant expression such as `(1 + 2) * 3.0 / 4.0`.
+///
+/// The expression is lexed, parsed, and type-checked like an expression in
+/// a program, with no variables or functions in scope, and then evaluated
+/// by [`consteval`].
+///
+/// # Returns
+///
+/// * `Result<ConstValue, CompileError>` - The value of the expression, or
+///   the error of the failing phase.
+pub fn eval_expr(source: &str) -> Result<ConstValue, CompileError> {
+    eval_expr_with_options(source, &CompileOptions::default())
+}
+
+/// Evaluates a constant expression using the given options.
+pub fn eval_expr_with_options(
+    source: &str,
+    options: &CompileOptions,
+) -> Result<ConstValue, CompileError> {
+    let (tokens, spans): (Vec<_>, Vec<_>) = lexer::lex_with_options(source, options)?
+        .into_iter()
+        .unzip();
+    let expr = parser::parse_expression(&tokens, options)
+        .map_err(|e| parser::locate_error(e, source, &spans))?;
+    let errors = semantic::analyze_expr_with_options(&expr, options);
+    if !errors.is_empty() {
+        return Err(CompileError::Semantic(errors, Vec::new()));
+    }
+    // Strings are the only well-typed values that are not constants
+    consteval::eval(&expr.arena, expr.root, options.int_width)
+        .map_err(|e| CompileError::Semantic(vec![SemanticError::InvalidConstant(e)], Vec::new()))
+}
+
 /// Compiles a C subset source string to an executable at the specified output path.
 ///
 /// # Arguments
@@ -34,50 +233,538 @@ use std::process::Command;
 ///
 /// # Returns
 ///
-/// * `Result<(), Box<dyn std::error::Error>>` - Ok if compilation succeeds, Err otherwise.
-pub fn compile(source: &str, output: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    // Lexical analysis
-    let tokens = lexer::lex(source)?;
+/// * `Result<CompileArtifacts, CompileError>` - What was written if
+///   compilation succeeds, the error of the failing phase otherwise, as
+///   [`compile_with_options`] returns.
+#[cfg(feature = "llvm")]
+pub fn compile(source: &str, output: &Path) -> Result<CompileArtifacts, CompileError> {
+    compile_with_options(source, output, &CompileOptions::default())
+}
 
-    // Parsing
-    let ast = parser::parse(&tokens)?;
+/// Compiles a C subset source string to an executable using the given options.
+///
+/// # Arguments
+///
+/// * `source` - The source code string.
+/// * `output` - The path where the executable should be written.
+/// * `options` - Options controlling the compilation.
+///
+/// # Returns
+///
+/// * `Result<CompileArtifacts, CompileError>` - The executable written, the
+///   time spent in each phase and the program's warnings if compilation
+///   succeeds, the error of the failing phase otherwise.
+#[cfg(feature = "llvm")]
+pub fn compile_with_options(
+    source: &str,
+    output: &Path,
+    options: &CompileOptions,
+) -> Result<CompileArtifacts, CompileError> {
+    let mut timings = Timings::new();
+    let context = Context::create();
+    let (program, module) = generate_module(&context, source, options, &mut timings)?;
+    link_ir(
+        &module.print_to_string().to_string(),
+        output,
+        options,
+        &mut timings,
+    )?;
+    Ok(artifacts(
+        vec![output.to_path_buf()],
+        &[program],
+        options,
+        timings,
+    ))
+}
 
-    // Semantic analysis
-    let errors = semantic::analyze(&ast);
+/// Compiles a program built or rewritten in code, such as by a
+/// [`template::Template`], to an executable using the given options.
+///
+/// The program is checked by semantic analysis first, as a parsed one is.
+/// Assertion failures report no line, as the program has no source; with
+/// [`CompileOptions::embed_source`], the program as printed by
+/// [`printer::print_program`] is embedded.
+#[cfg(feature = "llvm")]
+pub fn compile_program_with_options(
+    program: &Program,
+    output: &Path,
+    options: &CompileOptions,
+) -> Result<CompileArtifacts, CompileError> {
+    let mut timings = Timings::new();
+    let errors = timings.time(Phase::Semantic, || {
+        semantic::analyze_with_options(program, options)
+    });
     if !errors.is_empty() {
-        let error_msg = errors
-            .iter()
-            .map(|e| e.to_string())
-            .collect::<Vec<_>>()
-            .join("\n");
-        return Err(format!("Semantic errors:\n{}", error_msg).into());
+        return Err(CompileError::Semantic(errors, Vec::new()));
+    }
+    let context = Context::create();
+    let module = timings.time(Phase::Codegen, || {
+        ice::catch(program, || {
+            codegen::generate_module(&context, program, options)
+        })
+    })??;
+    if options.embed_source {
+        let source = printer::print_program(program);
+        codegen::embed(&module, &embed::Embedded::new(&source, options));
+    }
+    link_ir(
+        &module.print_to_string().to_string(),
+        output,
+        options,
+        &mut timings,
+    )?;
+    Ok(artifacts(
+        vec![output.to_path_buf()],
+        std::slice::from_ref(program),
+        options,
+        timings,
+    ))
+}
+
+/// Compiles several C subset source files, given as `(name, source)` pairs,
+/// to one executable at the specified output path.
+///
+/// Each file is compiled to its own module, and the modules are linked
+/// before the executable is built. Files call the functions of others
+/// through `extern` declarations, and exactly one of them defines `main`.
+/// A function may be defined by one file only, and the `extern` declarations
+/// of other files must match its definition.
+#[cfg(feature = "llvm")]
+pub fn compile_many(
+    sources: &[(&str, &str)],
+    output: &Path,
+) -> Result<CompileArtifacts, CompileError> {
+    compile_many_with_options(sources, output, &CompileOptions::default())
+}
+
+/// Compiles several C subset source files to one executable using the given
+/// options. The source name of each file overrides
+/// [`CompileOptions::source_name`]; errors are reported as
+/// [`CompileError::Import`] naming the file.
+#[cfg(feature = "llvm")]
+pub fn compile_many_with_options(
+    sources: &[(&str, &str)],
+    output: &Path,
+    options: &CompileOptions,
+) -> Result<CompileArtifacts, CompileError> {
+    let mut timings = Timings::new();
+    let context = Context::create();
+    let mut programs = Vec::new();
+    let mut modules = Vec::new();
+    for &(name, source) in sources {
+        let options = CompileOptions {
+            source_name: Some(name.to_string()),
+            ..options.clone()
+        };
+        let in_file = |error| CompileError::Import {
+            file: name.to_string(),
+            error: Box::new(error),
+        };
+        let (program, module) =
+            generate_module(&context, source, &options, &mut timings).map_err(in_file)?;
+        programs.push(program);
+        modules.push(module);
     }
+    check_linkage(sources, &programs)?;
+    let ir = timings.time(Phase::Codegen, || codegen::link_modules(modules))?;
+    link_ir(
+        &ir.print_to_string().to_string(),
+        output,
+        options,
+        &mut timings,
+    )?;
+    Ok(artifacts(
+        vec![output.to_path_buf()],
+        &programs,
+        options,
+        timings,
+    ))
+}
 
-    // Code generation
-    let ir = codegen::generate_ir(&ast)?;
+/// Checks that the programs of files compiled together agree: a function is
+/// defined by one file only, and the `extern` declarations of the others
+/// match its definition. Errors name the file they are found in.
+#[cfg(feature = "llvm")]
+fn check_linkage(sources: &[(&str, &str)], programs: &[Program]) -> Result<(), CompileError> {
+    let in_file = |file: &str, error| CompileError::Import {
+        file: file.to_string(),
+        error: Box::new(CompileError::Semantic(vec![error], Vec::new())),
+    };
+    let mut definitions = HashMap::new();
+    for (&(file, _), program) in sources.iter().zip(programs) {
+        for function in &program.functions {
+            if definitions
+                .insert(function.name.as_str(), (file, function))
+                .is_some()
+            {
+                return Err(in_file(
+                    file,
+                    SemanticError::DuplicateFunction(function.name.clone()),
+                ));
+            }
+        }
+    }
+    for (&(file, _), program) in sources.iter().zip(programs) {
+        for ext in &program.extern_functions {
+            let Some((defined_in, function)) = definitions.get(ext.name.as_str()) else {
+                continue;
+            };
+            let param_types: Vec<_> = function.params.iter().map(|(ty, _)| *ty).collect();
+            if ext.return_ty != function.return_ty
+                || ext.param_types != param_types
+                || ext.is_variadic
+            {
+                return Err(in_file(
+                    file,
+                    SemanticError::ConflictingDefinition(ext.name.clone(), defined_in.to_string()),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
 
-    // Write IR to temporary file
-    // Use output path with .ll extension
-    let ir_file = output.with_extension("ll");
-    fs::write(&ir_file, &ir)?;
+/// Describes the files a compilation wrote, with the warnings about the
+/// programs compiled.
+#[cfg(feature = "llvm")]
+fn artifacts(
+    outputs: Vec<PathBuf>,
+    programs: &[Program],
+    options: &CompileOptions,
+    timings: Timings,
+) -> CompileArtifacts {
+    CompileArtifacts {
+        outputs,
+        target: codegen::host_triple(),
+        opt_level: options.opt_level,
+        timings,
+        warnings: programs.iter().flat_map(analysis::lint).collect(),
+    }
+}
+
+/// Writes IR next to the output and builds the executable from it.
+#[cfg(feature = "llvm")]
+fn link_ir(
+    ir: &str,
+    output: &Path,
+    options: &CompileOptions,
+    timings: &mut Timings,
+) -> Result<(), CompileError> {
+    // Write IR to a temporary file next to the output. The extension is
+    // appended rather than replaced, so that compiling `prog` and `prog.out`
+    // concurrently does not share one IR file
+    let mut ir_file = output.as_os_str().to_owned();
+    ir_file.push(".ll");
+    let ir_file = PathBuf::from(ir_file);
+    fs::write(&ir_file, ir)?;
 
     // Compile IR to executable using clang
-    let status = Command::new("clang")
-        .args([
-            ir_file.to_str().unwrap(),
-            "-o",
-            output.to_str().unwrap(),
-            "-lc",
-            "-Wno-override-module",
-        ])
-        .status()?;
-
-    if !status.success() {
-        return Err("Compilation failed".into());
-    }
+    timings.time(Phase::Link, || link(&ir_file, output, options))?;
 
     // Clean up IR file
     let _ = fs::remove_file(ir_file);
-
     Ok(())
 }
+
+/// Compiles a C subset source string to LLVM IR using the given options.
+#[cfg(feature = "llvm")]
+pub fn emit_ir_with_options(
+    source: &str,
+    options: &CompileOptions,
+) -> Result<String, CompileError> {
+    generate_ir(source, options, &mut Timings::new())
+}
+
+/// Counts the blocks and IR instructions of each function of a C subset
+/// source string after optimization, as [`size::function_sizes`] does.
+#[cfg(feature = "llvm")]
+pub fn function_sizes_with_options(
+    source: &str,
+    options: &CompileOptions,
+) -> Result<size::FunctionSizes, CompileError> {
+    let context = Context::create();
+    let (_, module) = generate_module(&context, source, options, &mut Timings::new())?;
+    codegen::optimize_module(&module, options)?;
+    Ok(size::function_sizes(&module))
+}
+
+/// Compiles a C subset source string to a static library at the specified
+/// output path, using the given options.
+///
+/// The functions are compiled to an object file, which is archived with
+/// [`CompileOptions::ar`]. A C header declaring them, generated by
+/// [`cheader::generate`], is written next to the library with a `.h`
+/// extension. With [`CompileOptions::lto`], the archive holds LLVM bitcode,
+/// which only a linker doing link-time optimization can use.
+///
+/// The artifacts list the library, then the header.
+#[cfg(feature = "llvm")]
+pub fn compile_staticlib_with_options(
+    source: &str,
+    output: &Path,
+    options: &CompileOptions,
+) -> Result<CompileArtifacts, CompileError> {
+    let mut timings = Timings::new();
+    let context = Context::create();
+    let (ast, module) = generate_module(&context, source, options, &mut timings)?;
+
+    let mut object = output.as_os_str().to_owned();
+    object.push(".o");
+    let object = PathBuf::from(object);
+    timings.time(Phase::Codegen, || {
+        codegen::optimize_module(&module, options)?;
+        if options.lto {
+            codegen::write_bitcode(&module, &object)
+        } else {
+            codegen::write_object(&module, &object, options.opt_level)
+        }
+    })?;
+    let result = timings.time(Phase::Link, || archive(&object, output, options));
+    let _ = fs::remove_file(object);
+    result?;
+
+    let header = output.with_extension("h");
+    let name = header
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    fs::write(
+        &header,
+        cheader::generate(&exported(&ast, options), &name, options.int_width),
+    )?;
+    Ok(artifacts(
+        vec![output.to_path_buf(), header],
+        &[ast],
+        options,
+        timings,
+    ))
+}
+
+/// Returns the program without the functions it does not export, for the C
+/// header that declares them.
+fn exported(program: &Program, options: &CompileOptions) -> Program {
+    Program {
+        functions: program
+            .functions
+            .iter()
+            .filter(|function| options.exports_function(&function.name))
+            .cloned()
+            .collect(),
+        ..program.clone()
+    }
+}
+
+/// Runs the front end and code generation, recording their timings.
+#[cfg(feature = "llvm")]
+fn generate_ir(
+    source: &str,
+    options: &CompileOptions,
+    timings: &mut Timings,
+) -> Result<String, CompileError> {
+    let context = Context::create();
+    let (_, module) = generate_module(&context, source, options, timings)?;
+    Ok(module.print_to_string().to_string())
+}
+
+/// Runs the front end and generates the module of a source string in
+/// `context`, recording their timings. Returns the checked program too.
+#[cfg(feature = "llvm")]
+fn generate_module<'ctx>(
+    context: &'ctx Context,
+    source: &str,
+    options: &CompileOptions,
+    timings: &mut Timings,
+) -> Result<(Program, Module<'ctx>), CompileError> {
+    let (ast, starts) = front_end(source, options, timings)?;
+    let ast = assertions::locate(ast, source, &starts, options.recorded_source_name());
+
+    // Code generation, reporting panics as internal compiler errors
+    let module = timings.time(Phase::Codegen, || {
+        ice::catch(&ast, || codegen::generate_module(context, &ast, options))
+    })??;
+    if options.embed_source {
+        codegen::embed(&module, &embed::Embedded::new(source, options));
+    }
+    Ok((ast, module))
+}
+
+/// Runs lexing, parsing, and semantic analysis, recording their timings.
+/// Returns the program and the byte offset at which each statement of the
+/// main file starts, as listed by [`coverage::statements`]. The statements of
+/// imported files come last, and have no offsets.
+fn front_end(
+    source: &str,
+    options: &CompileOptions,
+    timings: &mut Timings,
+) -> Result<(Program, Vec<usize>), CompileError> {
+    // Lexical analysis
+    let (tokens, spans): (Vec<_>, Vec<_>) = timings
+        .time(Phase::Lex, || lexer::lex_with_options(source, options))?
+        .into_iter()
+        .unzip();
+
+    // Parsing, including the files the program imports
+    let (ast, positions) = timings.time(Phase::Parse, || {
+        let (ast, positions) = parser::parse_with_statement_positions(&tokens, options)
+            .map_err(|e| parser::locate_error(e, source, &spans))?;
+        Ok::<_, CompileError>((imports::merge(ast, options)?, positions))
+    })?;
+
+    // Semantic analysis, locating errors by the first token of their
+    // statement
+    let errors = timings.time(Phase::Semantic, || semantic::analyze_located(&ast, options));
+    if !errors.is_empty() {
+        let located = statement_spans(&ast, positions.iter().map(|&i| spans[i]));
+        let (errors, spans) = errors
+            .into_iter()
+            .map(|(error, stmt)| (error, stmt.and_then(|s| located.get(&s).copied())))
+            .unzip();
+        return Err(CompileError::Semantic(errors, spans));
+    }
+    let starts = positions.iter().map(|&i| spans[i].start).collect();
+    Ok((ast, starts))
+}
+
+/// Maps each statement of the main file, keyed as by
+/// [`coverage::statements`], to the span of its first token, given in the
+/// same order. The initializer of a `for` loop is located at the loop.
+fn statement_spans(
+    program: &Program,
+    spans: impl Iterator<Item = Span>,
+) -> HashMap<(usize, StmtId), Span> {
+    let mut located = HashMap::new();
+    for ((function, stmt), span) in coverage::statements(program).into_iter().zip(spans) {
+        if let Stmt::For {
+            init: Some(init), ..
+        } = &program.functions[function].arena[stmt]
+        {
+            located.insert((function, *init), span);
+        }
+        located.insert((function, stmt), span);
+    }
+    located
+}
+
+/// Invokes the archiver to turn an object file into a static library,
+/// replacing any existing library.
+#[cfg(feature = "llvm")]
+fn archive(object: &Path, output: &Path, options: &CompileOptions) -> Result<(), LinkError> {
+    let ar = options.ar();
+    let _ = fs::remove_file(output);
+    let result = Command::new(ar)
+        .arg("rcs")
+        .arg(output)
+        .arg(object)
+        // As for linking, keep archivers that record timestamps reproducible
+        .envs(options.deterministic.then_some(("ZERO_AR_DATE", "1")))
+        .output();
+    match result {
+        Ok(out) if out.status.success() => Ok(()),
+        Ok(out) => {
+            let mut diagnostics = String::from_utf8_lossy(&out.stderr).into_owned();
+            diagnostics.push_str(&String::from_utf8_lossy(&out.stdout));
+            Err(LinkError::Failed(
+                ar.to_string(),
+                out.status.code(),
+                diagnostics,
+            ))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err(LinkError::ArchiverNotFound(ar.to_string()))
+        }
+        Err(e) => Err(LinkError::Failed(ar.to_string(), None, e.to_string())),
+    }
+}
+
+/// Flags that link a hardened executable: position-independent, so that it
+/// is loaded at a random address, with its relocations made read-only
+/// before it runs. Apple's linker does both by default.
+#[cfg(feature = "llvm")]
+const HARDENING_LINK_FLAGS: &[&str] = if cfg!(target_vendor = "apple") {
+    &[]
+} else {
+    &["-pie", "-Wl,-z,relro", "-Wl,-z,now"]
+};
+
+/// Flags that link a stripped executable: without a symbol table, and with
+/// the sections that nothing refers to removed.
+#[cfg(feature = "llvm")]
+const STRIP_LINK_FLAGS: &[&str] = if cfg!(target_vendor = "apple") {
+    &["-Wl,-S,-x", "-Wl,-dead_strip"]
+} else {
+    &[
+        "-s",
+        "-ffunction-sections",
+        "-fdata-sections",
+        "-Wl,--gc-sections",
+    ]
+};
+
+/// Invokes the C compiler to turn an IR file into an executable, capturing
+/// its diagnostics on failure.
+#[cfg(feature = "llvm")]
+fn link(ir_file: &Path, output: &Path, options: &CompileOptions) -> Result<(), LinkError> {
+    let cc = options.cc();
+    let result = Command::new(cc)
+        .arg(ir_file)
+        .arg("-o")
+        .arg(output)
+        .args(["-lc", "-Wno-override-module"])
+        .args((options.opt_level > 0).then(|| format!("-O{}", options.opt_level)))
+        .args(options.lto.then_some("-flto"))
+        .args(match options.hardening {
+            true => HARDENING_LINK_FLAGS,
+            false => &[],
+        })
+        .args(match options.strip {
+            true => STRIP_LINK_FLAGS,
+            false => &[],
+        })
+        .args(options.unroll_loops.map(|unroll| match unroll {
+            true => "-funroll-loops",
+            false => "-fno-unroll-loops",
+        }))
+        .args(match options.vectorize {
+            Some(true) => &["-fvectorize", "-fslp-vectorize"][..],
+            Some(false) => &["-fno-vectorize", "-fno-slp-vectorize"][..],
+            None => &[],
+        })
+        .args(
+            options
+                .profile_generate
+                .as_ref()
+                .map(|dir| format!("-fprofile-generate={}", dir)),
+        )
+        .args(
+            options
+                .profile_use
+                .as_ref()
+                .map(|profile| format!("-fprofile-use={}", profile)),
+        )
+        .args(options.link_libs.iter().map(|lib| format!("-l{}", lib)))
+        .args(
+            options
+                .sanitizers
+                .iter()
+                .map(|sanitizer| format!("-fsanitize={}", sanitizer)),
+        )
+        // Keep linkers that record timestamps (e.g. ld64) reproducible
+        .envs(options.deterministic.then_some(("ZERO_AR_DATE", "1")))
+        .output();
+
+    match result {
+        Ok(out) if out.status.success() => Ok(()),
+        Ok(out) => {
+            let mut diagnostics = String::from_utf8_lossy(&out.stderr).into_owned();
+            diagnostics.push_str(&String::from_utf8_lossy(&out.stdout));
+            Err(LinkError::Failed(
+                cc.to_string(),
+                out.status.code(),
+                diagnostics,
+            ))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err(LinkError::CompilerNotFound(cc.to_string()))
+        }
+        Err(e) => Err(LinkError::Failed(cc.to_string(), None, e.to_string())),
+    }
+}
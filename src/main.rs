@@ -14,15 +14,78 @@
 //!
 //! - Compile C subset source files to native executables via LLVM
 //! - Optional output file specification
+//! - Configurable `int` width (`--int-width 32|64`)
+//! - Nesting limit for untrusted input (`--max-depth N`)
+//! - Per-phase timing report (`--timings`)
+//! - Machine-readable diagnostics (`--message-format=json`)
+//! - Colored diagnostics with terminal detection (`--color=auto|always|never`)
+//! - Optimization levels and extra link libraries (`-O2 -l m`)
+//! - Recompiling on every change to the source (`virtuc watch input.c --run`)
+//! - Reproducible builds that do not depend on paths (`--deterministic`)
+//! - Aborting runaway recursion with a message (`--checked-recursion`)
+//! - Building with AddressSanitizer or UBSan (`--sanitize=address`)
+//! - Statement coverage (`--coverage`, then `virtuc cov report input.c`)
+//! - Syntax highlighting for terminals and HTML (`virtuc highlight input.c`)
+//! - Call graphs with recursion reports, in Graphviz format with
+//!   `virtuc callgraph input.c --dot`
+//! - Warnings about likely mistakes, such as recursion without a base case
+//!   or unused functions, which `--strip-unused` leaves out of the build
+//! - Building a project from a `virtuc.toml` manifest (`virtuc build`)
+//! - Shell completion scripts (`virtuc completions bash|zsh|fish`)
+//! - Distinct exit codes for program errors, environment failures, and
+//!   internal compiler errors (see `virtuc::error::exit_code`)
+//! - Internal compiler error reports with the offending function's AST and
+//!   an optional minimized reproducer (`--ice-reproducer repro.c`)
+//! - Checking and emitting IR without linking (`virtuc check`, `virtuc emit`),
+//!   reading from stdin when the input is `-`
+//! - Checking the build environment, with suggested fixes (`virtuc doctor`)
+//! - Embedding the source and build options in executables, and reading
+//!   them back (`--embed-source`, `virtuc inspect`)
+//! - Static libraries with a C header for use from C
+//!   (`virtuc emit --emit=staticlib`)
+//! - Profile-guided optimization (`--profile-generate`, then
+//!   `virtuc merge-profdata` and `--profile-use`)
+//! - Size reports of executables and of each function's IR, and stripped
+//!   executables (`--size-report`, `--strip`)
 
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use std::backtrace::{Backtrace, BacktraceStatus};
 use std::fs;
-use std::path::Path;
+use std::io::{self, Read, Write};
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
 
-use virtuc::compile;
+use virtuc::analysis::{self, Warning};
+use virtuc::coverage;
+use virtuc::diagnostics::{self, ColorChoice, Diagnostic, MessageFormat};
+use virtuc::doctor;
+use virtuc::embed;
+use virtuc::error::{CompileError, GradeSpecError, InternalError, ManifestError, exit_code};
+use virtuc::grade::{self, GradeSpec};
+use virtuc::highlight;
+use virtuc::ice::BUG_REPORT_URL;
+use virtuc::imports;
+use virtuc::manifest::{MANIFEST_NAME, Manifest};
+use virtuc::optimize;
+use virtuc::printer::print_program;
+use virtuc::profile;
+use virtuc::run::{self, Capture, RunConfig, RunResult};
+use virtuc::serve;
+use virtuc::size;
+use virtuc::{
+    CompileOptions, IntWidth, Sanitizer, check_with_options, compile_many_with_options,
+    compile_staticlib_with_options, compile_with_options, emit_header_with_options,
+    emit_ir_with_options, emit_mir_with_options, function_sizes_with_options,
+    opt_view_with_options,
+};
 
 #[derive(Parser)]
-#[command(name = "virtuc")]
+#[command(name = "virtuc", version)]
 #[command(about = "A Rust-based subset C compiler")]
 struct Args {
     #[command(subcommand)]
@@ -32,42 +95,1193 @@ struct Args {
 #[derive(Subcommand)]
 enum Commands {
     /// Compile C source to executable
-    Compile {
-        /// Input C source file
+    #[command(after_help = "\
+Examples:
+  virtuc compile hello.c                 Compile to hello.out
+  virtuc compile hello.c -o hello -O2    Optimize and name the executable
+  virtuc compile math.c -l m             Link against libm
+  virtuc compile hello.c --message-format=json")]
+    Compile(CompileArgs),
+
+    /// Check C source for errors without generating code
+    #[command(after_help = "\
+Examples:
+  virtuc check hello.c
+  cat hello.c | virtuc check -")]
+    Check(CompileArgs),
+
+    /// Emit an intermediate form of C source (to stdout unless -o is given),
+    /// or a static library
+    #[command(after_help = "\
+Examples:
+  virtuc emit hello.c                    Print LLVM IR
+  virtuc emit --emit=ir - < hello.c > hello.ll
+  virtuc emit --emit=source hello.c      Print the formatted program
+  virtuc emit --emit=mir hello.c         Print the mid-level IR
+  virtuc emit --header math.c -o math.h  Declare the functions for C
+  virtuc emit --emit=staticlib math.c    Build math.a and math.h
+  virtuc emit --size-report hello.c      Count the IR instructions of each function")]
+    Emit {
+        #[command(flatten)]
+        compile: CompileArgs,
+
+        /// What to emit (ir, mir, ast, source, header, staticlib or size-report)
+        #[arg(long, value_enum, default_value = "ir", value_name = "KIND")]
+        emit: Emit,
+
+        /// Emit a C header; short for --emit=header
+        #[arg(long, conflicts_with = "emit")]
+        header: bool,
+    },
+
+    /// Print the program after each optimization pass
+    #[command(after_help = "\
+Examples:
+  virtuc opt-view -O1 hello.c            Print the source after each pass
+  virtuc opt-view -O1 --mir hello.c      Print the mid-level IR after each pass
+  virtuc opt-view --strip-unused hello.c Show which functions are stripped")]
+    OptView {
+        #[command(flatten)]
+        compile: CompileArgs,
+
+        /// Print the mid-level IR instead of the source
+        #[arg(long)]
+        mir: bool,
+    },
+
+    /// Recompile whenever the source file changes
+    #[command(after_help = "\
+Examples:
+  virtuc watch hello.c          Recompile on every save
+  virtuc watch hello.c --run    Recompile and rerun on every save")]
+    Watch {
+        #[command(flatten)]
+        compile: CompileArgs,
+
+        /// Run the executable after each successful compile
+        #[arg(long)]
+        run: bool,
+    },
+
+    /// Build the project described by a virtuc.toml manifest
+    #[command(after_help = "\
+Examples:
+  virtuc build                                 Build ./virtuc.toml
+  virtuc build --manifest-path demo/virtuc.toml")]
+    Build {
+        /// Path to the manifest
+        #[arg(long, value_name = "PATH", default_value = MANIFEST_NAME)]
+        manifest_path: PathBuf,
+
+        #[command(flatten)]
+        report: ReportArgs,
+    },
+
+    /// Print C source with syntax highlighting
+    #[command(after_help = "\
+Examples:
+  virtuc highlight hello.c                     Color the source for a terminal
+  virtuc highlight hello.c --format=html -o hello.html")]
+    Highlight {
+        /// C source file, or `-` for stdin
+        input: String,
+
+        /// Output format (ansi or html)
+        #[arg(long, value_enum, default_value = "ansi", value_name = "FORMAT")]
+        format: HighlightFormat,
+
+        /// Output file (defaults to stdout)
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    /// Print which functions call which, and the recursive ones
+    #[command(after_help = "\
+Examples:
+  virtuc callgraph hello.c                     List the calls of each function
+  virtuc callgraph hello.c --dot | dot -Tsvg > calls.svg")]
+    Callgraph {
+        /// C source file, or `-` for stdin
         input: String,
 
-        /// Output executable file
+        /// Print the graph in Graphviz dot format
+        #[arg(long)]
+        dot: bool,
+
+        /// Output file (defaults to stdout)
+        #[arg(short, long)]
+        output: Option<String>,
+
+        #[command(flatten)]
+        report: ReportArgs,
+    },
+
+    /// Check that the environment can compile programs
+    #[command(after_help = "\
+Examples:
+  virtuc doctor
+  virtuc doctor --cc gcc                       Check another C compiler")]
+    Doctor {
+        /// C compiler to check
+        #[arg(long, value_name = "PATH")]
+        cc: Option<String>,
+
+        /// Archiver to check
+        #[arg(long, value_name = "PATH")]
+        ar: Option<String>,
+    },
+
+    /// Show how an executable built with --embed-source was compiled
+    #[command(after_help = "\
+Examples:
+  virtuc compile --embed-source hello.c
+  virtuc inspect hello.out                     Show the version, options, and source
+  virtuc inspect hello.out --source > copy.c   Recover the source")]
+    Inspect {
+        /// Executable or object file
+        binary: PathBuf,
+
+        /// Print only the source
+        #[arg(long)]
+        source: bool,
+    },
+
+    /// Merge the raw profiles of a --profile-generate build for --profile-use
+    #[command(after_help = "\
+Examples:
+  virtuc compile -O2 --profile-generate=prof hello.c && ./hello.out
+  virtuc merge-profdata prof/*.profraw -o hello.profdata
+  virtuc compile -O2 --profile-use hello.profdata hello.c")]
+    MergeProfdata {
+        /// Raw profiles written by the instrumented program
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Merged profile to write
+        #[arg(short, long, default_value = "default.profdata")]
+        output: PathBuf,
+
+        /// The llvm-profdata tool, from the LLVM release of the C compiler
+        #[arg(long, value_name = "PATH", default_value = profile::DEFAULT_PROFDATA)]
+        llvm_profdata: String,
+    },
+
+    /// Compile and run every submission in a directory against a spec of
+    /// test cases, writing a JSON report
+    #[command(after_help = "\
+Examples:
+  virtuc grade --spec spec.toml submissions/
+  virtuc grade --spec spec.toml submissions/ -o report.json")]
+    Grade {
+        /// Directory of C submissions, one program per file
+        dir: PathBuf,
+
+        /// Test cases and limits (see the grade module documentation)
+        #[arg(long, value_name = "PATH")]
+        spec: PathBuf,
+
+        /// File to write the JSON report to (defaults to stdout)
         #[arg(short, long)]
         output: Option<String>,
     },
+
+    /// Check, compile, and run programs sent as JSON-RPC requests on stdin
+    #[command(after_help = "\
+Examples:
+  virtuc serve                                 Answer requests until stdin closes
+  virtuc serve --timeout 2 --max-output 65536  Tighten the limits on programs
+
+Each line of stdin is a JSON-RPC 2.0 request, such as:
+  {\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"run\",\"params\":{\"source\":\"int main() { return 3; }\"}}
+Methods: check, emit, run, shutdown.")]
+    Serve {
+        /// Kill programs that run longer than SECONDS
+        #[arg(long, value_name = "SECONDS", default_value = "10")]
+        timeout: u64,
+
+        /// Kill programs that write more than BYTES to stdout or stderr
+        #[arg(long, value_name = "BYTES", default_value = "1048576")]
+        max_output: usize,
+    },
+
+    /// Work with statement coverage of --coverage builds
+    Cov {
+        #[command(subcommand)]
+        command: CovCommand,
+    },
+
+    /// Print a shell completion script
+    #[command(after_help = "\
+Examples:
+  virtuc completions bash > /etc/bash_completion.d/virtuc
+  virtuc completions zsh > ~/.zfunc/_virtuc
+  virtuc completions fish > ~/.config/fish/completions/virtuc.fish")]
+    Completions {
+        /// Shell to generate the script for
+        shell: Shell,
+    },
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+#[derive(Subcommand)]
+enum CovCommand {
+    /// Show the source annotated with how often each line ran
+    #[command(after_help = "\
+Examples:
+  virtuc compile --coverage hello.c && ./hello.out
+  virtuc cov report hello.c              Read the counts from hello.out.cov")]
+    Report {
+        /// C source file the program was built from
+        input: String,
+
+        /// Counts file written by the program (defaults to the default
+        /// output path of the input with a `.cov` extension)
+        #[arg(long, value_name = "PATH")]
+        counts: Option<String>,
+
+        #[command(flatten)]
+        report: ReportArgs,
+    },
+}
+
+/// Intermediate forms that `virtuc emit` can print.
+#[derive(Clone, Copy, ValueEnum)]
+enum Emit {
+    /// LLVM IR
+    Ir,
+    /// Mid-level IR: three-address code in basic blocks
+    Mir,
+    /// The abstract syntax tree
+    Ast,
+    /// The program, formatted by the pretty printer
+    Source,
+    /// A C header declaring the functions
+    Header,
+    /// A static library of the functions, with a C header declaring them
+    Staticlib,
+    /// The blocks and IR instructions of each function after optimization
+    SizeReport,
+}
+
+/// Output formats of `virtuc highlight`.
+#[derive(Clone, Copy, ValueEnum)]
+enum HighlightFormat {
+    /// ANSI escape sequences for terminals
+    Ansi,
+    /// An HTML `<pre>` element, styled through CSS classes
+    Html,
+}
+
+/// Arguments shared by the commands that compile a source file.
+#[derive(clap::Args)]
+struct CompileArgs {
+    /// Input C source file, or `-` for stdin
+    input: String,
+
+    /// Output executable file
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// Width of the int type in bits (32 or 64)
+    #[arg(long, default_value = "64")]
+    int_width: IntWidth,
+
+    /// C compiler used to link the executable
+    #[arg(long, value_name = "PATH")]
+    cc: Option<String>,
+
+    /// Archiver used to build static libraries
+    #[arg(long, value_name = "PATH")]
+    ar: Option<String>,
+
+    /// Maximum nesting depth of expressions and statements
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Maximum number of chained operators in an expression
+    #[arg(long, value_name = "N")]
+    max_chain: Option<usize>,
+
+    /// Optimization level (0 to 3)
+    #[arg(short = 'O', long, default_value = "0", value_name = "LEVEL",
+          value_parser = clap::value_parser!(u8).range(0..=3))]
+    opt_level: u8,
+
+    /// Define a macro; only NDEBUG, which compiles out assertions, is
+    /// supported
+    #[arg(short = 'D', value_name = "NAME", value_parser = ["NDEBUG"])]
+    defines: Vec<String>,
+
+    /// Library to link against (may be repeated)
+    #[arg(short = 'l', long = "link-lib", value_name = "LIB")]
+    link_libs: Vec<String>,
+
+    /// Produce byte-identical output for identical input
+    #[arg(long)]
+    deterministic: bool,
+
+    /// Let float operations be optimized as if they were exact, which is
+    /// faster but changes results
+    #[arg(long = "ffast-math", conflicts_with = "deterministic")]
+    fast_math: bool,
+
+    /// Unroll loops (the default at -O2 and above)
+    #[arg(long = "funroll-loops", overrides_with = "no_unroll_loops")]
+    unroll_loops: bool,
+
+    /// Do not unroll loops
+    #[arg(long = "fno-unroll-loops", overrides_with = "unroll_loops")]
+    no_unroll_loops: bool,
+
+    /// Vectorize loops and straight-line code (the default at -O2 and
+    /// above)
+    #[arg(long = "fvectorize", overrides_with = "no_vectorize")]
+    vectorize: bool,
+
+    /// Do not vectorize code
+    #[arg(long = "fno-vectorize", overrides_with = "vectorize")]
+    no_vectorize: bool,
+
+    /// Instrument the program to write a raw profile into DIR (the current
+    /// directory if not given) when it exits
+    #[arg(long, value_name = "DIR", num_args = 0..=1, require_equals = true,
+          default_missing_value = ".", conflicts_with = "profile_use")]
+    profile_generate: Option<String>,
+
+    /// Optimize with a profile merged by `virtuc merge-profdata`
+    #[arg(long, value_name = "PATH")]
+    profile_use: Option<String>,
+
+    /// Optimize across modules at link time; static libraries then hold
+    /// LLVM bitcode for C programs linked with -flto
+    #[arg(long)]
+    lto: bool,
+
+    /// Harden the program with stack protectors and stack probes, linking a
+    /// position-independent executable with read-only relocations
+    #[arg(long)]
+    hardening: bool,
+
+    /// Strip the symbol table and let the linker remove unused sections
+    #[arg(long)]
+    strip: bool,
+
+    /// Export only these functions (and main); the others become internal
+    /// and are left out of generated headers
+    #[arg(long = "export", value_name = "NAME", value_delimiter = ',')]
+    exports: Vec<String>,
+
+    /// Start the program at NAME, an int function without parameters,
+    /// through a generated main; the program must not define main
+    #[arg(long, value_name = "NAME")]
+    entry: Option<String>,
+
+    /// Abort the compiled program with a message when its call depth
+    /// exceeds DEPTH (10000 if not given), instead of crashing
+    #[arg(long, value_name = "DEPTH", num_args = 0..=1, require_equals = true,
+          default_missing_value = "10000")]
+    checked_recursion: Option<u32>,
+
+    /// Count how often each statement runs, writing the counts to the
+    /// output path with a `.cov` extension (see `virtuc cov report`)
+    #[arg(long)]
+    coverage: bool,
+
+    /// Remove functions not reachable from main before generating code
+    #[arg(long)]
+    strip_unused: bool,
+
+    /// Accept identifiers with non-ASCII letters and digits
+    #[arg(long)]
+    allow_unicode_ids: bool,
+
+    /// Embed the source, compiler version, and options in the output (see
+    /// `virtuc inspect`)
+    #[arg(long)]
+    embed_source: bool,
+
+    /// Build with a sanitizer (address or undefined; may be repeated)
+    #[arg(long = "sanitize", value_name = "KIND", value_delimiter = ',')]
+    sanitizers: Vec<Sanitizer>,
+
+    #[command(flatten, next_help_heading = "Diagnostics")]
+    report: ReportArgs,
+}
+
+/// Arguments controlling how compilation results are reported.
+#[derive(clap::Args)]
+struct ReportArgs {
+    /// Print the time spent in each compilation phase
+    #[arg(long)]
+    timings: bool,
+
+    /// Format of diagnostics (human or json)
+    #[arg(long, default_value = "human", value_name = "FORMAT")]
+    message_format: MessageFormat,
+
+    /// When to color diagnostics (auto, always or never)
+    #[arg(long, default_value = "auto", value_name = "WHEN")]
+    color: ColorChoice,
+
+    /// On an internal compiler error, write a minimized program that
+    /// reproduces it to PATH
+    #[arg(long, value_name = "PATH")]
+    ice_reproducer: Option<PathBuf>,
+
+    /// When code generation fails, show the IR of the whole module generated
+    /// so far
+    #[arg(long)]
+    dump_ir_on_error: bool,
+
+    /// Print the size of each section of the executable after linking; with
+    /// emit, the blocks and IR instructions of each function instead
+    #[arg(long)]
+    size_report: bool,
+}
+
+impl CompileArgs {
+    /// The output path, defaulting to the input with a `.out` extension.
+    fn output(&self) -> String {
+        self.output
+            .clone()
+            .unwrap_or_else(|| default_output(&self.input))
+    }
+
+    fn options(&self) -> CompileOptions {
+        CompileOptions {
+            int_width: self.int_width,
+            source_name: Some(source_name(&self.input).to_string()),
+            cc: self.cc.clone(),
+            ar: self.ar.clone(),
+            max_depth: self.max_depth,
+            max_chain: self.max_chain,
+            opt_level: self.opt_level,
+            link_libs: self.link_libs.clone(),
+            deterministic: self.deterministic,
+            fast_math: self.fast_math,
+            unroll_loops: switch(self.unroll_loops, self.no_unroll_loops),
+            vectorize: switch(self.vectorize, self.no_vectorize),
+            // The program may run from any directory, so record an absolute path
+            profile_generate: self.profile_generate.as_ref().map(|dir| {
+                std::path::absolute(dir)
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_else(|_| dir.clone())
+            }),
+            profile_use: self.profile_use.clone(),
+            lto: self.lto,
+            hardening: self.hardening,
+            strip: self.strip,
+            exports: (!self.exports.is_empty()).then(|| self.exports.clone()),
+            entry: self.entry.clone(),
+            recursion_limit: self.checked_recursion,
+            sanitizers: self.sanitizers.clone(),
+            ndebug: self.defines.iter().any(|name| name == "NDEBUG"),
+            strip_unused: self.strip_unused,
+            allow_unicode_ids: self.allow_unicode_ids,
+            embed_source: self.embed_source,
+            dump_ir_on_error: self.report.dump_ir_on_error,
+            // The program may run from any directory, so record an absolute path
+            coverage_file: self.coverage.then(|| {
+                let counts = format!("{}.cov", self.output());
+                std::path::absolute(&counts)
+                    .map(|path| path.display().to_string())
+                    .unwrap_or(counts)
+            }),
+            ..CompileOptions::default()
+        }
+    }
+
+    /// Compiles the input file, reporting the result. Returns the exit code.
+    fn compile(&self) -> Result<u8, Box<dyn std::error::Error>> {
+        compile_file(&self.input, &self.output(), &self.options(), &self.report)
+    }
+}
+
+/// Returns the choice made by a pair of `--fX`/`--fno-X` flags, of which
+/// clap keeps only the last.
+fn switch(on: bool, off: bool) -> Option<bool> {
+    match (on, off) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+/// Compiles `input` to `output`, reporting the result. Returns the exit code.
+fn compile_file(
+    input: &str,
+    output: &str,
+    options: &CompileOptions,
+    report: &ReportArgs,
+) -> Result<u8, Box<dyn std::error::Error>> {
+    let source = read_source(input)?;
+    match compile_with_options(&source, Path::new(output), options) {
+        Ok(artifacts) => {
+            report_warnings(&artifacts.warnings, input, &source, options, report);
+            status(
+                report,
+                format_args!("Compiled {} to {}", source_name(input), output),
+            );
+            if report.timings {
+                status(report, &artifacts.timings);
+            }
+            if report.size_report {
+                status(report, size::section_sizes(Path::new(output))?);
+            }
+            Ok(exit_code::SUCCESS)
+        }
+        Err(e) => Ok(report_error(&e, input, &source, report)),
+    }
+}
+
+/// Checks the input without generating code, reporting any errors. Returns
+/// the exit code.
+fn check_file(args: &CompileArgs) -> Result<u8, Box<dyn std::error::Error>> {
+    let source = read_source(&args.input)?;
+    match check_with_options(&source, &args.options()) {
+        Ok(program) => {
+            report_warnings(
+                &analysis::lint(&program),
+                &args.input,
+                &source,
+                &args.options(),
+                &args.report,
+            );
+            Ok(exit_code::SUCCESS)
+        }
+        Err(e) => Ok(report_error(&e, &args.input, &source, &args.report)),
+    }
+}
+
+/// Emits an intermediate form of the input to the output file, or stdout if
+/// there is none. Returns the exit code.
+fn emit_file(args: &CompileArgs, emit: Emit) -> Result<u8, Box<dyn std::error::Error>> {
+    let source = read_source(&args.input)?;
+    let options = args.options();
+    let result = match emit {
+        Emit::Staticlib => return staticlib_file(args, &source, &options),
+        Emit::Ir => emit_ir_with_options(&source, &options),
+        Emit::Mir => emit_mir_with_options(&source, &options),
+        Emit::Ast => {
+            check_with_options(&source, &options).map(|program| format!("{:#?}\n", program))
+        }
+        Emit::Source => {
+            check_with_options(&source, &options).map(|program| print_program(&program))
+        }
+        Emit::Header => {
+            // Name the header after the file it is written to
+            let name = match args.output.as_deref() {
+                None | Some("-") => default_header_name(&args.input),
+                Some(path) => Path::new(path)
+                    .file_name()
+                    .map_or_else(String::new, |name| name.to_string_lossy().into_owned()),
+            };
+            emit_header_with_options(&source, &name, &options)
+        }
+        Emit::SizeReport => {
+            function_sizes_with_options(&source, &options).map(|sizes| sizes.to_string())
+        }
+    };
+    match result {
+        Ok(text) => {
+            match args.output.as_deref() {
+                None | Some("-") => io::stdout().write_all(text.as_bytes())?,
+                Some(path) => fs::write(path, text)?,
+            }
+            Ok(exit_code::SUCCESS)
+        }
+        Err(e) => Ok(report_error(&e, &args.input, &source, &args.report)),
+    }
+}
+
+/// Prints the input after each pass of the optimization pipeline its
+/// options select. Returns the exit code.
+fn opt_view_file(args: &CompileArgs, mir: bool) -> Result<u8, Box<dyn std::error::Error>> {
+    let source = read_source(&args.input)?;
+    let options = args.options();
+    if optimize::pipeline(&options).is_empty() {
+        eprintln!("note: no passes run at -O0 without --strip-unused");
+    }
+    match opt_view_with_options(&source, &options, mir) {
+        Ok(text) => {
+            match args.output.as_deref() {
+                None | Some("-") => io::stdout().write_all(text.as_bytes())?,
+                Some(path) => fs::write(path, text)?,
+            }
+            Ok(exit_code::SUCCESS)
+        }
+        Err(e) => Ok(report_error(&e, &args.input, &source, &args.report)),
+    }
+}
+
+/// Builds the input as a static library and its header, next to the output
+/// path (by default, the input with a `.a` extension). Returns the exit code.
+fn staticlib_file(
+    args: &CompileArgs,
+    source: &str,
+    options: &CompileOptions,
+) -> Result<u8, Box<dyn std::error::Error>> {
+    let output = match args.output.as_deref() {
+        Some("-") => return Err("cannot write a static library to stdout; pass -o <path>".into()),
+        Some(output) => output.to_string(),
+        None => match args.input.as_str() {
+            "-" => "a.a".to_string(),
+            input => input.trim_end_matches(".c").to_string() + ".a",
+        },
+    };
+    match compile_staticlib_with_options(source, Path::new(&output), options) {
+        Ok(artifacts) => {
+            report_warnings(
+                &artifacts.warnings,
+                &args.input,
+                source,
+                options,
+                &args.report,
+            );
+            status(
+                &args.report,
+                format_args!(
+                    "Compiled {} to {}",
+                    source_name(&args.input),
+                    artifacts
+                        .outputs
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(" and ")
+                ),
+            );
+            if args.report.timings {
+                status(&args.report, &artifacts.timings);
+            }
+            Ok(exit_code::SUCCESS)
+        }
+        Err(e) => Ok(report_error(&e, &args.input, source, &args.report)),
+    }
+}
+
+/// Writes the input with syntax highlighting to the output file, or stdout if
+/// there is none.
+fn highlight_file(
+    input: &str,
+    format: HighlightFormat,
+    output: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let source = read_source(input)?;
+    let text = match format {
+        HighlightFormat::Ansi => highlight::render_ansi(&source),
+        HighlightFormat::Html => highlight::render_html(&source),
+    };
+    match output {
+        None | Some("-") => io::stdout().write_all(text.as_bytes())?,
+        Some(path) => fs::write(path, text)?,
+    }
+    Ok(())
+}
+
+/// Writes the call graph of the input to the output file, or stdout if there
+/// is none: as a list of calls followed by the recursive functions, or in
+/// Graphviz format. Returns the exit code.
+fn call_graph_file(
+    input: &str,
+    dot: bool,
+    output: Option<&str>,
+    report: &ReportArgs,
+) -> Result<u8, Box<dyn std::error::Error>> {
+    let source = read_source(input)?;
+    let program = match check_with_options(&source, &CompileOptions::default()) {
+        Ok(program) => program,
+        Err(e) => return Ok(report_error(&e, input, &source, report)),
+    };
+    let graph = analysis::call_graph(&program);
+    let text = if dot {
+        graph.to_dot()
+    } else {
+        let mut text = graph.to_string();
+        let cycles = graph.cycles();
+        if !cycles.is_empty() {
+            text.push_str("\nRecursive:\n");
+            for cycle in cycles {
+                text.push_str(&format!("  {}\n", cycle.join(", ")));
+            }
+        }
+        text
+    };
+    match output {
+        None | Some("-") => io::stdout().write_all(text.as_bytes())?,
+        Some(path) => fs::write(path, text)?,
+    }
+    Ok(exit_code::SUCCESS)
+}
+
+/// The name of the header of the input, e.g. `math.h` for `src/math.c`.
+fn default_header_name(input: &str) -> String {
+    match input {
+        "-" => "a.h".to_string(),
+        input => Path::new(input)
+            .with_extension("h")
+            .file_name()
+            .map_or_else(
+                || "a.h".to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            ),
+    }
+}
+
+/// The output path of an input compiled without `-o`.
+fn default_output(input: &str) -> String {
+    // Note: Defaulting to ".out" extension is tailored towards macOS and Linux systems.
+    // Windows users should explicitly specify an output file with ".exe" extension.
+    match input {
+        "-" => "a.out".to_string(),
+        input => input.trim_end_matches(".c").to_string() + ".out",
+    }
+}
+
+/// Prints the source annotated with the counts of a coverage run. Returns
+/// the exit code.
+fn coverage_report(
+    input: &str,
+    counts: Option<&str>,
+    report: &ReportArgs,
+) -> Result<u8, Box<dyn std::error::Error>> {
+    let source = read_source(input)?;
+    let counts_path =
+        counts.map_or_else(|| format!("{}.cov", default_output(input)), str::to_string);
+    let counts = fs::read_to_string(&counts_path)
+        .map_err(|e| format!("failed to read counts file '{}': {}", counts_path, e))?;
+    let counts = coverage::parse_counts(&counts).map_err(|e| format!("{}: {}", counts_path, e))?;
+    match coverage::report(&source, &counts, &CompileOptions::default()) {
+        Ok(annotated) => {
+            io::stdout().write_all(annotated.as_bytes())?;
+            Ok(exit_code::SUCCESS)
+        }
+        Err(e) => Ok(report_error(&e, input, &source, report)),
+    }
+}
+
+/// Reads a source file, or stdin if the path is `-`.
+fn read_source(input: &str) -> io::Result<String> {
+    let bytes = if input == "-" {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes)?;
+        bytes
+    } else {
+        fs::read(input)?
+    };
+    String::from_utf8(bytes).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} is not valid UTF-8: invalid byte at offset {}",
+                source_name(input),
+                e.utf8_error().valid_up_to()
+            ),
+        )
+    })
+}
+
+/// The name of a source file in messages.
+fn source_name(input: &str) -> &str {
+    if input == "-" { "<stdin>" } else { input }
+}
+
+/// Prints the diagnostics of a failed compilation, returning its exit code.
+fn report_error(error: &CompileError, input: &str, source: &str, report: &ReportArgs) -> u8 {
+    print_diagnostics(error.into(), input, source, report);
+    if let CompileError::Internal(ice) = error {
+        report_internal_error(ice, report);
+    }
+    error.exit_code()
+}
+
+/// Prints the warnings about a program that compiles.
+fn report_warnings(
+    warnings: &[Warning],
+    input: &str,
+    source: &str,
+    options: &CompileOptions,
+    report: &ReportArgs,
+) {
+    print_diagnostics(
+        diagnostics::locate_warnings(warnings, source, options),
+        input,
+        source,
+        report,
+    );
+}
+
+/// Prints a status message: to stdout, or to stderr with
+/// `--message-format=json`, whose stdout holds nothing but diagnostics.
+fn status(report: &ReportArgs, message: impl std::fmt::Display) {
+    match report.message_format {
+        MessageFormat::Human => println!("{}", message),
+        MessageFormat::Json => eprintln!("{}", message),
+    }
+}
 
+/// Prints diagnostics about `input`, or the files it imports, in the
+/// requested format.
+fn print_diagnostics(diagnostics: Vec<Diagnostic>, input: &str, source: &str, report: &ReportArgs) {
+    let color = report.color.should_color();
+    for diagnostic in diagnostics {
+        // Diagnostics that name a file are about an imported file
+        let imported = diagnostic
+            .file
+            .as_ref()
+            .map(|file| fs::read_to_string(file).ok());
+        let (diagnostic, source) = match &imported {
+            Some(imported) => (diagnostic, imported.as_deref()),
+            None => (diagnostic.with_file(Some(source_name(input))), Some(source)),
+        };
+        match report.message_format {
+            MessageFormat::Human => eprintln!("{}", diagnostic.render(source, color)),
+            MessageFormat::Json => println!("{}", diagnostic.to_json(source)),
+        }
+    }
+}
+
+/// Prints what is needed to report an internal compiler error: the AST of
+/// the offending function and, if requested, a minimized reproducer.
+fn report_internal_error(error: &InternalError, report: &ReportArgs) {
+    if let Some(function) = &error.function {
+        eprintln!(
+            "note: AST of function '{}':\n{:#?}",
+            function.name, function
+        );
+    }
+    if let (Some(path), Some(reproducer)) = (&report.ice_reproducer, &error.reproducer) {
+        match fs::write(path, print_program(reproducer)) {
+            Ok(()) => eprintln!("note: wrote a minimized reproducer to {}", path.display()),
+            Err(e) => eprintln!(
+                "note: failed to write reproducer to {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+    print_panic_details();
+}
+
+/// Builds the project described by a manifest.
+fn build(manifest_path: &Path, report: &ReportArgs) -> Result<u8, Box<dyn std::error::Error>> {
+    let manifest = match Manifest::load(manifest_path) {
+        Ok(manifest) => manifest,
+        Err(e @ ManifestError::Io(..)) => return Err(e.into()),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return Ok(exit_code::COMPILE_ERROR);
+        }
+    };
+    let options = CompileOptions {
+        dump_ir_on_error: report.dump_ir_on_error,
+        ..manifest.options
+    };
+    let output = manifest.output.display().to_string();
+    match manifest.sources.as_slice() {
+        [source] => compile_file(&source.display().to_string(), &output, &options, report),
+        sources => compile_files(sources, &output, &options, report),
+    }
+}
+
+/// Compiles several source files separately and links them into one
+/// executable. Returns the exit code.
+fn compile_files(
+    inputs: &[PathBuf],
+    output: &str,
+    options: &CompileOptions,
+    report: &ReportArgs,
+) -> Result<u8, Box<dyn std::error::Error>> {
+    let names: Vec<String> = inputs.iter().map(|p| p.display().to_string()).collect();
+    let sources = names
+        .iter()
+        .map(|name| read_source(name))
+        .collect::<Result<Vec<_>, _>>()?;
+    let files: Vec<(&str, &str)> = names
+        .iter()
+        .map(String::as_str)
+        .zip(sources.iter().map(String::as_str))
+        .collect();
+    // Errors name the file they are about, so they are reported against it
+    let (main, main_source) = files[0];
+    match compile_many_with_options(&files, Path::new(output), options) {
+        Ok(artifacts) => {
+            report_warnings(&artifacts.warnings, main, main_source, options, report);
+            status(
+                report,
+                format_args!("Compiled {} files to {}", files.len(), output),
+            );
+            if report.timings {
+                status(report, &artifacts.timings);
+            }
+            Ok(exit_code::SUCCESS)
+        }
+        Err(e) => Ok(report_error(&e, main, main_source, report)),
+    }
+}
+
+/// Prints the result of each environment check. Returns the exit code.
+fn doctor(options: CompileOptions) -> u8 {
+    let checks = doctor::run_checks(&options);
+    for check in &checks {
+        println!("{}", check);
+    }
+    if checks
+        .iter()
+        .any(|check| check.status == doctor::Status::Error)
+    {
+        println!("\nvirtuc cannot compile programs until the errors above are fixed.");
+        exit_code::ENVIRONMENT
+    } else {
+        println!("\nvirtuc is ready to compile programs.");
+        exit_code::SUCCESS
+    }
+}
+
+/// Prints the build record embedded in a binary. Returns the exit code.
+fn inspect(binary: &Path, source_only: bool) -> Result<u8, Box<dyn std::error::Error>> {
+    let Some(embedded) = embed::find(&fs::read(binary)?) else {
+        eprintln!(
+            "error: {} has no embedded source; compile it with --embed-source",
+            binary.display()
+        );
+        return Ok(exit_code::COMPILE_ERROR);
+    };
+    if !source_only {
+        println!("virtuc version: {}", embedded.version);
+        if let Some(name) = &embedded.source_name {
+            println!("source file: {}", name);
+        }
+        println!("options: {}", embedded.options);
+        println!();
+    }
+    print!("{}", embedded.source);
+    Ok(exit_code::SUCCESS)
+}
+
+/// How often the watched file is checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Compiles (and optionally runs) the input, then again on every change to
+/// it or to the files it imports, until interrupted.
+fn watch(args: &CompileArgs, run: bool) -> ! {
+    let options = args.options();
+    let modified = |files: &[PathBuf]| -> Vec<_> {
+        files
+            .iter()
+            .map(|file| fs::metadata(file).and_then(|m| m.modified()).ok())
+            .collect()
+    };
+    loop {
+        // Imports may change with every save, so they are looked up again
+        let mut files = vec![PathBuf::from(&args.input)];
+        if let Ok(source) = fs::read_to_string(&args.input) {
+            files.extend(imports::imported_files(&source, &options));
+        }
+        let last = modified(&files);
+        let compiled = match args.compile() {
+            Ok(code) => code == exit_code::SUCCESS,
+            // Editors that save by replacing the file leave it briefly
+            // missing, so the error is reported and watching continues
+            Err(e) => {
+                eprintln!("error: {}", e);
+                false
+            }
+        };
+        if compiled && run {
+            let output = args.output();
+            let config = RunConfig {
+                stdout: Capture::Inherit,
+                stderr: Capture::Inherit,
+                ..RunConfig::default()
+            };
+            match run::run_program(Path::new(&output), &config) {
+                Ok(RunResult {
+                    exit_code: Some(code),
+                    ..
+                }) => status(
+                    &args.report,
+                    format_args!("{} exited with code {}", output, code),
+                ),
+                Ok(_) => status(
+                    &args.report,
+                    format_args!("{} was killed by a signal", output),
+                ),
+                Err(e) => eprintln!("failed to run {}: {}", output, e),
+            }
+        }
+        status(
+            &args.report,
+            format_args!("Watching {} for changes...", args.input),
+        );
+        loop {
+            thread::sleep(WATCH_INTERVAL);
+            let current = modified(&files);
+            // The input is missing while an editor replaces it
+            if current[0].is_some() && current != last {
+                break;
+            }
+        }
+    }
+}
+
+/// Runs a command, returning its exit code. Errors are failures of the
+/// environment, such as unreadable input files.
+fn run(args: Args) -> Result<u8, Box<dyn std::error::Error>> {
     match args.command {
-        Commands::Compile { input, output } => {
-            // Read input file
-            let source = fs::read_to_string(&input)?;
-
-            // Determine output file
-            // Note: Defaulting to ".out" extension is tailored towards macOS and Linux systems.
-            // Windows users should explicitly specify an output file with ".exe" extension.
-            let output_str =
-                output.unwrap_or_else(|| input.trim_end_matches(".c").to_string() + ".out");
-            let output_path = Path::new(&output_str);
-
-            // Compile
-            match compile(&source, output_path) {
-                Ok(_) => {
-                    println!("Compiled {} to {}", input, output_str);
-                    Ok(())
-                }
+        Commands::Compile(compile) => compile.compile(),
+        Commands::Check(compile) => check_file(&compile),
+        Commands::Emit {
+            compile,
+            header: true,
+            ..
+        } => emit_file(&compile, Emit::Header),
+        Commands::Emit { compile, .. } if compile.report.size_report => {
+            emit_file(&compile, Emit::SizeReport)
+        }
+        Commands::Emit { compile, emit, .. } => emit_file(&compile, emit),
+        Commands::OptView { compile, mir } => opt_view_file(&compile, mir),
+        Commands::Watch { compile, .. } if compile.input == "-" => {
+            Err("cannot watch stdin; pass a file path".into())
+        }
+        Commands::Watch { compile, run } => watch(&compile, run),
+        Commands::Build {
+            manifest_path,
+            report,
+        } => build(&manifest_path, &report),
+        Commands::Highlight {
+            input,
+            format,
+            output,
+        } => highlight_file(&input, format, output.as_deref()).map(|()| exit_code::SUCCESS),
+        Commands::Callgraph {
+            input,
+            dot,
+            output,
+            report,
+        } => call_graph_file(&input, dot, output.as_deref(), &report),
+        Commands::Doctor { cc, ar } => Ok(doctor(CompileOptions {
+            cc,
+            ar,
+            ..CompileOptions::default()
+        })),
+        Commands::Inspect { binary, source } => inspect(&binary, source),
+        Commands::Grade { dir, spec, output } => {
+            let spec = match GradeSpec::load(&spec) {
+                Ok(spec) => spec,
+                Err(e @ GradeSpecError::Io(..)) => return Err(e.into()),
                 Err(e) => {
-                    eprintln!("{}", e);
-                    std::process::exit(1);
+                    eprintln!("error: {}", e);
+                    return Ok(exit_code::USAGE);
                 }
+            };
+            let report = grade::grade_dir(&spec, &dir)?;
+            eprint!("{}", report);
+            let json = report.to_json() + "\n";
+            match output.as_deref() {
+                None | Some("-") => io::stdout().write_all(json.as_bytes())?,
+                Some(path) => fs::write(path, json)?,
             }
+            Ok(exit_code::SUCCESS)
+        }
+        Commands::Serve {
+            timeout,
+            max_output,
+        } => {
+            let limits = serve::Limits {
+                timeout: Duration::from_secs(timeout),
+                max_output,
+            };
+            serve::Server::new(limits)?.serve(io::stdin().lock(), io::stdout().lock())?;
+            Ok(exit_code::SUCCESS)
+        }
+        Commands::MergeProfdata {
+            inputs,
+            output,
+            llvm_profdata,
+        } => {
+            let inputs: Vec<&Path> = inputs.iter().map(PathBuf::as_path).collect();
+            profile::merge(&inputs, &output, &llvm_profdata)?;
+            println!("Merged {} profiles into {}", inputs.len(), output.display());
+            Ok(exit_code::SUCCESS)
+        }
+        Commands::Cov {
+            command:
+                CovCommand::Report {
+                    input,
+                    counts,
+                    report,
+                },
+        } => coverage_report(&input, counts.as_deref(), &report),
+        Commands::Completions { shell } => {
+            clap_complete::generate(shell, &mut Args::command(), "virtuc", &mut io::stdout());
+            Ok(exit_code::SUCCESS)
         }
     }
 }
+
+/// Location and backtrace of the last panic, recorded by the panic hook
+/// and printed with the internal compiler error it causes.
+static PANIC_DETAILS: Mutex<Option<String>> = Mutex::new(None);
+
+/// Records the details of a panic instead of showing a raw Rust panic
+/// message; the panic is reported as an internal compiler error where it is
+/// caught.
+fn record_panic(info: &PanicHookInfo) {
+    let mut details = String::new();
+    if let Some(location) = info.location() {
+        details.push_str(&format!("note: panicked at {}\n", location));
+    }
+    let backtrace = Backtrace::capture();
+    if backtrace.status() == BacktraceStatus::Captured {
+        details.push_str(&format!("{}\n", backtrace));
+    } else {
+        details.push_str("note: run with RUST_BACKTRACE=1 to include a backtrace\n");
+    }
+    *PANIC_DETAILS.lock().unwrap_or_else(|e| e.into_inner()) = Some(details);
+}
+
+/// Prints the details of the last panic and how the compiler was invoked,
+/// to complete an internal compiler error report.
+fn print_panic_details() {
+    if let Some(details) = PANIC_DETAILS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+    {
+        eprint!("{}", details);
+    }
+    let args: Vec<String> = std::env::args().collect();
+    eprintln!(
+        "note: virtuc {} ran as: {}",
+        virtuc::VERSION,
+        args.join(" ")
+    );
+}
+
+fn main() -> ExitCode {
+    panic::set_hook(Box::new(record_panic));
+    let args = Args::parse();
+
+    let code = match panic::catch_unwind(AssertUnwindSafe(|| run(args))) {
+        Ok(Ok(code)) => code,
+        Ok(Err(e)) => {
+            eprintln!("error: {}", e);
+            exit_code::ENVIRONMENT
+        }
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            eprintln!("error: internal compiler error: {}", message);
+            eprintln!(
+                "note: this is a bug in virtuc; please file an issue at {}",
+                BUG_REPORT_URL
+            );
+            print_panic_details();
+            exit_code::INTERNAL_ERROR
+        }
+    };
+    ExitCode::from(code)
+}
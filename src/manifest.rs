@@ -0,0 +1,267 @@
+//! # Project Manifest
+//!
+//! This module reads `virtuc.toml`, a small project file that records how a
+//! program is built so that `virtuc build` can replace long command lines.
+//!
+//! ## Format
+//!
+//! ```toml
+//! [package]
+//! name = "hello"
+//!
+//! [build]
+//! sources = ["src/hello.c"]  # defaults to main.c
+//! output = "hello"        # defaults to the package name
+//! opt-level = 2           # 0 to 3, defaults to 0
+//! int-width = 32          # 32 or 64, defaults to 64
+//! cc = "clang"
+//! libs = ["m"]
+//! deterministic = true    # reproducible artifacts, defaults to false
+//! fast-math = false       # not with deterministic, defaults to false
+//! unroll-loops = true     # defaults to true from opt-level 2
+//! vectorize = true        # defaults to true from opt-level 2
+//! lto = true              # link-time optimization, defaults to false
+//! hardening = true        # stack protectors and RELRO, defaults to false
+//! strip = true            # strip symbols and unused sections, defaults to false
+//! exports = ["area"]      # functions to export, defaults to all
+//! entry = "solve"         # function to start at, defaults to main
+//! ```
+//!
+//! Paths are relative to the directory containing the manifest. Several
+//! sources are compiled separately and linked into one executable, as
+//! [`compile_many`](crate::compile_many) does.
+//!
+//! ## Limitations
+//!
+//! The language has no preprocessor. The `defines` and `include-paths` keys are accepted for
+//! forward compatibility but must be empty.
+
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::ManifestError;
+use crate::options::{CompileOptions, IntWidth};
+
+/// The conventional file name of a project manifest.
+pub const MANIFEST_NAME: &str = "virtuc.toml";
+
+/// A parsed and validated project manifest.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Manifest {
+    /// Name of the package
+    pub name: String,
+    /// The source files to compile, at least one
+    pub sources: Vec<PathBuf>,
+    /// Path of the executable to produce
+    pub output: PathBuf,
+    /// Options for compiling the source
+    pub options: CompileOptions,
+}
+
+/// The manifest as written, before validation.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawManifest {
+    package: RawPackage,
+    #[serde(default)]
+    build: RawBuild,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPackage {
+    name: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct RawBuild {
+    sources: Option<Vec<PathBuf>>,
+    output: Option<PathBuf>,
+    #[serde(default)]
+    opt_level: u8,
+    int_width: Option<u32>,
+    cc: Option<String>,
+    #[serde(default)]
+    libs: Vec<String>,
+    #[serde(default)]
+    defines: Vec<String>,
+    #[serde(default)]
+    include_paths: Vec<PathBuf>,
+    #[serde(default)]
+    deterministic: bool,
+    #[serde(default)]
+    fast_math: bool,
+    unroll_loops: Option<bool>,
+    vectorize: Option<bool>,
+    #[serde(default)]
+    lto: bool,
+    #[serde(default)]
+    hardening: bool,
+    #[serde(default)]
+    strip: bool,
+    exports: Option<Vec<String>>,
+    entry: Option<String>,
+}
+
+impl Manifest {
+    /// Reads and validates the manifest at `path`.
+    pub fn load(path: &Path) -> Result<Self, ManifestError> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| ManifestError::Io(path.display().to_string(), e.to_string()))?;
+        let root = path.parent().unwrap_or(Path::new(""));
+        Self::from_toml(&text, root)
+    }
+
+    /// Parses and validates manifest text, resolving paths against `root`.
+    pub fn from_toml(text: &str, root: &Path) -> Result<Self, ManifestError> {
+        let raw: RawManifest =
+            toml::from_str(text).map_err(|e| ManifestError::Parse(e.message().to_string()))?;
+        let build = raw.build;
+
+        let sources: Vec<PathBuf> = match build.sources {
+            None => vec![root.join("main.c")],
+            Some(sources) if sources.is_empty() => {
+                return Err(ManifestError::Invalid("no source files given".into()));
+            }
+            Some(sources) => sources.iter().map(|source| root.join(source)).collect(),
+        };
+        if !build.defines.is_empty() {
+            return Err(ManifestError::Invalid(
+                "defines are not supported: the language has no preprocessor".into(),
+            ));
+        }
+        if !build.include_paths.is_empty() {
+            return Err(ManifestError::Invalid(
+                "include paths are not supported: only standard headers can be included".into(),
+            ));
+        }
+        if build.opt_level > 3 {
+            return Err(ManifestError::Invalid(format!(
+                "invalid opt-level {}: expected 0 to 3",
+                build.opt_level
+            )));
+        }
+        if build.fast_math && build.deterministic {
+            return Err(ManifestError::Invalid(
+                "fast-math cannot be used with deterministic builds".into(),
+            ));
+        }
+        let int_width = match build.int_width {
+            Some(bits) => bits.to_string().parse().map_err(ManifestError::Invalid)?,
+            None => IntWidth::default(),
+        };
+
+        let output = root.join(
+            build
+                .output
+                .unwrap_or_else(|| PathBuf::from(&raw.package.name)),
+        );
+        Ok(Manifest {
+            options: CompileOptions {
+                int_width,
+                source_name: Some(sources[0].display().to_string()),
+                cc: build.cc,
+                opt_level: build.opt_level,
+                link_libs: build.libs,
+                deterministic: build.deterministic,
+                fast_math: build.fast_math,
+                unroll_loops: build.unroll_loops,
+                vectorize: build.vectorize,
+                lto: build.lto,
+                hardening: build.hardening,
+                strip: build.strip,
+                exports: build.exports,
+                entry: build.entry,
+                ..CompileOptions::default()
+            },
+            name: raw.package.name,
+            sources,
+            output,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = Manifest::from_toml(
+            r#"
+            [package]
+            name = "hello"
+
+            [build]
+            sources = ["src/hello.c"]
+            opt-level = 2
+            int-width = 32
+            libs = ["m"]
+            deterministic = true
+            "#,
+            Path::new("proj"),
+        )
+        .unwrap();
+        assert_eq!(manifest.name, "hello");
+        assert_eq!(manifest.sources, [Path::new("proj/src/hello.c")]);
+        assert_eq!(manifest.output, Path::new("proj/hello"));
+        assert_eq!(manifest.options.opt_level, 2);
+        assert_eq!(manifest.options.int_width, IntWidth::W32);
+        assert_eq!(manifest.options.link_libs, vec!["m".to_string()]);
+        assert!(manifest.options.deterministic);
+    }
+
+    #[test]
+    fn test_manifest_defaults() {
+        let manifest = Manifest::from_toml("[package]\nname = \"app\"", Path::new("")).unwrap();
+        assert_eq!(manifest.sources, [Path::new("main.c")]);
+        assert_eq!(manifest.output, Path::new("app"));
+        assert_eq!(manifest.options.opt_level, 0);
+        assert_eq!(manifest.options.int_width, IntWidth::W64);
+    }
+
+    #[test]
+    fn test_manifest_sources() {
+        let manifest = Manifest::from_toml(
+            "[package]\nname = \"app\"\n[build]\nsources = [\"main.c\", \"util.c\"]",
+            Path::new("proj"),
+        )
+        .unwrap();
+        assert_eq!(
+            manifest.sources,
+            [Path::new("proj/main.c"), Path::new("proj/util.c")]
+        );
+        assert_eq!(manifest.options.source_name.as_deref(), Some("proj/main.c"));
+    }
+
+    #[test]
+    fn test_invalid_manifests() {
+        let invalid = |build: &str| {
+            Manifest::from_toml(
+                &format!("[package]\nname = \"app\"\n[build]\n{}", build),
+                Path::new(""),
+            )
+            .unwrap_err()
+        };
+        assert!(matches!(invalid("unknown = 1"), ManifestError::Parse(_)));
+        assert!(matches!(invalid("sources = []"), ManifestError::Invalid(_)));
+        assert!(matches!(
+            invalid("opt-level = 4"),
+            ManifestError::Invalid(_)
+        ));
+        assert!(matches!(
+            invalid("int-width = 16"),
+            ManifestError::Invalid(_)
+        ));
+        assert!(matches!(
+            invalid("defines = [\"DEBUG\"]"),
+            ManifestError::Invalid(_)
+        ));
+        assert!(matches!(
+            invalid("fast-math = true\ndeterministic = true"),
+            ManifestError::Invalid(_)
+        ));
+    }
+}
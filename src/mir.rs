@@ -0,0 +1,763 @@
+//! # Mid-level IR
+//!
+//! This module lowers a checked program to MIR: three-address code in
+//! explicit basic blocks, one [`Body`] per function. Control flow that the
+//! AST expresses with nesting (`if`, `for`, early `return`) becomes jumps
+//! between blocks, and every intermediate value gets a local of its own,
+//! which is the form that dataflow analyses and backends other than LLVM
+//! want.
+//!
+//! ## Locals
+//!
+//! Every value lives in a [`Local`]: first the parameters, then the
+//! variables in the order they are declared, then the temporaries that hold
+//! the results of operations and calls. Variables are resolved with
+//! [`resolve`], so a variable that shadows another gets a local of its own.
+//!
+//! ## Printing
+//!
+//! [`Module`] implements `Display` (`virtuc emit --emit=mir`):
+//!
+//! ```text
+//! fn square(_0: int) -> int {
+//!     let _0: int; // x
+//!     let _1: int;
+//! bb0:
+//!     _1 = _0 * _0;
+//!     return _1;
+//! }
+//! ```
+//!
+//! ## Scope
+//!
+//! MIR is consumed by the dataflow lints in [`analysis`](crate::analysis)
+//! and printed by `--emit=mir` and `virtuc opt-view --mir`. The LLVM backend
+//! does not consume it: [`codegen`](crate::codegen) lowers `if`, `for`, and
+//! `return` to basic blocks from the AST on its own, so a change to how
+//! control flow is lowered has to be made in both places.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::{self, Arena, BinOp, Expr, ExprId, Literal, Stmt, StmtId, Type};
+use crate::interner::Symbol;
+use crate::printer::{print_binop, print_literal, print_type};
+use crate::resolve::{self, Resolution, SymbolId};
+
+/// Identifies a local of a [`Body`].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub struct LocalId(pub usize);
+
+impl fmt::Display for LocalId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "_{}", self.0)
+    }
+}
+
+/// Identifies a basic block of a [`Body`].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub struct BlockId(pub usize);
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+/// A storage slot: a parameter, a variable, or a temporary.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Local {
+    /// The variable's name; `None` for temporaries
+    pub name: Option<Symbol>,
+    /// Type of the values it holds
+    pub ty: Type,
+}
+
+/// A value used by an operation.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Operand {
+    /// The current value of a local
+    Local(LocalId),
+    /// A constant
+    Const(Literal),
+}
+
+impl Operand {
+    /// Returns the local the operand reads, if any.
+    pub fn local(&self) -> Option<LocalId> {
+        match self {
+            Operand::Local(local) => Some(*local),
+            Operand::Const(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Local(local) => write!(f, "{}", local),
+            Operand::Const(literal) => write!(f, "{}", print_literal(literal)),
+        }
+    }
+}
+
+/// An operation producing a value.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Rvalue {
+    /// The operand itself
+    Use(Operand),
+    /// A binary operation
+    Binary(BinOp, Operand, Operand),
+    /// A call of a function by name
+    Call { callee: Symbol, args: Vec<Operand> },
+}
+
+impl Rvalue {
+    /// Returns the operands the value is computed from.
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Rvalue::Use(operand) => vec![operand],
+            Rvalue::Binary(_, left, right) => vec![left, right],
+            Rvalue::Call { args, .. } => args.iter().collect(),
+        }
+    }
+}
+
+impl fmt::Display for Rvalue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rvalue::Use(operand) => write!(f, "{}", operand),
+            Rvalue::Binary(op, left, right) => {
+                write!(f, "{} {} {}", left, print_binop(*op), right)
+            }
+            Rvalue::Call { callee, args } => {
+                let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+                write!(f, "{}({})", callee, args.join(", "))
+            }
+        }
+    }
+}
+
+/// A statement of a basic block.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Statement {
+    /// Stores a value in a local
+    Assign(LocalId, Rvalue),
+    /// Computes a value for its side effects, discarding it
+    Eval(Rvalue),
+}
+
+impl Statement {
+    /// Returns the operands the statement reads.
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Statement::Assign(_, value) | Statement::Eval(value) => value.operands(),
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Statement::Assign(local, value) => write!(f, "{} = {};", local, value),
+            Statement::Eval(value) => write!(f, "{};", value),
+        }
+    }
+}
+
+/// How control leaves a basic block.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Terminator {
+    /// Continues at another block
+    Goto(BlockId),
+    /// Continues at `then` if the condition is nonzero, else at `else_`
+    Branch {
+        cond: Operand,
+        then: BlockId,
+        else_: BlockId,
+    },
+    /// Returns from the function. A missing value stands for the zero value
+    /// of the return type, which functions return when control reaches
+    /// their end
+    Return(Option<Operand>),
+}
+
+impl Terminator {
+    /// Returns the blocks control may continue at.
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Goto(target) => vec![*target],
+            Terminator::Branch { then, else_, .. } => vec![*then, *else_],
+            Terminator::Return(_) => Vec::new(),
+        }
+    }
+
+    /// Returns the operands the terminator reads.
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Terminator::Branch { cond, .. } => vec![cond],
+            Terminator::Return(Some(value)) => vec![value],
+            Terminator::Goto(_) | Terminator::Return(None) => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Terminator::Goto(target) => write!(f, "goto {};", target),
+            Terminator::Branch { cond, then, else_ } => {
+                write!(f, "if {} goto {} else {};", cond, then, else_)
+            }
+            Terminator::Return(Some(value)) => write!(f, "return {};", value),
+            Terminator::Return(None) => write!(f, "return;"),
+        }
+    }
+}
+
+/// A straight-line sequence of statements ending in a terminator.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BasicBlock {
+    /// Statements, in order
+    pub statements: Vec<Statement>,
+    /// Where control goes after the statements
+    pub terminator: Terminator,
+}
+
+/// The MIR of a function.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Body {
+    /// Function name
+    pub name: String,
+    /// Return type
+    pub return_ty: Type,
+    /// Number of parameters, which are the first locals
+    pub param_count: usize,
+    /// Locals: parameters, then variables, then temporaries
+    pub locals: Vec<Local>,
+    /// Basic blocks; the first is the entry, and every block is reachable
+    /// from it
+    pub blocks: Vec<BasicBlock>,
+}
+
+impl Body {
+    /// Returns the block with the given ID.
+    pub fn block(&self, id: BlockId) -> &BasicBlock {
+        &self.blocks[id.0]
+    }
+
+    /// Returns the IDs of the blocks, entry first.
+    pub fn block_ids(&self) -> impl Iterator<Item = BlockId> {
+        (0..self.blocks.len()).map(BlockId)
+    }
+
+    /// Returns the predecessors of each block, indexed by block.
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+        for (id, block) in self.blocks.iter().enumerate() {
+            for successor in block.terminator.successors() {
+                predecessors[successor.0].push(BlockId(id));
+            }
+        }
+        predecessors
+    }
+}
+
+impl fmt::Display for Body {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<String> = (0..self.param_count)
+            .map(|i| format!("{}: {}", LocalId(i), print_type(self.locals[i].ty)))
+            .collect();
+        writeln!(
+            f,
+            "fn {}({}) -> {} {{",
+            self.name,
+            params.join(", "),
+            print_type(self.return_ty)
+        )?;
+        for (i, local) in self.locals.iter().enumerate() {
+            write!(f, "    let {}: {};", LocalId(i), print_type(local.ty))?;
+            match &local.name {
+                Some(name) => writeln!(f, " // {}", name)?,
+                None => writeln!(f)?,
+            }
+        }
+        for (i, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "{}:", BlockId(i))?;
+            for statement in &block.statements {
+                writeln!(f, "    {}", statement)?;
+            }
+            writeln!(f, "    {}", block.terminator)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+/// The MIR of a program's functions.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Module {
+    /// A body for each function the program defines, in program order
+    pub bodies: Vec<Body>,
+}
+
+impl Module {
+    /// Returns the body of the function `name`.
+    pub fn body(&self, name: &str) -> Option<&Body> {
+        self.bodies.iter().find(|body| body.name == name)
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, body) in self.bodies.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", body)?;
+        }
+        Ok(())
+    }
+}
+
+/// Lowers a program that passed semantic analysis to MIR.
+pub fn lower(program: &ast::Program) -> Module {
+    let resolution = resolve::resolve(program);
+    let return_types: HashMap<&str, Type> = program
+        .extern_functions
+        .iter()
+        .map(|f| (f.name.as_str(), f.return_ty))
+        .chain(
+            program
+                .functions
+                .iter()
+                .map(|f| (f.name.as_str(), f.return_ty)),
+        )
+        .collect();
+    let bodies = program
+        .functions
+        .iter()
+        .enumerate()
+        .map(|(index, function)| {
+            Lowering::new(index, function, &resolution, &return_types).lower(function)
+        })
+        .collect();
+    Module { bodies }
+}
+
+/// A block under construction, whose terminator is not yet known.
+struct OpenBlock {
+    statements: Vec<Statement>,
+    terminator: Option<Terminator>,
+}
+
+/// Lowers one function.
+struct Lowering<'a> {
+    /// Index of the function in the program, for looking up its symbols
+    index: usize,
+    /// The expressions and statements of the function
+    arena: &'a Arena,
+    resolution: &'a Resolution,
+    return_types: &'a HashMap<&'a str, Type>,
+    locals: Vec<Local>,
+    /// Local of each parameter and variable
+    variables: HashMap<SymbolId, LocalId>,
+    blocks: Vec<OpenBlock>,
+    /// The block statements are added to
+    current: BlockId,
+}
+
+impl<'a> Lowering<'a> {
+    fn new(
+        index: usize,
+        function: &'a ast::Function,
+        resolution: &'a Resolution,
+        return_types: &'a HashMap<&'a str, Type>,
+    ) -> Self {
+        let mut lowering = Lowering {
+            index,
+            arena: &function.arena,
+            resolution,
+            return_types,
+            locals: Vec::new(),
+            variables: HashMap::new(),
+            blocks: Vec::new(),
+            current: BlockId(0),
+        };
+        for ((ty, name), &symbol) in function.params.iter().zip(resolution.params(index)) {
+            let local = lowering.new_local(Some(Symbol::intern(name)), *ty);
+            lowering.variables.insert(symbol, local);
+        }
+        lowering.current = lowering.new_block();
+        lowering
+    }
+
+    fn lower(mut self, function: &ast::Function) -> Body {
+        self.lower_stmt(function.body);
+        self.terminate(Terminator::Return(None));
+        let (locals, blocks) = (self.locals, self.blocks);
+        Body {
+            name: function.name.clone(),
+            return_ty: function.return_ty,
+            param_count: function.params.len(),
+            locals,
+            blocks: reachable(blocks),
+        }
+    }
+
+    fn new_local(&mut self, name: Option<Symbol>, ty: Type) -> LocalId {
+        self.locals.push(Local { name, ty });
+        LocalId(self.locals.len() - 1)
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(OpenBlock {
+            statements: Vec::new(),
+            terminator: None,
+        });
+        BlockId(self.blocks.len() - 1)
+    }
+
+    fn push(&mut self, statement: Statement) {
+        self.blocks[self.current.0].statements.push(statement);
+    }
+
+    /// Ends the current block. Code that follows goes to a new block, which
+    /// is unreachable unless a jump to it is added.
+    fn terminate(&mut self, terminator: Terminator) {
+        self.blocks[self.current.0].terminator = Some(terminator);
+        self.current = self.new_block();
+    }
+
+    /// Ends the current block with a jump to `target`, and continues there.
+    fn goto(&mut self, target: BlockId) {
+        self.blocks[self.current.0].terminator = Some(Terminator::Goto(target));
+        self.current = target;
+    }
+
+    fn variable(&self, symbol: Option<SymbolId>) -> LocalId {
+        symbol
+            .and_then(|symbol| self.variables.get(&symbol))
+            .copied()
+            .expect("variables of a checked program are resolved")
+    }
+
+    fn lower_stmt(&mut self, id: StmtId) {
+        let arena = self.arena;
+        let stmt = &arena[id];
+        match stmt {
+            Stmt::Declaration { ty, name, init } => {
+                // As in C, the variable is in scope in its own initializer
+                let local = self.new_local(Some(*name), *ty);
+                if let Some(symbol) = self.resolution.declaration(self.index, id) {
+                    self.variables.insert(symbol, local);
+                }
+                if let Some(init) = init {
+                    let value = self.lower_rvalue(*init);
+                    self.push(Statement::Assign(local, value));
+                }
+            }
+            Stmt::Return(value) => {
+                let value = value.map(|value| self.lower_operand(value));
+                self.terminate(Terminator::Return(value));
+            }
+            Stmt::Block(stmts) => {
+                for stmt in stmts {
+                    self.lower_stmt(*stmt);
+                }
+            }
+            Stmt::If { cond, then, else_ } => {
+                let cond = self.lower_operand(*cond);
+                let then_block = self.new_block();
+                let else_block = self.new_block();
+                let join = match else_ {
+                    Some(_) => self.new_block(),
+                    None => else_block,
+                };
+                self.blocks[self.current.0].terminator = Some(Terminator::Branch {
+                    cond,
+                    then: then_block,
+                    else_: else_block,
+                });
+                self.current = then_block;
+                self.lower_stmt(*then);
+                self.goto(join);
+                if let Some(else_) = else_ {
+                    self.current = else_block;
+                    self.lower_stmt(*else_);
+                    self.goto(join);
+                }
+            }
+            Stmt::For {
+                init,
+                cond,
+                update,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.lower_stmt(*init);
+                }
+                let header = self.new_block();
+                self.goto(header);
+                let body_block = self.new_block();
+                let exit = self.new_block();
+                match cond {
+                    Some(cond) => {
+                        let cond = self.lower_operand(*cond);
+                        self.blocks[self.current.0].terminator = Some(Terminator::Branch {
+                            cond,
+                            then: body_block,
+                            else_: exit,
+                        });
+                    }
+                    None => {
+                        self.blocks[self.current.0].terminator = Some(Terminator::Goto(body_block))
+                    }
+                }
+                self.current = body_block;
+                self.lower_stmt(*body);
+                if let Some(update) = update {
+                    self.lower_effect(*update);
+                }
+                self.goto(header);
+                self.current = exit;
+            }
+            Stmt::Expr(expr) => self.lower_effect(*expr),
+            Stmt::Empty => {}
+        }
+    }
+
+    /// Lowers an expression evaluated only for its side effects.
+    fn lower_effect(&mut self, expr: ExprId) {
+        match &self.arena[expr] {
+            Expr::Call { .. } => {
+                let call = self.lower_rvalue(expr);
+                self.push(Statement::Eval(call));
+            }
+            Expr::Assignment { .. } => {
+                self.lower_operand(expr);
+            }
+            // Only the calls among the operands have effects, and lowering
+            // stores their results
+            _ => {
+                self.lower_rvalue(expr);
+            }
+        }
+    }
+
+    /// Lowers an expression to a value that is not yet stored.
+    fn lower_rvalue(&mut self, expr: ExprId) -> Rvalue {
+        match &self.arena[expr] {
+            Expr::Binary { left, op, right } => {
+                let left = self.lower_operand(*left);
+                let right = self.lower_operand(*right);
+                Rvalue::Binary(*op, left, right)
+            }
+            Expr::Call { name, args } => Rvalue::Call {
+                callee: *name,
+                args: args.iter().map(|arg| self.lower_operand(*arg)).collect(),
+            },
+            _ => Rvalue::Use(self.lower_operand(expr)),
+        }
+    }
+
+    /// Lowers an expression to an operand, storing the results of
+    /// operations in temporaries.
+    fn lower_operand(&mut self, expr: ExprId) -> Operand {
+        let arena = self.arena;
+        match &arena[expr] {
+            Expr::Literal(literal) => Operand::Const(literal.clone()),
+            Expr::Identifier(_) => {
+                Operand::Local(self.variable(self.resolution.expr(self.index, expr)))
+            }
+            Expr::Assignment { target, value } => {
+                let value = self.lower_rvalue(*value);
+                let local = self.variable(self.resolution.expr(self.index, *target));
+                self.push(Statement::Assign(local, value));
+                Operand::Local(local)
+            }
+            Expr::Binary { .. } | Expr::Call { .. } => {
+                let ty = self.type_of(expr);
+                let value = self.lower_rvalue(expr);
+                let temp = self.new_local(None, ty);
+                self.push(Statement::Assign(temp, value));
+                Operand::Local(temp)
+            }
+        }
+    }
+
+    /// Returns the type of a well-typed expression.
+    fn type_of(&self, expr: ExprId) -> Type {
+        let arena = self.arena;
+        match &arena[expr] {
+            Expr::Literal(Literal::Int(_)) => Type::Int,
+            Expr::Literal(Literal::Float(_)) => Type::Float,
+            Expr::Literal(Literal::String(_)) => Type::String,
+            Expr::Identifier(_) => {
+                self.locals[self.variable(self.resolution.expr(self.index, expr)).0].ty
+            }
+            Expr::Binary { left, op, .. } => match op {
+                BinOp::Plus | BinOp::Minus | BinOp::Multiply | BinOp::Divide => self.type_of(*left),
+                _ => Type::Int,
+            },
+            // Builtins such as those of <virtuc/random.h> return int
+            Expr::Call { name, .. } => self
+                .return_types
+                .get(name.as_str())
+                .copied()
+                .unwrap_or(Type::Int),
+            Expr::Assignment { target, .. } => self.type_of(*target),
+        }
+    }
+}
+
+/// Closes the blocks, dropping those unreachable from the entry and
+/// renumbering the rest in order.
+fn reachable(blocks: Vec<OpenBlock>) -> Vec<BasicBlock> {
+    let successors = |block: &OpenBlock| {
+        block
+            .terminator
+            .as_ref()
+            .map_or_else(Vec::new, Terminator::successors)
+    };
+    let mut seen = vec![false; blocks.len()];
+    let mut stack = vec![BlockId(0)];
+    while let Some(id) = stack.pop() {
+        if !std::mem::replace(&mut seen[id.0], true) {
+            stack.extend(successors(&blocks[id.0]));
+        }
+    }
+    let mut renumbered = vec![None; blocks.len()];
+    let mut next = 0;
+    for (id, &kept) in seen.iter().enumerate() {
+        if kept {
+            renumbered[id] = Some(BlockId(next));
+            next += 1;
+        }
+    }
+    let renumber = |id: BlockId| renumbered[id.0].expect("successors are reachable");
+    blocks
+        .into_iter()
+        .zip(seen)
+        .filter(|(_, kept)| *kept)
+        .map(|(block, _)| BasicBlock {
+            statements: block.statements,
+            terminator: match block.terminator.expect("reachable blocks are terminated") {
+                Terminator::Goto(target) => Terminator::Goto(renumber(target)),
+                Terminator::Branch { cond, then, else_ } => Terminator::Branch {
+                    cond,
+                    then: renumber(then),
+                    else_: renumber(else_),
+                },
+                Terminator::Return(value) => Terminator::Return(value),
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::parse;
+
+    fn lower_source(source: &str) -> Module {
+        lower(&parse(&lex(source).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn test_lower_straight_line() {
+        let module = lower_source("int square(int x) { return x * x; }");
+        assert_eq!(
+            module.to_string(),
+            "fn square(_0: int) -> int {\n    \
+                 let _0: int; // x\n    \
+                 let _1: int;\n\
+             bb0:\n    \
+                 _1 = _0 * _0;\n    \
+                 return _1;\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_lower_control_flow() {
+        let module = lower_source(
+            "int sum(int n) {\n\
+                 int total = 0;\n\
+                 for (int i = 0; i < n; i = i + 1) {\n\
+                     if (i > 2) { total = total + i; } else { total = total + 1; }\n\
+                 }\n\
+                 return total;\n\
+             }",
+        );
+        let body = module.body("sum").unwrap();
+        // entry, loop header, body, then, else, join, exit
+        assert_eq!(body.blocks.len(), 7);
+        let predecessors = body.predecessors();
+        let header = match body.block(BlockId(0)).terminator {
+            Terminator::Goto(header) => header,
+            ref other => panic!("expected a jump to the loop header, got {}", other),
+        };
+        // Entered from the entry block and from the end of the loop body
+        assert_eq!(predecessors[header.0].len(), 2);
+        let returns = body
+            .blocks
+            .iter()
+            .filter(|block| matches!(block.terminator, Terminator::Return(Some(_))))
+            .count();
+        assert_eq!(returns, 1);
+    }
+
+    #[test]
+    fn test_unreachable_code_is_dropped() {
+        let module = lower_source("int f() { return 1; return 2; }");
+        let body = module.body("f").unwrap();
+        assert_eq!(body.blocks.len(), 1);
+        assert_eq!(
+            body.blocks[0].terminator,
+            Terminator::Return(Some(Operand::Const(Literal::Int(1))))
+        );
+    }
+
+    #[test]
+    fn test_shadowed_variables_get_own_locals() {
+        let module =
+            lower_source("int f(int x) { int y = x; { int x = 2; y = y + x; } return x; }");
+        let body = module.body("f").unwrap();
+        let names: Vec<_> = body
+            .locals
+            .iter()
+            .map(|l| l.name.map(Symbol::as_str))
+            .collect();
+        assert_eq!(names, [Some("x"), Some("y"), Some("x")]);
+        assert_eq!(
+            body.blocks[0].statements[2],
+            Statement::Assign(
+                LocalId(1),
+                Rvalue::Binary(
+                    BinOp::Plus,
+                    Operand::Local(LocalId(1)),
+                    Operand::Local(LocalId(2))
+                )
+            )
+        );
+        assert_eq!(
+            body.blocks[0].terminator,
+            Terminator::Return(Some(Operand::Local(LocalId(0))))
+        );
+    }
+
+    #[test]
+    fn test_calls_for_effect() {
+        let module =
+            lower_source("#include <stdio.h>\nint main() { printf(\"%ld\\n\", 1 + 2); return 0; }");
+        let body = module.body("main").unwrap();
+        assert!(matches!(
+            &body.blocks[0].statements[..],
+            [
+                Statement::Assign(_, Rvalue::Binary(..)),
+                Statement::Eval(Rvalue::Call { callee, .. })
+            ] if callee == "printf"
+        ));
+    }
+}
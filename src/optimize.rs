@@ -0,0 +1,422 @@
+//! # AST Simplification
+//!
+//! This module rewrites the AST into a simpler, equivalent form before code
+//! generation. It runs when optimizations are enabled (`-O1` and above).
+//!
+//! ## Rewrites
+//!
+//! - **Identities**: `x + 0`, `x - 0`, `x * 1`, and `x / 1` become `x`
+//! - **Annihilation**: `x * 0` becomes `0` when `x` has no side effects
+//! - **Strength reduction**: `x * 2` becomes `x + x` when `x` has no side
+//!   effects
+//! - **Double negation**: `0 - (0 - x)`, the language's `-(-x)`, becomes `x`
+//!
+//! Float rewrites are limited to those that are exact in IEEE 754: `x + 0.0`
+//! is kept because it turns `-0.0` into `0.0`.
+//!
+//! ## Passes
+//!
+//! Each rewrite is a named [`Pass`] over the whole program, and
+//! [`pipeline`] lists the passes code generation runs for a set of options,
+//! in order: [`STRIP_UNUSED`] when unused functions are stripped, then the
+//! [`SIMPLIFY_PASSES`] when optimizations are enabled. [`run_pipeline`]
+//! reports the program after each pass, which `virtuc opt-view` prints.
+
+use std::fmt;
+
+use crate::analysis;
+use crate::ast::*;
+use crate::options::CompileOptions;
+
+/// A rewrite of a binary operation whose operands are already rewritten,
+/// returning the expression that replaces it, if any.
+type Rule = fn(&mut Arena, ExprId, BinOp, ExprId) -> Option<ExprId>;
+
+/// A named rewrite of a whole program.
+#[derive(Clone, Copy)]
+pub struct Pass {
+    /// Name, as printed by `virtuc opt-view`
+    pub name: &'static str,
+    /// What the pass does, in a few words
+    pub description: &'static str,
+    action: Action,
+}
+
+/// How a pass rewrites a program.
+#[derive(Clone, Copy)]
+enum Action {
+    /// Rewrites the program as a whole
+    Program(fn(&Program, &CompileOptions) -> Program),
+    /// Rewrites every binary operation, bottom-up
+    Rewrite(Rule),
+}
+
+impl Pass {
+    /// Runs the pass over a program.
+    pub fn run(&self, program: &Program, options: &CompileOptions) -> Program {
+        match self.action {
+            Action::Program(run) => run(program, options),
+            Action::Rewrite(rule) => rewrite_program(program, rule),
+        }
+    }
+}
+
+impl fmt::Debug for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pass")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Removes the functions not reachable from the entry point, with
+/// [`analysis::strip_unused_from`].
+pub const STRIP_UNUSED: Pass = Pass {
+    name: "strip-unused",
+    description: "remove functions not reachable from the entry point",
+    action: Action::Program(strip_unused),
+};
+
+/// The simplification passes, in the order they run.
+pub const SIMPLIFY_PASSES: &[Pass] = &[
+    Pass {
+        name: "annihilation",
+        description: "fold x * 0 to 0",
+        action: Action::Rewrite(annihilation),
+    },
+    Pass {
+        name: "identities",
+        description: "fold x + 0, x - 0, x * 1 and x / 1 to x",
+        action: Action::Rewrite(identities),
+    },
+    Pass {
+        name: "double-negation",
+        description: "fold 0 - (0 - x) to x",
+        action: Action::Rewrite(double_negation),
+    },
+    Pass {
+        name: "strength-reduction",
+        description: "replace x * 2 with x + x",
+        action: Action::Rewrite(strength_reduction),
+    },
+];
+
+/// Returns the passes run on a program before code generation with these
+/// options, in order.
+pub fn pipeline(options: &CompileOptions) -> Vec<Pass> {
+    let mut passes = Vec::new();
+    if options.strips_unused() {
+        passes.push(STRIP_UNUSED);
+    }
+    if options.opt_level > 0 {
+        passes.extend_from_slice(SIMPLIFY_PASSES);
+    }
+    passes
+}
+
+/// Runs the passes of [`pipeline`] over a program.
+pub fn optimize_program(program: &Program, options: &CompileOptions) -> Program {
+    run_pipeline(program, options, |_, _| {})
+}
+
+/// Runs the passes of [`pipeline`] over a program, calling `after` with
+/// each pass and the program it produced.
+pub fn run_pipeline(
+    program: &Program,
+    options: &CompileOptions,
+    mut after: impl FnMut(&Pass, &Program),
+) -> Program {
+    let mut program = program.clone();
+    for pass in pipeline(options) {
+        program = pass.run(&program, options);
+        after(&pass, &program);
+    }
+    program
+}
+
+/// Simplifies every function of a program with the [`SIMPLIFY_PASSES`].
+pub fn simplify_program(program: &Program) -> Program {
+    SIMPLIFY_PASSES
+        .iter()
+        .fold(program.clone(), |program, pass| {
+            pass.run(&program, &CompileOptions::default())
+        })
+}
+
+/// Simplifies the expressions in a statement.
+pub fn simplify_stmt(mut stmt: StmtTree) -> StmtTree {
+    for rule in rules() {
+        rewrite_stmt(&mut stmt.arena, stmt.root, rule);
+    }
+    stmt
+}
+
+/// Simplifies an expression.
+pub fn simplify_expr(mut expr: ExprTree) -> ExprTree {
+    for rule in rules() {
+        expr.root = rewrite_expr(&mut expr.arena, expr.root, rule);
+    }
+    expr
+}
+
+/// Returns the rules of the [`SIMPLIFY_PASSES`], in order.
+fn rules() -> impl Iterator<Item = Rule> {
+    SIMPLIFY_PASSES.iter().filter_map(|pass| match pass.action {
+        Action::Rewrite(rule) => Some(rule),
+        Action::Program(_) => None,
+    })
+}
+
+/// Removes the functions not reachable from the entry point of `options`.
+fn strip_unused(program: &Program, options: &CompileOptions) -> Program {
+    analysis::strip_unused_from(program, options.entry())
+}
+
+/// Rewrites the binary operations of every function of a program.
+fn rewrite_program(program: &Program, rule: Rule) -> Program {
+    let mut program = program.clone();
+    for function in &mut program.functions {
+        rewrite_stmt(&mut function.arena, function.body, rule);
+    }
+    program
+}
+
+/// Rewrites the binary operations in a statement, in place.
+fn rewrite_stmt(arena: &mut Arena, stmt: StmtId, rule: Rule) {
+    let mut rewritten = arena[stmt].clone();
+    match &mut rewritten {
+        Stmt::Declaration { init: expr, .. } | Stmt::Return(expr) => {
+            if let Some(expr) = expr {
+                *expr = rewrite_expr(arena, *expr, rule);
+            }
+        }
+        Stmt::Block(stmts) => {
+            for stmt in stmts {
+                rewrite_stmt(arena, *stmt, rule);
+            }
+        }
+        Stmt::If { cond, then, else_ } => {
+            *cond = rewrite_expr(arena, *cond, rule);
+            rewrite_stmt(arena, *then, rule);
+            if let Some(else_) = else_ {
+                rewrite_stmt(arena, *else_, rule);
+            }
+        }
+        Stmt::For {
+            init,
+            cond,
+            update,
+            body,
+        } => {
+            if let Some(init) = init {
+                rewrite_stmt(arena, *init, rule);
+            }
+            for expr in cond.iter_mut().chain(update) {
+                *expr = rewrite_expr(arena, *expr, rule);
+            }
+            rewrite_stmt(arena, *body, rule);
+        }
+        Stmt::Expr(expr) => *expr = rewrite_expr(arena, *expr, rule),
+        Stmt::Empty => {}
+    }
+    arena[stmt] = rewritten;
+}
+
+/// Rewrites the binary operations in an expression, bottom-up, returning
+/// the expression that replaces it.
+fn rewrite_expr(arena: &mut Arena, expr: ExprId, rule: Rule) -> ExprId {
+    match arena[expr].clone() {
+        Expr::Binary { left, op, right } => {
+            let left = rewrite_expr(arena, left, rule);
+            let right = rewrite_expr(arena, right, rule);
+            arena[expr] = Expr::Binary { left, op, right };
+            rule(arena, left, op, right).unwrap_or(expr)
+        }
+        Expr::Call { name, args } => {
+            let args = args
+                .into_iter()
+                .map(|arg| rewrite_expr(arena, arg, rule))
+                .collect();
+            arena[expr] = Expr::Call { name, args };
+            expr
+        }
+        Expr::Assignment { target, value } => {
+            let value = rewrite_expr(arena, value, rule);
+            arena[expr] = Expr::Assignment { target, value };
+            expr
+        }
+        Expr::Literal(_) | Expr::Identifier(_) => expr,
+    }
+}
+
+/// Returns the value of a literal expression.
+fn literal(arena: &Arena, expr: ExprId) -> Option<Literal> {
+    match &arena[expr] {
+        Expr::Literal(lit) => Some(lit.clone()),
+        _ => None,
+    }
+}
+
+/// `x * 0` and `0 * x` become `0` when `x` has no side effects.
+fn annihilation(arena: &mut Arena, left: ExprId, op: BinOp, right: ExprId) -> Option<ExprId> {
+    use Literal::Int;
+
+    match (literal(arena, left), op, literal(arena, right)) {
+        (_, BinOp::Multiply, Some(Int(0))) if is_pure(arena, left) => Some(right),
+        (Some(Int(0)), BinOp::Multiply, _) if is_pure(arena, right) => Some(left),
+        _ => None,
+    }
+}
+
+/// `x + 0`, `x - 0`, `x * 1`, `x / 1`, `0 + x` and `1 * x` become `x`.
+fn identities(arena: &mut Arena, left: ExprId, op: BinOp, right: ExprId) -> Option<ExprId> {
+    use Literal::{Float, Int};
+
+    match (literal(arena, left), op, literal(arena, right)) {
+        (_, BinOp::Plus | BinOp::Minus, Some(Int(0)))
+        | (_, BinOp::Minus, Some(Float(0.0)))
+        | (_, BinOp::Multiply | BinOp::Divide, Some(Int(1)))
+        | (_, BinOp::Multiply | BinOp::Divide, Some(Float(1.0))) => Some(left),
+        (Some(Int(0)), BinOp::Plus, _)
+        | (Some(Int(1)), BinOp::Multiply, _)
+        | (Some(Float(1.0)), BinOp::Multiply, _) => Some(right),
+        _ => None,
+    }
+}
+
+/// `0 - (0 - x)`, the language's `-(-x)`, becomes `x`.
+fn double_negation(arena: &mut Arena, left: ExprId, op: BinOp, right: ExprId) -> Option<ExprId> {
+    let zero = Some(Literal::Int(0));
+    match (op, &arena[right]) {
+        (
+            BinOp::Minus,
+            Expr::Binary {
+                left: inner,
+                op: BinOp::Minus,
+                right: x,
+            },
+        ) if literal(arena, left) == zero && literal(arena, *inner) == zero => Some(*x),
+        _ => None,
+    }
+}
+
+/// `x * 2` and `2 * x` become `x + x` when `x` has no side effects.
+fn strength_reduction(arena: &mut Arena, left: ExprId, op: BinOp, right: ExprId) -> Option<ExprId> {
+    use Literal::{Float, Int};
+
+    let x = match (literal(arena, left), op, literal(arena, right)) {
+        (_, BinOp::Multiply, Some(Int(2) | Float(2.0))) if is_pure(arena, left) => left,
+        (Some(Int(2) | Float(2.0)), BinOp::Multiply, _) if is_pure(arena, right) => right,
+        _ => return None,
+    };
+    let copy = arena.clone_expr(x);
+    Some(arena.alloc_expr(Expr::Binary {
+        left: x,
+        op: BinOp::Plus,
+        right: copy,
+    }))
+}
+
+/// Returns true if evaluating the expression has no side effects, so that
+/// it may be duplicated or dropped.
+fn is_pure(arena: &Arena, expr: ExprId) -> bool {
+    match &arena[expr] {
+        Expr::Literal(_) | Expr::Identifier(_) => true,
+        Expr::Binary { left, right, .. } => is_pure(arena, *left) && is_pure(arena, *right),
+        Expr::Call { .. } | Expr::Assignment { .. } => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{FnBuilder, ProgramBuilder, expr, stmt};
+
+    #[test]
+    fn test_identities() {
+        let x = || expr::ident("x");
+        assert_eq!(simplify_expr(expr::add(x(), expr::int(0))), x());
+        assert_eq!(simplify_expr(expr::add(expr::int(0), x())), x());
+        assert_eq!(simplify_expr(expr::mul(expr::int(1), x())), x());
+        assert_eq!(simplify_expr(expr::div(x(), expr::float(1.0))), x());
+        // -0.0 + 0.0 is 0.0, so this is not an identity
+        let e = expr::add(x(), expr::float(0.0));
+        assert_eq!(simplify_expr(e.clone()), e);
+    }
+
+    #[test]
+    fn test_strength_reduction() {
+        let x = || expr::ident("x");
+        assert_eq!(
+            simplify_expr(expr::mul(x(), expr::int(2))),
+            expr::add(x(), x())
+        );
+        assert_eq!(simplify_expr(expr::mul(x(), expr::int(0))), expr::int(0));
+        // Calls must still be evaluated exactly once
+        let call = expr::mul(expr::call("f", []), expr::int(2));
+        assert_eq!(simplify_expr(call.clone()), call);
+    }
+
+    #[test]
+    fn test_nested_rewrites() {
+        let x = || expr::ident("x");
+        // 0 - (0 - (x * 1 + 0)) => x
+        let e = expr::sub(
+            expr::int(0),
+            expr::sub(
+                expr::int(0),
+                expr::add(expr::mul(x(), expr::int(1)), expr::int(0)),
+            ),
+        );
+        assert_eq!(simplify_expr(e), x());
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let names = |options: &CompileOptions| -> Vec<_> {
+            pipeline(options).iter().map(|pass| pass.name).collect()
+        };
+        assert_eq!(names(&CompileOptions::default()), [] as [&str; 0]);
+        let options = CompileOptions {
+            opt_level: 1,
+            strip_unused: true,
+            ..CompileOptions::default()
+        };
+        assert_eq!(
+            names(&options),
+            [
+                "strip-unused",
+                "annihilation",
+                "identities",
+                "double-negation",
+                "strength-reduction"
+            ]
+        );
+
+        let program = ProgramBuilder::new()
+            .function(
+                FnBuilder::new("unused", Type::Int)
+                    .stmt(stmt::ret(expr::int(0)))
+                    .build(),
+            )
+            .function(
+                FnBuilder::new("main", Type::Int)
+                    .stmt(stmt::ret(expr::mul(expr::int(7), expr::int(1))))
+                    .build(),
+            )
+            .build();
+        let mut seen = Vec::new();
+        let optimized = run_pipeline(&program, &options, |pass, program| {
+            seen.push((pass.name, program.functions.len()));
+        });
+        assert_eq!(seen[0], ("strip-unused", 1));
+        assert_eq!(seen.len(), 5);
+        assert_eq!(optimized, optimize_program(&program, &options));
+        assert_eq!(
+            optimized.functions[0],
+            FnBuilder::new("main", Type::Int)
+                .stmt(stmt::ret(expr::int(7)))
+                .build()
+        );
+    }
+}
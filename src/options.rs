@@ -0,0 +1,384 @@
+//! # Compilation Options
+//!
+//! This module defines the options that control how a program is compiled.
+//! Options are collected into a single [`CompileOptions`] struct which is
+//! threaded through semantic analysis and code generation, so that every
+//! phase agrees on settings such as the width of `int`.
+//!
+//! ## Defaults
+//!
+//! `CompileOptions::default()` reproduces the historical behavior of the
+//! compiler: `int` is a 64-bit integer and the executable is linked without
+//! optimization.
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::analysis::ENTRY;
+
+/// The width of the `int` type in generated code.
+///
+/// VirtuC historically maps `int` to a 64-bit integer. The 32-bit mode
+/// matches the C ABI on mainstream platforms, which matters when calling
+/// into real C libraries that expect a 32-bit `int`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum IntWidth {
+    /// 32-bit `int`, as in C on mainstream platforms
+    W32,
+    /// 64-bit `int` (default)
+    #[default]
+    W64,
+}
+
+impl IntWidth {
+    /// Returns the width in bits.
+    pub fn bits(self) -> u32 {
+        match self {
+            IntWidth::W32 => 32,
+            IntWidth::W64 => 64,
+        }
+    }
+
+    /// Returns true if the value fits in an `int` of this width.
+    pub fn fits(self, value: i64) -> bool {
+        match self {
+            IntWidth::W32 => i32::try_from(value).is_ok(),
+            IntWidth::W64 => true,
+        }
+    }
+}
+
+impl fmt::Display for IntWidth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.bits())
+    }
+}
+
+impl FromStr for IntWidth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "32" => Ok(IntWidth::W32),
+            "64" => Ok(IntWidth::W64),
+            _ => Err(format!("invalid int width '{}': expected 32 or 64", s)),
+        }
+    }
+}
+
+/// A runtime sanitizer that executables can be built with.
+///
+/// Sanitizers are implemented by clang's instrumentation passes and runtime
+/// libraries. Code generation marks functions for instrumentation and keeps
+/// frame pointers for readable stack traces; the link step passes the
+/// matching `-fsanitize=` flag.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Sanitizer {
+    /// AddressSanitizer, which detects invalid memory accesses
+    Address,
+    /// UndefinedBehaviorSanitizer. Its checks are inserted by C front ends,
+    /// so for now this only links its runtime
+    Undefined,
+}
+
+impl Sanitizer {
+    /// Returns the name used by `-fsanitize=`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Sanitizer::Address => "address",
+            Sanitizer::Undefined => "undefined",
+        }
+    }
+}
+
+impl fmt::Display for Sanitizer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Sanitizer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "address" => Ok(Sanitizer::Address),
+            "undefined" => Ok(Sanitizer::Undefined),
+            _ => Err(format!(
+                "invalid sanitizer '{}': expected address or undefined",
+                s
+            )),
+        }
+    }
+}
+
+/// Options controlling a single compilation.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct CompileOptions {
+    /// Width of the `int` type
+    pub int_width: IntWidth,
+    /// Name of the source file, recorded in the emitted module
+    pub source_name: Option<String>,
+    /// C compiler used to link the executable (defaults to `clang`)
+    pub cc: Option<String>,
+    /// Archiver used to build static libraries (defaults to `ar`)
+    pub ar: Option<String>,
+    /// Maximum nesting depth of expressions and statements (defaults to
+    /// [`DEFAULT_MAX_DEPTH`])
+    pub max_depth: Option<usize>,
+    /// Maximum number of chained operators, such as the two in `a + b - c`,
+    /// including those of enclosing expressions (defaults to
+    /// [`DEFAULT_MAX_CHAIN`])
+    pub max_chain: Option<usize>,
+    /// Maximum number of tokens in a program (defaults to
+    /// [`DEFAULT_MAX_TOKENS`])
+    pub max_tokens: Option<usize>,
+    /// Maximum length of an identifier in bytes (defaults to
+    /// [`DEFAULT_MAX_IDENTIFIER_LEN`])
+    pub max_identifier_len: Option<usize>,
+    /// Maximum length of a string literal in bytes (defaults to
+    /// [`DEFAULT_MAX_STRING_LEN`])
+    pub max_string_len: Option<usize>,
+    /// Accept identifiers with non-ASCII letters and digits
+    pub allow_unicode_ids: bool,
+    /// Optimization level (0 to 3); above zero, the AST is simplified before
+    /// code generation and the level is passed to the C compiler
+    pub opt_level: u8,
+    /// Libraries to link against, as passed to `-l`
+    pub link_libs: Vec<String>,
+    /// Produce byte-identical artifacts for identical input, regardless of
+    /// where the source and output live
+    pub deterministic: bool,
+    /// Let LLVM optimize float operations as if they were real numbers,
+    /// changing their results (see [`CompileOptions::uses_fast_math`])
+    pub fast_math: bool,
+    /// Unroll loops; `None` leaves it to the optimization level (see
+    /// [`CompileOptions::unrolls_loops`])
+    pub unroll_loops: Option<bool>,
+    /// Vectorize loops and straight-line code; `None` leaves it to the
+    /// optimization level (see [`CompileOptions::vectorizes`])
+    pub vectorize: Option<bool>,
+    /// Instrument the executable to write a raw profile into this directory
+    /// when it exits (see [`profile`](crate::profile))
+    pub profile_generate: Option<String>,
+    /// Optimize the executable with this merged profile
+    pub profile_use: Option<String>,
+    /// Optimize across modules at link time: executables are linked with
+    /// `-flto`, and static libraries hold LLVM bitcode instead of machine
+    /// code, so that C code linked with `-flto` can inline their functions
+    pub lto: bool,
+    /// Harden the executable against exploits of memory errors: protect
+    /// every function with a stack array or address-taken local by a stack
+    /// canary, probe large stack frames, and link a position-independent
+    /// executable with read-only relocations
+    pub hardening: bool,
+    /// Strip the symbol table from the executable, and let the linker
+    /// remove the sections nothing refers to
+    pub strip: bool,
+    /// The functions to export from the compiled code; `None` exports all.
+    /// The others get internal linkage, and are left out of generated C
+    /// headers. `main` is always exported
+    pub exports: Option<Vec<String>>,
+    /// The function the program starts at, if not `main`. It must take no
+    /// parameters and return `int`; a `main` that calls it is generated,
+    /// so the program must not define `main` itself
+    pub entry: Option<String>,
+    /// Abort compiled programs whose call depth exceeds this limit, instead
+    /// of letting runaway recursion overflow the stack (off by default)
+    pub recursion_limit: Option<u32>,
+    /// Sanitizers to build the executable with
+    pub sanitizers: Vec<Sanitizer>,
+    /// Count how often each statement runs, writing the counts to this file
+    /// when the program exits
+    pub coverage_file: Option<String>,
+    /// Compile out `assert` checks, as if `NDEBUG` were defined
+    pub ndebug: bool,
+    /// Remove functions and extern declarations not reachable from `main`
+    /// before code generation
+    pub strip_unused: bool,
+    /// Embed the source, compiler version, and options in the output (see
+    /// [`embed`](crate::embed))
+    pub embed_source: bool,
+    /// Keep the IR of the whole module in a [`CodegenError`], so that it can
+    /// be shown with the error
+    ///
+    /// [`CodegenError`]: crate::error::CodegenError
+    pub dump_ir_on_error: bool,
+}
+
+/// Default nesting limit, well within the parser's stack usage on a 2 MiB
+/// thread stack in debug builds.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Default limit on chained operators. Later phases walk a chain
+/// recursively, but with smaller frames than the parser's nesting.
+pub const DEFAULT_MAX_CHAIN: usize = 1024;
+
+/// Default limit on the number of tokens in a program.
+pub const DEFAULT_MAX_TOKENS: usize = 1_000_000;
+
+/// Default limit on the length of an identifier.
+pub const DEFAULT_MAX_IDENTIFIER_LEN: usize = 1024;
+
+/// Default limit on the length of a string literal.
+pub const DEFAULT_MAX_STRING_LEN: usize = 1 << 20;
+
+/// Default call depth limit of `--checked-recursion`, well within an 8 MiB
+/// stack for the small frames the language produces.
+pub const DEFAULT_RECURSION_LIMIT: u32 = 10_000;
+
+impl CompileOptions {
+    /// Returns the C compiler used to link the executable.
+    pub fn cc(&self) -> &str {
+        self.cc.as_deref().unwrap_or("clang")
+    }
+
+    /// Returns the archiver used to build static libraries.
+    pub fn ar(&self) -> &str {
+        self.ar.as_deref().unwrap_or("ar")
+    }
+
+    /// Returns the source name recorded in the emitted module. Deterministic
+    /// builds record only the file name, so that the directory the project
+    /// is built in does not leak into the artifacts.
+    pub fn recorded_source_name(&self) -> Option<&str> {
+        let name = self.source_name.as_deref()?;
+        if self.deterministic {
+            Path::new(name)
+                .file_name()
+                .and_then(|n| n.to_str())
+                .or(Some(name))
+        } else {
+            Some(name)
+        }
+    }
+
+    /// Returns true if `assert` checks are compiled in: assertions are
+    /// compiled out under `NDEBUG` and when optimizing.
+    pub fn checks_assertions(&self) -> bool {
+        self.opt_level == 0 && !self.ndebug
+    }
+
+    /// Returns true if unused functions are removed before code generation.
+    /// Coverage builds keep them, so that their counts line up with the
+    /// statements of the source.
+    pub fn strips_unused(&self) -> bool {
+        self.strip_unused && self.coverage_file.is_none()
+    }
+
+    /// Returns true if float operations get LLVM's fast-math flags.
+    /// Deterministic builds keep strict IEEE semantics, so that results do
+    /// not depend on how the optimizer treats each operation.
+    pub fn uses_fast_math(&self) -> bool {
+        self.fast_math && !self.deterministic
+    }
+
+    /// Returns true if LLVM unrolls loops. As in clang, loops are unrolled
+    /// from `-O2` unless chosen otherwise, and never at `-O0`.
+    pub fn unrolls_loops(&self) -> bool {
+        self.opt_level > 0 && self.unroll_loops.unwrap_or(self.opt_level >= 2)
+    }
+
+    /// Returns true if LLVM vectorizes code. As in clang, code is vectorized
+    /// from `-O2` unless chosen otherwise, and never at `-O0`.
+    pub fn vectorizes(&self) -> bool {
+        self.opt_level > 0 && self.vectorize.unwrap_or(self.opt_level >= 2)
+    }
+
+    /// Returns true if the function `name` is visible outside the compiled
+    /// code (see [`CompileOptions::exports`]).
+    pub fn exports_function(&self, name: &str) -> bool {
+        name == "main"
+            || self
+                .exports
+                .as_ref()
+                .is_none_or(|exports| exports.iter().any(|export| export == name))
+    }
+
+    /// Returns the name of the function the program starts at.
+    pub fn entry(&self) -> &str {
+        self.entry.as_deref().unwrap_or(ENTRY)
+    }
+
+    /// Returns the maximum nesting depth accepted by the parser.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth.unwrap_or(DEFAULT_MAX_DEPTH)
+    }
+
+    /// Returns the maximum number of chained operators accepted by the parser.
+    pub fn max_chain(&self) -> usize {
+        self.max_chain.unwrap_or(DEFAULT_MAX_CHAIN)
+    }
+
+    /// Returns the maximum number of tokens accepted by the parser.
+    pub fn max_tokens(&self) -> usize {
+        self.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS)
+    }
+
+    /// Returns the maximum length of an identifier accepted by the lexer.
+    pub fn max_identifier_len(&self) -> usize {
+        self.max_identifier_len
+            .unwrap_or(DEFAULT_MAX_IDENTIFIER_LEN)
+    }
+
+    /// Returns the maximum length of a string literal accepted by the lexer.
+    pub fn max_string_len(&self) -> usize {
+        self.max_string_len.unwrap_or(DEFAULT_MAX_STRING_LEN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_int_width_from_str() {
+        assert_eq!("32".parse::<IntWidth>(), Ok(IntWidth::W32));
+        assert_eq!("64".parse::<IntWidth>(), Ok(IntWidth::W64));
+        assert!("16".parse::<IntWidth>().is_err());
+    }
+
+    #[test]
+    fn test_sanitizer_from_str() {
+        assert_eq!("address".parse::<Sanitizer>(), Ok(Sanitizer::Address));
+        assert_eq!("undefined".parse::<Sanitizer>(), Ok(Sanitizer::Undefined));
+        assert!("thread".parse::<Sanitizer>().is_err());
+    }
+
+    #[test]
+    fn test_int_width_fits() {
+        assert!(IntWidth::W32.fits(i32::MAX as i64));
+        assert!(!IntWidth::W32.fits(i32::MAX as i64 + 1));
+        assert!(IntWidth::W64.fits(i64::MAX));
+    }
+
+    #[test]
+    fn test_loop_optimizations() {
+        let at = |opt_level, choice| CompileOptions {
+            opt_level,
+            unroll_loops: choice,
+            vectorize: choice,
+            ..CompileOptions::default()
+        };
+        assert!(!at(1, None).unrolls_loops());
+        assert!(at(2, None).unrolls_loops() && at(2, None).vectorizes());
+        assert!(at(1, Some(true)).vectorizes());
+        assert!(!at(3, Some(false)).unrolls_loops());
+        assert!(!at(0, Some(true)).vectorizes());
+    }
+
+    #[test]
+    fn test_recorded_source_name() {
+        let mut options = CompileOptions {
+            source_name: Some("/home/user/project/hello.c".to_string()),
+            ..CompileOptions::default()
+        };
+        assert_eq!(
+            options.recorded_source_name(),
+            Some("/home/user/project/hello.c")
+        );
+        options.deterministic = true;
+        assert_eq!(options.recorded_source_name(), Some("hello.c"));
+    }
+}
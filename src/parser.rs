@@ -12,271 +12,599 @@
 //! - Functions: declarations and definitions
 //! - Control structures: if-else, for loops
 //!
+//! ## Entry Points
+//!
+//! [`parse`] parses a whole program. Fragments are parsed by
+//! [`parse_expression`], [`parse_statement`], and [`parse_type_name`], or
+//! straight from source by [`parse_expr_str`], [`parse_stmt_str`], and
+//! [`parse_type_str`]; a fragment must make up all of its input.
+//!
+//! [`parse_stream`] parses a program from a [`TokenStream`] without
+//! collecting all of its tokens first. Top-level items do not depend on
+//! each other, so tokens are buffered only until the item they belong to is
+//! complete, keeping memory proportional to the largest item rather than to
+//! the whole input.
+//!
 //! ## Parser Combinators
 //!
 //! Uses `nom`'s combinator approach to build modular parsers for each
 //! grammar rule. Provides good error messages and recovery for syntax errors.
+//!
+//! ## Error Reporting
+//!
+//! Parsers fail with a [`SyntaxError`], a custom `nom` error type that records
+//! which tokens or constructs were expected. When alternatives fail, the error
+//! that got furthest into the input wins, and errors at the same position are
+//! merged, yielding messages like `expected ';' after expression, found '}'`.
+//! Repetitions and optional parts are written so that the failure of an item
+//! which has started to parse is reported rather than discarded.
+//!
+//! ## Expressions
+//!
+//! Binary expressions are parsed by a Pratt (precedence climbing) parser
+//! driven by a declarative table following C's precedence and associativity
+//! rules, so `a < b < c` parses as `(a < b) < c` and `a = b = c` as
+//! `a = (b = c)`.
 
 use nom::{
     IResult,
     branch::alt,
-    combinator::{map, opt},
-    error::{Error, ErrorKind},
-    multi::{many0, separated_list0},
+    combinator::{cut, map, peek},
+    error::{ContextError, ErrorKind, ParseError as _},
     sequence::{delimited, preceded, terminated, tuple},
 };
 
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Range;
+use std::thread::LocalKey;
+
 use crate::ast::*;
-use crate::lexer::Token;
+use crate::diagnostics::Span;
+use crate::error::{CompileError, ParseError};
+use crate::interner::Symbol;
+use crate::lexer::{Token, TokenStream, lex_with_spans};
+use crate::options::{CompileOptions, DEFAULT_MAX_CHAIN, DEFAULT_MAX_DEPTH};
 
+/// Something the parser expected to find.
 #[derive(Debug, PartialEq, Clone)]
-enum TopLevel {
+enum Expected {
+    /// A specific token
+    Token(Token),
+    /// A grammar construct such as "expression"
+    Construct(&'static str),
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expected::Token(token) => write!(f, "'{}'", token),
+            Expected::Construct(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// The error type of the parser combinators.
+///
+/// Records the remaining input at the failure, what was expected there, and
+/// optionally what the expected item follows (e.g. "after expression").
+#[derive(Debug, PartialEq, Clone)]
+struct SyntaxError<'a> {
+    input: &'a [Token],
+    expected: Vec<Expected>,
+    context: Option<&'static str>,
+    kind: ErrorKind,
+}
+
+impl<'a> SyntaxError<'a> {
+    /// Creates an error expecting a single token or construct.
+    fn expected(input: &'a [Token], expected: Expected) -> Self {
+        Self {
+            input,
+            expected: vec![expected],
+            context: None,
+            kind: ErrorKind::Tag,
+        }
+    }
+
+    /// Renders the error as "expected X, found Y".
+    fn message(&self) -> String {
+        let found = match self.input.first() {
+            Some(token) => format!("'{}'", token),
+            None => "end of input".to_string(),
+        };
+        if self.expected.is_empty() {
+            return format!("unexpected {}", found);
+        }
+        let expected: Vec<String> = self.expected.iter().map(|e| e.to_string()).collect();
+        let expected = match expected.split_last() {
+            Some((last, [])) => last.clone(),
+            Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+            None => unreachable!(),
+        };
+        match self.context {
+            Some(context) if self.expected.len() == 1 => {
+                format!("expected {} {}, found {}", expected, context, found)
+            }
+            _ => format!("expected {}, found {}", expected, found),
+        }
+    }
+}
+
+impl<'a> nom::error::ParseError<&'a [Token]> for SyntaxError<'a> {
+    fn from_error_kind(input: &'a [Token], kind: ErrorKind) -> Self {
+        Self {
+            input,
+            expected: Vec::new(),
+            context: None,
+            kind,
+        }
+    }
+
+    fn append(_input: &'a [Token], _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+
+    /// Keeps the error that got furthest, merging errors at the same position.
+    fn or(mut self, other: Self) -> Self {
+        match self.input.len().cmp(&other.input.len()) {
+            Ordering::Less => self,
+            Ordering::Greater => other,
+            Ordering::Equal => {
+                for expected in other.expected {
+                    if !self.expected.contains(&expected) {
+                        self.expected.push(expected);
+                    }
+                }
+                self.context = self.context.or(other.context);
+                self
+            }
+        }
+    }
+}
+
+impl<'a> ContextError<&'a [Token]> for SyntaxError<'a> {
+    fn add_context(input: &'a [Token], context: &'static str, mut other: Self) -> Self {
+        if other.input.len() == input.len() && other.context.is_none() {
+            other.context = Some(context);
+        }
+        other
+    }
+}
+
+/// Result type of the parser combinators.
+type PResult<'a, T> = IResult<&'a [Token], T, SyntaxError<'a>>;
+
+thread_local! {
+    /// Current nesting depth and limit of the parse running on this thread.
+    static DEPTH: Cell<(usize, usize)> = const { Cell::new((0, DEFAULT_MAX_DEPTH)) };
+}
+
+thread_local! {
+    /// Number of chained operators open in the parse running on this thread,
+    /// and their limit.
+    static CHAIN: Cell<(usize, usize)> = const { Cell::new((0, DEFAULT_MAX_CHAIN)) };
+}
+
+thread_local! {
+    /// Arena of the function or fragment being parsed on this thread.
+    static ARENA: RefCell<Arena> = RefCell::new(Arena::new());
+}
+
+/// Allocates an expression in the arena of the running parse.
+fn alloc_expr(expr: Expr) -> ExprId {
+    ARENA.with(|arena| arena.borrow_mut().alloc_expr(expr))
+}
+
+/// Allocates a statement in the arena of the running parse.
+fn alloc_stmt(stmt: Stmt) -> StmtId {
+    ARENA.with(|arena| arena.borrow_mut().alloc_stmt(stmt))
+}
+
+/// Takes the arena of the running parse, leaving an empty one.
+fn take_arena() -> Arena {
+    ARENA.with(|arena| arena.take())
+}
+
+thread_local! {
+    /// Remaining input lengths at the start of each statement parsed so far,
+    /// when the running parse records statement positions.
+    static STATEMENTS: RefCell<Option<Vec<usize>>> = const { RefCell::new(None) };
+}
+
+/// One level of expression or statement nesting.
+///
+/// The parser is recursive, so unbounded nesting such as thousands of
+/// parentheses would overflow the stack. Entering a level fails with a
+/// non-recoverable `ErrorKind::TooLarge` once the limit is reached, and the
+/// level is released when the guard is dropped.
+///
+/// Operators chained without parentheses, such as `a + b + c`, are parsed in
+/// a loop but still deepen the tree that later phases walk recursively. They
+/// count against a separate, larger limit and fail with `ErrorKind::Count`.
+struct DepthGuard(&'static LocalKey<Cell<(usize, usize)>>);
+
+impl DepthGuard {
+    fn enter(input: &[Token]) -> Result<Self, nom::Err<SyntaxError<'_>>> {
+        Self::acquire(&DEPTH, ErrorKind::TooLarge, input)
+    }
+
+    fn chain(input: &[Token]) -> Result<Self, nom::Err<SyntaxError<'_>>> {
+        Self::acquire(&CHAIN, ErrorKind::Count, input)
+    }
+
+    fn acquire<'a>(
+        counter: &'static LocalKey<Cell<(usize, usize)>>,
+        kind: ErrorKind,
+        input: &'a [Token],
+    ) -> Result<Self, nom::Err<SyntaxError<'a>>> {
+        counter.with(|cell| {
+            let (depth, limit) = cell.get();
+            if depth >= limit {
+                Err(nom::Err::Failure(nom::error::ParseError::from_error_kind(
+                    input, kind,
+                )))
+            } else {
+                cell.set((depth + 1, limit));
+                Ok(DepthGuard(counter))
+            }
+        })
+    }
+}
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        self.0.with(|cell| {
+            let (depth, limit) = cell.get();
+            cell.set((depth - 1, limit));
+        });
+    }
+}
+
+/// A top-level item of a program.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum TopLevel {
     Include(String),
+    Import(String),
     Extern(ExternFunction),
     Function(Function),
 }
 
 /// Helper function to match a specific token
-fn token(expected: Token) -> impl Fn(&[Token]) -> IResult<&[Token], Token> {
-    move |input: &[Token]| {
-        if input.is_empty() {
-            return Err(nom::Err::Error(Error::new(input, ErrorKind::Eof)));
+fn token(expected: Token) -> impl Fn(&[Token]) -> PResult<'_, Token> {
+    move |input: &[Token]| match input.first() {
+        Some(found) if *found == expected => Ok((&input[1..], expected.clone())),
+        _ => Err(nom::Err::Error(SyntaxError::expected(
+            input,
+            Expected::Token(expected.clone()),
+        ))),
+    }
+}
+
+/// Replaces the expectations of a parser that fails without consuming any
+/// input with the name of the construct it parses, e.g. "expression".
+fn expecting<'a, O>(
+    name: &'static str,
+    mut parser: impl FnMut(&'a [Token]) -> PResult<'a, O>,
+) -> impl FnMut(&'a [Token]) -> PResult<'a, O> {
+    move |input: &'a [Token]| {
+        parser(input).map_err(|e| match e {
+            nom::Err::Error(err) if err.input.len() == input.len() => {
+                nom::Err::Error(SyntaxError::expected(input, Expected::Construct(name)))
+            }
+            e => e,
+        })
+    }
+}
+
+/// Parse an optional item that is absent exactly when the next token is
+/// `terminator`. Unlike `opt`, a failure inside the item is reported.
+fn optional_before<'a, O>(
+    terminator: Token,
+    parser: impl FnMut(&'a [Token]) -> PResult<'a, O>,
+) -> impl FnMut(&'a [Token]) -> PResult<'a, Option<O>> {
+    alt((map(peek(token(terminator)), |_| None), map(parser, Some)))
+}
+
+/// Parse a possibly empty, comma-separated list closed by `close`, consuming
+/// the closing token. A failure inside an item is reported.
+fn list<'a, O>(
+    mut item: impl FnMut(&'a [Token]) -> PResult<'a, O>,
+    close: Token,
+) -> impl FnMut(&'a [Token]) -> PResult<'a, Vec<O>> {
+    move |mut input: &'a [Token]| {
+        let mut items = Vec::new();
+        if let Ok((rest, _)) = token(close.clone())(input) {
+            return Ok((rest, items));
         }
-        if input[0] == expected {
-            Ok((&input[1..], expected.clone()))
-        } else {
-            Err(nom::Err::Error(Error::new(input, ErrorKind::Tag)))
+        loop {
+            let (rest, value) = item(input)?;
+            items.push(value);
+            input = rest;
+            match input.first() {
+                Some(Token::Comma) => input = &input[1..],
+                _ => break,
+            }
         }
+        let (input, _) = token(close.clone())(input)?;
+        Ok((input, items))
     }
 }
 
 /// Parse a type: int | float | string
-fn parse_type(input: &[Token]) -> IResult<&[Token], Type> {
-    alt((
-        map(token(Token::Int), |_| Type::Int),
-        map(token(Token::Float), |_| Type::Float),
-        map(token(Token::StringType), |_| Type::String),
-    ))(input)
+fn parse_type(input: &[Token]) -> PResult<'_, Type> {
+    expecting(
+        "type",
+        alt((
+            map(token(Token::Int), |_| Type::Int),
+            map(token(Token::Float), |_| Type::Float),
+            map(token(Token::StringType), |_| Type::String),
+        )),
+    )(input)
 }
 
 /// Parse an identifier
-fn parse_identifier(input: &[Token]) -> IResult<&[Token], String> {
-    if input.is_empty() {
-        return Err(nom::Err::Error(Error::new(input, ErrorKind::Eof)));
-    }
-    match &input[0] {
-        Token::Identifier(name) => Ok((&input[1..], name.clone())),
-        _ => Err(nom::Err::Error(Error::new(input, ErrorKind::Tag))),
+fn parse_identifier(input: &[Token]) -> PResult<'_, Symbol> {
+    match input.first() {
+        Some(Token::Identifier(name)) => Ok((&input[1..], *name)),
+        _ => Err(nom::Err::Error(SyntaxError::expected(
+            input,
+            Expected::Construct("identifier"),
+        ))),
     }
 }
 
 /// Parse a literal
-fn parse_literal(input: &[Token]) -> IResult<&[Token], Literal> {
-    if input.is_empty() {
-        return Err(nom::Err::Error(Error::new(input, ErrorKind::Eof)));
-    }
-    match &input[0] {
-        Token::IntLiteral(n) => Ok((&input[1..], Literal::Int(*n))),
-        Token::FloatLiteral(f) => Ok((&input[1..], Literal::Float(*f))),
-        Token::StringLiteral(s) => Ok((&input[1..], Literal::String(s.clone()))),
-        _ => Err(nom::Err::Error(Error::new(input, ErrorKind::Tag))),
-    }
-}
-
-/// Parse a binary operator
-fn parse_binop(input: &[Token]) -> IResult<&[Token], BinOp> {
-    alt((
-        map(token(Token::Plus), |_| BinOp::Plus),
-        map(token(Token::Minus), |_| BinOp::Minus),
-        map(token(Token::Multiply), |_| BinOp::Multiply),
-        map(token(Token::Divide), |_| BinOp::Divide),
-        map(token(Token::Equal), |_| BinOp::Equal),
-        map(token(Token::NotEqual), |_| BinOp::NotEqual),
-        map(token(Token::LessThan), |_| BinOp::LessThan),
-        map(token(Token::GreaterThan), |_| BinOp::GreaterThan),
-        map(token(Token::LessEqual), |_| BinOp::LessEqual),
-        map(token(Token::GreaterEqual), |_| BinOp::GreaterEqual),
-    ))(input)
+fn parse_literal(input: &[Token]) -> PResult<'_, Literal> {
+    match input.first() {
+        Some(Token::IntLiteral(n)) => Ok((&input[1..], Literal::Int(*n))),
+        Some(Token::FloatLiteral(f)) => Ok((&input[1..], Literal::Float(*f))),
+        Some(Token::StringLiteral(_)) => {
+            // Adjacent string literals are concatenated, as in C
+            let mut value = String::new();
+            let mut rest = input;
+            while let Some(Token::StringLiteral(s)) = rest.first() {
+                value.push_str(s);
+                rest = &rest[1..];
+            }
+            Ok((rest, Literal::String(value)))
+        }
+        _ => Err(nom::Err::Error(SyntaxError::expected(
+            input,
+            Expected::Construct("literal"),
+        ))),
+    }
 }
 
 /// Parse a primary expression: literal | identifier | (expr) | call
-fn parse_primary_expr(input: &[Token]) -> IResult<&[Token], Expr> {
-    alt((
-        map(parse_literal, Expr::Literal),
-        parse_call,
-        map(parse_identifier, Expr::Identifier),
-        delimited(token(Token::LParen), parse_expr, token(Token::RParen)),
-    ))(input)
-}
-
-/// Parse a function call: identifier(args)
-fn parse_call(input: &[Token]) -> IResult<&[Token], Expr> {
-    map(
-        tuple((
-            parse_identifier,
+fn parse_primary_expr(input: &[Token]) -> PResult<'_, ExprId> {
+    expecting(
+        "expression",
+        alt((
+            map(parse_literal, |lit| alloc_expr(Expr::Literal(lit))),
+            parse_call_or_identifier,
             delimited(
                 token(Token::LParen),
-                separated_list0(token(Token::Comma), parse_expr),
-                token(Token::RParen),
+                parse_expr,
+                context("after expression", token(Token::RParen)),
             ),
         )),
-        |(name, args)| Expr::Call { name, args },
     )(input)
 }
 
-/// Parse multiplicative expression: primary (*|/ primary)*
-/// Implements left-associative parsing for * and / operators.
-/// Higher precedence than addition, so parses before additive.
-fn parse_multiplicative(input: &[Token]) -> IResult<&[Token], Expr> {
-    let (input, mut expr) = parse_primary_expr(input)?;
-    let mut input = input;
-    // Loop to handle left-associative chaining: a * b / c -> ((a * b) / c)
-    loop {
-        let result = opt(tuple((
-            alt((token(Token::Multiply), token(Token::Divide))),
-            parse_primary_expr,
-        )))(input)?;
-        if let Some((op_token, right)) = result.1 {
-            let op = match op_token {
-                Token::Multiply => BinOp::Multiply,
-                Token::Divide => BinOp::Divide,
-                _ => unreachable!(),
-            };
-            expr = Expr::Binary {
-                left: Box::new(expr),
-                op,
-                right: Box::new(right),
-            };
-            input = result.0;
-        } else {
-            break;
-        }
+/// Parse a function call `identifier(args)` or a plain identifier
+fn parse_call_or_identifier(input: &[Token]) -> PResult<'_, ExprId> {
+    let (input, name) = parse_identifier(input)?;
+    if input.first() != Some(&Token::LParen) {
+        return Ok((input, alloc_expr(Expr::Identifier(name))));
     }
-    Ok((input, expr))
+    map(list(parse_expr, Token::RParen), move |args| {
+        alloc_expr(Expr::Call { name, args })
+    })(&input[1..])
 }
 
-/// Parse additive expression: multiplicative (+|- multiplicative)*
-/// Implements left-associative parsing for + and - operators.
-/// Lower precedence than multiplication, so these parse after multiplicative.
-fn parse_additive(input: &[Token]) -> IResult<&[Token], Expr> {
-    let (input, mut expr) = parse_multiplicative(input)?;
-    let mut input = input;
-    // Loop to handle left-associative chaining: a + b - c -> ((a + b) - c)
-    loop {
-        let result = opt(tuple((
-            alt((token(Token::Plus), token(Token::Minus))),
-            parse_multiplicative,
-        )))(input)?;
-        if let Some((op_token, right)) = result.1 {
-            let op = match op_token {
-                Token::Plus => BinOp::Plus,
-                Token::Minus => BinOp::Minus,
-                _ => unreachable!(),
-            };
-            // Left-associate: wrap left side with new operator
-            expr = Expr::Binary {
-                left: Box::new(expr),
-                op,
-                right: Box::new(right),
-            };
-            input = result.0;
-        } else {
-            break;
-        }
-    }
-    Ok((input, expr))
+/// Adds what the expected item follows to the errors of `parser`.
+fn context<'a, O>(
+    context: &'static str,
+    parser: impl FnMut(&'a [Token]) -> PResult<'a, O>,
+) -> impl FnMut(&'a [Token]) -> PResult<'a, O> {
+    nom::error::context(context, parser)
 }
 
-/// Parse comparison expression: additive (==|!=|<|>|<=|>= additive)*
-/// Handles comparison operators with lowest precedence.
-/// Unlike +/-, comparisons are non-associative (a < b < c is not allowed in C).
-fn parse_comparison(input: &[Token]) -> IResult<&[Token], Expr> {
-    let (input, mut expr) = parse_additive(input)?;
-    let mut input = input;
-    // Attempt to parse one comparison operator and right operand
-    let result = opt(tuple((parse_binop, parse_additive)))(input)?;
-    if let Some((op, right)) = result.1 {
-        expr = Expr::Binary {
-            left: Box::new(expr),
-            op,
-            right: Box::new(right),
-        };
-        input = result.0;
-    }
-    Ok((input, expr))
+/// Associativity of an infix operator.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Assoc {
+    Left,
+    Right,
+}
+
+/// An infix operator recognized by the expression parser.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Infix {
+    Assign,
+    Binary(BinOp),
+}
+
+/// The C operator precedence table, from lowest to highest binding power.
+///
+/// Each row is one precedence level; operators on the same row bind equally
+/// tightly and associate as the row specifies. New operators are added by
+/// extending this table rather than writing another layer of parsers.
+const PRECEDENCE_TABLE: &[(Assoc, &[(Token, Infix)])] = &[
+    (Assoc::Right, &[(Token::Assign, Infix::Assign)]),
+    (
+        Assoc::Left,
+        &[
+            (Token::Equal, Infix::Binary(BinOp::Equal)),
+            (Token::NotEqual, Infix::Binary(BinOp::NotEqual)),
+        ],
+    ),
+    (
+        Assoc::Left,
+        &[
+            (Token::LessThan, Infix::Binary(BinOp::LessThan)),
+            (Token::GreaterThan, Infix::Binary(BinOp::GreaterThan)),
+            (Token::LessEqual, Infix::Binary(BinOp::LessEqual)),
+            (Token::GreaterEqual, Infix::Binary(BinOp::GreaterEqual)),
+        ],
+    ),
+    (
+        Assoc::Left,
+        &[
+            (Token::Plus, Infix::Binary(BinOp::Plus)),
+            (Token::Minus, Infix::Binary(BinOp::Minus)),
+        ],
+    ),
+    (
+        Assoc::Left,
+        &[
+            (Token::Multiply, Infix::Binary(BinOp::Multiply)),
+            (Token::Divide, Infix::Binary(BinOp::Divide)),
+        ],
+    ),
+];
+
+/// Looks up an infix operator, returning it with its precedence level
+/// (1 = loosest) and associativity.
+fn infix_operator(token: &Token) -> Option<(Infix, usize, Assoc)> {
+    PRECEDENCE_TABLE
+        .iter()
+        .enumerate()
+        .find_map(|(level, (assoc, operators))| {
+            operators
+                .iter()
+                .find(|(t, _)| t == token)
+                .map(|(_, infix)| (*infix, level + 1, *assoc))
+        })
 }
 
-/// Parse an assignment expression: identifier = expr
-fn parse_assignment_expr(input: &[Token]) -> IResult<&[Token], Expr> {
-    alt((
-        map(
-            tuple((parse_identifier, token(Token::Assign), parse_expr)),
-            |(name, _, value)| Expr::Assignment {
-                name,
-                value: Box::new(value),
+/// Parse an expression whose operators all bind at least as tightly as
+/// `min_precedence`, using precedence climbing (a Pratt parser).
+///
+/// Left-associative operators parse their right operand one level tighter,
+/// so `a - b - c` becomes `(a - b) - c`; right-associative operators parse it
+/// at the same level, so `a = b = c` becomes `a = (b = c)`.
+fn parse_expr_bp(input: &[Token], min_precedence: usize) -> PResult<'_, ExprId> {
+    let (mut input, mut expr) = parse_primary_expr(input)?;
+    // Each left-associative operator deepens the tree even though the parser
+    // loops instead of recursing, and later phases walk the tree recursively
+    let mut guards = Vec::new();
+    while let Some((infix, precedence, assoc)) = input.first().and_then(infix_operator) {
+        if precedence < min_precedence {
+            break;
+        }
+        let next_precedence = match assoc {
+            Assoc::Left => {
+                guards.push(DepthGuard::chain(input)?);
+                precedence + 1
+            }
+            Assoc::Right => precedence,
+        };
+        // Parsing the right operand recurses, and right-associative operators
+        // nest the tree as deeply as that recursion
+        let (rest, right) = {
+            let _guard = DepthGuard::enter(input)?;
+            parse_expr_bp(&input[1..], next_precedence)?
+        };
+        expr = alloc_expr(match infix {
+            Infix::Binary(op) => Expr::Binary {
+                left: expr,
+                op,
+                right,
             },
-        ),
-        parse_comparison,
-    ))(input)
+            // Whether the target is assignable is checked during semantic analysis
+            Infix::Assign => Expr::Assignment {
+                target: expr,
+                value: right,
+            },
+        });
+        input = rest;
+    }
+    Ok((input, expr))
 }
 
 /// Parse expression (top level)
-fn parse_expr(input: &[Token]) -> IResult<&[Token], Expr> {
-    parse_assignment_expr(input)
+fn parse_expr(input: &[Token]) -> PResult<'_, ExprId> {
+    let _guard = DepthGuard::enter(input)?;
+    parse_expr_bp(input, 1)
 }
 
 /// Parse a declaration: type identifier (= expr)? ;
-fn parse_declaration(input: &[Token]) -> IResult<&[Token], Stmt> {
+fn parse_declaration(input: &[Token]) -> PResult<'_, StmtId> {
+    declaration("after declaration")(input)
+}
+
+/// Parse a declaration whose missing `;` is reported as expected `after`
+/// the given construct.
+fn declaration<'a>(after: &'static str) -> impl FnMut(&'a [Token]) -> PResult<'a, StmtId> {
     map(
         tuple((
             parse_type,
             parse_identifier,
-            opt(preceded(token(Token::Assign), parse_expr)),
-            token(Token::Semicolon),
+            alt((
+                map(preceded(token(Token::Assign), parse_expr), Some),
+                map(peek(token(Token::Semicolon)), |_| None),
+            )),
+            context(after, token(Token::Semicolon)),
         )),
-        |(ty, name, init, _)| Stmt::Declaration { ty, name, init },
-    )(input)
+        |(ty, name, init, _)| alloc_stmt(Stmt::Declaration { ty, name, init }),
+    )
 }
 
 /// Parse a return statement: return expr? ;
-fn parse_return(input: &[Token]) -> IResult<&[Token], Stmt> {
+fn parse_return(input: &[Token]) -> PResult<'_, StmtId> {
     map(
         tuple((
             token(Token::Return),
-            opt(parse_expr),
-            token(Token::Semicolon),
+            optional_before(Token::Semicolon, parse_expr),
+            context("after return statement", token(Token::Semicolon)),
         )),
-        |(_, expr, _)| Stmt::Return(expr),
+        |(_, expr, _)| alloc_stmt(Stmt::Return(expr)),
     )(input)
 }
 
 /// Parse a block: { statements }
-fn parse_block(input: &[Token]) -> IResult<&[Token], Stmt> {
-    map(
-        delimited(
-            token(Token::LBrace),
-            many0(parse_stmt),
-            token(Token::RBrace),
-        ),
-        Stmt::Block,
-    )(input)
+///
+/// Before each statement the closing brace is tried first, so that a
+/// statement which fails partway through is reported rather than ending the
+/// block early.
+fn parse_block(input: &[Token]) -> PResult<'_, StmtId> {
+    let (mut input, _) = token(Token::LBrace)(input)?;
+    let mut stmts = Vec::new();
+    loop {
+        match token(Token::RBrace)(input) {
+            Ok((rest, _)) => return Ok((rest, alloc_stmt(Stmt::Block(stmts)))),
+            Err(nom::Err::Error(close)) => {
+                let (rest, stmt) = parse_stmt(input).map_err(|e| match e {
+                    nom::Err::Error(e) => nom::Err::Error(close.or(e)),
+                    e => e,
+                })?;
+                stmts.push(stmt);
+                input = rest;
+            }
+            Err(e) => return Err(e),
+        }
+    }
 }
 
 /// Parse an if statement: if (expr) stmt (else stmt)?
-fn parse_if(input: &[Token]) -> IResult<&[Token], Stmt> {
-    map(
-        tuple((
-            token(Token::If),
-            delimited(token(Token::LParen), parse_expr, token(Token::RParen)),
-            parse_stmt,
-            opt(preceded(token(Token::Else), parse_stmt)),
-        )),
-        |(_, cond, then, else_)| Stmt::If {
-            cond,
-            then: Box::new(then),
-            else_: else_.map(Box::new),
-        },
-    )(input)
+fn parse_if(input: &[Token]) -> PResult<'_, StmtId> {
+    let (input, (_, cond, then)) = tuple((
+        token(Token::If),
+        delimited(
+            token(Token::LParen),
+            parse_expr,
+            context("after condition", token(Token::RParen)),
+        ),
+        parse_stmt,
+    ))(input)?;
+    // Once `else` is seen, the else branch must parse
+    let (input, else_) = match token(Token::Else)(input) {
+        Ok((rest, _)) => map(cut(parse_stmt), Some)(rest)?,
+        Err(_) => (input, None),
+    };
+    Ok((input, alloc_stmt(Stmt::If { cond, then, else_ })))
 }
 
 /// Parse a for loop: for (init? ; cond? ; update?) stmt
@@ -284,7 +612,7 @@ fn parse_if(input: &[Token]) -> IResult<&[Token], Stmt> {
 /// - init: Can be a declaration (int i = 0) or expression (i = 0)
 /// - cond: Condition checked before each iteration
 /// - update: Expression evaluated at end of each iteration
-fn parse_for(input: &[Token]) -> IResult<&[Token], Stmt> {
+fn parse_for(input: &[Token]) -> PResult<'_, StmtId> {
     map(
         tuple((
             token(Token::For),
@@ -292,49 +620,97 @@ fn parse_for(input: &[Token]) -> IResult<&[Token], Stmt> {
                 token(Token::LParen),
                 tuple((
                     alt((
-                        map(parse_declaration, |s| Some(Box::new(s))),
-                        map(parse_expr_stmt, |s| Some(Box::new(s))),
                         map(token(Token::Semicolon), |_| None),
+                        map(declaration("after loop initializer"), Some),
+                        map(
+                            terminated(
+                                parse_expr,
+                                context("after loop initializer", token(Token::Semicolon)),
+                            ),
+                            |e| Some(alloc_stmt(Stmt::Expr(e))),
+                        ),
                     )),
-                    opt(terminated(parse_expr, token(Token::Semicolon))),
-                    opt(parse_expr),
+                    terminated(
+                        optional_before(Token::Semicolon, parse_expr),
+                        context("after loop condition", token(Token::Semicolon)),
+                    ),
+                    optional_before(Token::RParen, parse_expr),
                 )),
-                token(Token::RParen),
+                context("after loop header", token(Token::RParen)),
             ),
             parse_stmt,
         )),
-        |(_, (init, cond, update), body)| Stmt::For {
-            init,
-            cond,
-            update,
-            body: Box::new(body),
+        |(_, (init, cond, update), body)| {
+            alloc_stmt(Stmt::For {
+                init,
+                cond,
+                update,
+                body,
+            })
         },
     )(input)
 }
 
 /// Parse an expression statement: expr ;
-fn parse_expr_stmt(input: &[Token]) -> IResult<&[Token], Stmt> {
-    map(terminated(parse_expr, token(Token::Semicolon)), Stmt::Expr)(input)
+fn parse_expr_stmt(input: &[Token]) -> PResult<'_, StmtId> {
+    map(
+        terminated(
+            parse_expr,
+            context("after expression", token(Token::Semicolon)),
+        ),
+        |expr| alloc_stmt(Stmt::Expr(expr)),
+    )(input)
 }
 
 /// Parse a statement
-fn parse_stmt(input: &[Token]) -> IResult<&[Token], Stmt> {
-    alt((
-        parse_declaration,
-        parse_return,
-        parse_if,
-        parse_for,
-        parse_block,
-        parse_expr_stmt,
-    ))(input)
+fn parse_stmt(input: &[Token]) -> PResult<'_, StmtId> {
+    let _guard = DepthGuard::enter(input)?;
+    let recorded = STATEMENTS.with(|s| s.borrow().as_ref().map(Vec::len));
+    let mark = ARENA.with(|arena| arena.borrow().mark());
+    let result = expecting(
+        "statement",
+        alt((
+            parse_declaration,
+            parse_return,
+            parse_if,
+            parse_for,
+            parse_block,
+            parse_expr_stmt,
+            map(token(Token::Semicolon), |_| alloc_stmt(Stmt::Empty)),
+        )),
+    )(input);
+    // Alternatives that fail after allocating, such as a declaration whose
+    // initializer is cut short, leave their nodes behind. The alternatives
+    // of a statement and of an expression are told apart by their first
+    // token, so none of them succeeds after another allocated, and dropping
+    // what a failed statement allocated leaves no unreachable node
+    if result.is_err() {
+        ARENA.with(|arena| arena.borrow_mut().truncate(mark));
+    }
+    // Statements are recorded once complete, so nested ones come first;
+    // those of a statement that failed to parse are dropped
+    if let Some(recorded) = recorded {
+        STATEMENTS.with(|s| {
+            if let Some(starts) = s.borrow_mut().as_mut() {
+                match result {
+                    Ok(_) => starts.push(input.len()),
+                    Err(_) => starts.truncate(recorded),
+                }
+            }
+        });
+    }
+    result
 }
 
 /// Parse a function parameter: type identifier
-fn parse_param(input: &[Token]) -> IResult<&[Token], (Type, String)> {
-    tuple((parse_type, parse_identifier))(input)
+fn parse_param(input: &[Token]) -> PResult<'_, (Type, String)> {
+    map(tuple((parse_type, parse_identifier)), |(ty, name)| {
+        (ty, name.to_string())
+    })(input)
 }
 
-fn parse_extern_param_list(input: &[Token]) -> IResult<&[Token], (Vec<Type>, bool)> {
+/// Parse extern parameter types, optionally ending in `...`
+fn parse_extern_param_list(input: &[Token]) -> PResult<'_, (Vec<Type>, bool)> {
     let mut types = vec![];
     let mut input = input;
     loop {
@@ -350,13 +726,15 @@ fn parse_extern_param_list(input: &[Token]) -> IResult<&[Token], (Vec<Type>, boo
         } else if let Some(&Token::Ellipsis) = input.first() {
             return Ok((&input[1..], (types, true)));
         } else {
-            return Err(nom::Err::Error(Error::new(input, ErrorKind::Tag)));
+            let mut err = SyntaxError::expected(input, Expected::Construct("type"));
+            err.expected.push(Expected::Token(Token::Ellipsis));
+            return Err(nom::Err::Error(err));
         }
     }
 }
 
 /// Parse an extern function: extern type identifier(types ...); or extern type identifier(types);
-fn parse_extern_function(input: &[Token]) -> IResult<&[Token], ExternFunction> {
+fn parse_extern_function(input: &[Token]) -> PResult<'_, ExternFunction> {
     map(
         tuple((
             token(Token::Extern),
@@ -365,11 +743,11 @@ fn parse_extern_function(input: &[Token]) -> IResult<&[Token], ExternFunction> {
             token(Token::LParen),
             parse_extern_param_list,
             token(Token::RParen),
-            token(Token::Semicolon),
+            context("after extern declaration", token(Token::Semicolon)),
         )),
         |(_, return_ty, name, _, (param_types, is_variadic), _, _)| ExternFunction {
             return_ty,
-            name,
+            name: name.to_string(),
             param_types,
             is_variadic,
         },
@@ -377,86 +755,495 @@ fn parse_extern_function(input: &[Token]) -> IResult<&[Token], ExternFunction> {
 }
 
 /// Parse an include directive token and return header name
-fn parse_include(input: &[Token]) -> IResult<&[Token], String> {
-    if input.is_empty() {
-        return Err(nom::Err::Error(Error::new(input, ErrorKind::Eof)));
+fn parse_include(input: &[Token]) -> PResult<'_, String> {
+    match input.first() {
+        Some(Token::Include(name)) => Ok((&input[1..], name.clone())),
+        _ => Err(nom::Err::Error(SyntaxError::expected(
+            input,
+            Expected::Construct("#include"),
+        ))),
     }
-    match &input[0] {
-        Token::Include(name) => Ok((&input[1..], name.clone())),
-        _ => Err(nom::Err::Error(Error::new(input, ErrorKind::Tag))),
+}
+
+/// Parse an import directive token and return the imported file
+fn parse_import(input: &[Token]) -> PResult<'_, String> {
+    match input.first() {
+        Some(Token::LocalInclude(file)) => Ok((&input[1..], file.clone())),
+        _ => Err(nom::Err::Error(SyntaxError::expected(
+            input,
+            Expected::Construct("#include"),
+        ))),
     }
 }
 
-/// Parse a top-level item: include, extern function or function definition
-fn parse_top_level(input: &[Token]) -> IResult<&[Token], TopLevel> {
-    alt((
-        map(parse_include, TopLevel::Include),
-        map(parse_extern_function, TopLevel::Extern),
-        map(parse_function, TopLevel::Function),
-    ))(input)
+/// Parse a top-level item: include, import, extern function or function
+/// definition
+fn parse_top_level(input: &[Token]) -> PResult<'_, TopLevel> {
+    expecting(
+        "declaration",
+        alt((
+            map(parse_include, TopLevel::Include),
+            map(parse_import, TopLevel::Import),
+            map(parse_extern_function, TopLevel::Extern),
+            map(parse_function, TopLevel::Function),
+        )),
+    )(input)
 }
 
 /// Parse a function: type identifier(params) { body }
-fn parse_function(input: &[Token]) -> IResult<&[Token], Function> {
+fn parse_function(input: &[Token]) -> PResult<'_, Function> {
+    // Each function starts a new arena, and any nodes left from an earlier
+    // item that failed are dropped
+    take_arena();
     map(
         tuple((
             parse_type,
             parse_identifier,
-            delimited(
-                token(Token::LParen),
-                separated_list0(token(Token::Comma), parse_param),
-                token(Token::RParen),
-            ),
+            preceded(token(Token::LParen), list(parse_param, Token::RParen)),
             parse_block,
         )),
         |(return_ty, name, params, body)| Function {
             return_ty,
-            name,
+            name: name.to_string(),
             params,
             body,
+            arena: take_arena(),
         },
     )(input)
 }
 
+/// Parse a single expression, such as `1 + 2 * 3`, making up all of `tokens`
+pub fn parse_expression(
+    tokens: &[Token],
+    options: &CompileOptions,
+) -> Result<ExprTree, ParseError> {
+    parse_tree(tokens, options, parse_expr)
+}
+
+/// Parse a single statement, such as `int x = 1;`, making up all of `tokens`
+pub fn parse_statement(tokens: &[Token], options: &CompileOptions) -> Result<StmtTree, ParseError> {
+    parse_tree(tokens, options, parse_stmt)
+}
+
+/// Parse a type name, such as `float`, making up all of `tokens`
+pub fn parse_type_name(tokens: &[Token], options: &CompileOptions) -> Result<Type, ParseError> {
+    parse_fragment(tokens, options, parse_type)
+}
+
+/// Parse a function definition, such as `int f(int x) { return x; }`,
+/// making up all of `tokens`
+pub fn parse_function_definition(
+    tokens: &[Token],
+    options: &CompileOptions,
+) -> Result<Function, ParseError> {
+    parse_fragment(tokens, options, parse_function)
+}
+
+/// Lex and parse an expression
+pub fn parse_expr_str(source: &str) -> Result<ExprTree, CompileError> {
+    parse_str(source, parse_expression)
+}
+
+/// Lex and parse a statement
+pub fn parse_stmt_str(source: &str) -> Result<StmtTree, CompileError> {
+    parse_str(source, parse_statement)
+}
+
+/// Lex and parse a type name
+pub fn parse_type_str(source: &str) -> Result<Type, CompileError> {
+    parse_str(source, parse_type_name)
+}
+
+/// Lex and parse a function definition
+pub fn parse_function_str(source: &str) -> Result<Function, CompileError> {
+    parse_str(source, parse_function_definition)
+}
+
+/// Lexes `source` and parses it with `parse`, locating errors in the source.
+fn parse_str<T>(
+    source: &str,
+    parse: impl Fn(&[Token], &CompileOptions) -> Result<T, ParseError>,
+) -> Result<T, CompileError> {
+    let (tokens, spans): (Vec<_>, Vec<_>) = lex_with_spans(source)?.into_iter().unzip();
+    parse(&tokens, &CompileOptions::default()).map_err(|e| locate_error(e, source, &spans))
+}
+
+/// Converts a parse error into a [`CompileError`] pointing at the offending
+/// token, or the end of input, given the spans of the tokens.
+pub(crate) fn locate_error(e: ParseError, source: &str, spans: &[Span]) -> CompileError {
+    let span = e.position().map(|i| {
+        spans
+            .get(i)
+            .copied()
+            .unwrap_or(Span::new(source.len(), source.len()))
+    });
+    CompileError::Parse(e, span)
+}
+
+/// Parses an expression or statement fragment with [`parse_fragment`],
+/// returning it with the arena its nodes were allocated in.
+fn parse_tree<'a, Id>(
+    tokens: &'a [Token],
+    options: &CompileOptions,
+    parser: impl Fn(&'a [Token]) -> PResult<'a, Id>,
+) -> Result<Tree<Id>, ParseError> {
+    take_arena();
+    let root = parse_fragment(tokens, options, parser);
+    let arena = take_arena();
+    root.map(|root| Tree { arena, root })
+}
+
+/// Runs `parser` on the whole of `tokens`, enforcing the size and nesting
+/// limits of the options.
+fn parse_fragment<'a, T>(
+    tokens: &'a [Token],
+    options: &CompileOptions,
+    parser: impl Fn(&'a [Token]) -> PResult<'a, T>,
+) -> Result<T, ParseError> {
+    start_parse(tokens, options)?;
+    let (rest, value) = parser(tokens).map_err(|e| parse_error(tokens, options, e))?;
+    if !rest.is_empty() {
+        let error = SyntaxError::from_error_kind(rest, ErrorKind::Eof);
+        return Err(parse_error(tokens, options, nom::Err::Error(error)));
+    }
+    Ok(value)
+}
+
+/// Checks the size limit of the options and resets the nesting depth and
+/// operator chain.
+fn start_parse(tokens: &[Token], options: &CompileOptions) -> Result<(), ParseError> {
+    if tokens.len() > options.max_tokens() {
+        return Err(ParseError::TooLarge {
+            limit: options.max_tokens(),
+            size: tokens.len(),
+        });
+    }
+    DEPTH.with(|cell| cell.set((0, options.max_depth())));
+    CHAIN.with(|cell| cell.set((0, options.max_chain())));
+    Ok(())
+}
+
+/// Converts an error of the parser combinators into a [`ParseError`] with a
+/// position in `tokens`.
+fn parse_error(tokens: &[Token], options: &CompileOptions, e: nom::Err<SyntaxError>) -> ParseError {
+    match e {
+        nom::Err::Failure(err) if err.kind == ErrorKind::TooLarge => ParseError::TooDeep {
+            limit: options.max_depth(),
+            position: tokens.len() - err.input.len(),
+        },
+        nom::Err::Failure(err) if err.kind == ErrorKind::Count => ParseError::TooLong {
+            limit: options.max_chain(),
+            position: tokens.len() - err.input.len(),
+        },
+        nom::Err::Error(err) | nom::Err::Failure(err) => ParseError::Syntax {
+            message: err.message(),
+            position: Some(tokens.len() - err.input.len()),
+            hint: None,
+        },
+        nom::Err::Incomplete(_) => ParseError::Syntax {
+            message: "incomplete input".to_string(),
+            position: None,
+            hint: None,
+        },
+    }
+}
+
 /// Parse the program: extern functions and functions
-pub fn parse(tokens: &[Token]) -> Result<Program, String> {
-    let (remaining, items) =
-        many0(parse_top_level)(tokens).map_err(|e| format!("Parse error: {:?}", e))?;
-    if !remaining.is_empty() {
-        return Err(format!("Unexpected tokens at end: {:?}", remaining));
+pub fn parse(tokens: &[Token]) -> Result<Program, ParseError> {
+    parse_with_options(tokens, &CompileOptions::default())
+}
+
+/// Parse the program, also returning the token index at which each statement
+/// starts.
+///
+/// Statements are listed in the order they finish parsing: nested statements
+/// before the statement containing them, as enumerated by
+/// [`coverage::statements`](crate::coverage::statements). Function bodies and
+/// the initializers of `for` loops are not listed.
+pub fn parse_with_statement_positions(
+    tokens: &[Token],
+    options: &CompileOptions,
+) -> Result<(Program, Vec<usize>), ParseError> {
+    STATEMENTS.with(|s| *s.borrow_mut() = Some(Vec::new()));
+    let result = parse_with_options(tokens, options);
+    let starts = STATEMENTS
+        .with(|s| s.borrow_mut().take())
+        .unwrap_or_default();
+    let positions = starts.iter().map(|rest| tokens.len() - rest).collect();
+    result.map(|program| (program, positions))
+}
+
+/// Parse the program, enforcing the size and nesting limits of the options
+pub fn parse_with_options(
+    tokens: &[Token],
+    options: &CompileOptions,
+) -> Result<Program, ParseError> {
+    let items = parse_items(tokens, 0, options)?;
+    Ok(assemble(items.into_iter().map(|(_, item)| item)))
+}
+
+/// Parse a program from a stream of tokens, one top-level item at a time.
+///
+/// The result is the same as lexing the whole source and calling
+/// [`parse_with_options`], with errors located in the source. Exceeding the
+/// token limit still lexes the rest of the input to report its size, but
+/// without keeping the tokens.
+pub fn parse_stream(
+    mut tokens: TokenStream,
+    options: &CompileOptions,
+) -> Result<Program, CompileError> {
+    let mut items = Vec::new();
+    let mut buffer = Vec::new();
+    let mut spans = Vec::new();
+    let mut count = 0;
+    // Nesting of braces in the buffered tokens
+    let mut depth = 0usize;
+    while let Some(token) = tokens.next() {
+        let (token, span) = token?;
+        count += 1;
+        if count > options.max_tokens() {
+            for token in tokens {
+                token?;
+                count += 1;
+            }
+            return Err(CompileError::Parse(
+                ParseError::TooLarge {
+                    limit: options.max_tokens(),
+                    size: count,
+                },
+                None,
+            ));
+        }
+        let ends_item = match &token {
+            Token::Include(_) | Token::LocalInclude(_) | Token::Semicolon => depth == 0,
+            Token::LBrace => {
+                depth += 1;
+                false
+            }
+            Token::RBrace => {
+                depth = depth.saturating_sub(1);
+                depth == 0
+            }
+            _ => false,
+        };
+        buffer.push(token);
+        spans.push(span);
+        if ends_item {
+            items.extend(parse_buffered(&buffer, &spans, tokens.span(), options)?);
+            buffer.clear();
+            spans.clear();
+        }
+    }
+    if !buffer.is_empty() {
+        items.extend(parse_buffered(&buffer, &spans, tokens.span(), options)?);
     }
+    Ok(assemble(items))
+}
+
+/// Parses the top-level items of buffered tokens, locating errors through
+/// their spans, or at `end` past the last token.
+fn parse_buffered(
+    tokens: &[Token],
+    spans: &[Span],
+    end: Span,
+    options: &CompileOptions,
+) -> Result<Vec<TopLevel>, CompileError> {
+    match parse_items(tokens, 0, options) {
+        Ok(items) => Ok(items.into_iter().map(|(_, item)| item).collect()),
+        Err(e) => {
+            let span = e.position().map(|i| spans.get(i).copied().unwrap_or(end));
+            Err(CompileError::Parse(e, span))
+        }
+    }
+}
+
+/// Parse the top-level items of `tokens[start..]`, returning each with the
+/// range of its tokens. Positions are indices into `tokens`.
+pub(crate) fn parse_items(
+    tokens: &[Token],
+    start: usize,
+    options: &CompileOptions,
+) -> Result<Vec<(Range<usize>, TopLevel)>, ParseError> {
+    start_parse(tokens, options)?;
+    let mut items = Vec::new();
+    let mut input = &tokens[start..];
+    while !input.is_empty() {
+        // Stray semicolons between items, e.g. after a function, are ignored
+        if input[0] == Token::Semicolon {
+            input = &input[1..];
+            continue;
+        }
+        let (rest, item) = parse_top_level(input).map_err(|e| {
+            let mut error = parse_error(tokens, options, e);
+            if let ParseError::Syntax {
+                position: Some(position),
+                hint,
+                ..
+            } = &mut error
+            {
+                *hint = top_level_hint(input, *position - (tokens.len() - input.len()));
+            }
+            error
+        })?;
+        let range = tokens.len() - input.len()..tokens.len() - rest.len();
+        items.push((range, item));
+        input = rest;
+    }
+    Ok(items)
+}
+
+/// Explains a failure to parse the top-level item starting at `item`, at
+/// the token `offset` tokens into it, when it is a common mistake.
+fn top_level_hint(item: &[Token], offset: usize) -> Option<String> {
+    match item {
+        [
+            Token::Int | Token::Float | Token::StringType,
+            Token::Identifier(name),
+            next,
+            ..,
+        ] if offset == 2 && matches!(next, Token::Assign | Token::Semicolon) => Some(format!(
+            "global variables are not supported; declare '{}' inside a function",
+            name
+        )),
+        [Token::RBrace, ..] if offset == 0 => {
+            Some("this '}' does not close any '{'; check the braces above it".to_string())
+        }
+        [
+            Token::Return
+            | Token::If
+            | Token::Else
+            | Token::For
+            | Token::Identifier(_)
+            | Token::LBrace,
+            ..,
+        ] if offset == 0 => Some("statements must be inside a function body".to_string()),
+        [_, ..] if offset == 0 => Some(
+            "only '#include' directives, extern declarations, and function definitions \
+             may appear at the top level"
+                .to_string(),
+        ),
+        _ => None,
+    }
+}
+
+/// Assembles a program from its top-level items, declaring the extern
+/// functions of the included headers that it calls.
+pub(crate) fn assemble(items: impl IntoIterator<Item = TopLevel>) -> Program {
     let mut includes = Vec::new();
+    let mut imports = Vec::new();
     let mut extern_functions = Vec::new();
     let mut functions = Vec::new();
     for item in items {
         match item {
             TopLevel::Include(h) => includes.push(h),
+            TopLevel::Import(file) => imports.push(file),
             TopLevel::Extern(e) => extern_functions.push(e),
             TopLevel::Function(f) => functions.push(f),
         }
     }
 
-    // Map includes to externs using registry
-    for header in &includes {
-        for ext in crate::header_registry::externs_for_header(header) {
-            if !extern_functions.iter().any(|e| e.name == ext.name) {
-                extern_functions.push(ext);
-            }
-        }
-    }
-
-    Ok(Program {
+    let mut program = Program {
         includes,
+        imports,
         extern_functions,
         functions,
-    })
+    };
+    crate::header_registry::declare_used_externs(&mut program);
+    program
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::builder::expr::{binary, int};
+    use crate::builder::{expr, stmt};
     use crate::lexer::lex;
 
+    #[test]
+    fn test_parse_stream() {
+        let source = "#include <stdio.h>
+extern int abs(int);
+int f(int x) { if (x) { return abs(x); } return 0; }
+int main() { return f(1); }
+";
+        let streamed = parse_stream(TokenStream::new(source), &CompileOptions::default());
+        assert_eq!(streamed.unwrap(), parse(&lex(source).unwrap()).unwrap());
+
+        // Errors are located in the source, including at its end
+        let source = "int f() { return 1; }\nint g() { return 2 }\n";
+        let Err(CompileError::Parse(_, Some(span))) =
+            parse_stream(TokenStream::new(source), &CompileOptions::default())
+        else {
+            panic!("expected a located parse error");
+        };
+        assert_eq!(span.start, source.find("2 }").unwrap() + 2);
+        let Err(CompileError::Parse(_, Some(span))) =
+            parse_stream(TokenStream::new("int f() {"), &CompileOptions::default())
+        else {
+            panic!("expected a located parse error");
+        };
+        assert_eq!(span, Span::new(9, 9));
+
+        let options = CompileOptions {
+            max_tokens: Some(3),
+            ..CompileOptions::default()
+        };
+        assert!(matches!(
+            parse_stream(TokenStream::new("int main() { return 0; }"), &options),
+            Err(CompileError::Parse(
+                ParseError::TooLarge { limit: 3, size: 9 },
+                None
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_adjacent_string_literals() {
+        let call = parse_expr_str(
+            r#"printf("%ld, "
+                                          "%ld\n" "", x)"#,
+        )
+        .unwrap();
+        let Expr::Call { args, .. } = call.get() else {
+            panic!("expected a call");
+        };
+        assert_eq!(
+            call.arena[args[0]],
+            Expr::Literal(Literal::String("%ld, %ld\n".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_expression() {
+        let options = CompileOptions::default();
+        let parsed = parse_expression(&lex("1 + 2 * x").unwrap(), &options).unwrap();
+        assert_eq!(
+            parsed,
+            expr::add(expr::int(1), expr::mul(expr::int(2), expr::ident("x")))
+        );
+        // The expression must make up the whole input
+        let error = parse_expression(&lex("1 + 2 3").unwrap(), &options).unwrap_err();
+        assert_eq!(error.position(), Some(3));
+        assert!(parse_expression(&[], &options).is_err());
+    }
+
+    #[test]
+    fn test_parse_fragments_from_source() {
+        assert_eq!(parse_type_str("float"), Ok(Type::Float));
+        assert_eq!(
+            parse_stmt_str("int x = 1;"),
+            Ok(stmt::decl(Type::Int, "x", expr::int(1)))
+        );
+        assert_eq!(parse_expr_str("f(2)"), Ok(expr::call("f", [expr::int(2)])));
+
+        // Errors point into the fragment
+        let Err(CompileError::Parse(_, Some(span))) = parse_stmt_str("return 1; x") else {
+            panic!("expected a located parse error");
+        };
+        assert_eq!(span, Span::new(10, 11));
+        assert!(matches!(parse_type_str("@"), Err(CompileError::Lex(_))));
+    }
+
     #[test]
     fn test_parse_function() {
         let tokens =
@@ -471,15 +1258,12 @@ mod tests {
             vec![(Type::Int, "a".to_string()), (Type::Int, "b".to_string())]
         );
         // Check body
-        if let Stmt::Block(stmts) = &func.body {
+        if let Stmt::Block(stmts) = func.body() {
             assert_eq!(stmts.len(), 1);
-            if let Stmt::Return(Some(Expr::Binary { left, op, right })) = &stmts[0] {
-                assert_eq!(**left, Expr::Identifier("a".to_string()));
-                assert_eq!(*op, BinOp::Plus);
-                assert_eq!(**right, Expr::Identifier("b".to_string()));
-            } else {
-                panic!("Expected return a + b");
-            }
+            assert_eq!(
+                func.arena.node(stmts[0]),
+                stmt::ret(expr::add(expr::ident("a"), expr::ident("b"))).node()
+            );
         } else {
             panic!("Expected block");
         }
@@ -490,6 +1274,18 @@ mod tests {
     }
 
     #[test]
+    fn test_parse_leaves_no_unreachable_nodes() {
+        let func = parse_function_str(
+            "int main() { int x = 1 + 2; for (x = 0; x < 3; x = x + 1) ; return x; }",
+        )
+        .unwrap();
+        // 1, 2, 1 + 2, x, 0, x = 0, x, 3, x < 3, x, x, 1, x + 1, x = x + 1, x
+        // and the declaration, initializer, loop, body, return and block
+        assert_eq!(func.arena.len(), 15 + 6);
+    }
+
+    #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_parse_extern_function() {
         let tokens = lex("extern int printf(int, int); int main() { return 0; }").unwrap();
         let ast = parse(&tokens).unwrap();
@@ -503,6 +1299,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_parse_extern_function_variadic() {
         let tokens = lex("extern int printf(int, ...); int main() { return 0; }").unwrap();
         let ast = parse(&tokens).unwrap();
@@ -513,6 +1310,317 @@ mod tests {
         assert_eq!(extern_func.is_variadic, true);
     }
 
+    /// Parses `int main() { return <expr>; }` and returns the expression.
+    fn parse_return_expr(expr: &str) -> ExprTree {
+        let tokens = lex(&format!("int main() {{ return {}; }}", expr)).unwrap();
+        let function = parse(&tokens).unwrap().functions.remove(0);
+        let root = match function.body() {
+            Stmt::Block(stmts) => match &function.arena[stmts[0]] {
+                Stmt::Return(Some(expr)) => *expr,
+                other => panic!("Expected return, got {:?}", other),
+            },
+            other => panic!("Expected block, got {:?}", other),
+        };
+        Tree {
+            arena: function.arena,
+            root,
+        }
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        // == binds looser than <, which binds looser than +, then *
+        assert_eq!(
+            parse_return_expr("1 == 2 < 3 + 4 * 5"),
+            binary(
+                int(1),
+                BinOp::Equal,
+                binary(
+                    int(2),
+                    BinOp::LessThan,
+                    binary(int(3), BinOp::Plus, binary(int(4), BinOp::Multiply, int(5)))
+                )
+            )
+        );
+        assert_eq!(
+            parse_return_expr("(1 - 2) - 3"),
+            parse_return_expr("1 - 2 - 3")
+        );
+    }
+
+    #[test]
+    fn test_parse_comparisons_are_left_associative() {
+        let id = expr::ident;
+        // Relational operators bind tighter than +'s operands but chain left
+        assert_eq!(
+            parse_return_expr("a < b + c < d"),
+            binary(
+                binary(
+                    id("a"),
+                    BinOp::LessThan,
+                    binary(id("b"), BinOp::Plus, id("c"))
+                ),
+                BinOp::LessThan,
+                id("d")
+            )
+        );
+        assert_eq!(
+            parse_return_expr("a == b != c"),
+            binary(
+                binary(id("a"), BinOp::Equal, id("b")),
+                BinOp::NotEqual,
+                id("c")
+            )
+        );
+        // Equality binds looser than relational on both sides
+        assert_eq!(
+            parse_return_expr("a < b == c >= d"),
+            binary(
+                binary(id("a"), BinOp::LessThan, id("b")),
+                BinOp::Equal,
+                binary(id("c"), BinOp::GreaterEqual, id("d"))
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_assignment_is_right_associative() {
+        assert_eq!(
+            parse_return_expr("a = b = 1 + 2"),
+            expr::assign(
+                expr::ident("a"),
+                expr::assign(expr::ident("b"), binary(int(1), BinOp::Plus, int(2)))
+            )
+        );
+        // Any expression parses as a target; semantic analysis rejects non-lvalues
+        assert_eq!(parse_return_expr("1 = 2"), expr::assign(int(1), int(2)));
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        let tokens = lex("int main() { return 0; } }").unwrap();
+        let err = parse(&tokens).unwrap_err();
+        assert_eq!(err.position(), Some(9));
+    }
+
+    #[test]
+    fn test_parse_error_messages() {
+        let message = |source: &str| match parse(&lex(source).unwrap()) {
+            Err(ParseError::Syntax { message, .. }) => message,
+            other => panic!("Expected syntax error, got {:?}", other),
+        };
+        assert_eq!(
+            message("int main() { x = 1 }"),
+            "expected ';' after expression, found '}'"
+        );
+        assert_eq!(
+            message("int main() { return 0 }"),
+            "expected ';' after return statement, found '}'"
+        );
+        assert_eq!(
+            message("int main() { int x = 1 + ; return x; }"),
+            "expected expression, found ';'"
+        );
+        assert_eq!(
+            message("int main() { f(1, ); }"),
+            "expected expression, found ')'"
+        );
+        assert_eq!(
+            message("int main() { if (1) return 0; else }"),
+            "expected statement, found '}'"
+        );
+        assert_eq!(
+            message("int main() { return 0;"),
+            "expected '}' or statement, found end of input"
+        );
+        assert_eq!(
+            message("int main() { return 0; } }"),
+            "expected declaration, found '}'"
+        );
+    }
+
+    #[test]
+    fn test_top_level_hints() {
+        let hint = |source: &str| match parse(&lex(source).unwrap()) {
+            Err(ParseError::Syntax { hint, .. }) => hint,
+            other => panic!("Expected syntax error, got {:?}", other),
+        };
+        assert_eq!(
+            hint("int main() { return 0; }\nint count = 0;").as_deref(),
+            Some("global variables are not supported; declare 'count' inside a function")
+        );
+        assert!(
+            hint("int main() { return 0; } }")
+                .unwrap()
+                .contains("does not close")
+        );
+        assert_eq!(
+            hint("x = 1;").as_deref(),
+            Some("statements must be inside a function body")
+        );
+        assert!(
+            hint("42;")
+                .unwrap()
+                .starts_with("only '#include' directives")
+        );
+        // Errors inside an item are precise enough on their own
+        assert_eq!(hint("int main() { return 0 }"), None);
+    }
+
+    #[test]
+    fn test_parse_null_statement() {
+        assert_eq!(
+            parse_stmt_str("if (x) ; else ;"),
+            Ok(stmt::if_(expr::ident("x"), stmt::empty(), stmt::empty()))
+        );
+        assert_eq!(
+            parse_stmt_str("{ ; ; }"),
+            Ok(stmt::block([stmt::empty(), stmt::empty()]))
+        );
+        assert_eq!(
+            parse_stmt_str("for (;;);"),
+            Ok(stmt::for_(None, None, None, stmt::empty()))
+        );
+
+        // Stray semicolons between top-level items are ignored
+        let program =
+            parse(&lex("; int f() { return 1; }; ; int main() { return f(); };").unwrap()).unwrap();
+        assert_eq!(program.functions.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_for_clauses() {
+        let init = || stmt::decl(Type::Int, "i", expr::int(0));
+        let cond = || expr::lt(expr::ident("i"), expr::int(3));
+        let update = || expr::assign(expr::ident("i"), expr::int(1));
+        // Every combination of present and omitted clauses
+        for mask in 0..8 {
+            let (has_init, has_cond, has_update) = (mask & 1 != 0, mask & 2 != 0, mask & 4 != 0);
+            let source = format!(
+                "for ({}; {}; {}) {{}}",
+                if has_init { "int i = 0" } else { "" },
+                if has_cond { "i < 3" } else { "" },
+                if has_update { "i = 1" } else { "" },
+            );
+            assert_eq!(
+                parse_stmt_str(&source),
+                Ok(stmt::for_(
+                    has_init.then(init),
+                    has_cond.then(cond),
+                    has_update.then(update),
+                    stmt::block([]),
+                )),
+                "{}",
+                source
+            );
+        }
+        // The initializer may also be an expression
+        assert_eq!(
+            parse_stmt_str("for (i = 1;;) {}"),
+            Ok(stmt::for_(
+                stmt::expr(update()),
+                None,
+                None,
+                stmt::block([])
+            ))
+        );
+
+        let message = |source: &str| match parse_stmt_str(source) {
+            Err(CompileError::Parse(ParseError::Syntax { message, .. }, _)) => message,
+            other => panic!("Expected syntax error, got {:?}", other),
+        };
+        assert_eq!(
+            message("for (int i = 0 i < 3;) {}"),
+            "expected ';' after loop initializer, found 'i'"
+        );
+        assert_eq!(
+            message("for (i = 0, i < 3;) {}"),
+            "expected ';' after loop initializer, found ','"
+        );
+        assert_eq!(
+            message("for (; i < 3) {}"),
+            "expected ';' after loop condition, found ')'"
+        );
+        assert_eq!(
+            message("for (;; i = 1;) {}"),
+            "expected ')' after loop header, found ';'"
+        );
+        assert_eq!(message("for (;; i =) {}"), "expected expression, found ')'");
+    }
+
+    #[test]
+    fn test_parse_nesting_limit() {
+        let nested = |depth: usize| {
+            format!(
+                "int main() {{ return {}1{}; }}",
+                "(".repeat(depth),
+                ")".repeat(depth)
+            )
+        };
+        let tokens = lex(&nested(200)).unwrap();
+        assert!(parse(&tokens).is_ok());
+
+        let tokens = lex(&nested(100_000)).unwrap();
+        let err = parse(&tokens).unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::TooDeep {
+                limit: DEFAULT_MAX_DEPTH,
+                ..
+            }
+        ));
+
+        // Blocks nest too, and the limit is configurable
+        let tokens = lex("int main() { { { { return 0; } } } }").unwrap();
+        let options = CompileOptions {
+            max_depth: Some(3),
+            ..CompileOptions::default()
+        };
+        assert!(matches!(
+            parse_with_options(&tokens, &options),
+            Err(ParseError::TooDeep { limit: 3, .. })
+        ));
+        assert!(parse(&tokens).is_ok());
+
+        // Operator chains build deep trees without deep recursion, and have
+        // a limit of their own
+        let chain = |length: usize| format!("int main() {{ return 1{}; }}", " + 1".repeat(length));
+        let tokens = lex(&chain(300)).unwrap();
+        assert!(parse(&tokens).is_ok());
+        let tokens = lex(&chain(100_000)).unwrap();
+        assert!(matches!(
+            parse(&tokens),
+            Err(ParseError::TooLong {
+                limit: DEFAULT_MAX_CHAIN,
+                ..
+            })
+        ));
+        let options = CompileOptions {
+            max_chain: Some(2),
+            ..CompileOptions::default()
+        };
+        let tokens = lex("int main() { return 1 * 2 + 3; }").unwrap();
+        assert!(parse_with_options(&tokens, &options).is_ok());
+        let tokens = lex("int main() { return 1 * 2 + 3 - 4; }").unwrap();
+        assert!(matches!(
+            parse_with_options(&tokens, &options),
+            Err(ParseError::TooLong { limit: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_size_limit() {
+        let tokens = lex("int main() { return 0; }").unwrap();
+        let options = CompileOptions {
+            max_tokens: Some(4),
+            ..CompileOptions::default()
+        };
+        assert_eq!(
+            parse_with_options(&tokens, &options),
+            Err(ParseError::TooLarge { limit: 4, size: 9 })
+        );
+    }
+
     #[test]
     fn test_parse_include() {
         let tokens = lex("#include <stdio.h> int main() { return 0; }").unwrap();
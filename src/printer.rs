@@ -0,0 +1,365 @@
+//! # Pretty Printer
+//!
+//! This module turns an AST back into source code. Parsing the printed source
+//! yields the original AST, which makes the printer useful both for tools
+//! that generate programs and for round-trip testing of the parser.
+//!
+//! ## Parentheses
+//!
+//! Binary expressions are parenthesized only where C's precedence and
+//! associativity rules require it, so `(a + b) * c` keeps its parentheses
+//! while `a + b * c` is printed as is.
+//!
+//! ## Limitations
+//!
+//! A few ASTs have no source form: negative literals (the language has no
+//! unary minus) and an `if` whose then-branch ends in an `if` without an else
+//! (the else would bind to the inner `if`). Such ASTs are printed on a best
+//! effort basis and do not round-trip.
+
+use crate::ast::*;
+
+/// Prints a program as source code.
+pub fn print_program(program: &Program) -> String {
+    let mut printer = Printer::default();
+    for header in &program.includes {
+        printer.line(&format!("#include <{}>", header));
+    }
+    for file in &program.imports {
+        printer.line(&format!("#include \"{}\"", file));
+    }
+    // Externs implied by includes are printed too: the parser only adds
+    // them when missing, so printing them keeps their order intact
+    for ext in &program.extern_functions {
+        printer.line(&print_extern(ext));
+    }
+    for function in &program.functions {
+        printer.function(function);
+    }
+    printer.out
+}
+
+/// Prints the expression `expr` of an arena as source code.
+pub fn print_expr(arena: &Arena, expr: ExprId) -> String {
+    match &arena[expr] {
+        Expr::Literal(literal) => print_literal(literal),
+        Expr::Identifier(name) => name.to_string(),
+        Expr::Binary { left, op, right } => {
+            let precedence = precedence(*op);
+            // Operators are left-associative: a right operand at the same
+            // level needs parentheses, a left one does not
+            format!(
+                "{} {} {}",
+                parenthesize(arena, *left, precedence),
+                print_binop(*op),
+                parenthesize(arena, *right, precedence + 1)
+            )
+        }
+        Expr::Call { name, args } => {
+            let args: Vec<String> = args.iter().map(|arg| print_expr(arena, *arg)).collect();
+            format!("{}({})", name, args.join(", "))
+        }
+        Expr::Assignment { target, value } => {
+            // Assignment is right-associative and binds loosest
+            format!(
+                "{} = {}",
+                parenthesize(arena, *target, ASSIGNMENT_PRECEDENCE + 1),
+                print_expr(arena, *value)
+            )
+        }
+    }
+}
+
+/// Precedence of assignment, below every binary operator.
+const ASSIGNMENT_PRECEDENCE: u8 = 1;
+
+/// Precedence of a binary operator, matching the parser's table.
+fn precedence(op: BinOp) -> u8 {
+    match op {
+        BinOp::Equal | BinOp::NotEqual => 2,
+        BinOp::LessThan | BinOp::GreaterThan | BinOp::LessEqual | BinOp::GreaterEqual => 3,
+        BinOp::Plus | BinOp::Minus => 4,
+        BinOp::Multiply | BinOp::Divide => 5,
+    }
+}
+
+/// Prints an operand, parenthesized if it binds looser than `min`.
+fn parenthesize(arena: &Arena, expr: ExprId, min: u8) -> String {
+    let own = match &arena[expr] {
+        Expr::Binary { op, .. } => precedence(*op),
+        Expr::Assignment { .. } => ASSIGNMENT_PRECEDENCE,
+        _ => u8::MAX,
+    };
+    if own < min {
+        format!("({})", print_expr(arena, expr))
+    } else {
+        print_expr(arena, expr)
+    }
+}
+
+pub(crate) fn print_binop(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Plus => "+",
+        BinOp::Minus => "-",
+        BinOp::Multiply => "*",
+        BinOp::Divide => "/",
+        BinOp::Equal => "==",
+        BinOp::NotEqual => "!=",
+        BinOp::LessThan => "<",
+        BinOp::GreaterThan => ">",
+        BinOp::LessEqual => "<=",
+        BinOp::GreaterEqual => ">=",
+    }
+}
+
+pub(crate) fn print_literal(literal: &Literal) -> String {
+    match literal {
+        Literal::Int(n) => n.to_string(),
+        Literal::Float(f) => {
+            // The lexer requires digits on both sides of the point
+            let text = f.to_string();
+            if text.contains('.') {
+                text
+            } else {
+                format!("{}.0", text)
+            }
+        }
+        Literal::String(s) => {
+            let mut out = String::from("\"");
+            for c in s.chars() {
+                match c {
+                    '\n' => out.push_str("\\n"),
+                    '\t' => out.push_str("\\t"),
+                    '\r' => out.push_str("\\r"),
+                    '\0' => out.push_str("\\0"),
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    c if c.is_ascii_control() => out.push_str(&format!("\\x{:02x}", c as u32)),
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+            out
+        }
+    }
+}
+
+pub(crate) fn print_type(ty: Type) -> &'static str {
+    match ty {
+        Type::Int => "int",
+        Type::Float => "float",
+        Type::String => "string",
+    }
+}
+
+fn print_extern(ext: &ExternFunction) -> String {
+    let mut params: Vec<&str> = ext.param_types.iter().map(|ty| print_type(*ty)).collect();
+    if ext.is_variadic {
+        params.push("...");
+    }
+    format!(
+        "extern {} {}({});",
+        print_type(ext.return_ty),
+        ext.name,
+        params.join(", ")
+    )
+}
+
+/// Accumulates indented lines of output.
+#[derive(Default)]
+struct Printer {
+    out: String,
+    indent: usize,
+}
+
+impl Printer {
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn function(&mut self, function: &Function) {
+        let params: Vec<String> = function
+            .params
+            .iter()
+            .map(|(ty, name)| format!("{} {}", print_type(*ty), name))
+            .collect();
+        let header = format!(
+            "{} {}({})",
+            print_type(function.return_ty),
+            function.name,
+            params.join(", ")
+        );
+        self.stmt_with_header(&function.arena, &header, function.body);
+    }
+
+    /// Prints `header` followed by `body`, on one line if it is a block.
+    fn stmt_with_header(&mut self, arena: &Arena, header: &str, body: StmtId) {
+        match &arena[body] {
+            Stmt::Block(stmts) => {
+                self.line(&format!("{} {{", header));
+                self.block_body(arena, stmts);
+            }
+            _ => {
+                self.line(header);
+                self.indent += 1;
+                self.stmt(arena, body);
+                self.indent -= 1;
+            }
+        }
+    }
+
+    /// Prints the statements of a block and its closing brace.
+    fn block_body(&mut self, arena: &Arena, stmts: &[StmtId]) {
+        self.block_contents(arena, stmts);
+        self.line("}");
+    }
+
+    fn block_contents(&mut self, arena: &Arena, stmts: &[StmtId]) {
+        self.indent += 1;
+        for stmt in stmts {
+            self.stmt(arena, *stmt);
+        }
+        self.indent -= 1;
+    }
+
+    fn stmt(&mut self, arena: &Arena, stmt: StmtId) {
+        match &arena[stmt] {
+            Stmt::Block(stmts) => {
+                self.line("{");
+                self.block_body(arena, stmts);
+            }
+            Stmt::If { cond, then, else_ } => self.if_stmt(arena, "", *cond, *then, *else_),
+            Stmt::For {
+                init,
+                cond,
+                update,
+                body,
+            } => {
+                let mut header = match init {
+                    Some(init) => format!("for ({}", inline_stmt(arena, *init)),
+                    None => "for (;".to_string(),
+                };
+                if let Some(cond) = cond {
+                    header.push_str(&format!(" {}", print_expr(arena, *cond)));
+                }
+                header.push(';');
+                if let Some(update) = update {
+                    header.push_str(&format!(" {}", print_expr(arena, *update)));
+                }
+                header.push(')');
+                self.stmt_with_header(arena, &header, *body);
+            }
+            _ => self.line(&inline_stmt(arena, stmt)),
+        }
+    }
+
+    /// Prints an if statement whose first line starts with `prefix`, keeping
+    /// `} else {` and `} else if` chains on one line.
+    fn if_stmt(
+        &mut self,
+        arena: &Arena,
+        prefix: &str,
+        cond: ExprId,
+        then: StmtId,
+        else_: Option<StmtId>,
+    ) {
+        let header = format!("{}if ({})", prefix, print_expr(arena, cond));
+        match (&arena[then], else_) {
+            (Stmt::Block(stmts), Some(else_)) => {
+                self.line(&format!("{} {{", header));
+                self.block_contents(arena, stmts);
+                match &arena[else_] {
+                    Stmt::If { cond, then, else_ } => {
+                        self.if_stmt(arena, "} else ", *cond, *then, *else_)
+                    }
+                    _ => self.stmt_with_header(arena, "} else", else_),
+                }
+            }
+            _ => {
+                self.stmt_with_header(arena, &header, then);
+                if let Some(else_) = else_ {
+                    self.stmt_with_header(arena, "else", else_);
+                }
+            }
+        }
+    }
+}
+
+/// Prints a simple statement on a single line.
+fn inline_stmt(arena: &Arena, stmt: StmtId) -> String {
+    match &arena[stmt] {
+        Stmt::Declaration { ty, name, init } => match init {
+            Some(init) => format!(
+                "{} {} = {};",
+                print_type(*ty),
+                name,
+                print_expr(arena, *init)
+            ),
+            None => format!("{} {};", print_type(*ty), name),
+        },
+        Stmt::Return(Some(expr)) => format!("return {};", print_expr(arena, *expr)),
+        Stmt::Return(None) => "return;".to_string(),
+        Stmt::Expr(expr) => format!("{};", print_expr(arena, *expr)),
+        Stmt::Empty => ";".to_string(),
+        // Compound statements are not valid in a for-loop header
+        _ => {
+            let mut printer = Printer::default();
+            printer.stmt(arena, stmt);
+            printer.out.trim_end().to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::parse;
+
+    fn round_trip(source: &str) -> String {
+        let program = parse(&lex(source).unwrap()).unwrap();
+        let printed = print_program(&program);
+        assert_eq!(parse(&lex(&printed).unwrap()).unwrap(), program);
+        printed
+    }
+
+    #[test]
+    fn test_print_program() {
+        let printed = round_trip(
+            "#include <stdio.h> extern int abs(int); int main() { int x = 1; for (;;) x = x + 1; if (x) { return 0; } else return 1; }",
+        );
+        assert_eq!(
+            printed,
+            "#include <stdio.h>\nextern int abs(int);\nint main() {\n    int x = 1;\n    for (;;)\n        x = x + 1;\n    if (x) {\n        return 0;\n    } else\n        return 1;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_print_else_if_chain() {
+        let printed = round_trip(
+            "int f(int x) { for (int i = 0; i < x; i = i + 1) { if (i == 1) { return 1; } else if (i == 2) { return 2; } else { return 3; } } return 0; }",
+        );
+        assert!(printed.contains("    for (int i = 0; i < x; i = i + 1) {\n        if (i == 1) {\n            return 1;\n        } else if (i == 2) {\n            return 2;\n        } else {\n            return 3;\n        }\n    }\n"));
+    }
+
+    #[test]
+    fn test_print_minimal_parentheses() {
+        let printed = round_trip(
+            "int main() { return (1 + 2) * 3 - (4 - 5) + 6 * 7 + (a = b = 1) + (x < y) + (1 < 2 < 3); }",
+        );
+        assert!(printed.contains(
+            "return (1 + 2) * 3 - (4 - 5) + 6 * 7 + (a = b = 1) + (x < y) + (1 < 2 < 3);"
+        ));
+    }
+
+    #[test]
+    fn test_print_literals() {
+        let printed =
+            round_trip(r#"int main() { printf("a\n\t\"q\"\\ \x01", 2.5, 100.0); return 0; }"#);
+        assert!(printed.contains(r#"printf("a\n\t\"q\"\\ \x01", 2.5, 100.0);"#));
+    }
+}
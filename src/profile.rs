@@ -0,0 +1,94 @@
+//! # Profile-Guided Optimization
+//!
+//! Profile-guided optimization takes three steps, all handled by the C
+//! compiler and LLVM's tools:
+//!
+//! 1. Compile with [`CompileOptions::profile_generate`], so that the program
+//!    counts how often each branch and call runs, and writes the counts to a
+//!    `.profraw` file in the chosen directory when it exits
+//! 2. Run the program on representative input, then merge the raw profiles
+//!    with [`merge`] (`virtuc merge-profdata`)
+//! 3. Compile again with [`CompileOptions::profile_use`] naming the merged
+//!    profile, so that LLVM optimizes the paths that ran most
+//!
+//! The profiles are those of clang, so `llvm-profdata` must come from the
+//! same LLVM release as the C compiler.
+//!
+//! [`CompileOptions::profile_generate`]: crate::options::CompileOptions::profile_generate
+//! [`CompileOptions::profile_use`]: crate::options::CompileOptions::profile_use
+
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::process::Command;
+
+/// The tool that merges raw profiles, unless another is chosen.
+pub const DEFAULT_PROFDATA: &str = "llvm-profdata";
+
+/// Merges the raw profiles `inputs` into one profile at `output` with
+/// `llvm-profdata` at the path `tool`.
+///
+/// # Errors
+///
+/// Returns an error if the tool cannot be run, or fails; the error carries
+/// its output.
+pub fn merge(inputs: &[&Path], output: &Path, tool: &str) -> io::Result<()> {
+    let result = Command::new(tool)
+        .arg("merge")
+        .arg("-o")
+        .arg(output)
+        .args(inputs)
+        .output();
+    match result {
+        Ok(out) if out.status.success() => Ok(()),
+        Ok(out) => {
+            let mut diagnostics = String::from_utf8_lossy(&out.stderr).into_owned();
+            diagnostics.push_str(&String::from_utf8_lossy(&out.stdout));
+            Err(io::Error::other(format!(
+                "merging profiles with '{}' failed ({}):\n{}",
+                tool,
+                out.status,
+                diagnostics.trim_end()
+            )))
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Err(io::Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "'{}' not found; install the LLVM tools that match the C compiler, \
+                 or select another with --llvm-profdata <path>",
+                tool
+            ),
+        )),
+        Err(e) => Err(io::Error::new(
+            e.kind(),
+            format!("cannot run '{}': {}", tool, e),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_without_tool() {
+        let e = merge(
+            &[Path::new("default.profraw")],
+            Path::new("merged.profdata"),
+            "virtuc-no-such-profdata",
+        )
+        .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+        assert!(e.to_string().contains("--llvm-profdata"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_merge_reports_failure() {
+        // `false` fails like a tool that rejects its input
+        let e = merge(&[Path::new("x.profraw")], Path::new("x.profdata"), "false").unwrap_err();
+        assert!(
+            e.to_string()
+                .starts_with("merging profiles with 'false' failed")
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! # Pseudo-Random Numbers
+//!
+//! Including `<virtuc/random.h>` makes two builtins available:
+//!
+//! - `int rand_seeded(int seed)`: restarts the sequence from `seed`, and
+//!   returns 0
+//! - `int rand_next()`: returns the next number of the sequence, between 0
+//!   and 2^31 - 1
+//!
+//! Unlike libc's `rand`, whose sequence differs between C libraries, the
+//! generator is a fixed 64-bit linear congruential generator that code
+//! generation emits into the program (see [`runtime`](crate::runtime)). A
+//! seed therefore gives the same numbers on every platform and with either
+//! `int` width, and [`Random`] reproduces them in Rust. A program that never
+//! seeds the generator behaves as if seeded with 1.
+
+use crate::ast::Program;
+
+/// The header that declares the builtins.
+pub const HEADER: &str = "virtuc/random.h";
+
+/// The name of the seeding builtin.
+pub const SEED: &str = "rand_seeded";
+
+/// The name of the builtin that returns the next number.
+pub const NEXT: &str = "rand_next";
+
+/// The multiplier of the generator, from Knuth's MMIX.
+pub const MULTIPLIER: u64 = 6364136223846793005;
+
+/// The increment of the generator, from Knuth's MMIX.
+pub const INCREMENT: u64 = 1442695040888963407;
+
+/// The state of a generator that was never seeded.
+pub const DEFAULT_SEED: u64 = 1;
+
+/// How far the state is shifted to produce a 31-bit number.
+pub const OUTPUT_SHIFT: u64 = 33;
+
+/// Returns true if the program includes [`HEADER`], making `rand_seeded`
+/// and `rand_next` builtins rather than ordinary functions.
+pub fn enabled(program: &Program) -> bool {
+    program.includes.iter().any(|header| header == HEADER)
+}
+
+/// Returns true if `name` is one of the builtins.
+pub fn is_builtin(name: &str) -> bool {
+    name == SEED || name == NEXT
+}
+
+/// The generator of compiled programs, implemented in Rust. It yields the
+/// numbers `rand_next()` returns, without end.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    /// Creates a generator, as `rand_seeded(seed)` does. Negative seeds are
+    /// sign-extended.
+    pub fn seeded(seed: i64) -> Self {
+        Self { state: seed as u64 }
+    }
+}
+
+impl Iterator for Random {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        self.state = self.state.wrapping_mul(MULTIPLIER).wrapping_add(INCREMENT);
+        Some((self.state >> OUTPUT_SHIFT) as i64)
+    }
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Self {
+            state: DEFAULT_SEED,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence() {
+        let first: Vec<i64> = Random::seeded(42).take(3).collect();
+        assert!(first.iter().all(|n| (0..1 << 31).contains(n)));
+        assert_ne!(first[0], first[1]);
+
+        // Reseeding restarts the sequence
+        assert!(Random::seeded(42).take(3).eq(first));
+        assert_eq!(Random::default(), Random::seeded(1));
+        assert_ne!(Random::seeded(-1).next(), Random::seeded(1).next());
+    }
+}
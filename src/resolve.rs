@@ -0,0 +1,384 @@
+//! # Name Resolution
+//!
+//! This module resolves every name in a program to the symbol it refers to,
+//! so that later passes look up variables by [`SymbolId`] instead of by
+//! name, and cannot confuse a variable with another one of the same name
+//! that it shadows.
+//!
+//! ## Scoping
+//!
+//! Semantic analysis relies on these scopes, which follow C's:
+//! - Functions and extern functions are global, and visible before their
+//!   definition
+//! - Parameters are scoped to their function
+//! - Locals are scoped to the enclosing block, or to the `for` loop that
+//!   declares them, and are visible from their own initializer on
+//!
+//! ## Lookups
+//!
+//! Node handles are only unique within the arena of a function, so the
+//! [`Resolution`] records the symbol of each identifier, call, and
+//! declaration by the index of its function in [`Program::functions`] and
+//! the handle of its node. It stays valid when the program is cloned or
+//! moved, but not once its functions are reordered or their code rewritten.
+//!
+//! Scopes are keyed by the [`interner::Symbol`] of each name, which the
+//! lexer interned, so looking a name up hashes an integer.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::{Arena, Expr, ExprId, Function, Program, Stmt, StmtId};
+use crate::interner;
+
+/// Identifies a symbol of a [`Resolution`].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub struct SymbolId(u32);
+
+impl fmt::Display for SymbolId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// What kind of entity a symbol is.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SymbolKind {
+    /// A function defined by the program
+    Function,
+    /// An extern function, declared by the program or an included header
+    Extern,
+    /// A function parameter
+    Parameter,
+    /// A local variable
+    Local,
+}
+
+/// A named entity of a program.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Symbol {
+    /// The name, which other symbols may share
+    pub name: interner::Symbol,
+    /// What the symbol is
+    pub kind: SymbolKind,
+}
+
+/// The symbols of a program and the nodes that refer to them.
+#[derive(Debug, Default, Clone)]
+pub struct Resolution {
+    symbols: Vec<Symbol>,
+    /// Symbol of each identifier and call
+    exprs: HashMap<(usize, ExprId), SymbolId>,
+    /// Symbol declared by each declaration
+    declarations: HashMap<(usize, StmtId), SymbolId>,
+    /// Symbols of the parameters of each function, by function index
+    params: Vec<Vec<SymbolId>>,
+    /// Variable each declaration redeclares in the same scope
+    redeclarations: HashMap<(usize, StmtId), SymbolId>,
+    /// Variables in scope at each identifier that names none
+    unresolved: HashMap<(usize, ExprId), Vec<SymbolId>>,
+}
+
+impl Resolution {
+    /// Returns the symbol with the given ID.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the ID belongs to another resolution.
+    pub fn symbol(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id.0 as usize]
+    }
+
+    /// Returns the name of a symbol.
+    pub fn name(&self, id: SymbolId) -> &str {
+        self.symbol(id).name.as_str()
+    }
+
+    /// Returns every symbol with its ID, in the order they were declared.
+    pub fn symbols(&self) -> impl Iterator<Item = (SymbolId, &Symbol)> {
+        self.symbols
+            .iter()
+            .enumerate()
+            .map(|(i, symbol)| (SymbolId(i as u32), symbol))
+    }
+
+    /// Returns the symbol an identifier or call of the function at index
+    /// `function` refers to, or `None` for other expressions and undefined
+    /// names.
+    pub fn expr(&self, function: usize, expr: ExprId) -> Option<SymbolId> {
+        self.exprs.get(&(function, expr)).copied()
+    }
+
+    /// Returns the symbol a declaration statement of the function at index
+    /// `function` declares.
+    pub fn declaration(&self, function: usize, stmt: StmtId) -> Option<SymbolId> {
+        self.declarations.get(&(function, stmt)).copied()
+    }
+
+    /// Returns the symbols of the parameters of the function at index
+    /// `function`.
+    pub fn params(&self, function: usize) -> &[SymbolId] {
+        self.params.get(function).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the variable that a declaration statement of the function at
+    /// index `function` redeclares in the same scope, if any. The
+    /// declaration still declares a new symbol.
+    pub fn redeclaration(&self, function: usize, stmt: StmtId) -> Option<SymbolId> {
+        self.redeclarations.get(&(function, stmt)).copied()
+    }
+
+    /// Returns the variables in scope at an identifier of the function at
+    /// index `function` that names none of them, in no particular order, to
+    /// suggest what was meant.
+    pub fn in_scope(&self, function: usize, expr: ExprId) -> &[SymbolId] {
+        self.unresolved
+            .get(&(function, expr))
+            .map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Resolves the names of a program.
+pub fn resolve(program: &Program) -> Resolution {
+    let mut resolver = Resolver {
+        resolution: Resolution::default(),
+        globals: HashMap::new(),
+        scopes: Vec::new(),
+        function: 0,
+    };
+    for extern_func in &program.extern_functions {
+        resolver.declare_global(&extern_func.name, SymbolKind::Extern);
+    }
+    // Definitions take precedence over extern declarations of the same name
+    for function in &program.functions {
+        resolver.declare_global(&function.name, SymbolKind::Function);
+    }
+    for (index, function) in program.functions.iter().enumerate() {
+        resolver.function = index;
+        resolver.function(function);
+    }
+    resolver.resolution
+}
+
+/// Walks a program, declaring and resolving symbols.
+struct Resolver {
+    resolution: Resolution,
+    /// Functions and extern functions by name
+    globals: HashMap<interner::Symbol, SymbolId>,
+    /// Variables in scope, innermost scope last
+    scopes: Vec<HashMap<interner::Symbol, SymbolId>>,
+    /// Index of the function being resolved
+    function: usize,
+}
+
+impl Resolver {
+    /// Adds a symbol to the resolution.
+    fn add(&mut self, name: interner::Symbol, kind: SymbolKind) -> SymbolId {
+        let id = SymbolId(self.resolution.symbols.len() as u32);
+        self.resolution.symbols.push(Symbol { name, kind });
+        id
+    }
+
+    fn declare_global(&mut self, name: &str, kind: SymbolKind) {
+        let name = interner::Symbol::intern(name);
+        let id = self.add(name, kind);
+        self.globals.insert(name, id);
+    }
+
+    /// Declares a variable in the innermost scope.
+    fn declare(&mut self, name: interner::Symbol, kind: SymbolKind) -> SymbolId {
+        let id = self.add(name, kind);
+        self.scopes
+            .last_mut()
+            .expect("variables are declared inside a function")
+            .insert(name, id);
+        id
+    }
+
+    /// Returns the innermost variable named `name`.
+    fn lookup(&self, name: interner::Symbol) -> Option<SymbolId> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name).copied())
+    }
+
+    fn function(&mut self, function: &Function) {
+        self.scopes.push(HashMap::new());
+        let params = function
+            .params
+            .iter()
+            .map(|(_, name)| self.declare(interner::Symbol::intern(name), SymbolKind::Parameter))
+            .collect();
+        self.resolution.params.push(params);
+        self.stmt(&function.arena, function.body);
+        self.scopes.pop();
+    }
+
+    fn stmt(&mut self, arena: &Arena, id: StmtId) {
+        let key = (self.function, id);
+        match &arena[id] {
+            Stmt::Declaration { name, init, .. } => {
+                if let Some(previous) = self
+                    .scopes
+                    .last()
+                    .and_then(|scope| scope.get(name).copied())
+                {
+                    self.resolution.redeclarations.insert(key, previous);
+                }
+                let id = self.declare(*name, SymbolKind::Local);
+                self.resolution.declarations.insert(key, id);
+                if let Some(init) = init {
+                    self.expr(arena, *init);
+                }
+            }
+            Stmt::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(arena, *value);
+                }
+            }
+            Stmt::Block(stmts) => {
+                self.scopes.push(HashMap::new());
+                for stmt in stmts {
+                    self.stmt(arena, *stmt);
+                }
+                self.scopes.pop();
+            }
+            Stmt::If { cond, then, else_ } => {
+                self.expr(arena, *cond);
+                self.stmt(arena, *then);
+                if let Some(else_) = else_ {
+                    self.stmt(arena, *else_);
+                }
+            }
+            Stmt::For {
+                init,
+                cond,
+                update,
+                body,
+            } => {
+                self.scopes.push(HashMap::new());
+                if let Some(init) = init {
+                    self.stmt(arena, *init);
+                }
+                for expr in cond.iter().chain(update) {
+                    self.expr(arena, *expr);
+                }
+                self.stmt(arena, *body);
+                self.scopes.pop();
+            }
+            Stmt::Expr(expr) => self.expr(arena, *expr),
+            Stmt::Empty => {}
+        }
+    }
+
+    fn expr(&mut self, arena: &Arena, id: ExprId) {
+        let key = (self.function, id);
+        match &arena[id] {
+            Expr::Literal(_) => {}
+            Expr::Identifier(name) => match self.lookup(*name) {
+                Some(id) => {
+                    self.resolution.exprs.insert(key, id);
+                }
+                None => {
+                    let in_scope = self.scopes.iter().flat_map(|scope| scope.values());
+                    let in_scope = in_scope.copied().collect();
+                    self.resolution.unresolved.insert(key, in_scope);
+                }
+            },
+            Expr::Binary { left, right, .. } => {
+                self.expr(arena, *left);
+                self.expr(arena, *right);
+            }
+            Expr::Call { name, args } => {
+                if let Some(&id) = self.globals.get(name) {
+                    self.resolution.exprs.insert(key, id);
+                }
+                for arg in args {
+                    self.expr(arena, *arg);
+                }
+            }
+            Expr::Assignment { target, value } => {
+                self.expr(arena, *target);
+                self.expr(arena, *value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_resolve_shadowing() {
+        let program = parse(
+            &lex("extern int abs(int);
+                  int f(int x) {
+                      int y = x;
+                      { int x = 2; y = x; }
+                      for (int x = 0; x < 3; x = x + 1) { y = abs(x); }
+                      return f(x);
+                  }")
+            .unwrap(),
+        )
+        .unwrap();
+        let resolution = resolve(&program);
+        let f = &program.functions[0];
+        let param = resolution.params(0)[0];
+        assert_eq!(resolution.symbol(param).kind, SymbolKind::Parameter);
+
+        let arena = &f.arena;
+        let Stmt::Block(body) = f.body() else {
+            panic!("expected a block");
+        };
+        let local = |stmt: StmtId| resolution.declaration(0, stmt).unwrap();
+
+        // `int y = x` reads the parameter
+        let Stmt::Declaration { init: Some(x), .. } = &arena[body[0]] else {
+            panic!("expected a declaration");
+        };
+        assert_eq!(resolution.expr(0, *x), Some(param));
+
+        // The block's `x` shadows the parameter only within the block
+        let Stmt::Block(inner) = &arena[body[1]] else {
+            panic!("expected a block");
+        };
+        let Stmt::Expr(assignment) = &arena[inner[1]] else {
+            panic!("expected an expression");
+        };
+        let Expr::Assignment { value, .. } = &arena[*assignment] else {
+            panic!("expected an assignment");
+        };
+        assert_eq!(resolution.expr(0, *value), Some(local(inner[0])));
+        assert_ne!(local(inner[0]), param);
+
+        // The loop's `x` is another variable again
+        let Stmt::For {
+            init: Some(init), ..
+        } = &arena[body[2]]
+        else {
+            panic!("expected a loop");
+        };
+        assert_ne!(local(*init), local(inner[0]));
+
+        // After both, `x` is the parameter again, and `f` the function
+        let Stmt::Return(Some(call)) = &arena[body[3]] else {
+            panic!("expected a return");
+        };
+        let Expr::Call { args, .. } = &arena[*call] else {
+            panic!("expected a call");
+        };
+        assert_eq!(resolution.expr(0, args[0]), Some(param));
+        let callee = resolution.expr(0, *call).unwrap();
+        assert_eq!(resolution.name(callee), "f");
+        assert_eq!(resolution.symbol(callee).kind, SymbolKind::Function);
+        let names: Vec<_> = resolution
+            .symbols()
+            .map(|(id, _)| resolution.name(id))
+            .collect();
+        assert_eq!(names, ["abs", "f", "x", "y", "x", "x"]);
+    }
+}
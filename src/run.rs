@@ -0,0 +1,432 @@
+//! # Running Compiled Programs
+//!
+//! This module runs compiled executables and collects what they did, so that
+//! tests, benchmarks, and grading harnesses share one way to run programs:
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use virtuc::run::{RunConfig, run_program};
+//!
+//! let config = RunConfig {
+//!     stdin: Some(b"42\n".to_vec()),
+//!     ..RunConfig::default()
+//! };
+//! let result = run_program(Path::new("hello.out"), &config).unwrap();
+//! println!("exited with {:?} in {:?}", result.exit_code, result.duration);
+//! ```
+//!
+//! ## Limits
+//!
+//! Untrusted programs can be run with a wall-clock [`RunConfig::timeout`], a
+//! cap on captured output with [`RunConfig::max_output`], a cap on their
+//! address space with [`RunConfig::max_memory`], a cleared environment, and a
+//! working directory of their own. A program that exceeds a limit is killed,
+//! and [`RunResult::exceeded`] says which limit it hit. Processes the program
+//! starts itself are not killed with it.
+//!
+//! The memory limit is enforced by the kernel (`RLIMIT_AS`), which fails the
+//! allocation or stack growth that would exceed it, and the program crashes.
+//! A program under a memory limit that is killed by `SIGSEGV`, `SIGBUS`, or
+//! `SIGABRT` is reported as exceeding it, as a crash for another reason
+//! cannot be told apart.
+
+use std::io::{self, Read, Write};
+use std::path::{self, Component, Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// What happens to an output stream of the program.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Capture {
+    /// Collect it into the [`RunResult`] (default)
+    #[default]
+    Pipe,
+    /// Pass it through to the stream of the current process
+    Inherit,
+    /// Discard it
+    Null,
+}
+
+impl Capture {
+    fn stdio(self) -> Stdio {
+        match self {
+            Capture::Pipe => Stdio::piped(),
+            Capture::Inherit => Stdio::inherit(),
+            Capture::Null => Stdio::null(),
+        }
+    }
+}
+
+/// How to run a program.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct RunConfig {
+    /// Command-line arguments
+    pub args: Vec<String>,
+    /// Bytes to write to the program's stdin; without them, stdin is empty
+    pub stdin: Option<Vec<u8>>,
+    /// What happens to the program's stdout
+    pub stdout: Capture,
+    /// What happens to the program's stderr
+    pub stderr: Capture,
+    /// Kill the program if it runs longer than this
+    pub timeout: Option<Duration>,
+    /// Kill the program if it writes more than this many bytes to a piped
+    /// stream; what fits is kept
+    pub max_output: Option<usize>,
+    /// Limit the address space of the program to this many bytes (Unix only)
+    pub max_memory: Option<u64>,
+    /// Start the program with no environment variables but those in `env`
+    pub clear_env: bool,
+    /// Environment variables to set for the program
+    pub env: Vec<(String, String)>,
+    /// The working directory of the program, instead of the current one
+    pub current_dir: Option<PathBuf>,
+}
+
+/// A limit of [`RunConfig`] that a program exceeded.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Limit {
+    /// The program ran longer than [`RunConfig::timeout`]
+    Timeout,
+    /// The program wrote more than [`RunConfig::max_output`]
+    Output,
+    /// The program ran out of [`RunConfig::max_memory`]
+    Memory,
+}
+
+impl std::fmt::Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Limit::Timeout => write!(f, "timed out"),
+            Limit::Output => write!(f, "exceeded the output limit"),
+            Limit::Memory => write!(f, "exceeded the memory limit"),
+        }
+    }
+}
+
+/// What a program did.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RunResult {
+    /// The exit code, or `None` if the program was killed by a signal
+    pub exit_code: Option<i32>,
+    /// The limit for which the program was killed, if any
+    pub exceeded: Option<Limit>,
+    /// The captured stdout, empty unless it was piped
+    pub stdout: Vec<u8>,
+    /// The captured stderr, empty unless it was piped
+    pub stderr: Vec<u8>,
+    /// The wall-clock time from start to exit
+    pub duration: Duration,
+}
+
+impl RunResult {
+    /// Returns true if the program exited with code 0.
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /// Returns true if the program was killed for running too long.
+    pub fn timed_out(&self) -> bool {
+        self.exceeded == Some(Limit::Timeout)
+    }
+
+    /// Returns the captured stdout as text, replacing invalid UTF-8.
+    pub fn stdout_text(&self) -> String {
+        String::from_utf8_lossy(&self.stdout).into_owned()
+    }
+
+    /// Returns the captured stderr as text, replacing invalid UTF-8.
+    pub fn stderr_text(&self) -> String {
+        String::from_utf8_lossy(&self.stderr).into_owned()
+    }
+}
+
+/// How often a program with limits is checked for having exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Runs the executable at `path` until it exits or exceeds a limit.
+///
+/// A relative path such as `hello.out` names a file in the current
+/// directory, not a program on `$PATH`, even when the program runs in
+/// another [`RunConfig::current_dir`].
+///
+/// # Errors
+///
+/// Returns the error of starting the program, or of collecting its output.
+pub fn run_program(path: &Path, config: &RunConfig) -> io::Result<RunResult> {
+    let path = match config.current_dir {
+        Some(_) => path::absolute(path)?,
+        None => executable_path(path),
+    };
+    let mut command = Command::new(path);
+    command
+        .args(&config.args)
+        .stdin(match config.stdin {
+            Some(_) => Stdio::piped(),
+            None => Stdio::null(),
+        })
+        .stdout(config.stdout.stdio())
+        .stderr(config.stderr.stdio());
+    if config.clear_env {
+        command.env_clear();
+    }
+    command.envs(config.env.iter().map(|(key, value)| (key, value)));
+    if let Some(dir) = &config.current_dir {
+        command.current_dir(dir);
+    }
+    #[cfg(unix)]
+    if let Some(bytes) = config.max_memory {
+        limit_memory(&mut command, bytes);
+    }
+
+    let start = Instant::now();
+    let mut child = command.spawn()?;
+    // Write stdin on another thread, so that a program that writes output
+    // before reading all its input cannot deadlock with us
+    let writer = child.stdin.take().map(|mut stdin| {
+        let input = config.stdin.clone().unwrap_or_default();
+        thread::spawn(move || {
+            // The program may exit without reading everything
+            let _ = stdin.write_all(&input);
+        })
+    });
+    let overflowed = Arc::new(AtomicBool::new(false));
+    let stdout = child
+        .stdout
+        .take()
+        .map(|stream| collect(stream, config.max_output, Arc::clone(&overflowed)));
+    let stderr = child
+        .stderr
+        .take()
+        .map(|stream| collect(stream, config.max_output, Arc::clone(&overflowed)));
+
+    let (status, exceeded) = if config.timeout.is_none() && config.max_output.is_none() {
+        (child.wait()?, None)
+    } else {
+        wait_within_limits(&mut child, start, config.timeout, &overflowed)?
+    };
+    let exceeded = exceeded.or_else(|| out_of_memory(config, status).then_some(Limit::Memory));
+    let duration = start.elapsed();
+    let stdout = join_output(stdout)?;
+    let stderr = join_output(stderr)?;
+    if let Some(writer) = writer {
+        let _ = writer.join();
+    }
+    Ok(RunResult {
+        exit_code: status.code(),
+        exceeded,
+        stdout,
+        stderr,
+        duration,
+    })
+}
+
+/// Waits for a program to exit, killing it when it runs past `timeout` or
+/// a stream overflows.
+fn wait_within_limits(
+    child: &mut Child,
+    start: Instant,
+    timeout: Option<Duration>,
+    overflowed: &AtomicBool,
+) -> io::Result<(std::process::ExitStatus, Option<Limit>)> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, None));
+        }
+        let exceeded = if overflowed.load(Ordering::Relaxed) {
+            Some(Limit::Output)
+        } else if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+            Some(Limit::Timeout)
+        } else {
+            None
+        };
+        if exceeded.is_some() {
+            // The program may exit on its own just before being killed
+            let _ = child.kill();
+            return Ok((child.wait()?, exceeded));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Caps the address space of the program `command` starts at `bytes`.
+#[cfg(unix)]
+fn limit_memory(command: &mut Command, bytes: u64) {
+    use std::os::unix::process::CommandExt;
+
+    let limit = libc::rlimit {
+        rlim_cur: bytes as libc::rlim_t,
+        rlim_max: bytes as libc::rlim_t,
+    };
+    // SAFETY: the closure runs in the child between fork and exec, where it
+    // only calls `setrlimit`, which is async-signal-safe, and allocates nothing
+    unsafe {
+        command.pre_exec(move || {
+            if libc::setrlimit(libc::RLIMIT_AS, &limit) == 0 {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            }
+        });
+    }
+}
+
+/// Returns true if a program under a memory limit crashed the way programs
+/// that run out of memory do.
+fn out_of_memory(config: &RunConfig, status: ExitStatus) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        config.max_memory.is_some()
+            && matches!(
+                status.signal(),
+                Some(libc::SIGSEGV | libc::SIGBUS | libc::SIGABRT)
+            )
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (config, status);
+        false
+    }
+}
+
+/// Reads a stream to its end on another thread, keeping at most `limit`
+/// bytes and flagging `overflowed` if there were more.
+fn collect<R: Read + Send + 'static>(
+    mut stream: R,
+    limit: Option<usize>,
+    overflowed: Arc<AtomicBool>,
+) -> JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        let mut buf = [0; 8192];
+        loop {
+            let n = stream.read(&mut buf)?;
+            if n == 0 {
+                return Ok(output);
+            }
+            let keep = match limit {
+                Some(limit) => n.min(limit.saturating_sub(output.len())),
+                None => n,
+            };
+            output.extend_from_slice(&buf[..keep]);
+            if keep < n {
+                overflowed.store(true, Ordering::Relaxed);
+            }
+        }
+    })
+}
+
+/// Returns what a [`collect`] thread read, if the stream was piped.
+fn join_output(handle: Option<JoinHandle<io::Result<Vec<u8>>>>) -> io::Result<Vec<u8>> {
+    match handle {
+        Some(handle) => handle.join().expect("output reader panicked"),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Prefixes a bare file name with `./`, so that it is not looked up on
+/// `$PATH`.
+fn executable_path(path: &Path) -> PathBuf {
+    let mut components = path.components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Path::new(".").join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_executable_path() {
+        assert_eq!(executable_path(Path::new("a.out")), Path::new("./a.out"));
+        assert_eq!(
+            executable_path(Path::new("bin/a.out")),
+            Path::new("bin/a.out")
+        );
+        assert_eq!(executable_path(Path::new("/bin/sh")), Path::new("/bin/sh"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_program() {
+        let config = RunConfig {
+            args: vec!["-c".to_string(), "cat; echo oops >&2; exit 3".to_string()],
+            stdin: Some(b"hello\n".to_vec()),
+            ..RunConfig::default()
+        };
+        let result = run_program(Path::new("/bin/sh"), &config).unwrap();
+        assert_eq!(result.exit_code, Some(3));
+        assert!(!result.success());
+        assert_eq!(result.stdout_text(), "hello\n");
+        assert_eq!(result.stderr_text(), "oops\n");
+
+        let config = RunConfig {
+            stdout: Capture::Null,
+            ..config
+        };
+        let result = run_program(Path::new("/bin/sh"), &config).unwrap();
+        assert!(result.stdout.is_empty());
+        assert_eq!(result.stderr_text(), "oops\n");
+        assert_eq!(result.exceeded, None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_program_limits() {
+        let shell = |script: &str| RunConfig {
+            args: vec!["-c".to_string(), script.to_string()],
+            ..RunConfig::default()
+        };
+
+        let config = RunConfig {
+            timeout: Some(Duration::from_millis(100)),
+            ..shell("echo started; exec sleep 10")
+        };
+        let result = run_program(Path::new("/bin/sh"), &config).unwrap();
+        assert!(result.timed_out());
+        assert_eq!(result.exit_code, None);
+        assert_eq!(result.stdout_text(), "started\n");
+        assert!(result.duration < Duration::from_secs(5));
+
+        let config = RunConfig {
+            max_output: Some(4),
+            ..shell("while true; do echo flood; done")
+        };
+        let result = run_program(Path::new("/bin/sh"), &config).unwrap();
+        assert_eq!(result.exceeded, Some(Limit::Output));
+        assert_eq!(result.stdout_text(), "floo");
+
+        // The shell reports the limit in KiB; crashes under it are reported
+        // as running out of memory
+        let config = RunConfig {
+            max_memory: Some(64 << 20),
+            ..shell("ulimit -v; kill -SEGV $$")
+        };
+        let result = run_program(Path::new("/bin/sh"), &config).unwrap();
+        assert_eq!(result.stdout_text(), "65536\n");
+        assert_eq!(result.exceeded, Some(Limit::Memory));
+        let result = run_program(Path::new("/bin/sh"), &shell("kill -SEGV $$")).unwrap();
+        assert_eq!(result.exceeded, None);
+
+        let dir = std::env::temp_dir();
+        let config = RunConfig {
+            clear_env: true,
+            env: vec![("GREETING".to_string(), "hi".to_string())],
+            current_dir: Some(dir.clone()),
+            ..shell("echo \"$GREETING ${HOME:-no home}\"; pwd -P")
+        };
+        let result = run_program(Path::new("/bin/sh"), &config).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        assert_eq!(
+            result.stdout_text(),
+            format!("hi no home\n{}\n", dir.display())
+        );
+    }
+}
@@ -0,0 +1,347 @@
+//! # Runtime Support
+//!
+//! This module defines the small runtime that checked builds rely on. Its
+//! functions are emitted as LLVM IR into the module that uses them, so no
+//! separate library has to be built, installed, or passed to the linker.
+//!
+//! ## Functions
+//!
+//! - `virtuc_rt_abort(message)`: prints `virtuc: <message>` on stderr and
+//!   aborts the program
+//! - `virtuc_rt_coverage_dump()`: writes the statement counters of a
+//!   `--coverage` build to its counts file; registered with `atexit`
+//! - `virtuc_rt_rand_seed(seed)` and `virtuc_rt_rand_next()`: the generator
+//!   behind the builtins of [`random`](crate::random)
+//!
+//! Every runtime function has internal linkage and a `virtuc_rt_` prefix, so
+//! it cannot clash with the functions of the program. Code generation calls
+//! these helpers instead of open-coding calls to libc, which keeps runtime
+//! behavior in one place.
+
+use inkwell::AddressSpace;
+use inkwell::IntPredicate;
+use inkwell::attributes::{Attribute, AttributeLoc};
+use inkwell::context::Context;
+use inkwell::module::{Linkage, Module};
+use inkwell::values::{FunctionValue, GlobalValue};
+
+use crate::coverage::COUNTS_HEADER;
+use crate::random;
+
+/// Name of the abort-with-message helper.
+pub const ABORT: &str = "virtuc_rt_abort";
+
+/// Name of the coverage counts writer.
+pub const COVERAGE_DUMP: &str = "virtuc_rt_coverage_dump";
+
+/// Name of the generator seeding helper.
+pub const RAND_SEED: &str = "virtuc_rt_rand_seed";
+
+/// Name of the helper returning the next random number.
+pub const RAND_NEXT: &str = "virtuc_rt_rand_next";
+
+/// Name of the generator state.
+const RAND_STATE: &str = "virtuc_rt.rand.state";
+
+/// Returns `virtuc_rt_abort`, defining it in the module on first use.
+///
+/// The helper takes a NUL-terminated message and never returns.
+pub fn abort<'ctx>(context: &'ctx Context, module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(function) = module.get_function(ABORT) {
+        return function;
+    }
+    let i32_type = context.i32_type();
+    let ptr_type = context.ptr_type(AddressSpace::default());
+
+    let fn_type = context.void_type().fn_type(&[ptr_type.into()], false);
+    let function = module.add_function(ABORT, fn_type, Some(Linkage::Internal));
+    for name in ["noreturn", "cold", "noinline"] {
+        let kind = Attribute::get_named_enum_kind_id(name);
+        function.add_attribute(
+            AttributeLoc::Function,
+            context.create_enum_attribute(kind, 0),
+        );
+    }
+
+    let dprintf = libc_function(module, "dprintf", || {
+        i32_type.fn_type(&[i32_type.into(), ptr_type.into()], true)
+    });
+    let libc_abort = libc_function(module, "abort", || context.void_type().fn_type(&[], false));
+
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(function, "entry"));
+    let format = builder
+        .build_global_string_ptr("virtuc: %s\n", "virtuc_rt.abort.format")
+        .unwrap();
+    let message = function.get_nth_param(0).unwrap();
+    builder
+        .build_call(
+            dprintf,
+            &[
+                i32_type.const_int(2, false).into(),
+                format.as_pointer_value().into(),
+                message.into(),
+            ],
+            "",
+        )
+        .unwrap();
+    builder.build_call(libc_abort, &[], "").unwrap();
+    builder.build_unreachable().unwrap();
+    function
+}
+
+/// Defines `virtuc_rt_coverage_dump`, which writes the `len` counters of
+/// the `i64` array `counters` to the counts file at `path`.
+///
+/// A counts file that cannot be opened is silently skipped, so coverage never
+/// changes the outcome of a run.
+pub fn coverage_dump<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    counters: GlobalValue<'ctx>,
+    len: u32,
+    path: &str,
+) -> FunctionValue<'ctx> {
+    let i32_type = context.i32_type();
+    let i64_type = context.i64_type();
+    let ptr_type = context.ptr_type(AddressSpace::default());
+
+    let fn_type = context.void_type().fn_type(&[], false);
+    let function = module.add_function(COVERAGE_DUMP, fn_type, Some(Linkage::Internal));
+    let fopen = libc_function(module, "fopen", || {
+        ptr_type.fn_type(&[ptr_type.into(), ptr_type.into()], false)
+    });
+    let fprintf = libc_function(module, "fprintf", || {
+        i32_type.fn_type(&[ptr_type.into(), ptr_type.into()], true)
+    });
+    let fclose = libc_function(module, "fclose", || {
+        i32_type.fn_type(&[ptr_type.into()], false)
+    });
+
+    let builder = context.create_builder();
+    let entry = context.append_basic_block(function, "entry");
+    let write = context.append_basic_block(function, "write");
+    let cond = context.append_basic_block(function, "loop.cond");
+    let body = context.append_basic_block(function, "loop.body");
+    let close = context.append_basic_block(function, "close");
+    let done = context.append_basic_block(function, "done");
+
+    // Open the counts file, giving up if that fails
+    builder.position_at_end(entry);
+    let path = builder
+        .build_global_string_ptr(path, "virtuc_rt.coverage.path")
+        .unwrap();
+    let mode = builder
+        .build_global_string_ptr("w", "virtuc_rt.coverage.mode")
+        .unwrap();
+    let file = builder
+        .build_call(
+            fopen,
+            &[
+                path.as_pointer_value().into(),
+                mode.as_pointer_value().into(),
+            ],
+            "file",
+        )
+        .unwrap()
+        .try_as_basic_value()
+        .unwrap_basic()
+        .into_pointer_value();
+    let is_null = builder.build_is_null(file, "is_null").unwrap();
+    builder
+        .build_conditional_branch(is_null, done, write)
+        .unwrap();
+
+    // Write the header, then one counter per line
+    builder.position_at_end(write);
+    let header = builder
+        .build_global_string_ptr(&format!("{}\n", COUNTS_HEADER), "virtuc_rt.coverage.header")
+        .unwrap();
+    let format = builder
+        .build_global_string_ptr("%llu\n", "virtuc_rt.coverage.format")
+        .unwrap();
+    builder
+        .build_call(
+            fprintf,
+            &[file.into(), header.as_pointer_value().into()],
+            "",
+        )
+        .unwrap();
+    let index_ptr = builder.build_alloca(i64_type, "index").unwrap();
+    builder
+        .build_store(index_ptr, i64_type.const_zero())
+        .unwrap();
+    builder.build_unconditional_branch(cond).unwrap();
+
+    builder.position_at_end(cond);
+    let index = builder
+        .build_load(i64_type, index_ptr, "index")
+        .unwrap()
+        .into_int_value();
+    let more = builder
+        .build_int_compare(
+            IntPredicate::ULT,
+            index,
+            i64_type.const_int(len as u64, false),
+            "more",
+        )
+        .unwrap();
+    builder.build_conditional_branch(more, body, close).unwrap();
+
+    builder.position_at_end(body);
+    let counter = unsafe {
+        builder.build_in_bounds_gep(
+            i64_type.array_type(len),
+            counters.as_pointer_value(),
+            &[i64_type.const_zero(), index],
+            "counter",
+        )
+    }
+    .unwrap();
+    let count = builder.build_load(i64_type, counter, "count").unwrap();
+    builder
+        .build_call(
+            fprintf,
+            &[file.into(), format.as_pointer_value().into(), count.into()],
+            "",
+        )
+        .unwrap();
+    let next = builder
+        .build_int_add(index, i64_type.const_int(1, false), "next")
+        .unwrap();
+    builder.build_store(index_ptr, next).unwrap();
+    builder.build_unconditional_branch(cond).unwrap();
+
+    builder.position_at_end(close);
+    builder.build_call(fclose, &[file.into()], "").unwrap();
+    builder.build_unconditional_branch(done).unwrap();
+
+    builder.position_at_end(done);
+    builder.build_return(None).unwrap();
+    function
+}
+
+/// Returns `virtuc_rt_rand_seed`, defining it in the module on first use.
+///
+/// The helper takes the seed as an `i64` and replaces the generator state
+/// with it.
+pub fn rand_seed<'ctx>(context: &'ctx Context, module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(function) = module.get_function(RAND_SEED) {
+        return function;
+    }
+    let i64_type = context.i64_type();
+    let fn_type = context.void_type().fn_type(&[i64_type.into()], false);
+    let function = module.add_function(RAND_SEED, fn_type, Some(Linkage::Internal));
+    let state = rand_state(context, module);
+
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(function, "entry"));
+    let seed = function.get_nth_param(0).unwrap();
+    builder.build_store(state.as_pointer_value(), seed).unwrap();
+    builder.build_return(None).unwrap();
+    function
+}
+
+/// Returns `virtuc_rt_rand_next`, defining it in the module on first use.
+///
+/// The helper advances the generator state and returns a number between 0
+/// and 2^31 - 1 as an `i64`, exactly as [`random::Random`] does.
+pub fn rand_next<'ctx>(context: &'ctx Context, module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    if let Some(function) = module.get_function(RAND_NEXT) {
+        return function;
+    }
+    let i64_type = context.i64_type();
+    let fn_type = i64_type.fn_type(&[], false);
+    let function = module.add_function(RAND_NEXT, fn_type, Some(Linkage::Internal));
+    let state = rand_state(context, module);
+
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(function, "entry"));
+    let current = builder
+        .build_load(i64_type, state.as_pointer_value(), "state")
+        .unwrap()
+        .into_int_value();
+    let product = builder
+        .build_int_mul(
+            current,
+            i64_type.const_int(random::MULTIPLIER, false),
+            "product",
+        )
+        .unwrap();
+    let next = builder
+        .build_int_add(
+            product,
+            i64_type.const_int(random::INCREMENT, false),
+            "next",
+        )
+        .unwrap();
+    builder.build_store(state.as_pointer_value(), next).unwrap();
+    let output = builder
+        .build_right_shift(
+            next,
+            i64_type.const_int(random::OUTPUT_SHIFT, false),
+            false,
+            "output",
+        )
+        .unwrap();
+    builder.build_return(Some(&output)).unwrap();
+    function
+}
+
+/// Returns the state of the random number generator, defining it in the
+/// module on first use.
+fn rand_state<'ctx>(context: &'ctx Context, module: &Module<'ctx>) -> GlobalValue<'ctx> {
+    if let Some(state) = module.get_global(RAND_STATE) {
+        return state;
+    }
+    let i64_type = context.i64_type();
+    let state = module.add_global(i64_type, None, RAND_STATE);
+    state.set_linkage(Linkage::Internal);
+    state.set_initializer(&i64_type.const_int(random::DEFAULT_SEED, false));
+    state
+}
+
+/// Returns the libc function `name`, declaring it with the type built by
+/// `fn_type` unless the program already declares it.
+fn libc_function<'ctx>(
+    module: &Module<'ctx>,
+    name: &str,
+    fn_type: impl FnOnce() -> inkwell::types::FunctionType<'ctx>,
+) -> FunctionValue<'ctx> {
+    module
+        .get_function(name)
+        .unwrap_or_else(|| module.add_function(name, fn_type(), None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abort_is_defined_once() {
+        let context = Context::create();
+        let module = context.create_module("test");
+        let first = abort(&context, &module);
+        let second = abort(&context, &module);
+        assert_eq!(first, second);
+        assert!(first.verify(false));
+
+        let ir = module.print_to_string().to_string();
+        assert!(ir.contains("define internal void @virtuc_rt_abort(ptr %0)"));
+        assert!(ir.contains("declare i32 @dprintf(i32, ptr, ...)"));
+    }
+
+    #[test]
+    fn test_rand_helpers_share_state() {
+        let context = Context::create();
+        let module = context.create_module("test");
+        let seed = rand_seed(&context, &module);
+        let next = rand_next(&context, &module);
+        assert_eq!(rand_next(&context, &module), next);
+        assert!(seed.verify(false) && next.verify(false));
+
+        let ir = module.print_to_string().to_string();
+        assert_eq!(ir.matches("@virtuc_rt.rand.state = internal").count(), 1);
+        assert!(ir.contains("define internal i64 @virtuc_rt_rand_next()"));
+    }
+}
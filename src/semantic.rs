@@ -10,26 +10,50 @@
 //! 2. **Type Checking**: Verify type compatibility in expressions and assignments
 //! 3. **Scope Resolution**: Ensure variables are declared before use
 //! 4. **Control Flow Validation**: Check loop and conditional constructs
+//! 5. **Constant Evaluation**: Reject constant expressions without a defined
+//!    value, such as `1 / 0`
 //!
 //! ## Symbol Tables
 //!
-//! Maintains scoped symbol tables for variables, functions, and types.
-//! Handles nested scopes for blocks, functions, and control structures.
+//! Functions are looked up by name in a global table. Variables are resolved
+//! to their symbols by [`resolve`](crate::resolve), which handles the scopes
+//! of blocks, functions, and loops, and their types are recorded by
+//! [`SymbolId`] as their declarations are checked.
 
+use crate::assertions;
 use crate::ast::*;
+use crate::consteval::{self, ConstValue};
 use crate::error::SemanticError;
+use crate::format::{self, ConversionKind};
+use crate::header_registry;
+use crate::options::{CompileOptions, IntWidth};
+use crate::resolve::{self, Resolution, SymbolId};
 use std::collections::HashMap;
 
 /// Represents the semantic analyzer.
 pub struct SemanticAnalyzer {
     /// Global function symbols: name -> (return_type, param_types, is_variadic)
     functions: HashMap<String, (Type, Vec<Type>, bool)>,
-    /// Stack of scopes for variables: each scope is name -> type
-    scopes: Vec<HashMap<String, Type>>,
+    /// The symbols of the names in the program being analyzed
+    resolution: Resolution,
+    /// Types of the variables declared so far
+    variables: HashMap<SymbolId, Type>,
     /// Current function's expected return type (during analysis)
     current_return_type: Option<Type>,
     /// Collected errors
     errors: Vec<SemanticError>,
+    /// Statement each error was found in, as the index of its function and
+    /// its handle, for the errors located so far
+    locations: Vec<Option<(usize, StmtId)>>,
+    /// Innermost statement being checked
+    statement: Option<(usize, StmtId)>,
+    /// Width of the `int` type, used for literal range and format checks
+    int_width: IntWidth,
+    /// Index in the program of the function being analyzed, for looking up
+    /// its symbols
+    function: usize,
+    /// Whether `assert` is the builtin of `<assert.h>`
+    assert_builtin: bool,
 }
 
 impl Default for SemanticAnalyzer {
@@ -43,28 +67,53 @@ impl SemanticAnalyzer {
     pub fn new() -> Self {
         Self {
             functions: HashMap::new(),
-            scopes: vec![HashMap::new()], // Global scope
+            resolution: Resolution::default(),
+            variables: HashMap::new(),
             current_return_type: None,
             errors: Vec::new(),
+            locations: Vec::new(),
+            statement: None,
+            int_width: IntWidth::default(),
+            function: 0,
+            assert_builtin: false,
+        }
+    }
+
+    /// Creates a new semantic analyzer configured by the compile options.
+    pub fn with_options(options: &CompileOptions) -> Self {
+        Self {
+            int_width: options.int_width,
+            ..Self::new()
         }
     }
 
     /// Analyzes the program and returns any semantic errors.
     pub fn analyze(&mut self, program: &Program) -> Vec<SemanticError> {
+        self.assert_builtin = assertions::enabled(program);
+        self.resolution = resolve::resolve(program);
         self.collect_functions(program);
-        for function in &program.functions {
+        for (index, function) in program.functions.iter().enumerate() {
+            self.function = index;
             self.analyze_function(function);
         }
+        self.locate();
         self.errors.clone()
     }
 
+    /// Analyzes a standalone expression, in which no variables or functions
+    /// are in scope, and returns its type and any semantic errors.
+    pub fn analyze_expr(&mut self, expr: &ExprTree) -> (Option<Type>, Vec<SemanticError>) {
+        let ty = self.check_expr(&expr.arena, expr.root);
+        (ty, self.errors.clone())
+    }
+
     /// Collects function declarations into the global symbol table.
     fn collect_functions(&mut self, program: &Program) {
         for function in &program.functions {
             let param_types: Vec<Type> = function.params.iter().map(|(ty, _)| *ty).collect();
             if self.functions.contains_key(&function.name) {
                 self.errors
-                    .push(SemanticError::DuplicateVariable(function.name.clone()));
+                    .push(SemanticError::DuplicateFunction(function.name.clone()));
             } else {
                 self.functions.insert(
                     function.name.clone(),
@@ -73,6 +122,14 @@ impl SemanticAnalyzer {
             }
         }
         for extern_func in &program.extern_functions {
+            if let Some(header) =
+                header_registry::conflicting_header(&program.includes, extern_func)
+            {
+                self.errors.push(SemanticError::ConflictingDeclaration(
+                    extern_func.name.clone(),
+                    header.to_string(),
+                ));
+            }
             if self.functions.contains_key(&extern_func.name) {
                 self.errors
                     .push(SemanticError::DuplicateVariable(extern_func.name.clone()));
@@ -106,32 +163,52 @@ impl SemanticAnalyzer {
         let prev_return_type = self.current_return_type;
         self.current_return_type = Some(function.return_ty);
 
-        // Enter function scope
-        self.scopes.push(HashMap::new());
         // Add parameters to scope
-        for (ty, name) in &function.params {
-            self.scopes.last_mut().unwrap().insert(name.clone(), *ty);
+        let params = self.resolution.params(self.function);
+        for (id, (ty, _)) in params.iter().zip(&function.params) {
+            self.variables.insert(*id, *ty);
         }
         // Analyze body
-        self.check_stmt(&function.body);
+        self.check_stmt(&function.arena, function.body);
 
         // Restore previous return type
         self.current_return_type = prev_return_type;
-        // Pop function scope
-        self.scopes.pop();
     }
 
-    /// Checks a statement.
-    fn check_stmt(&mut self, stmt: &Stmt) {
-        match stmt {
+    /// Records the current statement as the location of the errors found
+    /// since the last call.
+    fn locate(&mut self) {
+        let statement = self.statement;
+        self.locations.resize(self.errors.len(), statement);
+    }
+
+    /// Checks a statement, locating the errors found in it.
+    fn check_stmt(&mut self, arena: &Arena, stmt: StmtId) {
+        self.locate();
+        let outer = self.statement.replace((self.function, stmt));
+        self.check_stmt_kind(arena, stmt);
+        self.locate();
+        self.statement = outer;
+    }
+
+    /// Checks a statement of any kind.
+    fn check_stmt_kind(&mut self, arena: &Arena, stmt: StmtId) {
+        match &arena[stmt] {
             Stmt::Declaration { ty, name, init } => {
-                if self.scopes.last().unwrap().contains_key(name) {
+                let id = self.resolution.declaration(self.function, stmt);
+                if let Some(previous) = self.resolution.redeclaration(self.function, stmt) {
                     self.errors
-                        .push(SemanticError::DuplicateVariable(name.clone()));
+                        .push(SemanticError::DuplicateVariable(name.to_string()));
+                    // Later uses refer to the first declaration
+                    if let (Some(id), Some(ty)) = (id, self.variables.get(&previous).copied()) {
+                        self.variables.insert(id, ty);
+                    }
                 } else {
-                    self.scopes.last_mut().unwrap().insert(name.clone(), *ty);
+                    if let Some(id) = id {
+                        self.variables.insert(id, *ty);
+                    }
                     if let Some(expr) = init {
-                        let expr_ty = self.check_expr(expr);
+                        let expr_ty = self.check_expr(arena, *expr);
                         if expr_ty != Some(*ty) {
                             self.errors.push(SemanticError::TypeMismatch(format!(
                                 "Cannot assign {:?} to {:?}",
@@ -143,7 +220,7 @@ impl SemanticAnalyzer {
             }
             Stmt::Return(expr) => {
                 if let Some(e) = expr {
-                    let expr_ty = self.check_expr(e);
+                    let expr_ty = self.check_expr(arena, *e);
                     // Only check return type if the expression type is valid (not None from undefined var)
                     if let Some(expected_ty) = self.current_return_type
                         && let Some(actual_ty) = expr_ty
@@ -163,22 +240,20 @@ impl SemanticAnalyzer {
                 }
             }
             Stmt::Block(stmts) => {
-                self.scopes.push(HashMap::new());
                 for stmt in stmts {
-                    self.check_stmt(stmt);
+                    self.check_stmt(arena, *stmt);
                 }
-                self.scopes.pop();
             }
             Stmt::If { cond, then, else_ } => {
-                let cond_ty = self.check_expr(cond);
+                let cond_ty = self.check_expr(arena, *cond);
                 if cond_ty != Some(Type::Int) {
                     self.errors.push(SemanticError::TypeMismatch(
                         "Condition must be int".to_string(),
                     ));
                 }
-                self.check_stmt(then);
+                self.check_stmt(arena, *then);
                 if let Some(else_stmt) = else_ {
-                    self.check_stmt(else_stmt);
+                    self.check_stmt(arena, *else_stmt);
                 }
             }
             Stmt::For {
@@ -187,12 +262,11 @@ impl SemanticAnalyzer {
                 update,
                 body,
             } => {
-                self.scopes.push(HashMap::new());
                 if let Some(init_stmt) = init {
-                    self.check_stmt(init_stmt);
+                    self.check_stmt(arena, *init_stmt);
                 }
                 if let Some(cond_expr) = cond {
-                    let cond_ty = self.check_expr(cond_expr);
+                    let cond_ty = self.check_expr(arena, *cond_expr);
                     if cond_ty != Some(Type::Int) {
                         self.errors.push(SemanticError::TypeMismatch(
                             "Condition must be int".to_string(),
@@ -200,140 +274,391 @@ impl SemanticAnalyzer {
                     }
                 }
                 if let Some(update_expr) = update {
-                    self.check_expr(update_expr);
+                    self.check_expr(arena, *update_expr);
                 }
-                self.check_stmt(body);
-                self.scopes.pop();
-            }
-            Stmt::Expr(expr) => {
-                self.check_expr(expr);
+                self.check_stmt(arena, *body);
             }
+            Stmt::Expr(expr) => match &arena[*expr] {
+                // The builtin `assert` is a statement, checked like a call
+                Expr::Call { name, args } if self.assert_builtin && name == assertions::NAME => {
+                    self.check_call(arena, name.as_str(), args);
+                }
+                _ => {
+                    self.check_expr(arena, *expr);
+                }
+            },
+            Stmt::Empty => {}
         }
     }
 
     /// Checks an expression and returns its type.
-    fn check_expr(&mut self, expr: &Expr) -> Option<Type> {
+    fn check_expr(&mut self, arena: &Arena, id: ExprId) -> Option<Type> {
+        let expr = &arena[id];
         match expr {
             Expr::Literal(lit) => match lit {
-                Literal::Int(_) => Some(Type::Int),
+                Literal::Int(n) => {
+                    if !self.int_width.fits(*n) {
+                        self.errors
+                            .push(SemanticError::IntegerOutOfRange(*n, self.int_width));
+                    }
+                    Some(Type::Int)
+                }
                 Literal::Float(_) => Some(Type::Float),
                 Literal::String(_) => Some(Type::String),
             },
             Expr::Identifier(name) => {
-                if let Some(ty) = self.lookup_variable(name) {
+                if let Some(ty) = self.lookup_variable(id) {
                     Some(ty)
                 } else {
-                    self.errors
-                        .push(SemanticError::UndefinedVariable(name.clone()));
+                    let suggestion = self.suggest_variable(id, name.as_str());
+                    self.errors.push(SemanticError::UndefinedVariable(
+                        name.to_string(),
+                        suggestion,
+                    ));
                     None
                 }
             }
-            Expr::Binary { left, op, right } => {
-                let left_ty = self.check_expr(left);
-                let right_ty = self.check_expr(right);
-                match op {
-                    BinOp::Plus | BinOp::Minus | BinOp::Multiply | BinOp::Divide => {
-                        if left_ty == right_ty && left_ty.is_some() {
-                            left_ty
-                        } else {
-                            self.errors.push(SemanticError::TypeMismatch(
-                                "Arithmetic operands must have same type".to_string(),
-                            ));
-                            None
-                        }
-                    }
-                    BinOp::Equal
-                    | BinOp::NotEqual
-                    | BinOp::LessThan
-                    | BinOp::GreaterThan
-                    | BinOp::LessEqual
-                    | BinOp::GreaterEqual => {
-                        if left_ty == right_ty && left_ty.is_some() {
-                            Some(Type::Int) // Comparisons return int
-                        } else {
-                            self.errors.push(SemanticError::TypeMismatch(
-                                "Comparison operands must have same type".to_string(),
-                            ));
-                            None
-                        }
-                    }
+            Expr::Binary { left, op, right } => self.check_binary(arena, *left, *op, *right).0,
+            Expr::Call { name, args } => {
+                if self.assert_builtin && name == assertions::NAME {
+                    self.errors.push(SemanticError::MisplacedAssertion);
                 }
+                self.check_call(arena, name.as_str(), args)
             }
-            Expr::Call { name, args } => {
-                let func_info = self.functions.get(name).cloned();
-                if let Some((ret_ty, param_types, is_variadic)) = func_info {
-                    if !is_variadic {
-                        if args.len() != param_types.len() {
-                            self.errors.push(SemanticError::WrongArgumentCount(
-                                name.clone(),
-                                param_types.len(),
-                                args.len(),
-                            ));
-                            return Some(ret_ty);
-                        }
-                    } else if args.len() < param_types.len() {
-                        self.errors.push(SemanticError::WrongArgumentCount(
-                            name.clone(),
-                            param_types.len(),
-                            args.len(),
-                        ));
-                        return Some(ret_ty);
-                    }
-                    for (i, arg) in args.iter().enumerate().take(param_types.len()) {
-                        let arg_ty = self.check_expr(arg);
-                        if arg_ty != Some(param_types[i]) {
-                            self.errors.push(SemanticError::TypeMismatch(format!(
-                                "Argument {} type mismatch",
-                                i
-                            )));
-                        }
-                    }
-                    Some(ret_ty)
+            Expr::Assignment { target, value } => {
+                let value_ty = self.check_expr(arena, *value);
+                let var_ty = self.check_lvalue(arena, *target)?;
+                if value_ty != Some(var_ty) {
+                    self.errors.push(SemanticError::TypeMismatch(format!(
+                        "Cannot assign {:?} to {:?}",
+                        value_ty, var_ty
+                    )));
+                }
+                Some(var_ty)
+            }
+        }
+    }
+
+    /// Checks a function call and returns its type.
+    fn check_call(&mut self, arena: &Arena, name: &str, args: &[ExprId]) -> Option<Type> {
+        let func_info = self.functions.get(name).cloned();
+        if let Some((ret_ty, param_types, is_variadic)) = func_info {
+            if !is_variadic {
+                if args.len() != param_types.len() {
+                    self.errors.push(SemanticError::WrongArgumentCount(
+                        name.to_string(),
+                        param_types.len(),
+                        args.len(),
+                    ));
+                    return Some(ret_ty);
+                }
+            } else if args.len() < param_types.len() {
+                self.errors.push(SemanticError::WrongArgumentCount(
+                    name.to_string(),
+                    param_types.len(),
+                    args.len(),
+                ));
+                return Some(ret_ty);
+            }
+            let mut arg_types = Vec::with_capacity(args.len());
+            for (i, arg) in args.iter().enumerate() {
+                let arg_ty = self.check_expr(arena, *arg);
+                if i < param_types.len() && arg_ty != Some(param_types[i]) {
+                    self.errors.push(SemanticError::TypeMismatch(format!(
+                        "Argument {} type mismatch",
+                        i
+                    )));
+                }
+                arg_types.push(arg_ty);
+            }
+            if name == "printf"
+                && let Some(Expr::Literal(Literal::String(format))) =
+                    args.first().map(|a| &arena[*a])
+            {
+                self.check_printf_format(format, &arg_types[1..]);
+            }
+            Some(ret_ty)
+        } else {
+            let suggestion = closest_match(name, self.functions.keys().map(String::as_str));
+            self.errors.push(SemanticError::UndefinedFunction(
+                name.to_string(),
+                suggestion,
+            ));
+            None
+        }
+    }
+
+    /// Checks a binary operation, returning its type and, if it is a
+    /// constant expression, its value.
+    fn check_binary(
+        &mut self,
+        arena: &Arena,
+        left: ExprId,
+        op: BinOp,
+        right: ExprId,
+    ) -> (Option<Type>, Option<ConstValue>) {
+        let (left_ty, left_value) = self.check_operand(arena, left);
+        let (right_ty, right_value) = self.check_operand(arena, right);
+        let ty = match op {
+            BinOp::Plus | BinOp::Minus | BinOp::Multiply | BinOp::Divide => {
+                if left_ty == right_ty && left_ty.is_some() {
+                    left_ty
                 } else {
-                    self.errors
-                        .push(SemanticError::UndefinedFunction(name.clone()));
+                    self.errors.push(SemanticError::TypeMismatch(
+                        "Arithmetic operands must have same type".to_string(),
+                    ));
                     None
                 }
             }
-            Expr::Assignment { name, value } => {
-                let value_ty = self.check_expr(value);
-                if let Some(var_ty) = self.lookup_variable(name) {
-                    if value_ty != Some(var_ty) {
-                        self.errors.push(SemanticError::TypeMismatch(format!(
-                            "Cannot assign {:?} to {:?}",
-                            value_ty, var_ty
-                        )));
-                    }
-                    Some(var_ty)
+            BinOp::Equal
+            | BinOp::NotEqual
+            | BinOp::LessThan
+            | BinOp::GreaterThan
+            | BinOp::LessEqual
+            | BinOp::GreaterEqual => {
+                if left_ty == right_ty && left_ty.is_some() {
+                    Some(Type::Int) // Comparisons return int
                 } else {
-                    self.errors
-                        .push(SemanticError::UndefinedVariable(name.clone()));
+                    self.errors.push(SemanticError::TypeMismatch(
+                        "Comparison operands must have same type".to_string(),
+                    ));
                     None
                 }
             }
+        };
+        let value = match (ty, left_value, right_value) {
+            (Some(_), Some(left), Some(right)) => {
+                match consteval::eval_binary(op, left, right, self.int_width) {
+                    Ok(value) => Some(value),
+                    Err(e) => {
+                        self.errors.push(SemanticError::InvalidConstant(e));
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        (ty, value)
+    }
+
+    /// Checks an operand of a binary operation, returning its type and, if it
+    /// is a constant, its value.
+    fn check_operand(&mut self, arena: &Arena, expr: ExprId) -> (Option<Type>, Option<ConstValue>) {
+        match &arena[expr] {
+            Expr::Binary { left, op, right } => self.check_binary(arena, *left, *op, *right),
+            _ => (
+                self.check_expr(arena, expr),
+                consteval::eval(arena, expr, self.int_width).ok(),
+            ),
         }
     }
 
-    /// Looks up a variable in the current scopes.
-    fn lookup_variable(&self, name: &str) -> Option<Type> {
-        for scope in self.scopes.iter().rev() {
-            if let Some(ty) = scope.get(name) {
-                return Some(*ty);
+    /// Checks that an assignment target is an lvalue and returns its type.
+    fn check_lvalue(&mut self, arena: &Arena, target: ExprId) -> Option<Type> {
+        let kind = match &arena[target] {
+            Expr::Identifier(_) => return self.check_expr(arena, target),
+            Expr::Literal(_) => "a literal",
+            Expr::Binary { .. } => "the result of a binary operation",
+            Expr::Call { .. } => "the result of a function call",
+            Expr::Assignment { .. } => "the result of an assignment",
+        };
+        self.check_expr(arena, target);
+        self.errors
+            .push(SemanticError::InvalidAssignmentTarget(format!(
+                "cannot assign to {}",
+                kind
+            )));
+        None
+    }
+
+    /// Checks a printf format string against the types of the remaining arguments.
+    ///
+    /// With 64-bit `int`, only conversions that mix up ints and floats are
+    /// reported. With 32-bit `int`, the format is checked strictly: it must be
+    /// valid, have a conversion for each argument, and match their types, and
+    /// `l`/`ll`/`j`/`z`/`t` modifiers, which would read 64 bits from a 32-bit
+    /// argument, are rejected. Integer conversions without a length modifier
+    /// read a C `int`, which is accepted in both widths.
+    fn check_printf_format(&mut self, format: &str, args: &[Option<Type>]) {
+        let strict = self.int_width == IntWidth::W32;
+        let conversions = match format::conversions(format) {
+            Ok(conversions) => conversions,
+            Err(msg) => {
+                if strict {
+                    self.errors.push(SemanticError::InvalidFormat(msg));
+                }
+                return;
             }
+        };
+        if strict && conversions.len() != args.len() {
+            self.errors.push(SemanticError::InvalidFormat(format!(
+                "format expects {} argument(s), got {}",
+                conversions.len(),
+                args.len()
+            )));
+            return;
         }
-        None
+        for (i, (conv, arg_ty)) in conversions.iter().zip(args).enumerate() {
+            // Undefined variables etc. are already reported
+            let Some(arg_ty) = arg_ty else { continue };
+            let ok = match conv.kind {
+                ConversionKind::Int => *arg_ty == Type::Int && !(conv.long && strict),
+                ConversionKind::Float => *arg_ty == Type::Float,
+                ConversionKind::String => *arg_ty == Type::String,
+            };
+            let mixed_up = matches!(
+                (conv.kind, arg_ty),
+                (ConversionKind::Int, Type::Float) | (ConversionKind::Float, Type::Int)
+            );
+            if !ok && (strict || mixed_up) {
+                let hint = match (conv.kind, arg_ty) {
+                    (ConversionKind::Int, Type::Float) => "; use '%f' to print a float",
+                    (ConversionKind::Float, Type::Int) => "; use '%d' to print an int",
+                    _ => "",
+                };
+                self.errors.push(SemanticError::InvalidFormat(format!(
+                    "'%{}' does not match argument {} of type {:?}{}",
+                    conv.spec,
+                    i + 1,
+                    arg_ty,
+                    hint
+                )));
+            }
+        }
+    }
+
+    /// Looks up the type of the variable an identifier refers to.
+    fn lookup_variable(&self, identifier: ExprId) -> Option<Type> {
+        let id = self.resolution.expr(self.function, identifier)?;
+        self.variables.get(&id).copied()
+    }
+
+    /// Suggests the variable in scope at an undefined identifier whose name
+    /// is closest to `name`.
+    fn suggest_variable(&self, identifier: ExprId, name: &str) -> Option<String> {
+        let in_scope = self.resolution.in_scope(self.function, identifier);
+        closest_match(name, in_scope.iter().map(|id| self.resolution.name(*id)))
     }
 }
 
+/// Returns the candidate closest to `name` by edit distance, if it is close
+/// enough to plausibly be a typo (at most one edit per three characters).
+fn closest_match<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<String> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+/// Computes the optimal string alignment distance between two strings: the
+/// Levenshtein distance, with the transposition of adjacent characters, the
+/// most common typo, counted as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // Distances from the prefixes of `a` two rows back, one row back, and
+    // in the current row, to each prefix of `b`
+    let mut previous: Vec<usize> = Vec::new();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let substitution = row[j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            current[j] = substitution.min(row[j] + 1).min(current[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(previous[j - 2] + 1);
+            }
+        }
+        previous = std::mem::replace(&mut row, current);
+    }
+    row[b.len()]
+}
+
 /// Convenience function to analyze a program.
 pub fn analyze(program: &Program) -> Vec<SemanticError> {
     let mut analyzer = SemanticAnalyzer::new();
     analyzer.analyze(program)
 }
 
+/// Analyzes a program using the given compile options.
+pub fn analyze_with_options(program: &Program, options: &CompileOptions) -> Vec<SemanticError> {
+    analyze_located(program, options)
+        .into_iter()
+        .map(|(error, _)| error)
+        .collect()
+}
+
+/// Analyzes a program as [`analyze_with_options`] does, pairing each error
+/// with the innermost statement it was found in, if any, as the index of its
+/// function and its handle.
+pub(crate) fn analyze_located(
+    program: &Program,
+    options: &CompileOptions,
+) -> Vec<(SemanticError, Option<(usize, StmtId)>)> {
+    let mut analyzer = SemanticAnalyzer::with_options(options);
+    let errors = analyzer.analyze(program);
+    let mut located: Vec<_> = errors.into_iter().zip(analyzer.locations).collect();
+    located.extend(
+        undefined_exports(program, options)
+            .into_iter()
+            .chain(invalid_entry(program, options))
+            .map(|error| (error, None)),
+    );
+    located
+}
+
+/// Reports the functions that [`CompileOptions::exports`] names but the
+/// program does not define.
+fn undefined_exports(program: &Program, options: &CompileOptions) -> Vec<SemanticError> {
+    let defined = || program.functions.iter().map(|f| f.name.as_str());
+    options
+        .exports
+        .iter()
+        .flatten()
+        .filter(|name| !defined().any(|defined| defined == *name))
+        .map(|name| SemanticError::UndefinedFunction(name.clone(), closest_match(name, defined())))
+        .collect()
+}
+
+/// Checks that the function [`CompileOptions::entry`] names can start the
+/// program in place of `main`.
+fn invalid_entry(program: &Program, options: &CompileOptions) -> Option<SemanticError> {
+    let name = options.entry.as_deref()?;
+    let defined = || program.functions.iter().map(|f| f.name.as_str());
+    let Some(function) = program.functions.iter().find(|f| f.name == name) else {
+        return Some(SemanticError::UndefinedFunction(
+            name.to_string(),
+            closest_match(name, defined()),
+        ));
+    };
+    let invalid = |reason: &str| {
+        Some(SemanticError::InvalidEntry(
+            name.to_string(),
+            reason.to_string(),
+        ))
+    };
+    if !function.params.is_empty() || function.return_ty != Type::Int {
+        invalid("it must take no parameters and return int")
+    } else if name != "main" && defined().any(|defined| defined == "main") {
+        invalid("the program defines main, which would be replaced")
+    } else {
+        None
+    }
+}
+
+/// Analyzes a standalone expression using the given compile options.
+pub fn analyze_expr_with_options(expr: &ExprTree, options: &CompileOptions) -> Vec<SemanticError> {
+    let mut analyzer = SemanticAnalyzer::with_options(options);
+    analyzer.analyze_expr(expr).1
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::ConstEvalError;
     use crate::lexer::lex;
     use crate::parser::parse;
 
@@ -353,7 +678,118 @@ mod tests {
         let ast = parse(&tokens).unwrap();
         let errors = analyze(&ast);
         assert_eq!(errors.len(), 1);
-        assert!(matches!(errors[0], SemanticError::UndefinedVariable(_)));
+        assert!(matches!(errors[0], SemanticError::UndefinedVariable(..)));
+    }
+
+    #[test]
+    fn test_undefined_exports() {
+        let ast = parse(&lex("int area() { return 1; }").unwrap()).unwrap();
+        let options = CompileOptions {
+            exports: Some(vec!["area".to_string(), "areas".to_string()]),
+            ..CompileOptions::default()
+        };
+        assert_eq!(
+            analyze_with_options(&ast, &options),
+            [SemanticError::UndefinedFunction(
+                "areas".to_string(),
+                Some("area".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_invalid_entry() {
+        let errors = |source: &str, entry: &str| {
+            let ast = parse(&lex(source).unwrap()).unwrap();
+            let options = CompileOptions {
+                entry: Some(entry.to_string()),
+                ..CompileOptions::default()
+            };
+            analyze_with_options(&ast, &options)
+        };
+        assert!(errors("int solve() { return 0; }", "solve").is_empty());
+        assert_eq!(
+            errors("int solve() { return 0; }", "solv"),
+            [SemanticError::UndefinedFunction(
+                "solv".to_string(),
+                Some("solve".to_string())
+            )]
+        );
+        assert!(matches!(
+            &errors("int solve(int n) { return n; }", "solve")[..],
+            [SemanticError::InvalidEntry(name, _)] if name == "solve"
+        ));
+        assert!(matches!(
+            &errors("float solve() { return 0.0; }", "solve")[..],
+            [SemanticError::InvalidEntry(..)]
+        ));
+        assert!(matches!(
+            &errors(
+                "int solve() { return 0; } int main() { return 1; }",
+                "solve"
+            )[..],
+            [SemanticError::InvalidEntry(..)]
+        ));
+    }
+
+    #[test]
+    fn test_undefined_names_suggest_closest_match() {
+        let input = "int count() { return 1; } int main() { int total = 0; total = totl + cont(); return 0; }";
+        let tokens = lex(input).unwrap();
+        let ast = parse(&tokens).unwrap();
+        let errors = analyze(&ast);
+        assert_eq!(
+            errors[..2],
+            [
+                SemanticError::UndefinedVariable("totl".to_string(), Some("total".to_string())),
+                SemanticError::UndefinedFunction("cont".to_string(), Some("count".to_string())),
+            ]
+        );
+
+        let input = "int main() { int total = 0; return xyz; }";
+        let ast = parse(&lex(input).unwrap()).unwrap();
+        assert_eq!(
+            analyze(&ast),
+            vec![SemanticError::UndefinedVariable("xyz".to_string(), None)]
+        );
+
+        // Swapped letters count as one typo
+        let input = "int count() { return 1; } int main() { return coutn(); }";
+        let ast = parse(&lex(input).unwrap()).unwrap();
+        assert_eq!(
+            analyze(&ast),
+            vec![SemanticError::UndefinedFunction(
+                "coutn".to_string(),
+                Some("count".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_invalid_assignment_target() {
+        let input = "int f() { return 1; } int main() { int x = 0; x = 1; f() = 2; (x = 1) = 2; return 0; }";
+        let ast = parse(&lex(input).unwrap()).unwrap();
+        assert_eq!(
+            analyze(&ast),
+            vec![
+                SemanticError::InvalidAssignmentTarget(
+                    "cannot assign to the result of a function call".to_string()
+                ),
+                SemanticError::InvalidAssignmentTarget(
+                    "cannot assign to the result of an assignment".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("count", "count"), 0);
+        assert_eq!(edit_distance("coutn", "count"), 1);
+        assert_eq!(edit_distance("ab", "ba"), 1);
+        assert_eq!(edit_distance("ca", "abc"), 3);
     }
 
     #[test]
@@ -365,6 +801,27 @@ mod tests {
         assert!(!errors.is_empty()); // Should have type mismatch
     }
 
+    #[test]
+    fn test_extern_conflicting_with_header() {
+        let input = "#include <stdio.h>
+                     extern int printf(int);
+                     int main() { return printf(1); }";
+        let errors = analyze(&parse(&lex(input).unwrap()).unwrap());
+        assert_eq!(
+            errors,
+            [SemanticError::ConflictingDeclaration(
+                "printf".to_string(),
+                "stdio.h".to_string()
+            )]
+        );
+
+        // Redeclaring with the header's signature is fine
+        let input = "#include <stdio.h>
+                     extern int printf(string, ...);
+                     int main() { return printf(\"hi\"); }";
+        assert!(analyze(&parse(&lex(input).unwrap()).unwrap()).is_empty());
+    }
+
     #[test]
     fn test_duplicate_variable() {
         let input = "int foo() { int x = 5; int x = 6; return x; }";
@@ -375,6 +832,26 @@ mod tests {
         assert!(matches!(errors[0], SemanticError::DuplicateVariable(_)));
     }
 
+    #[test]
+    fn test_shadowed_variables_have_their_own_types() {
+        let input = "float f(int x) {
+            { float x = 1.5; x = x * 2.0; }
+            for (float x = 0.0; x < 1.0; x = x + 0.5) { }
+            int y = x + 1;
+            { float y = 2.5; return y; }
+        }";
+        let ast = parse(&lex(input).unwrap()).unwrap();
+        assert_eq!(analyze(&ast), []);
+
+        // Out of its block, the inner variable is gone
+        let input = "int f() { { float x = 1.5; } return x; }";
+        let ast = parse(&lex(input).unwrap()).unwrap();
+        assert!(matches!(
+            &analyze(&ast)[..],
+            [SemanticError::UndefinedVariable(name, None)] if name == "x"
+        ));
+    }
+
     #[test]
     fn test_return_type_mismatch_float_to_int() {
         let input = "int foo() { return 3.14; }";
@@ -413,4 +890,99 @@ mod tests {
         let errors = analyze(&ast);
         assert!(errors.is_empty());
     }
+
+    #[test]
+    fn test_int_literal_out_of_range_for_32_bit() {
+        let input = "int foo() { return 3000000000; }";
+        let tokens = lex(input).unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert!(analyze(&ast).is_empty());
+        let options = CompileOptions {
+            int_width: IntWidth::W32,
+            ..CompileOptions::default()
+        };
+        assert_eq!(
+            analyze_with_options(&ast, &options),
+            vec![SemanticError::IntegerOutOfRange(3000000000, IntWidth::W32)]
+        );
+    }
+
+    #[test]
+    fn test_invalid_constant_expressions() {
+        let errors = |input: &str| analyze(&parse(&lex(input).unwrap()).unwrap());
+        assert!(errors("int foo() { return 2 * 3 - 10 / 2; }").is_empty());
+        assert_eq!(
+            errors("int foo(int x) { return x + (1 - 1 + 4) / (2 - 2); }"),
+            vec![SemanticError::InvalidConstant(
+                ConstEvalError::DivisionByZero
+            )]
+        );
+        assert_eq!(
+            errors("int foo() { return 9223372036854775807 + 1 + 1; }"),
+            vec![SemanticError::InvalidConstant(ConstEvalError::Overflow)]
+        );
+        assert!(errors("float foo() { return 1.0 / 0.0; }").is_empty());
+    }
+
+    #[test]
+    fn test_assert_builtin() {
+        let errors = |input: &str| analyze(&parse(&lex(input).unwrap()).unwrap());
+        assert!(errors("#include <assert.h> int main() { assert(1 < 2); return 0; }").is_empty());
+        assert_eq!(
+            errors("#include <assert.h> int main() { return assert(1); }"),
+            vec![SemanticError::MisplacedAssertion]
+        );
+        assert_eq!(
+            errors("#include <assert.h> int main() { assert(1, 2); return 0; }"),
+            vec![SemanticError::WrongArgumentCount(
+                "assert".to_string(),
+                1,
+                2
+            )]
+        );
+        // Without the header, assert is an ordinary function
+        assert!(
+            errors("int assert(int x) { return x; } int main() { return assert(1); }").is_empty()
+        );
+    }
+
+    #[test]
+    fn test_printf_format_checking() {
+        let valid = r#"#include <stdio.h> int main() { printf("%s %d %5.2f%%\n", "a", 1, 2.0); return 0; }"#;
+        let ast = parse(&lex(valid).unwrap()).unwrap();
+        assert!(analyze(&ast).is_empty());
+
+        let wrong_type = r#"#include <stdio.h> int main() { printf("%d\n", 2.0); return 0; }"#;
+        let errors = analyze(&parse(&lex(wrong_type).unwrap()).unwrap());
+        assert_eq!(errors.len(), 1);
+        assert!(
+            matches!(&errors[0], SemanticError::InvalidFormat(msg) if msg.ends_with("use '%f' to print a float"))
+        );
+
+        // The number of arguments is only checked with 32-bit int
+        let wrong_count = r#"#include <stdio.h> int main() { printf("%d %d\n", 1); return 0; }"#;
+        let ast = parse(&lex(wrong_count).unwrap()).unwrap();
+        assert!(analyze(&ast).is_empty());
+        let options = CompileOptions {
+            int_width: IntWidth::W32,
+            ..CompileOptions::default()
+        };
+        let errors = analyze_with_options(&ast, &options);
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], SemanticError::InvalidFormat(_)));
+    }
+
+    #[test]
+    fn test_printf_long_modifier_depends_on_int_width() {
+        let input = r#"#include <stdio.h> int main() { printf("%ld\n", 1); return 0; }"#;
+        let ast = parse(&lex(input).unwrap()).unwrap();
+        assert!(analyze(&ast).is_empty());
+        let options = CompileOptions {
+            int_width: IntWidth::W32,
+            ..CompileOptions::default()
+        };
+        let errors = analyze_with_options(&ast, &options);
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], SemanticError::InvalidFormat(_)));
+    }
 }
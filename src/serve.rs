@@ -0,0 +1,415 @@
+//! # Compiler Service
+//!
+//! This module keeps the compiler running as a service, `virtuc serve`, so
+//! that playground backends and grading servers can check, compile, and run
+//! programs without embedding the crate. Clients speak JSON-RPC 2.0 over the
+//! service's stdin and stdout, one request per line and one response per
+//! line:
+//!
+//! ```text
+//! --> {"jsonrpc":"2.0","id":1,"method":"run","params":{"source":"int main() { return 3; }"}}
+//! <-- {"jsonrpc":"2.0","id":1,"result":{"ok":true,"diagnostics":[],"exit_code":3,...}}
+//! ```
+//!
+//! ## Methods
+//!
+//! - `check`: semantic errors, or the warnings of a valid program
+//! - `emit`: the program as `ir` (LLVM IR, the default), `mir`, or formatted
+//!   `source`, named by the `emit` parameter
+//! - `run`: compiles the program and runs it with the `stdin` text and
+//!   `args` given, returning its exit code and output
+//! - `shutdown`: answers `null` and stops the service
+//!
+//! Each method takes the program as `source`, and optionally `opt_level` (0
+//! to 3) and `int_width` (32 or 64). A program that does not compile is not
+//! an error of the request: the result has `"ok": false` and the program's
+//! diagnostics, as printed by `--message-format=json`.
+//!
+//! ## Limits
+//!
+//! Programs run in a scratch directory with an empty environment, and are
+//! killed when they exceed the [`Limits`] of the service. A request may ask
+//! for lower limits with `timeout_ms` and `max_output`, but not for higher
+//! ones.
+
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::analysis;
+use crate::diagnostics::{Diagnostic, locate_warnings};
+use crate::error::CompileError;
+use crate::options::{CompileOptions, IntWidth};
+use crate::printer::print_program;
+use crate::run::{RunConfig, run_program};
+
+/// Limits on the programs the service runs.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Limits {
+    /// Longest a program may run
+    pub timeout: Duration,
+    /// Most bytes a program may write to each of stdout and stderr
+    pub max_output: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            timeout: Duration::from_secs(10),
+            max_output: 1 << 20,
+        }
+    }
+}
+
+/// JSON-RPC error codes.
+mod code {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const INTERNAL_ERROR: i64 = -32603;
+}
+
+/// An error response: (code, message).
+type RpcError = (i64, String);
+
+/// A request.
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// The parameters of the methods that take a program; each method ignores
+/// those it has no use for.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Params {
+    source: String,
+    #[serde(default)]
+    opt_level: u8,
+    #[serde(default)]
+    int_width: Option<u32>,
+    #[serde(default)]
+    emit: EmitKind,
+    #[serde(default)]
+    stdin: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    timeout_ms: Option<u64>,
+    #[serde(default)]
+    max_output: Option<usize>,
+}
+
+/// The forms `emit` can return.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum EmitKind {
+    #[default]
+    Ir,
+    Mir,
+    Source,
+}
+
+impl Params {
+    /// Returns the compile options the parameters select.
+    fn options(&self) -> Result<CompileOptions, RpcError> {
+        if self.opt_level > 3 {
+            return Err(invalid_params("opt_level must be 0 to 3"));
+        }
+        let int_width = match self.int_width {
+            Some(bits) => bits
+                .to_string()
+                .parse()
+                .map_err(|e: String| invalid_params(&e))?,
+            None => IntWidth::default(),
+        };
+        Ok(CompileOptions {
+            opt_level: self.opt_level,
+            int_width,
+            source_name: Some("<request>".to_string()),
+            ..CompileOptions::default()
+        })
+    }
+}
+
+/// A compiler service, answering one request at a time.
+#[derive(Debug)]
+pub struct Server {
+    limits: Limits,
+    /// Scratch directory for executables, removed with the server
+    dir: PathBuf,
+    /// Number of programs run so far, naming their executables
+    runs: u64,
+    /// Whether `shutdown` was requested
+    stopped: bool,
+}
+
+impl Server {
+    /// Creates a service that runs programs within `limits`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the scratch directory cannot be created.
+    pub fn new(limits: Limits) -> io::Result<Self> {
+        static SERVERS: AtomicU64 = AtomicU64::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "virtuc-serve-{}-{}",
+            process::id(),
+            SERVERS.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir)?;
+        Ok(Server {
+            limits,
+            dir,
+            runs: 0,
+            stopped: false,
+        })
+    }
+
+    /// Returns true once `shutdown` has been requested.
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Answers a request, given as a line of JSON. Returns the response, or
+    /// `None` for a notification, a request without an `id`.
+    pub fn handle(&mut self, line: &str) -> Option<String> {
+        let request: Request = match serde_json::from_str::<Value>(line) {
+            Err(e) => return Some(error_response(Value::Null, code::PARSE_ERROR, e)),
+            Ok(value) => match serde_json::from_value(value) {
+                Ok(request) => request,
+                Err(e) => return Some(error_response(Value::Null, code::INVALID_REQUEST, e)),
+            },
+        };
+        let result = self.dispatch(&request.method, request.params);
+        let id = request.id?;
+        let response = match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err((code, message)) => return Some(error_response(id, code, message)),
+        };
+        Some(response.to_string())
+    }
+
+    /// Answers requests read from `input` until it ends or `shutdown` is
+    /// requested, writing the responses to `output`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of reading a request or writing a response.
+    pub fn serve(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle(&line) {
+                writeln!(output, "{}", response)?;
+                output.flush()?;
+            }
+            if self.stopped {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls a method, returning its result or the error response.
+    fn dispatch(&mut self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "check" => {
+                let params: Params = parse_params(params)?;
+                let options = params.options()?;
+                Ok(match crate::check_with_options(&params.source, &options) {
+                    Ok(program) => {
+                        let warnings =
+                            locate_warnings(&analysis::lint(&program), &params.source, &options);
+                        succeeded(&warnings, &params.source)
+                    }
+                    Err(e) => failed(&e, &params.source),
+                })
+            }
+            "emit" => {
+                let params: Params = parse_params(params)?;
+                let source = &params.source;
+                let options = params.options()?;
+                let output = match params.emit {
+                    EmitKind::Ir => crate::emit_ir_with_options(source, &options),
+                    EmitKind::Mir => crate::emit_mir_with_options(source, &options),
+                    EmitKind::Source => {
+                        crate::check_with_options(source, &options).map(|p| print_program(&p))
+                    }
+                };
+                Ok(match output {
+                    Ok(output) => {
+                        let mut result = succeeded(&[], source);
+                        result["output"] = output.into();
+                        result
+                    }
+                    Err(e) => failed(&e, source),
+                })
+            }
+            "run" => {
+                let params: Params = parse_params(params)?;
+                self.run(params)
+            }
+            "shutdown" => {
+                self.stopped = true;
+                Ok(Value::Null)
+            }
+            _ => Err((
+                code::METHOD_NOT_FOUND,
+                format!("unknown method '{}'", method),
+            )),
+        }
+    }
+
+    /// Compiles and runs a program within the limits of the service.
+    fn run(&mut self, params: Params) -> Result<Value, RpcError> {
+        let source = &params.source;
+        let options = params.options()?;
+        self.runs += 1;
+        let executable = self.dir.join(format!("program-{}", self.runs));
+        let artifacts = match crate::compile_with_options(source, &executable, &options) {
+            Ok(artifacts) => artifacts,
+            Err(e) => return Ok(failed(&e, source)),
+        };
+        let timeout = params
+            .timeout_ms
+            .map_or(self.limits.timeout, Duration::from_millis)
+            .min(self.limits.timeout);
+        let max_output = params
+            .max_output
+            .unwrap_or(self.limits.max_output)
+            .min(self.limits.max_output);
+        let config = RunConfig {
+            args: params.args,
+            stdin: Some(params.stdin.into_bytes()),
+            timeout: Some(timeout),
+            max_output: Some(max_output),
+            clear_env: true,
+            current_dir: Some(self.dir.clone()),
+            ..RunConfig::default()
+        };
+        let result = run_program(&executable, &config);
+        let _ = fs::remove_file(&executable);
+        let run =
+            result.map_err(|e| (code::INTERNAL_ERROR, format!("cannot run program: {}", e)))?;
+        let warnings = locate_warnings(&artifacts.warnings, source, &options);
+        let mut result = succeeded(&warnings, source);
+        result["exit_code"] = run.exit_code.into();
+        result["exceeded"] = run.exceeded.map(|limit| limit.to_string()).into();
+        result["stdout"] = run.stdout_text().into();
+        result["stderr"] = run.stderr_text().into();
+        result["duration_ms"] = (run.duration.as_millis() as u64).into();
+        Ok(result)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Deserializes the parameters of a method.
+fn parse_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| invalid_params(&e.to_string()))
+}
+
+/// An error for parameters that do not fit the method.
+fn invalid_params(message: &str) -> RpcError {
+    (code::INVALID_PARAMS, message.to_string())
+}
+
+/// The result for a program that compiled, with its warnings.
+fn succeeded(warnings: &[Diagnostic], source: &str) -> Value {
+    json!({"ok": true, "diagnostics": to_json(warnings, source)})
+}
+
+/// The result for a program that failed to compile.
+fn failed(error: &CompileError, source: &str) -> Value {
+    let diagnostics: Vec<Diagnostic> = error.into();
+    json!({"ok": false, "diagnostics": to_json(&diagnostics, source)})
+}
+
+/// Converts diagnostics to JSON values, locating their spans in `source`.
+fn to_json(diagnostics: &[Diagnostic], source: &str) -> Value {
+    diagnostics
+        .iter()
+        .map(|d| {
+            serde_json::from_str::<Value>(&d.to_json(Some(source))).expect("diagnostics are JSON")
+        })
+        .collect()
+}
+
+/// Formats an error response to the request `id`.
+fn error_response(id: Value, code: i64, message: impl ToString) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {"code": code, "message": message.to_string()},
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(server: &mut Server, method: &str, params: Value) -> Value {
+        let request = json!({"jsonrpc": "2.0", "id": 7, "method": method, "params": params});
+        let response = server.handle(&request.to_string()).unwrap();
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["id"], 7);
+        response
+    }
+
+    #[test]
+    fn test_check_and_emit() {
+        let mut server = Server::new(Limits::default()).unwrap();
+        let result = &call(
+            &mut server,
+            "check",
+            json!({"source": "int main() { return x; }"}),
+        )["result"];
+        assert_eq!(result["ok"], false);
+        assert_eq!(result["diagnostics"][0]["code"], "E0201");
+
+        let source = "int unused() { return 1; } int main() { return 0; }";
+        let result = &call(&mut server, "check", json!({"source": source}))["result"];
+        assert_eq!(result["ok"], true);
+        assert_eq!(result["diagnostics"][0]["severity"], "warning");
+
+        let params = json!({"source": "int main() { return 1 + 2; }", "emit": "mir"});
+        let result = &call(&mut server, "emit", params)["result"];
+        assert!(result["output"].as_str().unwrap().contains("_0 = 1 + 2;"));
+    }
+
+    #[test]
+    fn test_protocol_errors() {
+        let mut server = Server::new(Limits::default()).unwrap();
+        let response = call(&mut server, "compile", json!({}));
+        assert_eq!(response["error"]["code"], code::METHOD_NOT_FOUND);
+        let response = call(&mut server, "check", json!({"source": "", "opt_level": 9}));
+        assert_eq!(response["error"]["code"], code::INVALID_PARAMS);
+        let response = call(&mut server, "check", json!({"sauce": ""}));
+        assert_eq!(response["error"]["code"], code::INVALID_PARAMS);
+        let response: Value = serde_json::from_str(&server.handle("{not json").unwrap()).unwrap();
+        assert_eq!(response["error"]["code"], code::PARSE_ERROR);
+        // Notifications get no response
+        let notification = json!({"jsonrpc": "2.0", "method": "shutdown"});
+        assert_eq!(server.handle(&notification.to_string()), None);
+        assert!(server.stopped());
+    }
+}
@@ -0,0 +1,174 @@
+//! # Binary Size Reports
+//!
+//! This module measures what a compiled program is made of, to show what
+//! bloats it. [`section_sizes`] reads the sections of a linked executable
+//! (`virtuc compile --size-report`), and [`function_sizes`] counts the IR
+//! instructions of each function in a module after optimization
+//! (`virtuc emit --size-report`).
+//!
+//! Executables shrink with [`CompileOptions::strip`], which drops the symbol
+//! table and lets the linker remove unused sections.
+//!
+//! [`CompileOptions::strip`]: crate::options::CompileOptions::strip
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use inkwell::memory_buffer::MemoryBuffer;
+use inkwell::module::Module;
+
+/// The sections of an executable, in file order.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SectionSizes {
+    /// Name and size in bytes of each non-empty section
+    pub sections: Vec<(String, u64)>,
+}
+
+impl SectionSizes {
+    /// Returns the total size of the sections in bytes.
+    pub fn total(&self) -> u64 {
+        self.sections.iter().map(|(_, size)| size).sum()
+    }
+}
+
+impl fmt::Display for SectionSizes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.total();
+        for (name, size) in &self.sections {
+            let percent = if total == 0 {
+                0.0
+            } else {
+                *size as f64 / total as f64 * 100.0
+            };
+            writeln!(f, "{:<20} {:>10} {:>5.1}%", name, size, percent)?;
+        }
+        write!(f, "{:<20} {:>10}", "total", total)
+    }
+}
+
+/// The size of a function's IR.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FunctionSize {
+    /// Function name
+    pub name: String,
+    /// Number of basic blocks
+    pub blocks: usize,
+    /// Number of instructions
+    pub instructions: usize,
+}
+
+/// The functions defined by a module, largest first.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FunctionSizes {
+    /// Size of each function
+    pub functions: Vec<FunctionSize>,
+}
+
+impl FunctionSizes {
+    /// Returns the total number of instructions.
+    pub fn total(&self) -> usize {
+        self.functions.iter().map(|f| f.instructions).sum()
+    }
+}
+
+impl fmt::Display for FunctionSizes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<24} {:>7} {:>12}",
+            "function", "blocks", "instructions"
+        )?;
+        for function in &self.functions {
+            writeln!(
+                f,
+                "{:<24} {:>7} {:>12}",
+                function.name, function.blocks, function.instructions
+            )?;
+        }
+        writeln!(f, "{:<24} {:>7} {:>12}", "total", "", self.total())
+    }
+}
+
+/// Reads the sizes of the sections of the executable or object file at
+/// `path`.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read, or is not an object file
+/// LLVM understands.
+pub fn section_sizes(path: &Path) -> io::Result<SectionSizes> {
+    let buffer = MemoryBuffer::create_from_file(path)
+        .map_err(|e| io::Error::other(format!("cannot read {}: {}", path.display(), e)))?;
+    let object = buffer
+        .create_object_file()
+        .map_err(|()| io::Error::other(format!("{} is not an object file", path.display())))?;
+    let sections = object
+        .get_sections()
+        .filter(|section| section.size() > 0)
+        .filter_map(|section| {
+            let name = section.get_name()?.to_string_lossy().into_owned();
+            (!name.is_empty()).then_some((name, section.size()))
+        })
+        .collect();
+    Ok(SectionSizes { sections })
+}
+
+/// Counts the blocks and instructions of each function the module defines.
+pub fn function_sizes(module: &Module) -> FunctionSizes {
+    let mut functions: Vec<FunctionSize> = module
+        .get_functions()
+        .filter(|function| function.count_basic_blocks() > 0)
+        .map(|function| FunctionSize {
+            name: function.get_name().to_string_lossy().into_owned(),
+            blocks: function.count_basic_blocks() as usize,
+            instructions: function
+                .get_basic_block_iter()
+                .map(|block| block.get_instructions().count())
+                .sum(),
+        })
+        .collect();
+    functions.sort_by(|a, b| {
+        b.instructions
+            .cmp(&a.instructions)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    FunctionSizes { functions }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::generate_module;
+    use crate::lexer::lex;
+    use crate::options::CompileOptions;
+    use crate::parser::parse;
+    use inkwell::context::Context;
+
+    #[test]
+    fn test_function_sizes() {
+        let source = "int square(int x) { return x * x; }\n\
+                      int main() { if (square(2) > 3) { return 1; } return 0; }";
+        let program = parse(&lex(source).unwrap()).unwrap();
+        let context = Context::create();
+        let module = generate_module(&context, &program, &CompileOptions::default()).unwrap();
+        let sizes = function_sizes(&module);
+        let names: Vec<_> = sizes.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["main", "square"]);
+        assert_eq!(sizes.functions[1].blocks, 1);
+        assert_eq!(
+            sizes.total(),
+            sizes.functions[0].instructions + sizes.functions[1].instructions
+        );
+        assert!(sizes.to_string().starts_with("function"));
+    }
+
+    #[test]
+    fn test_section_sizes_of_non_object() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "not an object").unwrap();
+        let e = section_sizes(&path).unwrap_err();
+        assert!(e.to_string().contains("is not an object file"));
+    }
+}
@@ -0,0 +1,500 @@
+//! # Program Templates
+//!
+//! This module lets a host program generate code at run time from a program
+//! it parsed once, the template: it can add functions, specialize a function
+//! for constant arguments, and unroll the loops whose trip count is then
+//! known, before compiling the result with
+//! [`compile_program_with_options`](crate::compile_program_with_options).
+//!
+//! ## Usage
+//!
+//! ```
+//! use virtuc::ast::Literal;
+//! use virtuc::template::Template;
+//!
+//! let mut template = Template::parse(
+//!     "int dot(int n, int x) {
+//!          int total = 0;
+//!          for (int i = 0; i < n; i = i + 1) { total = total + x * i; }
+//!          return total;
+//!      }",
+//! )
+//! .unwrap();
+//! template
+//!     .specialize("dot", "dot4", &[("n", Literal::Int(4))])
+//!     .unwrap()
+//!     .unroll("dot4", 16)
+//!     .unwrap()
+//!     .inject_source("int main() { return dot4(2); }")
+//!     .unwrap();
+//! let program = template.into_program();
+//! assert!(virtuc::semantic::analyze(&program).is_empty());
+//! ```
+//!
+//! The rewrites keep the program valid, but injected functions are not
+//! checked until the program is compiled.
+
+use crate::ast::*;
+use crate::error::{CompileError, TemplateError};
+use crate::header_registry;
+use crate::interner::Symbol;
+use crate::parser;
+
+/// A program being rewritten into the program to compile.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Template {
+    program: Program,
+}
+
+impl Template {
+    /// Starts from a program, usually one that passed semantic analysis.
+    pub fn new(program: Program) -> Self {
+        Template { program }
+    }
+
+    /// Starts from source code, which must pass semantic analysis.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the failing phase, as [`check`](crate::check)
+    /// does.
+    pub fn parse(source: &str) -> Result<Self, CompileError> {
+        crate::check(source).map(Template::new)
+    }
+
+    /// Returns the function `name`, if the template defines it.
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.program.functions.iter().find(|f| f.name == name)
+    }
+
+    /// Adds a function.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the template already defines or declares a
+    /// function with the same name.
+    pub fn inject(&mut self, function: Function) -> Result<&mut Self, TemplateError> {
+        let declared = self
+            .program
+            .extern_functions
+            .iter()
+            .any(|e| e.name == function.name);
+        if declared || self.function(&function.name).is_some() {
+            return Err(TemplateError::DuplicateFunction(function.name));
+        }
+        self.program.functions.push(function);
+        Ok(self)
+    }
+
+    /// Parses a function definition and adds it, as [`inject`](Self::inject)
+    /// does. The function may call the functions of the template.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the source is not a function definition, or if
+    /// the template already has a function with its name.
+    pub fn inject_source(&mut self, source: &str) -> Result<&mut Self, TemplateError> {
+        let function =
+            parser::parse_function_str(source).map_err(|e| TemplateError::Source(Box::new(e)))?;
+        self.inject(function)
+    }
+
+    /// Adds `new_name`, a copy of the function `name` whose parameters in
+    /// `bindings` are replaced by constant values. Reads of a parameter
+    /// become the value itself, unless the function assigns or redeclares
+    /// the parameter, in which case it becomes a local variable initialized
+    /// to the value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `name` is not defined, `new_name` already is, or
+    /// a binding names no parameter or has a value of another type.
+    pub fn specialize(
+        &mut self,
+        name: &str,
+        new_name: &str,
+        bindings: &[(&str, Literal)],
+    ) -> Result<&mut Self, TemplateError> {
+        let mut function = self
+            .function(name)
+            .ok_or_else(|| TemplateError::UndefinedFunction(name.to_string()))?
+            .clone();
+        let mut locals = Vec::new();
+        for (param, value) in bindings {
+            let index = function
+                .params
+                .iter()
+                .position(|(_, p)| p == param)
+                .ok_or_else(|| {
+                    TemplateError::UndefinedParameter(name.to_string(), param.to_string())
+                })?;
+            let (ty, _) = function.params.remove(index);
+            if literal_type(value) != ty {
+                return Err(TemplateError::MismatchedBinding(
+                    name.to_string(),
+                    param.to_string(),
+                ));
+            }
+            let param = Symbol::intern(param);
+            let arena = &mut function.arena;
+            if writes(arena, function.body, param) {
+                let init = arena.alloc_expr(Expr::Literal(value.clone()));
+                locals.push(arena.alloc_stmt(Stmt::Declaration {
+                    ty,
+                    name: param,
+                    init: Some(init),
+                }));
+            } else {
+                let mut roots = Vec::new();
+                exprs(arena, function.body, &mut roots);
+                for expr in roots {
+                    substitute(arena, expr, param, value);
+                }
+            }
+        }
+        if let Stmt::Block(stmts) = &mut function.arena[function.body] {
+            stmts.splice(0..0, locals);
+        }
+        function.name = new_name.to_string();
+        self.inject(function)
+    }
+
+    /// Unrolls the counted loops of the function `name` that run at most
+    /// `max_trips` times. A counted loop declares an int variable
+    /// initialized to a constant, compares it with `<` or `<=` against a
+    /// constant, and adds a positive constant to it, such as
+    /// `for (int i = 0; i < 4; i = i + 1)`, and its body does not assign
+    /// the variable. Loops nested in an unrolled loop are unrolled first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the template does not define `name`.
+    pub fn unroll(&mut self, name: &str, max_trips: u64) -> Result<&mut Self, TemplateError> {
+        let function = self
+            .program
+            .functions
+            .iter_mut()
+            .find(|f| f.name == name)
+            .ok_or_else(|| TemplateError::UndefinedFunction(name.to_string()))?;
+        unroll_stmt(&mut function.arena, function.body, max_trips);
+        Ok(self)
+    }
+
+    /// Returns the program as rewritten so far.
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Finishes the program, declaring the functions of included headers
+    /// that injected functions call.
+    pub fn into_program(mut self) -> Program {
+        header_registry::declare_used_externs(&mut self.program);
+        self.program
+    }
+}
+
+/// Returns the type of a literal.
+fn literal_type(value: &Literal) -> Type {
+    match value {
+        Literal::Int(_) => Type::Int,
+        Literal::Float(_) => Type::Float,
+        Literal::String(_) => Type::String,
+    }
+}
+
+/// Returns true if the statement declares or assigns the variable `name`.
+fn writes(arena: &Arena, stmt: StmtId, name: Symbol) -> bool {
+    let in_expr = |expr: &ExprId| writes_expr(arena, *expr, name);
+    let in_stmt = |stmt: &StmtId| writes(arena, *stmt, name);
+    match &arena[stmt] {
+        Stmt::Declaration { name: declared, .. } if *declared == name => true,
+        Stmt::Declaration { init, .. } => init.as_ref().is_some_and(in_expr),
+        Stmt::Return(value) => value.as_ref().is_some_and(in_expr),
+        Stmt::Block(stmts) => stmts.iter().any(in_stmt),
+        Stmt::If { cond, then, else_ } => {
+            in_expr(cond) || in_stmt(then) || else_.as_ref().is_some_and(in_stmt)
+        }
+        Stmt::For {
+            init,
+            cond,
+            update,
+            body,
+        } => {
+            init.as_ref().is_some_and(in_stmt)
+                || cond.as_ref().is_some_and(in_expr)
+                || update.as_ref().is_some_and(in_expr)
+                || in_stmt(body)
+        }
+        Stmt::Expr(expr) => in_expr(expr),
+        Stmt::Empty => false,
+    }
+}
+
+/// Returns true if the expression assigns the variable `name`.
+fn writes_expr(arena: &Arena, expr: ExprId, name: Symbol) -> bool {
+    match &arena[expr] {
+        Expr::Assignment { target, value } => {
+            matches!(arena[*target], Expr::Identifier(t) if t == name)
+                || writes_expr(arena, *value, name)
+        }
+        Expr::Binary { left, right, .. } => {
+            writes_expr(arena, *left, name) || writes_expr(arena, *right, name)
+        }
+        Expr::Call { args, .. } => args.iter().any(|arg| writes_expr(arena, *arg, name)),
+        Expr::Literal(_) | Expr::Identifier(_) => false,
+    }
+}
+
+/// Collects the expressions of a statement that are not part of another
+/// expression.
+fn exprs(arena: &Arena, stmt: StmtId, found: &mut Vec<ExprId>) {
+    match &arena[stmt] {
+        Stmt::Declaration { init: expr, .. } | Stmt::Return(expr) => found.extend(expr),
+        Stmt::Block(stmts) => {
+            for stmt in stmts {
+                exprs(arena, *stmt, found);
+            }
+        }
+        Stmt::If { cond, then, else_ } => {
+            found.push(*cond);
+            exprs(arena, *then, found);
+            if let Some(else_) = else_ {
+                exprs(arena, *else_, found);
+            }
+        }
+        Stmt::For {
+            init,
+            cond,
+            update,
+            body,
+        } => {
+            if let Some(init) = init {
+                exprs(arena, *init, found);
+            }
+            found.extend(cond.iter().chain(update));
+            exprs(arena, *body, found);
+        }
+        Stmt::Expr(expr) => found.push(*expr),
+        Stmt::Empty => {}
+    }
+}
+
+/// Replaces the reads of the variable `name` with `value`, in place.
+fn substitute(arena: &mut Arena, expr: ExprId, name: Symbol, value: &Literal) {
+    match &arena[expr] {
+        Expr::Identifier(id) if *id == name => arena[expr] = Expr::Literal(value.clone()),
+        Expr::Binary { left, right, .. } => {
+            let (left, right) = (*left, *right);
+            substitute(arena, left, name, value);
+            substitute(arena, right, name, value);
+        }
+        Expr::Call { args, .. } => {
+            for arg in args.clone() {
+                substitute(arena, arg, name, value);
+            }
+        }
+        Expr::Assignment { value: v, .. } => substitute(arena, *v, name, value),
+        Expr::Literal(_) | Expr::Identifier(_) => {}
+    }
+}
+
+/// Unrolls the counted loops of a statement in place, innermost first.
+fn unroll_stmt(arena: &mut Arena, stmt: StmtId, max_trips: u64) {
+    match arena[stmt].clone() {
+        Stmt::Block(stmts) => {
+            for stmt in stmts {
+                unroll_stmt(arena, stmt, max_trips);
+            }
+        }
+        Stmt::If { then, else_, .. } => {
+            unroll_stmt(arena, then, max_trips);
+            if let Some(else_) = else_ {
+                unroll_stmt(arena, else_, max_trips);
+            }
+        }
+        Stmt::For {
+            init,
+            cond,
+            update,
+            body,
+        } => {
+            unroll_stmt(arena, body, max_trips);
+            let (Some(init), Some(cond), Some(update)) = (init, cond, update) else {
+                return;
+            };
+            match trip_count(arena, init, cond, update, body) {
+                Some(trips) if trips <= max_trips => {
+                    // The body is copied into a block, so that its
+                    // declarations stay local to each copy
+                    let body = match arena[body] {
+                        Stmt::Block(_) => body,
+                        _ => arena.alloc_stmt(Stmt::Block(vec![body])),
+                    };
+                    let mut stmts = vec![init];
+                    for trip in 0..trips {
+                        if trip > 0 {
+                            let update = arena.clone_expr(update);
+                            stmts.push(arena.alloc_stmt(Stmt::Expr(update)));
+                        }
+                        stmts.push(arena.clone_stmt(body));
+                    }
+                    arena[stmt] = Stmt::Block(stmts);
+                }
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Returns the number of times a counted loop runs, or `None` if the loop
+/// is not counted.
+fn trip_count(
+    arena: &Arena,
+    init: StmtId,
+    cond: ExprId,
+    update: ExprId,
+    body: StmtId,
+) -> Option<u64> {
+    let Stmt::Declaration {
+        ty: Type::Int,
+        name,
+        init: Some(start),
+    } = &arena[init]
+    else {
+        return None;
+    };
+    let Expr::Literal(Literal::Int(start)) = arena[*start] else {
+        return None;
+    };
+    let is_var = |expr: ExprId| matches!(&arena[expr], Expr::Identifier(id) if id == name);
+    let end = match &arena[cond] {
+        Expr::Binary { left, op, right } if is_var(*left) => match (op, &arena[*right]) {
+            (BinOp::LessThan, Expr::Literal(Literal::Int(end))) => *end as i128,
+            (BinOp::LessEqual, Expr::Literal(Literal::Int(end))) => *end as i128 + 1,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let step = match &arena[update] {
+        Expr::Assignment { target, value } if is_var(*target) => match &arena[*value] {
+            Expr::Binary {
+                left,
+                op: BinOp::Plus,
+                right,
+            } if is_var(*left) => match arena[*right] {
+                Expr::Literal(Literal::Int(step)) if step > 0 => step as i128,
+                _ => return None,
+            },
+            _ => return None,
+        },
+        _ => return None,
+    };
+    if writes(arena, body, *name) {
+        return None;
+    }
+    let start = start as i128;
+    let trips = if start >= end {
+        0
+    } else {
+        (end - start + step - 1) / step
+    };
+    u64::try_from(trips).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::print_program;
+    use crate::semantic;
+
+    const TEMPLATE: &str = "int scale(int n, int x) {
+        int total = 0;
+        for (int i = 0; i < n; i = i + 1) { total = total + x; }
+        return total;
+    }
+    int countdown(int n) { for (; n > 0; n = n - 1) { } return n; }";
+
+    #[test]
+    fn test_specialize_and_unroll() {
+        let mut template = Template::parse(TEMPLATE).unwrap();
+        template
+            .specialize("scale", "scale3", &[("n", Literal::Int(3))])
+            .unwrap()
+            .unroll("scale3", 8)
+            .unwrap();
+        let scale3 = template.function("scale3").unwrap();
+        assert_eq!(scale3.params, [(Type::Int, "x".to_string())]);
+        let printed = print_program(&Program {
+            functions: vec![scale3.clone()],
+            ..template.program().clone()
+        });
+        assert_eq!(printed.matches("total = total + x;").count(), 3);
+        assert_eq!(printed.matches("i = i + 1;").count(), 2);
+        assert!(!printed.contains("for"));
+        // The template itself is left as it was
+        assert!(template.function("scale").unwrap().params.len() == 2);
+
+        // Assigned parameters become locals
+        template
+            .specialize("countdown", "countdown5", &[("n", Literal::Int(5))])
+            .unwrap();
+        let countdown5 = template.function("countdown5").unwrap();
+        let Stmt::Block(body) = countdown5.body() else {
+            panic!("expected a block");
+        };
+        assert!(
+            matches!(&countdown5.arena[body[0]], Stmt::Declaration { name, .. } if name == "n")
+        );
+        assert!(semantic::analyze(&template.into_program()).is_empty());
+    }
+
+    #[test]
+    fn test_unroll_limits() {
+        let mut template = Template::parse(
+            "int f() { int s = 0; for (int i = 0; i <= 100; i = i + 1) { s = s + i; } return s; }
+             int g() { int s = 0; for (int i = 0; i < 4; i = i + 1) { i = i + 1; } return s; }",
+        )
+        .unwrap();
+        let before = template.clone();
+        template.unroll("f", 100).unwrap().unroll("g", 100).unwrap();
+        // f runs 101 times, and g assigns its counter
+        assert_eq!(template, before);
+        template.unroll("f", 101).unwrap();
+        assert_ne!(template, before);
+    }
+
+    #[test]
+    fn test_inject() {
+        let mut template =
+            Template::parse("#include <stdio.h>\nint twice(int x) { return x * 2; }").unwrap();
+        template
+            .inject_source(r#"int main() { printf("%ld\n", twice(2)); return 0; }"#)
+            .unwrap();
+        assert_eq!(
+            template.inject_source("int twice(int x) { return x; }"),
+            Err(TemplateError::DuplicateFunction("twice".to_string()))
+        );
+        assert!(matches!(
+            template.inject_source("int broken( { }"),
+            Err(TemplateError::Source(_))
+        ));
+        assert_eq!(
+            template.specialize("twice", "t", &[("y", Literal::Int(1))]),
+            Err(TemplateError::UndefinedParameter(
+                "twice".to_string(),
+                "y".to_string()
+            ))
+        );
+        assert_eq!(
+            template.specialize("twice", "t", &[("x", Literal::Float(1.0))]),
+            Err(TemplateError::MismatchedBinding(
+                "twice".to_string(),
+                "x".to_string()
+            ))
+        );
+        let program = template.into_program();
+        assert!(program.extern_functions.iter().any(|e| e.name == "printf"));
+        assert!(semantic::analyze(&program).is_empty());
+    }
+}
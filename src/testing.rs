@@ -0,0 +1,251 @@
+//! # Property Testing Support
+//!
+//! This module provides [`proptest`] strategies that generate valid
+//! [`Program`]s, available to downstream crates behind the `testing` feature.
+//! Generated programs pass semantic analysis and always terminate, so they
+//! can be printed, parsed, compiled, and run in property tests.
+//!
+//! ## Generated Programs
+//!
+//! A program consists of up to three helper functions `f0`, `f1`, ... and a
+//! `main`. To keep every program well-formed and terminating:
+//! - All values are `int`, and locals are declared at the top of a function
+//! - Functions only call helpers defined before them, so there is no recursion
+//! - Loops run a bounded number of times on counters the body never assigns
+//! - Divisors have the form `x * x + 1`, which is never zero or minus one
+//!
+//! Shrinking is inherited from the underlying strategies: failing programs
+//! shrink towards fewer functions, fewer statements, and smaller literals.
+
+use std::sync::Arc;
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::sample::select;
+
+use crate::ast::*;
+use crate::builder::{FnBuilder, ProgramBuilder, expr, stmt};
+
+/// Maximum nesting of loops; each level has its own counter variable.
+const MAX_LOOP_DEPTH: usize = 2;
+
+/// Names visible while generating a function body.
+#[derive(Debug, Clone)]
+struct Scope {
+    /// Variables that may be read
+    readable: Vec<String>,
+    /// Variables that may be assigned (excludes loop counters)
+    assignable: Vec<String>,
+    /// Functions that may be called, with their arity
+    callable: Vec<(String, usize)>,
+}
+
+/// Generates a valid, terminating program.
+pub fn arb_program() -> impl Strategy<Value = Program> {
+    vec(0usize..3, 0..3).prop_flat_map(|arities| {
+        let helpers: Vec<BoxedStrategy<Function>> = arities
+            .iter()
+            .enumerate()
+            .map(|(k, arity)| {
+                let callable = arities[..k]
+                    .iter()
+                    .enumerate()
+                    .map(|(j, arity)| (format!("f{}", j), *arity))
+                    .collect();
+                let params = (0..*arity).map(|i| format!("p{}", i)).collect();
+                arb_function(format!("f{}", k), params, callable)
+            })
+            .collect();
+        let callable: Vec<(String, usize)> = arities
+            .iter()
+            .enumerate()
+            .map(|(j, arity)| (format!("f{}", j), *arity))
+            .collect();
+        (
+            helpers,
+            arb_function("main".to_string(), Vec::new(), callable),
+        )
+            .prop_map(|(helpers, main)| {
+                helpers
+                    .into_iter()
+                    .chain(std::iter::once(main))
+                    .fold(ProgramBuilder::new().include("stdio.h"), |program, f| {
+                        program.function(f)
+                    })
+                    .build()
+            })
+    })
+}
+
+/// Generates a function with the given parameters that may call `callable`.
+fn arb_function(
+    name: String,
+    params: Vec<String>,
+    callable: Vec<(String, usize)>,
+) -> BoxedStrategy<Function> {
+    (1usize..4)
+        .prop_flat_map(move |locals| {
+            let locals: Vec<String> = (0..locals).map(|i| format!("v{}", i)).collect();
+            let counters: Vec<String> = (0..MAX_LOOP_DEPTH).map(|i| format!("i{}", i)).collect();
+            let mut assignable = params.clone();
+            assignable.extend(locals.iter().cloned());
+            let mut readable = assignable.clone();
+            readable.extend(counters.iter().cloned());
+            let scope = Arc::new(Scope {
+                readable,
+                assignable,
+                callable: callable.clone(),
+            });
+            // Initializers may only read parameters and literals
+            let init_scope = Arc::new(Scope {
+                readable: params.clone(),
+                assignable: Vec::new(),
+                callable: callable.clone(),
+            });
+            let name = name.clone();
+            let params = params.clone();
+            (
+                vec(arb_expr(init_scope), locals.len()),
+                vec(arb_stmt(scope.clone(), 2, 0), 0..5),
+                arb_expr(scope),
+            )
+                .prop_map(move |(inits, body, ret)| {
+                    let function = params
+                        .iter()
+                        .fold(FnBuilder::new(name.clone(), Type::Int), |f, p| {
+                            f.param(Type::Int, p.clone())
+                        });
+                    let function = locals.iter().zip(inits).fold(function, |f, (local, init)| {
+                        f.stmt(stmt::decl(Type::Int, local.clone(), init))
+                    });
+                    let function = counters.iter().fold(function, |f, counter| {
+                        f.stmt(stmt::decl(Type::Int, counter.clone(), expr::int(0)))
+                    });
+                    body.into_iter()
+                        .fold(function, |f, s| f.stmt(s))
+                        .stmt(stmt::ret(ret))
+                        .build()
+                })
+        })
+        .boxed()
+}
+
+/// Generates an `int` expression over the names in `scope`.
+fn arb_expr(scope: Arc<Scope>) -> BoxedStrategy<ExprTree> {
+    let literal = (0i64..1000).prop_map(expr::int);
+    let leaf = if scope.readable.is_empty() {
+        literal.boxed()
+    } else {
+        prop_oneof![
+            literal,
+            select(scope.readable.clone()).prop_map(expr::ident)
+        ]
+        .boxed()
+    };
+    leaf.prop_recursive(3, 16, 4, move |inner| {
+        let arithmetic = (
+            inner.clone(),
+            select(vec![
+                BinOp::Plus,
+                BinOp::Minus,
+                BinOp::Multiply,
+                BinOp::Divide,
+            ]),
+            inner.clone(),
+        )
+            .prop_map(|(left, op, right)| {
+                let right = if op == BinOp::Divide {
+                    expr::add(expr::mul(right.clone(), right), expr::int(1))
+                } else {
+                    right
+                };
+                expr::binary(left, op, right)
+            });
+        let comparison = (
+            inner.clone(),
+            select(vec![
+                BinOp::Equal,
+                BinOp::NotEqual,
+                BinOp::LessThan,
+                BinOp::GreaterThan,
+                BinOp::LessEqual,
+                BinOp::GreaterEqual,
+            ]),
+            inner.clone(),
+        )
+            .prop_map(|(left, op, right)| expr::binary(left, op, right));
+        if scope.callable.is_empty() {
+            prop_oneof![arithmetic, comparison].boxed()
+        } else {
+            let call = select(scope.callable.clone()).prop_flat_map(move |(name, arity)| {
+                vec(inner.clone(), arity).prop_map(move |args| expr::call(name.clone(), args))
+            });
+            prop_oneof![arithmetic, comparison, call].boxed()
+        }
+    })
+    .boxed()
+}
+
+/// Generates a statement nested at most `depth` blocks deep, inside
+/// `loop_depth` loops.
+fn arb_stmt(scope: Arc<Scope>, depth: u32, loop_depth: usize) -> BoxedStrategy<StmtTree> {
+    let assign = (select(scope.assignable.clone()), arb_expr(scope.clone()))
+        .prop_map(|(name, value)| stmt::expr(expr::assign(expr::ident(name), value)));
+    let print = arb_expr(scope.clone())
+        .prop_map(|value| stmt::expr(expr::call("printf", [expr::string("%ld\n"), value])));
+    if depth == 0 {
+        return prop_oneof![assign, print].boxed();
+    }
+    let block = |scope: Arc<Scope>, loop_depth| {
+        vec(arb_stmt(scope, depth - 1, loop_depth), 0..3).prop_map(stmt::block)
+    };
+    let if_ = (
+        arb_expr(scope.clone()),
+        block(scope.clone(), loop_depth),
+        proptest::option::of(block(scope.clone(), loop_depth)),
+    )
+        .prop_map(|(cond, then, else_)| stmt::if_(cond, then, else_));
+    if loop_depth == MAX_LOOP_DEPTH {
+        return prop_oneof![assign, print, if_].boxed();
+    }
+    let counter = format!("i{}", loop_depth);
+    let for_ = (1i64..5, block(scope, loop_depth + 1)).prop_map(move |(bound, body)| {
+        let i = || expr::ident(counter.clone());
+        stmt::for_(
+            stmt::expr(expr::assign(i(), expr::int(0))),
+            expr::lt(i(), expr::int(bound)),
+            expr::assign(i(), expr::add(i(), expr::int(1))),
+            body,
+        )
+    });
+    prop_oneof![assign, print, if_, for_].boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "llvm")]
+    use crate::codegen::generate_ir;
+    use crate::lexer::lex;
+    use crate::parser::parse;
+    use crate::printer::print_program;
+    use crate::semantic::analyze;
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_print_parse_round_trip(program in arb_program()) {
+            let source = print_program(&program);
+            let tokens = lex(&source).unwrap();
+            prop_assert_eq!(parse(&tokens).unwrap(), program);
+        }
+
+        #[test]
+        fn prop_generated_programs_are_valid(program in arb_program()) {
+            prop_assert_eq!(analyze(&program), vec![]);
+            #[cfg(feature = "llvm")]
+            prop_assert!(generate_ir(&program).is_ok());
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! # Compilation Timings
+//!
+//! This module records how long each phase of the compilation pipeline takes.
+//! Timings are returned from [`compile_with_options`](crate::compile_with_options)
+//! and printed by the CLI with `--timings`, which helps diagnose whether the
+//! compiler's own phases or the external link step dominate a build.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// A phase of the compilation pipeline.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Phase {
+    /// Lexical analysis
+    Lex,
+    /// Parsing
+    Parse,
+    /// Semantic analysis
+    Semantic,
+    /// LLVM IR generation
+    Codegen,
+    /// Linking with the external C compiler
+    Link,
+}
+
+impl Phase {
+    /// Returns the short name of the phase.
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Lex => "lex",
+            Phase::Parse => "parse",
+            Phase::Semantic => "semantic",
+            Phase::Codegen => "codegen",
+            Phase::Link => "link",
+        }
+    }
+}
+
+/// Wall-clock time spent in each phase of a compilation, in pipeline order.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Timings {
+    phases: Vec<(Phase, Duration)>,
+}
+
+impl Timings {
+    /// Creates an empty set of timings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `f`, recording its wall time under `phase`.
+    pub fn time<T>(&mut self, phase: Phase, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.phases.push((phase, start.elapsed()));
+        result
+    }
+
+    /// Returns the time spent in `phase`, if it ran.
+    pub fn get(&self, phase: Phase) -> Option<Duration> {
+        self.phases
+            .iter()
+            .filter(|(p, _)| *p == phase)
+            .map(|(_, d)| *d)
+            .reduce(|a, b| a + b)
+    }
+
+    /// Returns the recorded phases in the order they ran.
+    pub fn phases(&self) -> &[(Phase, Duration)] {
+        &self.phases
+    }
+
+    /// Returns the total time across all phases.
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|(_, d)| *d).sum()
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.total();
+        for (phase, duration) in &self.phases {
+            let percent = if total.is_zero() {
+                0.0
+            } else {
+                duration.as_secs_f64() / total.as_secs_f64() * 100.0
+            };
+            writeln!(
+                f,
+                "{:<10} {:>10.3}ms {:>5.1}%",
+                phase.name(),
+                duration.as_secs_f64() * 1000.0,
+                percent
+            )?;
+        }
+        write!(
+            f,
+            "{:<10} {:>10.3}ms",
+            "total",
+            total.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_records_phases_in_order() {
+        let mut timings = Timings::new();
+        let value = timings.time(Phase::Lex, || 42);
+        timings.time(Phase::Parse, || ());
+        assert_eq!(value, 42);
+        let phases: Vec<Phase> = timings.phases().iter().map(|(p, _)| *p).collect();
+        assert_eq!(phases, vec![Phase::Lex, Phase::Parse]);
+        assert!(timings.get(Phase::Lex).is_some());
+        assert!(timings.get(Phase::Link).is_none());
+    }
+
+    #[test]
+    fn test_display_includes_total() {
+        let mut timings = Timings::new();
+        timings.time(Phase::Codegen, || ());
+        let text = timings.to_string();
+        assert!(text.contains("codegen"));
+        assert!(text.contains("total"));
+    }
+}
@@ -0,0 +1,119 @@
+//! Golden-file tests for end-to-end programs.
+//!
+//! Every `tests/programs/*.c` is compiled, run, and checked against its
+//! sibling `.expected` file, which records the exit code and stdout:
+//!
+//! ```text
+//! exit: 55
+//! --- stdout
+//! 0 1 1 2 3 5 8 13 21 34
+//! ```
+//!
+//! The LLVM IR generated for each program is also compared against a `.ll`
+//! snapshot, normalized with [`normalize_ir`] so that host-specific module
+//! lines and the names of temporaries do not matter.
+//!
+//! Run with `UPDATE_GOLDEN=1` to write the `.expected` and `.ll` files from
+//! the actual results instead of comparing against them.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tempfile::TempDir;
+use virtuc::codegen::{generate_ir, normalize_ir};
+use virtuc::compile;
+use virtuc::lexer::lex;
+use virtuc::parser::parse;
+use virtuc::run::{self, RunConfig};
+
+/// Directory holding the golden programs.
+fn programs_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs")
+}
+
+/// Compiles and runs a program, returning its result in `.expected` format.
+fn run_program(path: &Path, temp_dir: &TempDir) -> Result<String, String> {
+    let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let output_path = temp_dir.path().join(path.file_stem().unwrap());
+    compile(&source, &output_path).map_err(|e| format!("compilation failed: {}", e))?;
+    // A program that loops forever fails the test instead of hanging it
+    let config = RunConfig {
+        timeout: Some(Duration::from_secs(30)),
+        ..RunConfig::default()
+    };
+    let result =
+        run::run_program(&output_path, &config).map_err(|e| format!("failed to run: {}", e))?;
+    if let Some(limit) = result.exceeded {
+        return Err(format!("the program {}", limit));
+    }
+    let exit = match result.exit_code {
+        Some(code) => code.to_string(),
+        None => "signal".to_string(),
+    };
+    Ok(format!(
+        "exit: {}\n--- stdout\n{}",
+        exit,
+        result.stdout_text()
+    ))
+}
+
+/// Checks the result of `run` on every program against the sibling file with
+/// the given extension, or writes that file in update mode.
+fn check_programs(extension: &str, run: impl Fn(&Path) -> Result<String, String>) {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut programs: Vec<PathBuf> = fs::read_dir(programs_dir())
+        .expect("failed to read tests/programs")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "c"))
+        .collect();
+    programs.sort();
+    assert!(!programs.is_empty(), "no golden programs found");
+
+    let mut failures = Vec::new();
+    for program in &programs {
+        let name = program.file_name().unwrap().to_string_lossy();
+        let actual = match run(program) {
+            Ok(actual) => actual,
+            Err(e) => {
+                failures.push(format!("{}: {}", name, e));
+                continue;
+            }
+        };
+        let expected_path = program.with_extension(extension);
+        if update {
+            fs::write(&expected_path, &actual).expect("failed to write golden file");
+            continue;
+        }
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => failures.push(format!(
+                "{}: output mismatch\n--- expected\n{}\n--- actual\n{}",
+                name, expected, actual
+            )),
+            Err(_) => failures.push(format!(
+                "{}: missing {} (run with UPDATE_GOLDEN=1 to create it)",
+                name,
+                expected_path.display()
+            )),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
+
+#[test]
+fn test_golden_programs() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    check_programs("expected", |path| run_program(path, &temp_dir));
+}
+
+#[test]
+fn test_ir_snapshots() {
+    check_programs("ll", |path| {
+        let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let tokens = lex(&source).map_err(|e| e.to_string())?;
+        let program = parse(&tokens).map_err(|e| e.to_string())?;
+        let ir = generate_ir(&program).map_err(|e| e.to_string())?;
+        Ok(normalize_ir(&ir))
+    });
+}
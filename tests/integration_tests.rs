@@ -1,6 +1,15 @@
 use std::process::Command;
 use tempfile::TempDir;
-use virtuc::compile;
+use virtuc::coverage;
+use virtuc::error::{CompileError, LinkError, SemanticError, exit_code};
+use virtuc::format::{Arg, printf};
+use virtuc::random::Random;
+use virtuc::run::{RunConfig, run_program};
+use virtuc::{
+    CompileOptions, ConstValue, IntWidth, Phase, check, compile, compile_many,
+    compile_program_with_options, compile_staticlib_with_options, compile_with_options, eval_expr,
+    eval_expr_with_options,
+};
 
 #[test]
 fn test_compile_and_run_simple_program() {
@@ -23,12 +32,11 @@ fn test_compile_and_run_simple_program() {
     compile(source, &output_path).expect("Compilation failed");
 
     // Run the generated executable
-    let status = Command::new(&output_path)
-        .status()
+    let result = run_program(&output_path, &RunConfig::default())
         .expect("failed to run generated executable");
 
     // Check exit code (30 + 12 = 42)
-    assert_eq!(status.code(), Some(42));
+    assert_eq!(result.exit_code, Some(42));
 }
 
 #[test]
@@ -53,12 +61,11 @@ fn test_compile_and_run_control_flow() {
     compile(source, &output_path).expect("Compilation failed");
 
     // Run the generated executable
-    let status = Command::new(&output_path)
-        .status()
+    let result = run_program(&output_path, &RunConfig::default())
         .expect("failed to run generated executable");
 
     // Check exit code
-    assert_eq!(status.code(), Some(1));
+    assert_eq!(result.exit_code, Some(1));
 }
 
 #[test]
@@ -81,15 +88,14 @@ fn test_compile_and_run_with_printf() {
     compile(source, &output_path).expect("Compilation failed");
 
     // Run the generated executable and capture output
-    let output = Command::new(&output_path)
-        .output()
+    let result = run_program(&output_path, &RunConfig::default())
         .expect("failed to run generated executable");
 
     // Check exit code
-    assert_eq!(output.status.code(), Some(42));
+    assert_eq!(result.exit_code, Some(42));
 
     // Check stdout contains "Hello, World!"
-    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = result.stdout_text();
     assert!(stdout.contains("Hello, World!"));
 }
 
@@ -113,15 +119,14 @@ fn test_compile_and_run_with_include() {
     compile(source, &output_path).expect("Compilation failed");
 
     // Run the generated executable and capture output
-    let output = Command::new(&output_path)
-        .output()
+    let result = run_program(&output_path, &RunConfig::default())
         .expect("failed to run generated executable");
 
     // Check exit code
-    assert_eq!(output.status.code(), Some(7));
+    assert_eq!(result.exit_code, Some(7));
 
     // Check stdout contains "Hello from include!"
-    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = result.stdout_text();
     assert!(stdout.contains("Hello from include!"));
 }
 
@@ -141,12 +146,11 @@ fn test_printf_with_integer() {
 
     compile(source, &output_path).expect("Compilation failed");
 
-    let output = Command::new(&output_path)
-        .output()
+    let result = run_program(&output_path, &RunConfig::default())
         .expect("failed to run generated executable");
 
-    assert_eq!(output.status.code(), Some(0));
-    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(result.exit_code, Some(0));
+    let stdout = result.stdout_text();
     assert!(stdout.contains("Number: 7"));
 }
 
@@ -166,12 +170,11 @@ fn test_printf_with_multiple_args_include() {
 
     compile(source, &output_path).expect("Compilation failed");
 
-    let output = Command::new(&output_path)
-        .output()
+    let result = run_program(&output_path, &RunConfig::default())
         .expect("failed to run generated executable");
 
-    assert_eq!(output.status.code(), Some(0));
-    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(result.exit_code, Some(0));
+    let stdout = result.stdout_text();
     assert!(stdout.contains("Hi 10"));
 }
 
@@ -189,7 +192,8 @@ fn test_non_variadic_wrong_arity() {
         }
     "#;
 
-    assert!(compile(source, &output_path).is_err());
+    let error = compile(source, &output_path).unwrap_err();
+    assert_eq!(error.exit_code(), exit_code::COMPILE_ERROR);
 }
 
 #[test]
@@ -223,9 +227,9 @@ fn test_duplicate_includes() {
     "#;
 
     compile(source, &output_path).expect("Compilation failed");
-    let output = Command::new(&output_path).output().expect("failed to run");
-    assert_eq!(output.status.code(), Some(0));
-    let stdout = String::from_utf8_lossy(&output.stdout);
+    let result = run_program(&output_path, &RunConfig::default()).expect("failed to run");
+    assert_eq!(result.exit_code, Some(0));
+    let stdout = result.stdout_text();
     assert!(stdout.contains("Duplicate include"));
 }
 
@@ -244,9 +248,9 @@ fn test_variadic_format_mix() {
     "#;
 
     compile(source, &output_path).expect("Compilation failed");
-    let output = Command::new(&output_path).output().expect("failed to run");
-    assert_eq!(output.status.code(), Some(0));
-    let stdout = String::from_utf8_lossy(&output.stdout);
+    let result = run_program(&output_path, &RunConfig::default()).expect("failed to run");
+    assert_eq!(result.exit_code, Some(0));
+    let stdout = result.stdout_text();
     assert!(stdout.contains("val 42 ff") || stdout.contains("val 42 FF"));
 }
 
@@ -265,9 +269,9 @@ fn test_printf_many_args() {
     "#;
 
     compile(source, &output_path).expect("Compilation failed");
-    let output = Command::new(&output_path).output().expect("failed to run");
-    assert_eq!(output.status.code(), Some(0));
-    let stdout = String::from_utf8_lossy(&output.stdout);
+    let result = run_program(&output_path, &RunConfig::default()).expect("failed to run");
+    assert_eq!(result.exit_code, Some(0));
+    let stdout = result.stdout_text();
     assert!(stdout.contains("1 2 3 4 5 6 7 8 9 10"));
 }
 
@@ -288,12 +292,11 @@ fn test_for_loop_sum() {
     "#;
 
     compile(source, &output_path).expect("Compilation failed");
-    let status = Command::new(&output_path)
-        .status()
+    let result = run_program(&output_path, &RunConfig::default())
         .expect("failed to run generated executable");
 
     // Sum of 1 to 10 is 55
-    assert_eq!(status.code(), Some(55));
+    assert_eq!(result.exit_code, Some(55));
 }
 
 #[test]
@@ -313,9 +316,1386 @@ fn test_for_loop_with_printf() {
     "#;
 
     compile(source, &output_path).expect("Compilation failed");
-    let output = Command::new(&output_path).output().expect("failed to run");
+    let result = run_program(&output_path, &RunConfig::default()).expect("failed to run");
 
-    assert_eq!(output.status.code(), Some(0));
-    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(result.exit_code, Some(0));
+    let stdout = result.stdout_text();
     assert_eq!(stdout.trim(), "0 1 2 3 4");
 }
+
+#[test]
+fn test_int_width_32() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let output_path = temp_dir.path().join("test_int32");
+
+    let source = r#"
+        #include <stdio.h>
+
+        int square(int x) {
+            return x * x;
+        }
+
+        int main() {
+            printf("%d %d\n", square(0 - 7), 2147483647);
+            return square(3);
+        }
+    "#;
+
+    let options = CompileOptions {
+        int_width: IntWidth::W32,
+        ..CompileOptions::default()
+    };
+    compile_with_options(source, &output_path, &options).expect("Compilation failed");
+    let result = run_program(&output_path, &RunConfig::default()).expect("failed to run");
+
+    assert_eq!(result.exit_code, Some(9));
+    let stdout = result.stdout_text();
+    assert_eq!(stdout.trim(), "49 2147483647");
+}
+
+#[test]
+fn test_missing_compiler_is_reported() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let output_path = temp_dir.path().join("test_missing_cc");
+
+    let options = CompileOptions {
+        cc: Some("/nonexistent/virtuc-test-cc".to_string()),
+        ..CompileOptions::default()
+    };
+    let err = compile_with_options("int main() { return 0; }", &output_path, &options)
+        .expect_err("compilation should fail without a compiler");
+    assert!(err.to_string().contains("--cc"));
+    assert!(matches!(
+        err,
+        CompileError::Link(LinkError::CompilerNotFound(_))
+    ));
+}
+
+#[test]
+fn test_linker_diagnostics_are_captured() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let output_path = temp_dir.path().join("test_no_main");
+
+    // No main function, so linking fails
+    let err = compile_with_options(
+        "int helper() { return 0; }",
+        &output_path,
+        &CompileOptions::default(),
+    )
+    .expect_err("linking should fail without main");
+    let CompileError::Link(LinkError::Failed(_, _, diagnostics)) = err else {
+        panic!("expected linker failure, got {:?}", err);
+    };
+    assert!(diagnostics.contains("main"));
+}
+
+#[test]
+fn test_compile_reports_phase_timings() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let output_path = temp_dir.path().join("test_timings");
+
+    let timings = compile_with_options(
+        "int main() { return 0; }",
+        &output_path,
+        &CompileOptions::default(),
+    )
+    .expect("Compilation failed")
+    .timings;
+
+    let phases: Vec<Phase> = timings.phases().iter().map(|(p, _)| *p).collect();
+    assert_eq!(
+        phases,
+        vec![
+            Phase::Lex,
+            Phase::Parse,
+            Phase::Semantic,
+            Phase::Codegen,
+            Phase::Link
+        ]
+    );
+    assert!(timings.total() >= timings.get(Phase::Link).unwrap());
+}
+
+#[test]
+fn test_compile_errors_convert_to_diagnostics() {
+    use virtuc::diagnostics::Diagnostic;
+
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let output_path = temp_dir.path().join("test_diagnostics");
+
+    let source = "int main() { return x; }";
+    let err = compile_with_options(source, &output_path, &CompileOptions::default())
+        .expect_err("compilation should fail");
+    let diagnostics: Vec<Diagnostic> = (&err).into();
+    assert_eq!(diagnostics.len(), 1);
+    let json = diagnostics[0]
+        .clone()
+        .with_file(Some("main.c"))
+        .to_json(Some(source));
+    assert!(json.starts_with(r#"{"severity":"error","code":"E0201""#));
+    assert!(json.contains(r#""file":"main.c""#));
+
+    let source = "int main() {\n  return 0;\n}\n}";
+    let err = compile_with_options(source, &output_path, &CompileOptions::default())
+        .expect_err("compilation should fail");
+    let diagnostics: Vec<Diagnostic> = (&err).into();
+    let json = diagnostics[0].to_json(Some(source));
+    assert!(json.contains(r#""line":4,"column":1"#));
+}
+
+#[test]
+fn test_chained_comparisons_follow_c_semantics() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let output_path = temp_dir.path().join("test_chained");
+
+    // (3 > 2) > 1 is 1 > 1, which is false in C; 1 < 2 < 3 is true
+    let source = r#"
+        int main() {
+            int a = 3 > 2 > 1;
+            int b = 1 < 2 < 3;
+            int c = 1 + 1 == 2 == 1;
+            return a * 100 + b * 10 + c;
+        }
+    "#;
+
+    compile(source, &output_path).expect("Compilation failed");
+
+    let result = run_program(&output_path, &RunConfig::default())
+        .expect("failed to run generated executable");
+
+    assert_eq!(result.exit_code, Some(11));
+}
+
+#[test]
+fn test_check_runs_front_end_only() {
+    let program = check("int main() { return 0; }").expect("check failed");
+    assert_eq!(program.functions[0].name, "main");
+
+    let err = check("int main() { return x; }").expect_err("check should fail");
+    assert!(matches!(err, CompileError::Semantic(..)));
+}
+
+#[test]
+fn test_pipeline_types_are_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<virtuc::lexer::Token>();
+    assert_send_sync::<virtuc::ast::Program>();
+    assert_send_sync::<CompileError>();
+    assert_send_sync::<CompileOptions>();
+    assert_send_sync::<virtuc::Timings>();
+    assert_send_sync::<virtuc::CompileArtifacts>();
+    assert_send_sync::<virtuc::diagnostics::Diagnostic>();
+}
+
+#[test]
+fn test_concurrent_compiles() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+
+    let handles: Vec<_> = (0..8)
+        .map(|i| {
+            // `prog{i}` and `prog{i}.out` share a stem, and must not share
+            // intermediate files
+            let output_path = if i % 2 == 0 {
+                temp_dir.path().join(format!("prog{}", i / 2))
+            } else {
+                temp_dir.path().join(format!("prog{}.out", i / 2))
+            };
+            std::thread::spawn(move || {
+                let source = format!(
+                    "int f(int n) {{ int s = 0; for (int i = 0; i < n; i = i + 1) s = s + i; return s; }} int main() {{ return f({}); }}",
+                    i + 2
+                );
+                compile(&source, &output_path).expect("Compilation failed");
+                let result = run_program(&output_path, &RunConfig::default()).expect("failed to run generated executable");
+                (i, result.exit_code)
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        let (i, code) = handle.join().expect("compile thread panicked");
+        // Sum of 0..n for n = i + 2
+        assert_eq!(code, Some((i + 2) * (i + 1) / 2));
+    }
+}
+
+#[test]
+fn test_cli_generates_completions() {
+    for shell in ["bash", "zsh", "fish"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_virtuc"))
+            .args(["completions", shell])
+            .output()
+            .expect("failed to run virtuc");
+        assert!(output.status.success());
+        let script = String::from_utf8_lossy(&output.stdout);
+        assert!(script.contains("virtuc"));
+        assert!(script.contains("opt-level"));
+    }
+}
+
+#[test]
+fn test_cli_reads_stdin_and_writes_stdout() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let run = |args: &[&str], input: &str| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_virtuc"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("failed to run virtuc");
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    let output = run(&["emit", "--emit=ir", "-"], "int main() { return 0; }");
+    assert!(output.status.success());
+    let ir = String::from_utf8_lossy(&output.stdout);
+    assert!(ir.contains("define i64 @main()"));
+    assert!(ir.contains("source_filename = \"<stdin>\""));
+
+    let output = run(&["emit", "--emit=mir", "-"], "int main() { return 1 + 2; }");
+    assert!(output.status.success());
+    let mir = String::from_utf8_lossy(&output.stdout);
+    assert!(mir.starts_with("fn main() -> int {"), "{}", mir);
+    assert!(mir.contains("_0 = 1 + 2;"), "{}", mir);
+
+    let output = run(
+        &["opt-view", "-O1", "-"],
+        "int main() { int x = 3; return x * 1 + x * 2; }",
+    );
+    assert!(output.status.success());
+    let view = String::from_utf8_lossy(&output.stdout);
+    assert!(view.starts_with("// *** input ***\n"), "{}", view);
+    assert!(
+        view.contains("// *** after annihilation (no changes) ***"),
+        "{}",
+        view
+    );
+    assert!(view.contains("return x + (x + x);"), "{}", view);
+
+    let output = run(&["check", "--color=never", "-"], "int main() { return x; }");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--> <stdin>"));
+}
+
+#[test]
+fn test_deterministic_builds_are_byte_identical() {
+    let source = r#"
+        #include <stdio.h>
+        int square(int x) { return x * x; }
+        int main() { printf("%ld\n", square(7)); return 0; }
+    "#;
+
+    // Build the same program from two different directories
+    let build = |dir: &str| {
+        let temp_dir = TempDir::new().expect("failed to create temp dir");
+        let project = temp_dir.path().join(dir);
+        std::fs::create_dir(&project).unwrap();
+        let output_path = project.join("prog");
+        let options = CompileOptions {
+            source_name: Some(project.join("prog.c").display().to_string()),
+            deterministic: true,
+            ..CompileOptions::default()
+        };
+        compile_with_options(source, &output_path, &options).expect("Compilation failed");
+        std::fs::read(&output_path).unwrap()
+    };
+
+    assert_eq!(build("first"), build("second-checkout"));
+}
+
+#[test]
+fn test_cli_exit_codes() {
+    let temp_dir = TempDir::new().unwrap();
+    let invalid = temp_dir.path().join("invalid.c");
+    std::fs::write(&invalid, "int main() { return x; }").unwrap();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_virtuc"))
+            .args(args)
+            .output()
+            .expect("failed to run virtuc")
+            .status
+            .code()
+    };
+
+    let undefined = temp_dir.path().join("undefined.c");
+    std::fs::write(
+        &undefined,
+        "extern int missing(int); int main() { return missing(1); }",
+    )
+    .unwrap();
+    let output = temp_dir.path().join("out");
+    let output = output.to_str().unwrap();
+
+    let invalid = invalid.to_str().unwrap();
+    assert_eq!(
+        run(&["check", invalid]),
+        Some(exit_code::COMPILE_ERROR.into())
+    );
+    // Link failures are the program's fault when it leaves symbols
+    // undefined, and the environment's when the C compiler is missing
+    let undefined = undefined.to_str().unwrap();
+    assert_eq!(
+        run(&["compile", undefined, "-o", output]),
+        Some(exit_code::COMPILE_ERROR.into())
+    );
+    assert_eq!(
+        run(&[
+            "compile",
+            undefined,
+            "-o",
+            output,
+            "--cc",
+            "virtuc-no-such-cc"
+        ]),
+        Some(exit_code::ENVIRONMENT.into())
+    );
+    assert_eq!(
+        run(&["check", "does-not-exist.c"]),
+        Some(exit_code::ENVIRONMENT.into())
+    );
+    assert_eq!(
+        run(&["check", "--opt-level=9", invalid]),
+        Some(exit_code::USAGE.into())
+    );
+    assert_eq!(
+        run(&["check", "--ffast-math", "--deterministic", invalid]),
+        Some(exit_code::USAGE.into())
+    );
+    assert_eq!(
+        run(&[
+            "merge-profdata",
+            "--llvm-profdata",
+            "virtuc-no-such-tool",
+            "x.profraw"
+        ]),
+        Some(exit_code::ENVIRONMENT.into())
+    );
+}
+
+#[test]
+fn test_checked_recursion_aborts_runaway_recursion() {
+    let source = r#"
+        int depth(int n) { if (n == 0) { return 0; } return 1 + depth(n - 1); }
+        int forever(int n) { return forever(n + 1); }
+        int main() { return depth(50) - 50 + forever(0); }
+    "#;
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let output_path = temp_dir.path().join("recurse");
+    let options = CompileOptions {
+        recursion_limit: Some(100),
+        ..CompileOptions::default()
+    };
+    compile_with_options(source, &output_path, &options).expect("Compilation failed");
+
+    let result =
+        run_program(&output_path, &RunConfig::default()).expect("Failed to run executable");
+    assert!(!result.success());
+    assert_eq!(
+        result.stderr_text(),
+        "virtuc: recursion limit of 100 calls exceeded in function 'forever'\n"
+    );
+
+    // Returning restores the depth, so bounded recursion stays within the limit
+    let source = r#"
+        int depth(int n) { if (n == 0) { return 0; } return 1 + depth(n - 1); }
+        int main() { int total = 0; for (int i = 0; i < 10; i = i + 1) { total = total + depth(90); } return total - 900; }
+    "#;
+    compile_with_options(source, &output_path, &options).expect("Compilation failed");
+    let result = run_program(&output_path, &RunConfig::default()).unwrap();
+    assert_eq!(result.exit_code, Some(0));
+}
+
+#[test]
+fn test_coverage_counts_statements() {
+    let source = "int main() {
+    int total = 0;
+    for (int i = 0; i < 3; i = i + 1) {
+        total = total + i;
+    }
+    if (total > 100) {
+        return 1;
+    }
+    return 0;
+}
+";
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let output_path = temp_dir.path().join("covered");
+    let counts_path = temp_dir.path().join("covered.cov");
+    let options = CompileOptions {
+        coverage_file: Some(counts_path.display().to_string()),
+        ..CompileOptions::default()
+    };
+    compile_with_options(source, &output_path, &options).expect("Compilation failed");
+    let result = run_program(&output_path, &RunConfig::default()).unwrap();
+    assert_eq!(result.exit_code, Some(0));
+
+    let counts = std::fs::read_to_string(&counts_path).expect("counts file not written");
+    let counts = coverage::parse_counts(&counts).unwrap();
+    let annotated = coverage::report(source, &counts, &options).unwrap();
+    let lines: Vec<&str> = annotated.lines().collect();
+    assert_eq!(lines[1], "        1:    2:    int total = 0;");
+    assert_eq!(lines[3], "        3:    4:        total = total + i;");
+    assert_eq!(lines[5], "        1:    6:    if (total > 100) {");
+    assert_eq!(lines[6], "    #####:    7:        return 1;");
+    assert_eq!(lines[8], "        1:    9:    return 0;");
+}
+
+#[test]
+fn test_assert_aborts_with_location() {
+    let source = "#include <assert.h>
+int main() {
+    int x = 2;
+    assert(x > 1);
+    assert(x * 2 == 5);
+    return 0;
+}
+";
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let output_path = temp_dir.path().join("asserts");
+    let options = CompileOptions {
+        source_name: Some("asserts.c".to_string()),
+        ..CompileOptions::default()
+    };
+    compile_with_options(source, &output_path, &options).expect("Compilation failed");
+    let result =
+        run_program(&output_path, &RunConfig::default()).expect("Failed to run executable");
+    assert!(!result.success());
+    assert_eq!(
+        result.stderr_text(),
+        "virtuc: asserts.c:5: Assertion `x * 2 == 5' failed.\n"
+    );
+
+    // Assertions are compiled out under NDEBUG and when optimizing
+    for options in [
+        CompileOptions {
+            ndebug: true,
+            ..options.clone()
+        },
+        CompileOptions {
+            opt_level: 2,
+            ..options.clone()
+        },
+    ] {
+        compile_with_options(source, &output_path, &options).expect("Compilation failed");
+        let result = run_program(&output_path, &RunConfig::default()).unwrap();
+        assert_eq!(result.exit_code, Some(0));
+    }
+}
+
+#[test]
+fn test_eval_expr() {
+    assert_eq!(eval_expr("(1 + 2) * 3 - 4"), Ok(ConstValue::Int(5)));
+    assert_eq!(eval_expr("1.0 / 4.0"), Ok(ConstValue::Float(0.25)));
+    assert_eq!(eval_expr("2 <= 1"), Ok(ConstValue::Int(0)));
+
+    // Each phase reports its own errors
+    assert!(matches!(eval_expr("1 +"), Err(CompileError::Parse(..))));
+    assert!(matches!(
+        eval_expr("1 + 2.0"),
+        Err(CompileError::Semantic(..))
+    ));
+    assert!(matches!(
+        eval_expr("x + 1"),
+        Err(CompileError::Semantic(..))
+    ));
+    assert!(matches!(
+        eval_expr("1 / 0"),
+        Err(CompileError::Semantic(..))
+    ));
+    assert!(matches!(
+        eval_expr("\"text\""),
+        Err(CompileError::Semantic(..))
+    ));
+
+    let options = CompileOptions {
+        int_width: IntWidth::W32,
+        ..CompileOptions::default()
+    };
+    assert!(eval_expr_with_options("2147483647 + 1", &options).is_err());
+    assert_eq!(
+        eval_expr("2147483647 + 1"),
+        Ok(ConstValue::Int(2_147_483_648))
+    );
+}
+
+#[test]
+fn test_cli_call_graph_and_recursion_warning() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("calls.c");
+    std::fs::write(
+        &input,
+        "int spin(int n) { return spin(n + 1); }
+int fact(int n) { if (n < 2) { return 1; } return n * fact(n - 1); }
+int main() { return fact(3); }
+",
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_virtuc"))
+            .args(args)
+            .arg(&input)
+            .output()
+            .expect("failed to run virtuc")
+    };
+
+    let output = run(&["callgraph"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "spin -> spin\nfact -> fact\nmain -> fact\n\nRecursive:\n  spin\n  fact\n"
+    );
+    let output = run(&["callgraph", "--dot"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("\"main\" -> \"fact\";"));
+
+    // Only the recursion without a base case is reported, and it does not
+    // fail the check
+    let output = run(&["check", "--color=never"]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("warning[W0001]: function 'spin' calls itself"));
+    assert!(!stderr.contains("'fact'"));
+    assert!(stderr.contains("warning[W0002]: function 'spin' is never used"));
+
+    let output = run(&["emit", "--strip-unused"]);
+    let ir = String::from_utf8_lossy(&output.stdout);
+    assert!(ir.contains("@fact"));
+    assert!(!ir.contains("@spin"));
+}
+
+#[test]
+fn test_cli_compile_with_imports() {
+    let temp_dir = TempDir::new().unwrap();
+    let lib = temp_dir.path().join("lib");
+    std::fs::create_dir(&lib).unwrap();
+    std::fs::write(
+        lib.join("square.c"),
+        "#include <stdio.h>\nint square(int x) { return x * x; }\n",
+    )
+    .unwrap();
+    std::fs::write(
+        lib.join("sum.c"),
+        "#include \"square.c\"\nint sum_squares(int a, int b) { return square(a) + square(b); }\n",
+    )
+    .unwrap();
+    let input = temp_dir.path().join("main.c");
+    std::fs::write(
+        &input,
+        "#include \"lib/sum.c\"\n#include \"lib/square.c\"\n\
+         int main() { printf(\"%d\\n\", square(2)); return sum_squares(1, 2); }\n",
+    )
+    .unwrap();
+    let output_path = temp_dir.path().join("main");
+    let output = Command::new(env!("CARGO_BIN_EXE_virtuc"))
+        .args(["compile", "-o"])
+        .arg(&output_path)
+        .arg(&input)
+        .output()
+        .expect("failed to run virtuc");
+    assert!(output.status.success(), "{:?}", output);
+    let result = run_program(&output_path, &RunConfig::default()).unwrap();
+    assert_eq!(result.exit_code, Some(5));
+    assert_eq!(result.stdout_text(), "4\n");
+
+    // Errors in an imported file are shown in that file
+    std::fs::write(
+        lib.join("square.c"),
+        "int square(int x) {\n  return x $ x;\n}\n",
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_virtuc"))
+        .args(["check", "--color=never"])
+        .arg(&input)
+        .output()
+        .expect("failed to run virtuc");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("square.c:2:12"), "{}", stderr);
+    assert!(stderr.contains("return x $ x;"), "{}", stderr);
+}
+
+#[test]
+fn test_compile_many_links_modules() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("many");
+    let main = r#"
+        #include <stdio.h>
+        #include <assert.h>
+        extern int square(int);
+        int main() {
+            assert(square(3) == 9);
+            printf("%d\n", square(4));
+            return square(2);
+        }
+    "#;
+    // Both modules use the internal runtime helpers
+    let square = r#"
+        #include <assert.h>
+        int square(int x) { assert(x >= 0); return x * x; }
+    "#;
+    compile_many(&[("main.c", main), ("square.c", square)], &output_path)
+        .expect("Compilation failed");
+    let result = run_program(&output_path, &RunConfig::default()).unwrap();
+    assert_eq!(result.exit_code, Some(4));
+    assert_eq!(result.stdout_text(), "16\n");
+
+    // Errors name their file, and functions are defined once
+    let error = compile_many(
+        &[("main.c", main), ("bad.c", "int f() { return x; }")],
+        &output_path,
+    )
+    .unwrap_err();
+    assert!(matches!(&error, CompileError::Import { file, .. } if file == "bad.c"));
+    let error = compile_many(
+        &[("main.c", main), ("square.c", square), ("again.c", square)],
+        &output_path,
+    )
+    .unwrap_err();
+    assert_eq!(
+        error,
+        CompileError::Import {
+            file: "again.c".to_string(),
+            error: Box::new(CompileError::Semantic(
+                vec![SemanticError::DuplicateFunction("square".to_string())],
+                Vec::new()
+            )),
+        }
+    );
+    assert_eq!(error.exit_code(), exit_code::COMPILE_ERROR);
+
+    // Extern declarations match the definition
+    let float_square = "float square(float x) { return x * x; }";
+    let error = compile_many(
+        &[("main.c", main), ("square.c", float_square)],
+        &output_path,
+    )
+    .unwrap_err();
+    assert_eq!(
+        error,
+        CompileError::Import {
+            file: "main.c".to_string(),
+            error: Box::new(CompileError::Semantic(
+                vec![SemanticError::ConflictingDefinition(
+                    "square".to_string(),
+                    "square.c".to_string()
+                )],
+                Vec::new()
+            )),
+        }
+    );
+}
+
+#[test]
+fn test_cli_emit_staticlib_used_from_c() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("math.c");
+    std::fs::write(
+        &input,
+        "int square(int x) { return x * x; }\nfloat half(float x) { return x / 2.0; }\n",
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_virtuc"))
+        .args(["emit", "--emit=staticlib"])
+        .arg(&input)
+        .output()
+        .expect("failed to run virtuc");
+    assert!(output.status.success(), "{:?}", output);
+    let header = std::fs::read_to_string(temp_dir.path().join("math.h")).unwrap();
+    assert!(header.contains("int64_t square(int64_t x);"), "{}", header);
+
+    // A C program links against the library through the header
+    let user = temp_dir.path().join("use.c");
+    std::fs::write(
+        &user,
+        "#include \"math.h\"\n#include <stdio.h>\n\
+         int main(void) { printf(\"%lld %g\\n\", (long long)square(7), half(3.0)); return 0; }\n",
+    )
+    .unwrap();
+    let program = temp_dir.path().join("use");
+    let status = Command::new("clang")
+        .arg(&user)
+        .arg(temp_dir.path().join("math.a"))
+        .arg("-o")
+        .arg(&program)
+        .status()
+        .expect("failed to run clang");
+    assert!(status.success());
+    let result = run_program(&program, &RunConfig::default()).unwrap();
+    assert_eq!(result.stdout_text(), "49 1.5\n");
+}
+
+#[test]
+fn test_cli_emit_header() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("geometry.c");
+    std::fs::write(
+        &input,
+        "float area(float w, float h) { return w * h; }\nint main() { return 0; }\n",
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_virtuc"))
+            .args(["emit"])
+            .args(args)
+            .arg(&input)
+            .output()
+            .expect("failed to run virtuc")
+    };
+
+    let output = run(&["--header"]);
+    assert!(output.status.success());
+    let header = String::from_utf8_lossy(&output.stdout);
+    assert!(header.contains("#ifndef GEOMETRY_H"), "{}", header);
+    assert!(header.contains("double area(double w, double h);"));
+    assert!(!header.contains("main"));
+
+    // The include guard follows the output file
+    let path = temp_dir.path().join("shapes.h");
+    let output = run(&[
+        "--emit=header",
+        "--int-width",
+        "32",
+        "-o",
+        path.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    let header = std::fs::read_to_string(&path).unwrap();
+    assert!(header.contains("#ifndef SHAPES_H"), "{}", header);
+    assert!(!header.contains("stdint.h"));
+
+    // Functions that are not exported are left out
+    std::fs::write(
+        &input,
+        "float area(float w, float h) { return w * h; }\n\
+         float square(float w) { return area(w, w); }\n",
+    )
+    .unwrap();
+    let output = run(&["--header", "--export=square"]);
+    assert!(output.status.success());
+    let header = String::from_utf8_lossy(&output.stdout);
+    assert!(header.contains("double square(double w);"), "{}", header);
+    assert!(!header.contains("area"));
+}
+
+#[test]
+fn test_cli_embed_source_and_inspect() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("embedded.c");
+    let source = "int main() { return 3; }\n";
+    std::fs::write(&input, source).unwrap();
+    let binary = temp_dir.path().join("embedded");
+    let virtuc = |args: &[&std::ffi::OsStr]| {
+        Command::new(env!("CARGO_BIN_EXE_virtuc"))
+            .args(args)
+            .output()
+            .expect("failed to run virtuc")
+    };
+    let output = virtuc(&[
+        "compile".as_ref(),
+        "--embed-source".as_ref(),
+        "-O1".as_ref(),
+        "--deterministic".as_ref(),
+        "-o".as_ref(),
+        binary.as_os_str(),
+        input.as_os_str(),
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        run_program(&binary, &RunConfig::default())
+            .unwrap()
+            .exit_code,
+        Some(3)
+    );
+
+    let output = virtuc(&["inspect".as_ref(), binary.as_os_str()]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "virtuc version: {}\nsource file: embedded.c\noptions: --int-width 64 -O1\n\n{}",
+            virtuc::VERSION,
+            source
+        )
+    );
+    let output = virtuc(&["inspect".as_ref(), "--source".as_ref(), binary.as_os_str()]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), source);
+
+    // Binaries built without the flag have no record
+    let plain = temp_dir.path().join("plain");
+    compile(source, &plain).unwrap();
+    let output = virtuc(&["inspect".as_ref(), plain.as_os_str()]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_cli_doctor() {
+    let output = Command::new(env!("CARGO_BIN_EXE_virtuc"))
+        .arg("doctor")
+        .output()
+        .expect("failed to run virtuc");
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("linking: compiled and ran a test program"),
+        "{}",
+        stdout
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_virtuc"))
+        .args(["doctor", "--cc", "virtuc-no-such-cc"])
+        .output()
+        .expect("failed to run virtuc");
+    assert_eq!(output.status.code(), Some(3));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("error   C compiler: 'virtuc-no-such-cc' not found"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("help: install clang"));
+}
+
+#[test]
+fn test_run_program_with_stdin() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("echo");
+    let source = r#"
+        #include <stdio.h>
+        extern int getchar(...);
+        extern int putchar(int);
+        int main() {
+            int count = 0;
+            // Echo the first line
+            for (int c = getchar(); c != 10; c = getchar()) {
+                putchar(c);
+                count = count + 1;
+            }
+            putchar(10);
+            return count;
+        }
+    "#;
+    compile(source, &output_path).expect("Compilation failed");
+    let config = RunConfig {
+        stdin: Some(b"hello\nworld\n".to_vec()),
+        ..RunConfig::default()
+    };
+    let result = run_program(&output_path, &config).expect("failed to run");
+    assert_eq!(result.exit_code, Some(5));
+    assert_eq!(result.stdout_text(), "hello\n");
+    assert!(result.stderr.is_empty());
+}
+
+#[test]
+fn test_random_builtins_match_reference() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("random");
+    let source = r#"
+        #include <stdio.h>
+        #include <virtuc/random.h>
+        int main() {
+            rand_seeded(0 - 7);
+            for (int i = 0; i < 5; i = i + 1) {
+                printf("%d\n", rand_next());
+            }
+            return 0;
+        }
+    "#;
+    let expected: String = Random::seeded(-7)
+        .take(5)
+        .map(|n| format!("{}\n", n))
+        .collect();
+
+    // The sequence does not depend on the width of int
+    for int_width in [IntWidth::W64, IntWidth::W32] {
+        let options = CompileOptions {
+            int_width,
+            ..CompileOptions::default()
+        };
+        compile_with_options(source, &output_path, &options).expect("Compilation failed");
+        let result = run_program(&output_path, &RunConfig::default()).unwrap();
+        assert_eq!(result.stdout_text(), expected);
+    }
+}
+
+#[test]
+fn test_printf_formatter_matches_native() {
+    let temp_dir = TempDir::new().unwrap();
+    let output_path = temp_dir.path().join("floats");
+    let cases: &[(&str, f64)] = &[
+        ("%f", 1.23456),
+        ("%.2f", 0.125),
+        ("%.0f", 2.5),
+        ("%.1f", 0.05),
+        ("%f", 1e300),
+        ("%12.4e", 0.000123456),
+        ("%E", 0.0),
+        ("%g", 123456789.0),
+        ("%g", 1e-300),
+        ("%.3g", 0.0009995),
+        ("%#g", 2.0),
+        ("%+08.2f", -9.87654),
+        ("%-10.3G|", 1e-5),
+    ];
+    // Float literals have neither exponents nor signs
+    let literal = |value: f64| {
+        let digits = format!("{}", value.abs());
+        let digits = if digits.contains('.') {
+            digits
+        } else {
+            digits + ".0"
+        };
+        if value < 0.0 {
+            format!("0.0 - {}", digits)
+        } else {
+            digits
+        }
+    };
+    let calls: String = cases
+        .iter()
+        .map(|(format, value)| format!("printf(\"{}\\n\", {});\n", format, literal(*value)))
+        .collect();
+    let source = format!(
+        "#include <stdio.h>\nint main() {{\n{}return 0;\n}}\n",
+        calls
+    );
+    compile(&source, &output_path).expect("Compilation failed");
+    let result = run_program(&output_path, &RunConfig::default()).unwrap();
+
+    let mut expected = Vec::new();
+    for (format, value) in cases {
+        expected.extend(printf(&format!("{}\n", format), &[Arg::Float(*value)]).unwrap());
+    }
+    assert_eq!(result.stdout_text(), String::from_utf8(expected).unwrap());
+}
+
+#[test]
+fn test_lto_builds() {
+    let temp_dir = TempDir::new().unwrap();
+    let source = "int square(int x) { return x * x; }\nint main() { return square(3) - 9; }\n";
+    let options = CompileOptions {
+        opt_level: 2,
+        lto: true,
+        ..CompileOptions::default()
+    };
+
+    // Static libraries hold bitcode rather than machine code
+    let library = temp_dir.path().join("math.a");
+    compile_staticlib_with_options(source, &library, &options).expect("Compilation failed");
+    let archive = std::fs::read(&library).unwrap();
+    let bitcode_magic = b"BC\xc0\xde";
+    assert!(archive.windows(4).any(|window| window == bitcode_magic));
+
+    let program = temp_dir.path().join("square");
+    compile_with_options(source, &program, &options).expect("Compilation failed");
+    let result = run_program(&program, &RunConfig::default()).unwrap();
+    assert!(result.success());
+}
+
+#[test]
+fn test_hardened_build() {
+    let temp_dir = TempDir::new().unwrap();
+    let program = temp_dir.path().join("hardened");
+    let source = r#"
+        #include <stdio.h>
+        int fib(int n) { if (n < 2) { return n; } return fib(n - 1) + fib(n - 2); }
+        int main() { printf("%ld\n", fib(10)); return 0; }
+    "#;
+    let options = CompileOptions {
+        hardening: true,
+        ..CompileOptions::default()
+    };
+    compile_with_options(source, &program, &options).expect("Compilation failed");
+    let result = run_program(&program, &RunConfig::default()).unwrap();
+    assert!(result.success());
+    assert_eq!(result.stdout_text(), "55\n");
+}
+
+#[test]
+fn test_cli_entry() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("candidates.c");
+    std::fs::write(
+        &input,
+        "int solve_a() { return 3; }\nint solve_b() { return solve_a() + 4; }\n",
+    )
+    .unwrap();
+    let output_path = temp_dir.path().join("candidates");
+    let compile = |entry: &str| {
+        Command::new(env!("CARGO_BIN_EXE_virtuc"))
+            .args(["compile", "--entry", entry, "-o"])
+            .arg(&output_path)
+            .arg(&input)
+            .output()
+            .expect("failed to run virtuc")
+    };
+
+    for (entry, code) in [("solve_a", 3), ("solve_b", 7)] {
+        let output = compile(entry);
+        assert!(output.status.success(), "{:?}", output);
+        let result = run_program(&output_path, &RunConfig::default()).unwrap();
+        assert_eq!(result.exit_code, Some(code));
+    }
+
+    let output = compile("solve_c");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Undefined function: solve_c"));
+}
+
+// Section names differ between object formats; these are ELF's
+#[cfg(target_os = "linux")]
+#[test]
+fn test_cli_size_report() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("sizes.c");
+    std::fs::write(
+        &input,
+        "int helper() { return 4; }\nint main() { return helper(); }\n",
+    )
+    .unwrap();
+    let output_path = temp_dir.path().join("sizes");
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_virtuc"))
+            .args(args)
+            .arg("-o")
+            .arg(&output_path)
+            .arg(&input)
+            .output()
+            .expect("failed to run virtuc")
+    };
+
+    let output = run(&["compile", "--size-report"]);
+    assert!(output.status.success(), "{:?}", output);
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert!(report.contains(".text"), "{}", report);
+    assert!(report.contains(".symtab"), "{}", report);
+
+    let output = run(&["compile", "--strip", "--size-report"]);
+    assert!(output.status.success(), "{:?}", output);
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert!(!report.contains(".symtab"), "{}", report);
+    let result = run_program(&output_path, &RunConfig::default()).unwrap();
+    assert_eq!(result.exit_code, Some(4));
+
+    let output = run(&["emit", "--size-report"]);
+    assert!(output.status.success(), "{:?}", output);
+    let report = std::fs::read_to_string(&output_path).unwrap();
+    assert!(report.starts_with("function"), "{}", report);
+    assert!(report.contains("helper"));
+}
+
+#[test]
+fn test_compile_artifacts() {
+    use virtuc::analysis::Warning;
+
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let output_path = temp_dir.path().join("artifacts");
+    let options = CompileOptions {
+        opt_level: 1,
+        ..CompileOptions::default()
+    };
+    let artifacts = compile_with_options(
+        "int unused() { return 1; } int main() { return 0; }",
+        &output_path,
+        &options,
+    )
+    .expect("Compilation failed");
+    assert_eq!(artifacts.output(), Some(output_path.as_path()));
+    assert_eq!(artifacts.outputs.len(), 1);
+    assert_eq!(artifacts.opt_level, 1);
+    assert!(!artifacts.target.is_empty());
+    assert_eq!(
+        artifacts.warnings,
+        [Warning::UnusedFunction("unused".to_string())]
+    );
+
+    let library = temp_dir.path().join("libartifacts.a");
+    let artifacts = compile_staticlib_with_options(
+        "int twice(int x) { return x * 2; }",
+        &library,
+        &CompileOptions::default(),
+    )
+    .expect("Compilation failed");
+    assert_eq!(
+        artifacts.outputs,
+        [library.clone(), library.with_extension("h")]
+    );
+    assert!(artifacts.outputs.iter().all(|path| path.exists()));
+}
+
+#[test]
+fn test_compile_template() {
+    use virtuc::ast::Literal;
+    use virtuc::template::Template;
+
+    let mut template = Template::parse(
+        "int power(int n, int x) {
+             int result = 1;
+             for (int i = 0; i < n; i = i + 1) { result = result * x; }
+             return result;
+         }",
+    )
+    .expect("Template failed to parse");
+    template
+        .specialize("power", "cube", &[("n", Literal::Int(3))])
+        .unwrap()
+        .unroll("cube", 8)
+        .unwrap()
+        .inject_source("int main() { return cube(3); }")
+        .unwrap();
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let output_path = temp_dir.path().join("cube");
+    compile_program_with_options(
+        &template.into_program(),
+        &output_path,
+        &CompileOptions::default(),
+    )
+    .expect("Compilation failed");
+    let result = run_program(&output_path, &RunConfig::default()).unwrap();
+    assert_eq!(result.exit_code, Some(27));
+
+    // Injected functions are checked when the program is compiled
+    let mut template = Template::parse("int one() { return 1; }").unwrap();
+    template
+        .inject_source("int main() { return two(); }")
+        .unwrap();
+    let result =
+        compile_program_with_options(template.program(), &output_path, &CompileOptions::default());
+    assert!(matches!(result, Err(CompileError::Semantic(..))));
+}
+
+#[test]
+fn test_cli_serve() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let requests = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"check","params":{"source":"int main() { return x; }"}}"#,
+        r#"{"jsonrpc":"2.0","id":2,"method":"run","params":{"source":"int main() { return 3; }"}}"#,
+        r#"{"jsonrpc":"2.0","id":3,"method":"run","params":{"source":"int main() { for (;;) { } return 0; }","timeout_ms":200}}"#,
+        r#"{"jsonrpc":"2.0","id":4,"method":"shutdown"}"#,
+        r#"{"jsonrpc":"2.0","id":5,"method":"check","params":{"source":""}}"#,
+    ];
+    let mut child = Command::new(env!("CARGO_BIN_EXE_virtuc"))
+        .arg("serve")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run virtuc");
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(requests.join("\n").as_bytes()).unwrap();
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let responses: Vec<&str> = stdout.lines().collect();
+    // The request after shutdown is not answered
+    assert_eq!(responses.len(), 4, "{}", stdout);
+    assert!(responses[0].contains(r#""ok":false"#), "{}", responses[0]);
+    assert!(responses[0].contains("E0201"), "{}", responses[0]);
+    assert!(
+        responses[1].contains(r#""exit_code":3"#),
+        "{}",
+        responses[1]
+    );
+    assert!(
+        responses[2].contains(r#""exceeded":"timed out""#),
+        "{}",
+        responses[2]
+    );
+    assert!(
+        responses[3].contains(r#""result":null"#),
+        "{}",
+        responses[3]
+    );
+}
+
+#[test]
+fn test_cli_grade() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let submissions = temp_dir.path().join("submissions");
+    std::fs::create_dir(&submissions).unwrap();
+    let echo = "#include <stdio.h>\nint main() { printf(\"hello\\n\"); return 0; }";
+    std::fs::write(submissions.join("a_good.c"), echo).unwrap();
+    std::fs::write(
+        submissions.join("b_wrong.c"),
+        "#include <stdio.h>\nint main() { printf(\"bye\\n\"); return 1; }",
+    )
+    .unwrap();
+    std::fs::write(submissions.join("c_broken.c"), "int main() { return x; }").unwrap();
+    std::fs::write(
+        submissions.join("d_slow.c"),
+        "int main() { for (;;) { } return 0; }",
+    )
+    .unwrap();
+    std::fs::write(submissions.join("notes.txt"), "not a submission").unwrap();
+    let spec = temp_dir.path().join("spec.toml");
+    std::fs::write(
+        &spec,
+        "[limits]\ntimeout-ms = 300\n\n[[test]]\nname = \"greets\"\nstdout = \"hello\\n\"\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_virtuc"))
+        .args(["grade", "--spec"])
+        .arg(&spec)
+        .arg(&submissions)
+        .output()
+        .expect("failed to run virtuc");
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("a_good.c: 1/1 tests passed"), "{}", stderr);
+    assert!(
+        stderr.contains("c_broken.c: does not compile"),
+        "{}",
+        stderr
+    );
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let submissions = report["submissions"].as_array().unwrap();
+    assert_eq!(submissions.len(), 4);
+    let wrong = &submissions[1]["tests"][0];
+    assert_eq!(wrong["passed"], false);
+    assert_eq!(wrong["exit_code"], 1);
+    assert_eq!(wrong["mismatch"]["line"], 1);
+    assert_eq!(wrong["mismatch"]["expected"], "hello");
+    assert_eq!(wrong["mismatch"]["actual"], "bye");
+    assert_eq!(submissions[2]["compiled"], false);
+    assert!(
+        submissions[2]["errors"][0]
+            .as_str()
+            .unwrap()
+            .starts_with("E0201")
+    );
+    assert_eq!(submissions[3]["tests"][0]["exceeded"], "timed out");
+}
+
+#[test]
+fn test_cli_build_links_manifest_sources() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let write = |name: &str, text: &str| std::fs::write(temp_dir.path().join(name), text).unwrap();
+    write(
+        "virtuc.toml",
+        "[package]\nname = \"app\"\n\n[build]\nsources = [\"main.c\", \"twice.c\"]\n",
+    );
+    write(
+        "main.c",
+        "extern int twice(int);\nint main() { return twice(21); }\n",
+    );
+    write("twice.c", "int twice(int x) { return x * 2; }\n");
+    let build = || {
+        Command::new(env!("CARGO_BIN_EXE_virtuc"))
+            .arg("build")
+            .current_dir(temp_dir.path())
+            .output()
+            .expect("failed to run virtuc")
+    };
+
+    let output = build();
+    assert!(output.status.success(), "{:?}", output);
+    let result = run_program(&temp_dir.path().join("app"), &RunConfig::default()).unwrap();
+    assert_eq!(result.exit_code, Some(42));
+
+    // A function defined twice is an error in the program, not in the compiler
+    write("twice.c", "int main() { return 0; }\n");
+    let output = build();
+    assert_eq!(
+        output.status.code(),
+        Some(exit_code::COMPILE_ERROR.into()),
+        "{:?}",
+        output
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("E0211"), "{}", stderr);
+    assert!(stderr.contains("twice.c"), "{}", stderr);
+}
+
+#[test]
+fn test_cli_json_messages_are_located() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let input = temp_dir.path().join("w.c");
+    let output_path = temp_dir.path().join("w.out");
+    let compile = |source: &str| {
+        std::fs::write(&input, source).unwrap();
+        Command::new(env!("CARGO_BIN_EXE_virtuc"))
+            .args(["compile", "--message-format=json", "--timings"])
+            .arg(&input)
+            .arg("-o")
+            .arg(&output_path)
+            .output()
+            .expect("failed to run virtuc")
+    };
+    let records = |stdout: &[u8]| -> Vec<serde_json::Value> {
+        String::from_utf8_lossy(stdout)
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("stdout holds only JSON records"))
+            .collect()
+    };
+
+    // Warnings are located at the function they are about, and status
+    // messages go to stderr
+    let output = compile("int unused() { return 1; }\n\nint main() { return 0; }\n");
+    assert!(output.status.success(), "{:?}", output);
+    let warnings = records(&output.stdout);
+    assert_eq!(warnings.len(), 1, "{:?}", warnings);
+    assert_eq!(warnings[0]["code"], "W0002");
+    assert_eq!(warnings[0]["span"]["line"], 1);
+    assert_eq!(warnings[0]["span"]["column"], 5);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Compiled"), "{}", stderr);
+
+    // Semantic errors are located at their statement
+    let output = compile("int main() {\n    int x = 1;\n    return y;\n}\n");
+    assert_eq!(output.status.code(), Some(exit_code::COMPILE_ERROR.into()));
+    let errors = records(&output.stdout);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert_eq!(errors[0]["code"], "E0201");
+    assert_eq!(errors[0]["span"]["line"], 3);
+    assert_eq!(errors[0]["span"]["column"], 5);
+}
+
+#[test]
+fn test_cli_grade_limits_memory() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let submissions = temp_dir.path().join("submissions");
+    std::fs::create_dir(&submissions).unwrap();
+    std::fs::write(
+        submissions.join("deep.c"),
+        "int f(int n) { return f(n + 1) + 1; }\nint main() { return f(0); }",
+    )
+    .unwrap();
+    let spec = temp_dir.path().join("spec.toml");
+    let grade = |limits: &str| {
+        std::fs::write(
+            &spec,
+            format!("[limits]\n{}\n[[test]]\nname = \"runs\"\n", limits),
+        )
+        .unwrap();
+        Command::new(env!("CARGO_BIN_EXE_virtuc"))
+            .args(["grade", "--spec"])
+            .arg(&spec)
+            .arg(&submissions)
+            .output()
+            .expect("failed to run virtuc")
+    };
+
+    let output = grade("memory-mb = 16");
+    assert!(output.status.success(), "{:?}", output);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let test = &report["submissions"][0]["tests"][0];
+    assert_eq!(test["passed"], false);
+    assert_eq!(test["exceeded"], "exceeded the memory limit");
+
+    // An invalid spec is a usage error
+    let output = grade("memory-mb = 0");
+    assert_eq!(output.status.code(), Some(i32::from(exit_code::USAGE)));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("invalid memory-mb 0"), "{}", stderr);
+}
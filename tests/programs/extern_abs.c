@@ -0,0 +1,7 @@
+extern int abs(int);
+
+int main() {
+    int a = 3;
+    int b = 10;
+    return abs(a - b) * 2;
+}
@@ -0,0 +1,15 @@
+declare i64 @abs(i64)
+
+define i64 @main() {
+v0:
+  %v1 = alloca i64, align 8
+  store i64 3, ptr %v1, align 8
+  %v2 = alloca i64, align 8
+  store i64 10, ptr %v2, align 8
+  %v3 = load i64, ptr %v1, align 8
+  %v4 = load i64, ptr %v2, align 8
+  %v5 = sub i64 %v3, %v4
+  %v6 = call i64 @abs(i64 %v5)
+  %v7 = mul i64 %v6, 2
+  ret i64 %v7
+}
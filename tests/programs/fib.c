@@ -0,0 +1,16 @@
+#include <stdio.h>
+
+int fib(int n) {
+    if (n < 2) {
+        return n;
+    }
+    return fib(n - 1) + fib(n - 2);
+}
+
+int main() {
+    for (int i = 0; i < 10; i = i + 1) {
+        printf("%ld ", fib(i));
+    }
+    printf("\n");
+    return fib(10);
+}
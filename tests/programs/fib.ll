@@ -0,0 +1,63 @@
+@str = private unnamed_addr constant [5 x i8] c"%ld \00", align 1
+@str.1 = private unnamed_addr constant [2 x i8] c"\0A\00", align 1
+
+declare i64 @printf(ptr, ...)
+
+define i64 @fib(i64 %v0) {
+v1:
+  %v2 = alloca i64, align 8
+  store i64 %v0, ptr %v2, align 8
+  %v3 = load i64, ptr %v2, align 8
+  %v4 = icmp slt i64 %v3, 2
+  %v5 = zext i1 %v4 to i64
+  %v6 = icmp ne i64 %v5, 0
+  br i1 %v6, label %v7, label %v8
+
+v7:
+  %v9 = load i64, ptr %v2, align 8
+  ret i64 %v9
+
+v8:
+  br label %v10
+
+v10:
+  %v11 = load i64, ptr %v2, align 8
+  %v12 = sub i64 %v11, 1
+  %v13 = call i64 @fib(i64 %v12)
+  %v14 = load i64, ptr %v2, align 8
+  %v15 = sub i64 %v14, 2
+  %v16 = call i64 @fib(i64 %v15)
+  %v17 = add i64 %v13, %v16
+  ret i64 %v17
+}
+
+define i64 @main() {
+v0:
+  %v1 = alloca i64, align 8
+  store i64 0, ptr %v1, align 8
+  br label %v2
+
+v2:
+  %v3 = load i64, ptr %v1, align 8
+  %v4 = icmp slt i64 %v3, 10
+  %v5 = zext i1 %v4 to i64
+  %v6 = icmp ne i64 %v5, 0
+  br i1 %v6, label %v7, label %v8
+
+v7:
+  %v9 = load i64, ptr %v1, align 8
+  %v10 = call i64 @fib(i64 %v9)
+  %v11 = call i64 (ptr, ...) @printf(ptr @str, i64 %v10)
+  br label %v12
+
+v12:
+  %v13 = load i64, ptr %v1, align 8
+  %v14 = add i64 %v13, 1
+  store i64 %v14, ptr %v1, align 8
+  br label %v2
+
+v8:
+  %v15 = call i64 (ptr, ...) @printf(ptr @str.1)
+  %v16 = call i64 @fib(i64 10)
+  ret i64 %v16
+}
@@ -0,0 +1,14 @@
+#include <stdio.h>
+
+float area(float r) {
+    return 3.5 * r * r;
+}
+
+int main() {
+    float sum = 0.0;
+    for (int i = 0; i < 4; i = i + 1) {
+        sum = sum + area(2.0);
+    }
+    printf("%.2f\n", sum);
+    return 0;
+}
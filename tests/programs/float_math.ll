@@ -0,0 +1,48 @@
+@str = private unnamed_addr constant [6 x i8] c"%.2f\0A\00", align 1
+
+declare i64 @printf(ptr, ...)
+
+define double @area(double %v0) {
+v1:
+  %v2 = alloca double, align 8
+  store double %v0, ptr %v2, align 8
+  %v3 = load double, ptr %v2, align 8
+  %v4 = fmul double 3.500000e+00, %v3
+  %v5 = load double, ptr %v2, align 8
+  %v6 = fmul double %v4, %v5
+  ret double %v6
+}
+
+define i64 @main() {
+v0:
+  %v1 = alloca double, align 8
+  store double 0.000000e+00, ptr %v1, align 8
+  %v2 = alloca i64, align 8
+  store i64 0, ptr %v2, align 8
+  br label %v3
+
+v3:
+  %v4 = load i64, ptr %v2, align 8
+  %v5 = icmp slt i64 %v4, 4
+  %v6 = zext i1 %v5 to i64
+  %v7 = icmp ne i64 %v6, 0
+  br i1 %v7, label %v8, label %v9
+
+v8:
+  %v10 = load double, ptr %v1, align 8
+  %v11 = call double @area(double 2.000000e+00)
+  %v12 = fadd double %v10, %v11
+  store double %v12, ptr %v1, align 8
+  br label %v13
+
+v13:
+  %v14 = load i64, ptr %v2, align 8
+  %v15 = add i64 %v14, 1
+  store i64 %v15, ptr %v2, align 8
+  br label %v3
+
+v9:
+  %v16 = load double, ptr %v1, align 8
+  %v17 = call i64 (ptr, ...) @printf(ptr @str, double %v16)
+  ret i64 0
+}
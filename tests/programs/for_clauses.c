@@ -0,0 +1,83 @@
+#include <stdio.h>
+
+int all_clauses() {
+    int n = 0;
+    for (int i = 0; i < 3; i = i + 1) {
+        n = n + 1;
+    }
+    return n;
+}
+
+int no_init() {
+    int i = 0;
+    for (; i < 4; i = i + 1) {
+    }
+    return i;
+}
+
+int no_cond() {
+    for (int i = 0;; i = i + 1) {
+        if (i > 4) {
+            return i;
+        }
+    }
+    return 0;
+}
+
+int no_update() {
+    int n = 0;
+    for (int i = 0; i < 3;) {
+        i = i + 1;
+        n = n + 2;
+    }
+    return n;
+}
+
+int only_init() {
+    for (int i = 7;;) {
+        return i;
+    }
+    return 0;
+}
+
+int only_cond() {
+    int i = 0;
+    for (; i < 8;) {
+        i = i + 1;
+    }
+    return i;
+}
+
+int only_update() {
+    int i = 0;
+    for (;; i = i + 1) {
+        if (i > 8) {
+            return i;
+        }
+    }
+    return 0;
+}
+
+int no_clauses() {
+    int i = 0;
+    for (;;) {
+        i = i + 1;
+        if (i == 10) {
+            return i;
+        }
+    }
+    return 0;
+}
+
+int empty_body() {
+    int i = 0;
+    for (; i < 11; i = i + 1);
+    return i;
+}
+
+int main() {
+    printf("%ld %ld %ld %ld ", all_clauses(), no_init(), no_cond(), no_update());
+    printf("%ld %ld %ld %ld ", only_init(), only_cond(), only_update(), no_clauses());
+    printf("%ld\n", empty_body());
+    return 0;
+}
@@ -0,0 +1,283 @@
+@str = private unnamed_addr constant [17 x i8] c"%ld %ld %ld %ld \00", align 1
+@str.1 = private unnamed_addr constant [17 x i8] c"%ld %ld %ld %ld \00", align 1
+@str.2 = private unnamed_addr constant [5 x i8] c"%ld\0A\00", align 1
+
+declare i64 @printf(ptr, ...)
+
+define i64 @all_clauses() {
+v0:
+  %v1 = alloca i64, align 8
+  store i64 0, ptr %v1, align 8
+  %v2 = alloca i64, align 8
+  store i64 0, ptr %v2, align 8
+  br label %v3
+
+v3:
+  %v4 = load i64, ptr %v2, align 8
+  %v5 = icmp slt i64 %v4, 3
+  %v6 = zext i1 %v5 to i64
+  %v7 = icmp ne i64 %v6, 0
+  br i1 %v7, label %v8, label %v9
+
+v8:
+  %v10 = load i64, ptr %v1, align 8
+  %v11 = add i64 %v10, 1
+  store i64 %v11, ptr %v1, align 8
+  br label %v12
+
+v12:
+  %v13 = load i64, ptr %v2, align 8
+  %v14 = add i64 %v13, 1
+  store i64 %v14, ptr %v2, align 8
+  br label %v3
+
+v9:
+  %v15 = load i64, ptr %v1, align 8
+  ret i64 %v15
+}
+
+define i64 @no_init() {
+v0:
+  %v1 = alloca i64, align 8
+  store i64 0, ptr %v1, align 8
+  br label %v2
+
+v2:
+  %v3 = load i64, ptr %v1, align 8
+  %v4 = icmp slt i64 %v3, 4
+  %v5 = zext i1 %v4 to i64
+  %v6 = icmp ne i64 %v5, 0
+  br i1 %v6, label %v7, label %v8
+
+v7:
+  br label %v9
+
+v9:
+  %v10 = load i64, ptr %v1, align 8
+  %v11 = add i64 %v10, 1
+  store i64 %v11, ptr %v1, align 8
+  br label %v2
+
+v8:
+  %v12 = load i64, ptr %v1, align 8
+  ret i64 %v12
+}
+
+define i64 @no_cond() {
+v0:
+  %v1 = alloca i64, align 8
+  store i64 0, ptr %v1, align 8
+  br label %v2
+
+v2:
+  br label %v3
+
+v3:
+  %v4 = load i64, ptr %v1, align 8
+  %v5 = icmp sgt i64 %v4, 4
+  %v6 = zext i1 %v5 to i64
+  %v7 = icmp ne i64 %v6, 0
+  br i1 %v7, label %v8, label %v9
+
+v10:
+  %v11 = load i64, ptr %v1, align 8
+  %v12 = add i64 %v11, 1
+  store i64 %v12, ptr %v1, align 8
+  br label %v2
+
+v13:                                         ; No predecessors!
+  ret i64 0
+
+v8:
+  %v14 = load i64, ptr %v1, align 8
+  ret i64 %v14
+
+v9:
+  br label %v15
+
+v15:
+  br label %v10
+}
+
+define i64 @no_update() {
+v0:
+  %v1 = alloca i64, align 8
+  store i64 0, ptr %v1, align 8
+  %v2 = alloca i64, align 8
+  store i64 0, ptr %v2, align 8
+  br label %v3
+
+v3:
+  %v4 = load i64, ptr %v2, align 8
+  %v5 = icmp slt i64 %v4, 3
+  %v6 = zext i1 %v5 to i64
+  %v7 = icmp ne i64 %v6, 0
+  br i1 %v7, label %v8, label %v9
+
+v8:
+  %v10 = load i64, ptr %v2, align 8
+  %v11 = add i64 %v10, 1
+  store i64 %v11, ptr %v2, align 8
+  %v12 = load i64, ptr %v1, align 8
+  %v13 = add i64 %v12, 2
+  store i64 %v13, ptr %v1, align 8
+  br label %v3
+
+v9:
+  %v14 = load i64, ptr %v1, align 8
+  ret i64 %v14
+}
+
+define i64 @only_init() {
+v0:
+  %v1 = alloca i64, align 8
+  store i64 7, ptr %v1, align 8
+  br label %v2
+
+v2:
+  br label %v3
+
+v3:
+  %v4 = load i64, ptr %v1, align 8
+  ret i64 %v4
+
+v5:                                         ; No predecessors!
+  ret i64 0
+}
+
+define i64 @only_cond() {
+v0:
+  %v1 = alloca i64, align 8
+  store i64 0, ptr %v1, align 8
+  br label %v2
+
+v2:
+  %v3 = load i64, ptr %v1, align 8
+  %v4 = icmp slt i64 %v3, 8
+  %v5 = zext i1 %v4 to i64
+  %v6 = icmp ne i64 %v5, 0
+  br i1 %v6, label %v7, label %v8
+
+v7:
+  %v9 = load i64, ptr %v1, align 8
+  %v10 = add i64 %v9, 1
+  store i64 %v10, ptr %v1, align 8
+  br label %v2
+
+v8:
+  %v11 = load i64, ptr %v1, align 8
+  ret i64 %v11
+}
+
+define i64 @only_update() {
+v0:
+  %v1 = alloca i64, align 8
+  store i64 0, ptr %v1, align 8
+  br label %v2
+
+v2:
+  br label %v3
+
+v3:
+  %v4 = load i64, ptr %v1, align 8
+  %v5 = icmp sgt i64 %v4, 8
+  %v6 = zext i1 %v5 to i64
+  %v7 = icmp ne i64 %v6, 0
+  br i1 %v7, label %v8, label %v9
+
+v10:
+  %v11 = load i64, ptr %v1, align 8
+  %v12 = add i64 %v11, 1
+  store i64 %v12, ptr %v1, align 8
+  br label %v2
+
+v13:                                         ; No predecessors!
+  ret i64 0
+
+v8:
+  %v14 = load i64, ptr %v1, align 8
+  ret i64 %v14
+
+v9:
+  br label %v15
+
+v15:
+  br label %v10
+}
+
+define i64 @no_clauses() {
+v0:
+  %v1 = alloca i64, align 8
+  store i64 0, ptr %v1, align 8
+  br label %v2
+
+v2:
+  br label %v3
+
+v3:
+  %v4 = load i64, ptr %v1, align 8
+  %v5 = add i64 %v4, 1
+  store i64 %v5, ptr %v1, align 8
+  %v6 = load i64, ptr %v1, align 8
+  %v7 = icmp eq i64 %v6, 10
+  %v8 = zext i1 %v7 to i64
+  %v9 = icmp ne i64 %v8, 0
+  br i1 %v9, label %v10, label %v11
+
+v12:                                         ; No predecessors!
+  ret i64 0
+
+v10:
+  %v13 = load i64, ptr %v1, align 8
+  ret i64 %v13
+
+v11:
+  br label %v14
+
+v14:
+  br label %v2
+}
+
+define i64 @empty_body() {
+v0:
+  %v1 = alloca i64, align 8
+  store i64 0, ptr %v1, align 8
+  br label %v2
+
+v2:
+  %v3 = load i64, ptr %v1, align 8
+  %v4 = icmp slt i64 %v3, 11
+  %v5 = zext i1 %v4 to i64
+  %v6 = icmp ne i64 %v5, 0
+  br i1 %v6, label %v7, label %v8
+
+v7:
+  br label %v9
+
+v9:
+  %v10 = load i64, ptr %v1, align 8
+  %v11 = add i64 %v10, 1
+  store i64 %v11, ptr %v1, align 8
+  br label %v2
+
+v8:
+  %v12 = load i64, ptr %v1, align 8
+  ret i64 %v12
+}
+
+define i64 @main() {
+v0:
+  %v1 = call i64 @all_clauses()
+  %v2 = call i64 @no_init()
+  %v3 = call i64 @no_cond()
+  %v4 = call i64 @no_update()
+  %v5 = call i64 (ptr, ...) @printf(ptr @str, i64 %v1, i64 %v2, i64 %v3, i64 %v4)
+  %v6 = call i64 @only_init()
+  %v7 = call i64 @only_cond()
+  %v8 = call i64 @only_update()
+  %v9 = call i64 @no_clauses()
+  %v10 = call i64 (ptr, ...) @printf(ptr @str.1, i64 %v6, i64 %v7, i64 %v8, i64 %v9)
+  %v11 = call i64 @empty_body()
+  %v12 = call i64 (ptr, ...) @printf(ptr @str.2, i64 %v11)
+  ret i64 0
+}
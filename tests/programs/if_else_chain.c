@@ -0,0 +1,18 @@
+#include <stdio.h>
+
+int classify(int x) {
+    if (x < 0) {
+        return 0;
+    } else if (x == 0) {
+        return 1;
+    } else if (x < 10) {
+        return 2;
+    } else {
+        return 3;
+    }
+}
+
+int main() {
+    printf("%ld %ld %ld %ld\n", classify(0 - 5), classify(0), classify(7), classify(12));
+    return classify(7);
+}
@@ -0,0 +1,60 @@
+@str = private unnamed_addr constant [17 x i8] c"%ld %ld %ld %ld\0A\00", align 1
+
+declare i64 @printf(ptr, ...)
+
+define i64 @classify(i64 %v0) {
+v1:
+  %v2 = alloca i64, align 8
+  store i64 %v0, ptr %v2, align 8
+  %v3 = load i64, ptr %v2, align 8
+  %v4 = icmp slt i64 %v3, 0
+  %v5 = zext i1 %v4 to i64
+  %v6 = icmp ne i64 %v5, 0
+  br i1 %v6, label %v7, label %v8
+
+v7:
+  ret i64 0
+
+v8:
+  %v9 = load i64, ptr %v2, align 8
+  %v10 = icmp eq i64 %v9, 0
+  %v11 = zext i1 %v10 to i64
+  %v12 = icmp ne i64 %v11, 0
+  br i1 %v12, label %v13, label %v14
+
+v15:
+  ret i64 0
+
+v13:
+  ret i64 1
+
+v14:
+  %v16 = load i64, ptr %v2, align 8
+  %v17 = icmp slt i64 %v16, 10
+  %v18 = zext i1 %v17 to i64
+  %v19 = icmp ne i64 %v18, 0
+  br i1 %v19, label %v20, label %v21
+
+v22:
+  br label %v15
+
+v20:
+  ret i64 2
+
+v21:
+  ret i64 3
+
+v23:                                          ; No predecessors!
+  br label %v22
+}
+
+define i64 @main() {
+v0:
+  %v1 = call i64 @classify(i64 -5)
+  %v2 = call i64 @classify(i64 0)
+  %v3 = call i64 @classify(i64 7)
+  %v4 = call i64 @classify(i64 12)
+  %v5 = call i64 (ptr, ...) @printf(ptr @str, i64 %v1, i64 %v2, i64 %v3, i64 %v4)
+  %v6 = call i64 @classify(i64 7)
+  ret i64 %v6
+}
@@ -0,0 +1,13 @@
+#include <stdio.h>
+
+int main() {
+    int total = 0;
+    for (int i = 1; i <= 3; i = i + 1) {
+        for (int j = 1; j <= 3; j = j + 1) {
+            printf("%ld ", i * j);
+            total = total + i * j;
+        }
+        printf("\n");
+    }
+    return total;
+}
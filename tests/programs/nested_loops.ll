@@ -0,0 +1,65 @@
+@str = private unnamed_addr constant [5 x i8] c"%ld \00", align 1
+@str.1 = private unnamed_addr constant [2 x i8] c"\0A\00", align 1
+
+declare i64 @printf(ptr, ...)
+
+define i64 @main() {
+v0:
+  %v1 = alloca i64, align 8
+  store i64 0, ptr %v1, align 8
+  %v2 = alloca i64, align 8
+  store i64 1, ptr %v2, align 8
+  br label %v3
+
+v3:
+  %v4 = load i64, ptr %v2, align 8
+  %v5 = icmp sle i64 %v4, 3
+  %v6 = zext i1 %v5 to i64
+  %v7 = icmp ne i64 %v6, 0
+  br i1 %v7, label %v8, label %v9
+
+v8:
+  %v10 = alloca i64, align 8
+  store i64 1, ptr %v10, align 8
+  br label %v11
+
+v12:
+  %v13 = load i64, ptr %v2, align 8
+  %v14 = add i64 %v13, 1
+  store i64 %v14, ptr %v2, align 8
+  br label %v3
+
+v9:
+  %v15 = load i64, ptr %v1, align 8
+  ret i64 %v15
+
+v11:
+  %v16 = load i64, ptr %v10, align 8
+  %v17 = icmp sle i64 %v16, 3
+  %v18 = zext i1 %v17 to i64
+  %v19 = icmp ne i64 %v18, 0
+  br i1 %v19, label %v20, label %v21
+
+v20:
+  %v22 = load i64, ptr %v2, align 8
+  %v23 = load i64, ptr %v10, align 8
+  %v24 = mul i64 %v22, %v23
+  %v25 = call i64 (ptr, ...) @printf(ptr @str, i64 %v24)
+  %v26 = load i64, ptr %v1, align 8
+  %v27 = load i64, ptr %v2, align 8
+  %v28 = load i64, ptr %v10, align 8
+  %v29 = mul i64 %v27, %v28
+  %v30 = add i64 %v26, %v29
+  store i64 %v30, ptr %v1, align 8
+  br label %v31
+
+v31:
+  %v32 = load i64, ptr %v10, align 8
+  %v33 = add i64 %v32, 1
+  store i64 %v33, ptr %v10, align 8
+  br label %v11
+
+v21:
+  %v34 = call i64 (ptr, ...) @printf(ptr @str.1)
+  br label %v12
+}
@@ -0,0 +1,19 @@
+#include <stdio.h>
+#include <virtuc/random.h>
+
+int roll() {
+    int n = rand_next();
+    return n - n / 6 * 6 + 1;
+}
+
+int main() {
+    rand_seeded(2024);
+    int total = 0;
+    for (int i = 0; i < 10; i = i + 1) {
+        int die = roll();
+        printf("%d ", die);
+        total = total + die;
+    }
+    printf("\n");
+    return total;
+}
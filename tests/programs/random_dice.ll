@@ -0,0 +1,75 @@
+@virtuc_rt.rand.state = internal global i64 1
+@str = private unnamed_addr constant [4 x i8] c"%d \00", align 1
+@str.1 = private unnamed_addr constant [2 x i8] c"\0A\00", align 1
+
+declare i64 @printf(ptr, ...)
+
+define i64 @roll() {
+v0:
+  %v1 = alloca i64, align 8
+  %v2 = call i64 @virtuc_rt_rand_next()
+  store i64 %v2, ptr %v1, align 8
+  %v3 = load i64, ptr %v1, align 8
+  %v4 = load i64, ptr %v1, align 8
+  %v5 = sdiv i64 %v4, 6
+  %v6 = mul i64 %v5, 6
+  %v7 = sub i64 %v3, %v6
+  %v8 = add i64 %v7, 1
+  ret i64 %v8
+}
+
+define i64 @main() {
+v0:
+  call void @virtuc_rt_rand_seed(i64 2024)
+  %v1 = alloca i64, align 8
+  store i64 0, ptr %v1, align 8
+  %v2 = alloca i64, align 8
+  store i64 0, ptr %v2, align 8
+  br label %v3
+
+v3:
+  %v4 = load i64, ptr %v2, align 8
+  %v5 = icmp slt i64 %v4, 10
+  %v6 = zext i1 %v5 to i64
+  %v7 = icmp ne i64 %v6, 0
+  br i1 %v7, label %v8, label %v9
+
+v8:
+  %v10 = alloca i64, align 8
+  %v11 = call i64 @roll()
+  store i64 %v11, ptr %v10, align 8
+  %v12 = load i64, ptr %v10, align 8
+  %v13 = call i64 (ptr, ...) @printf(ptr @str, i64 %v12)
+  %v14 = load i64, ptr %v1, align 8
+  %v15 = load i64, ptr %v10, align 8
+  %v16 = add i64 %v14, %v15
+  store i64 %v16, ptr %v1, align 8
+  br label %v17
+
+v17:
+  %v18 = load i64, ptr %v2, align 8
+  %v19 = add i64 %v18, 1
+  store i64 %v19, ptr %v2, align 8
+  br label %v3
+
+v9:
+  %v20 = call i64 (ptr, ...) @printf(ptr @str.1)
+  %v21 = load i64, ptr %v1, align 8
+  ret i64 %v21
+}
+
+define internal i64 @virtuc_rt_rand_next() {
+v0:
+  %v1 = load i64, ptr @virtuc_rt.rand.state, align 8
+  %v2 = mul i64 %v1, 6364136223846793005
+  %v3 = add i64 %v2, 1442695040888963407
+  store i64 %v3, ptr @virtuc_rt.rand.state, align 8
+  %v4 = lshr i64 %v3, 33
+  ret i64 %v4
+}
+
+define internal void @virtuc_rt_rand_seed(i64 %v0) {
+v1:
+  store i64 %v0, ptr @virtuc_rt.rand.state, align 8
+  ret void
+}
@@ -0,0 +1,23 @@
+#include <stdio.h>
+
+int scale(int x) {
+    int y = x * 2;
+    {
+        int x = 100;
+        y = y + x;
+    }
+    for (int x = 0; x < 3; x = x + 1) {
+        y = y + x;
+    }
+    return y + x;
+}
+
+int main() {
+    int x = 1;
+    {
+        int x = 2;
+        printf("%ld\n", x);
+    }
+    printf("%ld\n", scale(5));
+    return x;
+}
@@ -0,0 +1,63 @@
+@str = private unnamed_addr constant [5 x i8] c"%ld\0A\00", align 1
+@str.1 = private unnamed_addr constant [5 x i8] c"%ld\0A\00", align 1
+
+declare i64 @printf(ptr, ...)
+
+define i64 @scale(i64 %v0) {
+v1:
+  %v2 = alloca i64, align 8
+  store i64 %v0, ptr %v2, align 8
+  %v3 = alloca i64, align 8
+  %v4 = load i64, ptr %v2, align 8
+  %v5 = mul i64 %v4, 2
+  store i64 %v5, ptr %v3, align 8
+  %v6 = alloca i64, align 8
+  store i64 100, ptr %v6, align 8
+  %v7 = load i64, ptr %v3, align 8
+  %v8 = load i64, ptr %v6, align 8
+  %v9 = add i64 %v7, %v8
+  store i64 %v9, ptr %v3, align 8
+  %v10 = alloca i64, align 8
+  store i64 0, ptr %v10, align 8
+  br label %v11
+
+v11:
+  %v12 = load i64, ptr %v10, align 8
+  %v13 = icmp slt i64 %v12, 3
+  %v14 = zext i1 %v13 to i64
+  %v15 = icmp ne i64 %v14, 0
+  br i1 %v15, label %v16, label %v17
+
+v16:
+  %v18 = load i64, ptr %v3, align 8
+  %v19 = load i64, ptr %v10, align 8
+  %v20 = add i64 %v18, %v19
+  store i64 %v20, ptr %v3, align 8
+  br label %v21
+
+v21:
+  %v22 = load i64, ptr %v10, align 8
+  %v23 = add i64 %v22, 1
+  store i64 %v23, ptr %v10, align 8
+  br label %v11
+
+v17:
+  %v24 = load i64, ptr %v3, align 8
+  %v25 = load i64, ptr %v2, align 8
+  %v26 = add i64 %v24, %v25
+  ret i64 %v26
+}
+
+define i64 @main() {
+v0:
+  %v1 = alloca i64, align 8
+  store i64 1, ptr %v1, align 8
+  %v2 = alloca i64, align 8
+  store i64 2, ptr %v2, align 8
+  %v3 = load i64, ptr %v2, align 8
+  %v4 = call i64 (ptr, ...) @printf(ptr @str, i64 %v3)
+  %v5 = call i64 @scale(i64 5)
+  %v6 = call i64 (ptr, ...) @printf(ptr @str.1, i64 %v5)
+  %v7 = load i64, ptr %v1, align 8
+  ret i64 %v7
+}